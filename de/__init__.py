@@ -5,14 +5,37 @@ import humanize
 from .core import chunks
 from .display import print_table
 from .formats import ParquetCpp
-from .estimate import estimate as _estimate, compare_formats_tables, compare_formats
+from .report import load_report, write_report
+from .estimate import (
+    estimate as _estimate,
+    chunk_table,
+    compare_formats_tables,
+    compare_formats,
+    deletion_savings,
+    duplication,
+    estimate_against_bloom,
+    experiment,
+    export_bloom,
+    overlap,
+    upset,
+)
 
 __all__ = [
+    "chunk_table",
     "chunks",
     "compare_formats_tables",
     "compare_formats",
+    "deletion_savings",
+    "duplication",
     "estimate",
+    "estimate_against_bloom",
+    "experiment",
+    "export_bloom",
+    "load_report",
+    "overlap",
+    "upset",
     "visualize",
+    "write_report",
 ]
 
 
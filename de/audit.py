@@ -0,0 +1,278 @@
+"""End-to-end audit of a directory or Hub repository.
+
+The audit scans the parquet footers, estimates the deduplication of all the
+files, clusters the files sharing chunks and recommends rewrites of the
+parquet files whose layout prevents deduplication, with the saving measured by
+actually rewriting them to a temporary directory. All three come from a
+single chunking of every file.
+"""
+
+from collections import defaultdict
+from dataclasses import dataclass, field, replace
+from itertools import combinations
+from pathlib import Path
+import tempfile
+
+import pyarrow.parquet as pq
+
+from .core import chunks
+from .estimate import cdc_confidence
+from .fileutils import detect_format
+from .formats import ParquetCpp
+from .manifest import fetch, list_hub_tree
+
+# Confidence below which a parquet file is considered written without CDC.
+CDC_THRESHOLD = 0.5
+# Jaccard similarity of the unique chunk bytes above which files are clustered.
+SIMILARITY_THRESHOLD = 0.5
+
+
+@dataclass(frozen=True)
+class FileScan:
+    path: str
+    format: str
+    size: int
+    # the parquet compression codecs and CDC confidence, empty and None for
+    # other formats
+    codecs: tuple[str, ...] = ()
+    cdc_confidence: float | None = None
+
+
+@dataclass(frozen=True)
+class Recommendation:
+    action: str
+    files: list[str]
+    chunk_bytes: int
+    rewritten_chunk_bytes: int
+
+    @property
+    def saving_bytes(self) -> int:
+        return self.chunk_bytes - self.rewritten_chunk_bytes
+
+    @property
+    def saving(self) -> float:
+        return self.saving_bytes / self.chunk_bytes if self.chunk_bytes else 0.0
+
+    def __str__(self):
+        return (
+            f"{self.action} on these {len(self.files)} files for an estimated "
+            f"{self.saving:.0%} saving"
+        )
+
+
+@dataclass
+class AuditResult:
+    files: list[FileScan]
+    estimate: dict
+    clusters: list[list[str]]
+    recommendations: list[Recommendation] = field(default_factory=list)
+
+
+def scan_footer(path) -> FileScan:
+    """Classify a file and, for parquet files, read the compression codecs of
+    its column chunks from the footer and score whether it was written with
+    content defined chunking."""
+    path = Path(path)
+    fmt = detect_format(path)
+    size = path.stat().st_size
+    if fmt != "parquet":
+        return FileScan(str(path), fmt, size)
+    metadata = pq.ParquetFile(path).metadata
+    codecs = {
+        metadata.row_group(i).column(j).compression
+        for i in range(metadata.num_row_groups)
+        for j in range(metadata.num_columns)
+    }
+    confidence = cdc_confidence(path)["confidence"]
+    return FileScan(str(path), fmt, size, tuple(sorted(codecs)), confidence)
+
+
+@dataclass(frozen=True)
+class _FileChunks:
+    """The total bytes of a file and its unique chunks, mapping their hash to
+    their (size, compressed size)."""
+
+    total: int
+    unique: dict[int, tuple[int, int]]
+
+
+def _file_chunks(path) -> _FileChunks:
+    # chunk boundaries only depend on the bytes of the file itself, so files
+    # chunked one at a time yield the same chunks as chunked together
+    total, unique = 0, {}
+    for hash, chunk in chunks([str(path)]):
+        total += chunk["size"]
+        unique[hash] = (chunk["size"], chunk["compressed"])
+    return _FileChunks(total, unique)
+
+
+def _union(files) -> dict[int, tuple[int, int]]:
+    union = {}
+    for file in files:
+        union.update(file.unique)
+    return union
+
+
+def _estimate(files) -> dict:
+    """The deduplication stats of the files, like estimate_labeled()."""
+    union = _union(files)
+    total = sum(file.total for file in files)
+    chunk_bytes = sum(size for size, _ in union.values())
+    return {
+        "numfiles": len(files),
+        "total_len": total,
+        "chunk_bytes": chunk_bytes,
+        "compressed_chunk_bytes": sum(compressed for _, compressed in union.values()),
+        "dedup_ratio": chunk_bytes / total if total else 1.0,
+    }
+
+
+def _clusters(paths, files, threshold) -> list[list[str]]:
+    unique = [sum(size for size, _ in file.unique.values()) for file in files]
+    seen_in = defaultdict(list)
+    for i, file in enumerate(files):
+        for hash in file.unique:
+            seen_in[hash].append(i)
+    sizes = _union(files)
+    shared = defaultdict(int)
+    for hash, indices in seen_in.items():
+        for pair in combinations(indices, 2):
+            shared[pair] += sizes[hash][0]
+
+    parent = list(range(len(paths)))
+
+    def find(i):
+        while parent[i] != i:
+            parent[i] = parent[parent[i]]
+            i = parent[i]
+        return i
+
+    for (a, b), size in shared.items():
+        if size / (unique[a] + unique[b] - size) >= threshold:
+            parent[find(a)] = find(b)
+    groups = defaultdict(list)
+    for i, path in enumerate(paths):
+        groups[find(i)].append(path)
+    clusters = [group for group in groups.values() if len(group) > 1]
+    return sorted(clusters, key=len, reverse=True)
+
+
+def similarity_clusters(paths, threshold=SIMILARITY_THRESHOLD) -> list[list[str]]:
+    """Group the files whose unique chunk bytes have a Jaccard similarity of at
+    least threshold, transitively. Files similar to no other file are left
+    out; clusters are sorted by descending size."""
+    string_paths = list(map(str, paths))
+    files = [_file_chunks(path) for path in string_paths]
+    return _clusters(string_paths, files, threshold)
+
+
+def _rewrite_candidate(scan, cdc_threshold) -> bool:
+    """Whether a file may deduplicate better rewritten with CDC and zstd."""
+    if scan.format != "parquet":
+        return False
+    return scan.codecs != ("ZSTD",) or (
+        scan.cdc_confidence is not None and scan.cdc_confidence < cdc_threshold
+    )
+
+
+def _rewrite_recommendations(candidates) -> list[Recommendation]:
+    # CDC only helps deduplicating across files holding the same kind of rows,
+    # so the candidates are grouped by schema and single files are skipped;
+    # files whose chunks don't match can't be found by similarity clustering
+    # since their misaligned pages are exactly what CDC fixes
+    groups = defaultdict(list)
+    for label, schema, before, after in candidates:
+        groups[schema].append((label, before, after))
+
+    recommendations = []
+    for group in groups.values():
+        if len(group) < 2:
+            continue
+        labels, before, after = zip(*group)
+        before = _estimate(before)["chunk_bytes"]
+        after = _estimate(after)["chunk_bytes"]
+        if after < before:
+            recommendations.append(
+                Recommendation("enable CDC + zstd", list(labels), before, after)
+            )
+    return sorted(recommendations, key=lambda r: r.saving_bytes, reverse=True)
+
+
+def _audit_files(files, similarity_threshold, cdc_threshold) -> AuditResult:
+    """Audit the (label, path) pairs of files, every file being read only
+    while it is the current one, so that the files of a repository can be
+    downloaded one at a time. Every file is chunked once, and the rewrite
+    candidates once more after being rewritten to a temporary directory."""
+    fmt = ParquetCpp(use_cdc=True, compression="zstd")
+    labels, scans, chunked, candidates = [], [], [], []
+    with tempfile.TemporaryDirectory() as tmp:
+        for label, path in files:
+            scan = replace(scan_footer(path), path=label)
+            labels.append(label)
+            scans.append(scan)
+            chunked.append(_file_chunks(path))
+            if _rewrite_candidate(scan, cdc_threshold):
+                schema = pq.read_schema(path).to_string(show_schema_metadata=False)
+                rewritten = fmt.write(f"{len(candidates):08d}", Path(path), Path(tmp))
+                candidates.append((label, schema, chunked[-1], _file_chunks(rewritten)))
+                rewritten.unlink()
+    if not labels:
+        raise ValueError("No files to audit")
+    return AuditResult(
+        files=scans,
+        estimate=_estimate(chunked),
+        clusters=_clusters(labels, chunked, similarity_threshold),
+        recommendations=_rewrite_recommendations(candidates),
+    )
+
+
+def audit_paths(
+    paths,
+    similarity_threshold=SIMILARITY_THRESHOLD,
+    cdc_threshold=CDC_THRESHOLD,
+) -> AuditResult:
+    """Audit local files, see audit()."""
+    files = [(str(path), path) for path in paths]
+    return _audit_files(files, similarity_threshold, cdc_threshold)
+
+
+def _fetched(entries, fetcher):
+    """Download the entries one at a time, yielding their key and their
+    path, which is removed once the next entry is requested."""
+    with tempfile.TemporaryDirectory() as tmp:
+        for entry in entries:
+            path = Path(tmp) / entry.key
+            path.parent.mkdir(parents=True, exist_ok=True)
+            fetcher(entry.url, path)
+            try:
+                yield entry.key, path
+            finally:
+                path.unlink()
+
+
+def audit(
+    repo_or_dir,
+    revision="main",
+    repo_type="datasets",
+    fetcher=fetch,
+    similarity_threshold=SIMILARITY_THRESHOLD,
+    cdc_threshold=CDC_THRESHOLD,
+) -> AuditResult:
+    """Audit all the files of a local directory or of a Hub repository.
+
+    Runs the footer scan, the dedup estimate and the similarity clustering,
+    then recommends rewrites, e.g. "enable CDC + zstd on these 12 files for an
+    estimated 38% saving". Every file is chunked once and nothing is written
+    next to the audited files. A repository (anything that isn't an existing
+    directory) is listed with the Hub tree API and its files are downloaded
+    one at a time with fetcher(url, dest) and removed once audited, so the
+    audit only needs the disk space of the largest file; the paths of the
+    result are then relative to the repository root.
+    """
+    root = Path(repo_or_dir)
+    if root.is_dir():
+        paths = sorted(path for path in root.rglob("*") if path.is_file())
+        return audit_paths(paths, similarity_threshold, cdc_threshold)
+    entries = list_hub_tree(str(repo_or_dir), revision=revision, repo_type=repo_type)
+    files = _fetched(entries, fetcher)
+    return _audit_files(files, similarity_threshold, cdc_threshold)
@@ -0,0 +1,189 @@
+"""Canonical byte serialization of Arrow arrays, the input of the decoded
+value deduplication of estimate_values().
+
+The physical layout of an Arrow array depends on how it is chunked and
+sliced, on its dictionary encoding, on the offset or view variant of its type
+and on the endianness of the platform, so its buffers can't be chunked as
+is. The canonical form only depends on the logical values and their type,
+every value being written in order as a record:
+
+- a null value is the single byte 0x00, a valid value is the byte 0x01
+  followed by its payload;
+- booleans are one byte, 0 or 1;
+- integers, floats, dates, times, timestamps and durations are the
+  little-endian bytes of their storage at the width and in the unit of the
+  type; every NaN is written as the quiet NaN of its width, the signed zeros
+  stay distinct;
+- decimals are the little-endian two's complement of the unscaled value at
+  the width of the type and fixed size binaries are their bytes;
+- strings and binaries of every variant are their byte length as a
+  little-endian u64 followed by the bytes, UTF-8 for strings;
+- lists of every variant, list views included, are their element count as
+  a little-endian u64 followed by the records of the elements, maps being
+  lists of key and item structs;
+- structs are the records of their fields in order;
+- dictionary arrays are written as their decoded values and extension arrays
+  as their storage.
+
+The type itself is not written, so an int32 and an int64 column holding the
+same values don't share bytes while a string column deduplicates against its
+large_string or dictionary encoded rewrite. Other types, like intervals and
+unions, raise a TypeError.
+"""
+
+import sys
+
+import numpy as np
+import pyarrow as pa
+
+_BINARY_TYPES = (
+    pa.types.is_binary,
+    pa.types.is_large_binary,
+    pa.types.is_binary_view,
+    pa.types.is_string,
+    pa.types.is_large_string,
+    pa.types.is_string_view,
+)
+_LIST_TYPES = (
+    pa.types.is_list,
+    pa.types.is_large_list,
+    pa.types.is_fixed_size_list,
+    pa.types.is_map,
+    pa.types.is_list_view,
+    pa.types.is_large_list_view,
+)
+# bytes copied at a time by _gather(), bounding its index temporaries
+_GATHER_BYTES = 1 << 24
+
+
+def _fixed(data: np.ndarray, width: int) -> tuple:
+    """Segments of `width` bytes of data per value."""
+    count = len(data) // width
+    return (
+        data,
+        np.arange(count, dtype=np.int64) * width,
+        np.full(count, width, dtype=np.int64),
+    )
+
+
+def _u64(values: np.ndarray) -> tuple:
+    return _fixed(values.astype("<u8").view(np.uint8), 8)
+
+
+def _copy(out, positions, data, starts, lengths) -> None:
+    """Copy the segments of data at starts to the positions of out."""
+    total = int(lengths.sum())
+    if total:
+        value = np.repeat(np.arange(len(lengths)), lengths)
+        within = np.arange(total) - np.repeat(np.cumsum(lengths) - lengths, lengths)
+        out[positions[value] + within] = data[starts[value] + within]
+
+
+def _gather(parts: list) -> tuple:
+    """Concatenate per value its segment of every part. A part is the (data,
+    starts, lengths) of one segment of data per value, the result is the
+    records of the values laid out contiguously in the same form. The
+    segments are copied about _GATHER_BYTES at a time, so the index arrays
+    don't grow with the size of the array."""
+    count = len(parts[0][2])
+    lengths = np.zeros(count, dtype=np.int64)
+    for _, _, part_lengths in parts:
+        lengths += part_lengths
+    starts = np.cumsum(lengths) - lengths
+    out = np.empty(int(lengths.sum()), dtype=np.uint8)
+    position = starts.copy()
+    for data, part_starts, part_lengths in parts:
+        ends = np.cumsum(part_lengths)
+        begin = 0
+        while begin < count:
+            # the values ending within the slice, at least one
+            copied = int(ends[begin - 1]) if begin else 0
+            end = int(np.searchsorted(ends, copied + _GATHER_BYTES, side="right"))
+            end = max(end, begin + 1)
+            chunk = slice(begin, end)
+            _copy(out, position[chunk], data, part_starts[chunk], part_lengths[chunk])
+            begin = end
+        position += part_lengths
+    return out, starts, lengths
+
+
+def _payload(array: pa.Array) -> list:
+    """The parts of the payloads of all the values, nulls included."""
+    t, count, offset = array.type, len(array), array.offset
+    if pa.types.is_boolean(t):
+        bits = np.frombuffer(array.buffers()[1], dtype=np.uint8)
+        values = np.unpackbits(bits, bitorder="little")[offset : offset + count]
+        return [_fixed(values, 1)]
+    if pa.types.is_interval(t):
+        raise TypeError(f"Can't serialize {t} values canonically")
+    if pa.types.is_integer(t) or pa.types.is_floating(t) or pa.types.is_temporal(t):
+        kind = "f" if pa.types.is_floating(t) else "i"
+        if pa.types.is_unsigned_integer(t):
+            kind = "u"
+        width = t.bit_width // 8
+        dtype = np.dtype(f"{kind}{width}")
+        values = np.frombuffer(array.buffers()[1], dtype=dtype)
+        values = values[offset : offset + count]
+        if kind == "f":
+            values = np.where(np.isnan(values), dtype.type(np.nan), values)
+        return [_fixed(values.astype(dtype.newbyteorder("<")).view(np.uint8), width)]
+    if pa.types.is_fixed_size_binary(t) or pa.types.is_decimal(t):
+        width = t.byte_width
+        values = np.frombuffer(array.buffers()[1], dtype=np.uint8)
+        values = values[offset * width : (offset + count) * width]
+        if pa.types.is_decimal(t) and sys.byteorder == "big":
+            values = values.reshape(-1, width)[:, ::-1].ravel()
+        return [_fixed(values, width)]
+    if any(is_type(t) for is_type in _BINARY_TYPES):
+        array = array.cast(pa.large_binary())
+        _, offsets, data = array.buffers()
+        offsets = np.frombuffer(offsets, dtype=np.int64)
+        offsets = offsets[array.offset : array.offset + count + 1]
+        if data is None:
+            data = np.empty(0, dtype=np.uint8)
+        else:
+            data = np.frombuffer(data, dtype=np.uint8)
+        lengths = np.diff(offsets)
+        return [_u64(lengths), (data, offsets[:-1], lengths)]
+    if any(is_type(t) for is_type in _LIST_TYPES):
+        if pa.types.is_fixed_size_list(t):
+            offsets = (np.arange(count + 1, dtype=np.int64) + offset) * t.list_size
+            first, sizes = offsets[:-1], np.diff(offsets)
+        elif pa.types.is_list_view(t) or pa.types.is_large_list_view(t):
+            # the views can overlap or be out of order, so each has its size
+            first = array.offsets.to_numpy().astype(np.int64)
+            sizes = array.sizes.to_numpy().astype(np.int64)
+        else:
+            offsets = array.offsets.to_numpy().astype(np.int64)
+            first, sizes = offsets[:-1], np.diff(offsets)
+        data, starts, _ = _records(array.values)
+        bounds = np.append(starts, len(data))
+        elements = (data, bounds[first], bounds[first + sizes] - bounds[first])
+        return [_u64(sizes), elements]
+    if pa.types.is_struct(t):
+        return [_records(field) for field in array.flatten()]
+    raise TypeError(f"Can't serialize {t} values canonically")
+
+
+def _records(array: pa.Array) -> tuple:
+    if pa.types.is_dictionary(array.type):
+        return _records(array.dictionary_decode())
+    if isinstance(array.type, pa.BaseExtensionType):
+        return _records(array.storage)
+    if not len(array):
+        empty = np.empty(0, dtype=np.int64)
+        return np.empty(0, dtype=np.uint8), empty, empty
+    valid = array.is_valid().to_numpy(zero_copy_only=False)
+    parts = [_fixed(valid.astype(np.uint8), 1)]
+    if not pa.types.is_null(array.type):
+        # the payload of the nulls is dropped
+        for data, starts, lengths in _payload(array):
+            parts.append((data, starts, np.where(valid, lengths, 0)))
+    return _gather(parts)
+
+
+def serialize(values: pa.Array | pa.ChunkedArray) -> bytes:
+    """Serialize an Arrow array or chunked array in the canonical form of the
+    module docstring."""
+    arrays = values.chunks if isinstance(values, pa.ChunkedArray) else [values]
+    return b"".join(_records(array)[0].tobytes() for array in arrays)
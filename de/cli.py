@@ -1,6 +1,8 @@
+import csv
 import json
 from pathlib import Path
 import sys
+import warnings
 
 import click
 import numpy as np
@@ -11,16 +13,95 @@ import plotly.io as pio
 import plotly.graph_objects as go
 
 from . import display
-from .estimate import estimate
-from .fileutils import checkout_file_revisions, get_page_chunk_sizes
+from .audit import CDC_THRESHOLD, SIMILARITY_THRESHOLD, audit
+from .core import dot_plot, expand_paths, export_caibx, export_hash_lists
+from .core import export_store
+from .estimate import estimate, estimate_pages, upset
+from .estimate import cdc_confidence, column_maps, marginal_contributions, plan
+from .estimate import DedupRegressionError, check_dedup, packing, simulate_cache
+from .estimate import diff, diff_snapshots, intra_chunk_redundancy, stage_timings
+from .estimate import ingest_throughput
+from .estimate import estimate_shifted, format_pairs, recommend_chunk_size
+from .estimate import estimate_delta
+from .estimate import chunk_size_stats, estimate_dictionary, estimate_hash_only
+from .estimate import CHUNKERS, CODECS, chunker_sensitivity, retention
+from .estimate import SWEEP_CHUNK_SIZES, chunk_size_sweep, export_manifest
+from .estimate import reconstruct
+from .fileutils import checkout_file_revisions, get_page_chunk_sizes, resolve_paths
+from .fileutils import IgnoreRules, filesystem_savings, filter_paths
 from .formats import ParquetCpp, ParquetRs, JsonLines, Sqlite, CdcParams
-from .estimate import compare_formats_tables, compare_formats
+from .manifest import SamplingRules, estimate_manifest, read_hub_tree, read_s3_inventory
+from .report import verify_report, write_report
+from .estimate import compare_formats_tables, compare_formats, codec_sensitivity
+from .estimate import row_order_churn, shard_renames, similarity_matrix
+from .estimate import leaderboard, refcounts
 from .synthetic import DataGenerator
+from .xet import estimate_upload
 
 
 pio.renderers.default = "browser"  # Opens in a new browser tab
 
 
+def filter_options(command):
+    """Add the exclude pattern and file size filter options to a command."""
+    options = [
+        click.option(
+            "--exclude",
+            multiple=True,
+            help="Skip the paths matching this .gitignore style pattern",
+        ),
+        click.option(
+            "--exclude-from",
+            default=None,
+            type=click.Path(exists=True, dir_okay=False),
+            help="Read the exclude patterns from a .gitignore style file",
+        ),
+        click.option("--min-size", default=None, help="Skip smaller files", type=int),
+        click.option("--max-size", default=None, help="Skip larger files", type=int),
+    ]
+    for option in reversed(options):
+        command = option(command)
+    return command
+
+
+def apply_filters(paths, exclude, exclude_from, min_size, max_size, root=None):
+    """Filter the files, the patterns being relative to root, the current
+    directory by default, and to every directory given for its files."""
+    patterns = list(exclude)
+    if exclude_from:
+        patterns += Path(exclude_from).read_text().splitlines()
+    rules = IgnoreRules(patterns) if patterns else None
+    root = Path.cwd() if root is None else root
+    selected = []
+    for path in map(Path, paths):
+        if path.is_dir():
+            files, base = map(Path, expand_paths([str(path)])), path
+        else:
+            files, base = [path], root
+        selected += filter_paths(files, rules, min_size, max_size, root=base)
+    return selected
+
+
+def parse_tags(ctx, param, values):
+    tags = {}
+    for value in values:
+        key, sep, tag = value.partition("=")
+        if not sep or not key:
+            raise click.BadParameter(f"{value!r} is not KEY=VALUE")
+        tags[key] = tag
+    return tags
+
+
+tag_option = click.option(
+    "--tag",
+    "tags",
+    multiple=True,
+    callback=parse_tags,
+    metavar="KEY=VALUE",
+    help="Attach metadata such as an experiment id to the outputs, repeatable",
+)
+
+
 @click.group()
 @click.option("--plot", is_flag=True, help="Show plots after each command")
 @click.pass_context
@@ -240,13 +321,1270 @@ def stats(
 
 @cli.command()
 @click.argument("files", nargs=-1, type=click.Path(exists=True))
-def dedup(files):
-    result = estimate(files)
+@click.option(
+    "--size-maps", is_flag=True, help="Also render the chunk sizes along each file"
+)
+@click.option(
+    "--image-format",
+    default="png",
+    type=click.Choice(["png", "bmp", "jpeg", "webp"]),
+    help="Raster format of the generated images",
+)
+@click.option("--quality", default=90, help="Quality of lossy image formats", type=int)
+@click.option(
+    "--image-size", default=256, help="Size of the images in pixels", type=int
+)
+@click.option(
+    "--thumbnail-size",
+    default=None,
+    help="Also write thumbnails of this size",
+    type=int,
+)
+@click.option(
+    "--captions", is_flag=True, help="Render the file name and stats below the images"
+)
+@click.option(
+    "--self-maps",
+    is_flag=True,
+    help="Also render the repeated chunks within each file",
+)
+@click.option(
+    "--codec",
+    default=None,
+    help="Codec estimating the compressed size of the unique chunks, lz4 by "
+    "default",
+    type=click.Choice(CODECS),
+)
+@click.option(
+    "--codec-level",
+    default=None,
+    help="Level of the codec, its default if not given",
+    type=int,
+)
+@click.option(
+    "--no-compression",
+    is_flag=True,
+    help="Skip compressing the unique chunks for a faster scan",
+)
+@click.option(
+    "--sidecars",
+    is_flag=True,
+    help="Write a .blocks.json file mapping the image blocks to chunks next to "
+    "every ownership image",
+)
+@click.option(
+    "--poster",
+    is_flag=True,
+    help="Write a single poster image of all the files sorted by dedup ratio "
+    "instead of one ownership image per file",
+)
+@click.option(
+    "--stream-images",
+    is_flag=True,
+    help="Write the size and self maps as soon as each file is chunked",
+)
+@click.option(
+    "--decompress-pages",
+    is_flag=True,
+    help="Also estimate the dedup of the decompressed parquet pages",
+)
+@click.option(
+    "--detect-shifts",
+    is_flag=True,
+    help="Also estimate the dedup lost to misaligned chunk boundaries",
+)
+@click.option(
+    "--detect-deltas",
+    is_flag=True,
+    help="Also estimate the savings of delta-compressing near-duplicate chunks",
+)
+@click.option(
+    "--attribution",
+    default="order",
+    type=click.Choice(["order", "mtime", "largest"]),
+    help="Which file owns the shared chunks in the images",
+)
+@click.option(
+    "--cache",
+    default=None,
+    type=click.Path(dir_okay=False),
+    help="Cache the chunk recipes to speed up repeated runs",
+)
+@click.option(
+    "--mmap",
+    is_flag=True,
+    help="Memory map the files instead of reading them, faster for large files",
+)
+@click.option(
+    "--decompress",
+    is_flag=True,
+    help="Chunk gzip, zstd and xz compressed files decompressed",
+)
+@click.option(
+    "--follow-symlinks/--skip-symlinks",
+    default=True,
+    help="Read the targets of symlinked inputs or skip them",
+)
+@click.option(
+    "--dedupe-hardlinks",
+    is_flag=True,
+    help="Only read the first of the inputs linking to the same file",
+)
+@click.option(
+    "--canonicalize",
+    is_flag=True,
+    help="Resolve and sort the input paths before the analysis",
+)
+@click.option(
+    "--report",
+    default=None,
+    type=click.Path(dir_okay=False, writable=True),
+    help="Write a versioned .json or .parquet report of the results",
+)
+@click.option(
+    "--deterministic",
+    is_flag=True,
+    help="Sort the inputs and fix the workers for byte-identical reruns",
+)
+@click.option(
+    "--fail-if-dedup-below",
+    default=None,
+    type=click.FloatRange(0, 1),
+    help="Exit with an error if less than this fraction of bytes is deduplicated",
+)
+@click.option(
+    "--max-tracked-chunks",
+    default=None,
+    type=click.IntRange(1),
+    help="Switch to approximate counts instead of tracking more unique chunks",
+)
+@click.option(
+    "--index-dir",
+    default=None,
+    type=click.Path(exists=True, file_okay=False, writable=True),
+    help="Spill the chunk index to this directory to bound the memory",
+)
+@click.option(
+    "--memory-budget",
+    default=None,
+    type=click.IntRange(1024),
+    help="Approximate the counts with sketches of this many bytes",
+)
+@tag_option
+@filter_options
+def dedup(
+    files,
+    size_maps,
+    image_format,
+    quality,
+    image_size,
+    thumbnail_size,
+    captions,
+    self_maps,
+    codec,
+    codec_level,
+    no_compression,
+    sidecars,
+    poster,
+    stream_images,
+    decompress_pages,
+    detect_shifts,
+    detect_deltas,
+    attribution,
+    cache,
+    mmap,
+    decompress,
+    follow_symlinks,
+    dedupe_hardlinks,
+    canonicalize,
+    report,
+    deterministic,
+    fail_if_dedup_below,
+    max_tracked_chunks,
+    index_dir,
+    memory_budget,
+    tags,
+    exclude,
+    exclude_from,
+    min_size,
+    max_size,
+):
+    files = apply_filters(files, exclude, exclude_from, min_size, max_size)
+    if deterministic:
+        if attribution == "mtime":
+            raise click.ClickException("The mtime attribution is not deterministic")
+        # sorted before resolving so the same hard link is kept on every run
+        files = sorted(files)
+    files = resolve_paths(
+        files,
+        follow_symlinks=follow_symlinks,
+        dedupe_hardlinks=dedupe_hardlinks,
+        canonicalize=canonicalize,
+    )
+    if no_compression and codec is not None:
+        raise click.ClickException("Pass either --no-compression or --codec")
+    if mmap and cache is not None:
+        raise click.ClickException("--mmap and --cache are mutually exclusive")
+    modes = [max_tracked_chunks, index_dir, memory_budget]
+    if sum(mode is not None for mode in modes) > 1:
+        raise click.ClickException(
+            "--max-tracked-chunks, --index-dir and --memory-budget are mutually "
+            "exclusive"
+        )
+    if index_dir is not None:
+        options = dict(index_dir=index_dir)
+    elif memory_budget is not None:
+        options = dict(memory_budget=memory_budget)
+    elif max_tracked_chunks is None:
+        options = dict(
+            size_maps=size_maps,
+            self_maps=self_maps,
+            sidecars=sidecars,
+            poster=poster,
+            codec_level=codec_level,
+            stream_images=stream_images,
+            attribution=attribution,
+            cache_path=cache,
+            mmap=mmap,
+            decompress=decompress,
+            image_format=image_format,
+            quality=quality,
+            image_size=image_size,
+            thumbnail_size=thumbnail_size,
+            captions=captions,
+            tags=tags,
+            deterministic=deterministic,
+        )
+    else:
+        options = dict(max_tracked_chunks=max_tracked_chunks)
+    if codec is not None:
+        options["codec"] = codec
+    options["compress"] = not no_compression
+    with warnings.catch_warnings():
+        # also listed in the warnings of the result
+        warnings.simplefilter("ignore", RuntimeWarning)
+        result = estimate(files, **options)
+    for warning in result["warnings"]:
+        print(f"Warning: {warning}", file=sys.stderr)
     print(
         f"Deduplication ratio: {result['dedup_ratio']:.2%} ({naturalsize(result['chunk_bytes'])} / {naturalsize(result['total_len'])})"
     )
+    if "error_bound" in result:
+        print(f"Approximated within ±{result['error_bound']:.1%} of the chunk bytes")
+    if result["xet_bytes"] is not None:
+        print(
+            f"Xet deduplication ratio: {result['xet_dedup_ratio']:.2%} ({naturalsize(result['xet_bytes'])} / {naturalsize(result['total_len'])})"
+        )
+    # sparse holes and clones are already stored once by the filesystem, so
+    # they are reported apart from the content dedup
+    savings = filesystem_savings(files)
+    if savings["files"]:
+        print(
+            f"Already deduplicated by the filesystem: "
+            f"{naturalsize(savings['sparse_bytes'])} sparse, "
+            f"{naturalsize(savings['cloned_bytes'])} cloned "
+            f"in {savings['files']} files"
+        )
+    result["sparse_bytes"] = savings["sparse_bytes"]
+    result["cloned_bytes"] = savings["cloned_bytes"]
+    if decompress_pages:
+        pages = estimate_pages(files)
+        print(
+            f"Decompressed pages deduplication ratio: {pages['dedup_ratio']:.2%} ({naturalsize(pages['chunk_bytes'])} / {naturalsize(pages['total_len'])})"
+        )
+    if detect_shifts:
+        shifted = estimate_shifted(files)
+        print(
+            f"Shift-tolerant deduplication ratio: {shifted['aligned_dedup_ratio']:.2%} "
+            f"({naturalsize(shifted['shifted_bytes'])} found at shifted boundaries)"
+        )
+    if detect_deltas:
+        delta = estimate_delta(files)
+        print(
+            f"Delta-compressed deduplication ratio: "
+            f"{delta['delta_dedup_ratio']:.2%} "
+            f"({naturalsize(delta['delta_savings'])} saved on "
+            f"{delta['similar_chunks']} near-duplicate chunks)"
+        )
+    if report:
+        write_report([result], report, inputs=files, tags=tags)
+    try:
+        check_dedup(result, fail_if_dedup_below)
+    except DedupRegressionError as e:
+        raise click.ClickException(str(e))
+
+
+@cli.command()
+@click.pass_context
+@click.argument("original", type=click.Path(exists=True, dir_okay=False))
+@click.argument("updated", type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--target-dir",
+    "-d",
+    help="Directory to store the recompressed files",
+    type=click.Path(file_okay=False, writable=True),
+    required=True,
+)
+@click.option("--use-cdc", is_flag=True, help="Use content defined chunking")
+def codec_impact(ctx, original, updated, target_dir, use_cdc):
+    """Compare the deduplication ratio of two file versions under each codec."""
+    directory = Path(target_dir)
+    directory.mkdir(exist_ok=True)
+    results = codec_sensitivity(original, updated, directory, use_cdc=use_cdc)
+    display.print_table(results)
+    if ctx.obj["plot"]:
+        display.plot_bars(results)
+
+
+@cli.command()
+@click.pass_context
+@click.argument("original", type=click.Path(exists=True, dir_okay=False))
+@click.argument("updated", type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--sort-by",
+    "-k",
+    multiple=True,
+    required=True,
+    help="Column to sort the rows by, can be repeated",
+)
+@click.option(
+    "--target-dir",
+    "-d",
+    help="Directory to store the sorted files",
+    type=click.Path(file_okay=False, writable=True),
+    required=True,
+)
+def row_order(ctx, original, updated, sort_by, target_dir):
+    """Compare the dedup of two file versions before and after sorting the rows."""
+    directory = Path(target_dir)
+    directory.mkdir(exist_ok=True)
+    results = row_order_churn(original, updated, list(sort_by), directory)
+    display.print_table(results)
+    if ctx.obj["plot"]:
+        display.plot_bars(results)
+
+
+@cli.command()
+@click.argument("manifest", type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--manifest-format",
+    default="s3-inventory",
+    type=click.Choice(["s3-inventory", "hub-tree"]),
+    help="S3 inventory CSV or JSON listing of the Hub tree API",
+)
+@click.option(
+    "--inventory-fields",
+    default="bucket,key,size",
+    help="Comma separated columns of the S3 inventory",
+)
+@click.option("--repo", default=None, help="Repository id of a Hub tree listing")
+@click.option("--revision", default="main", help="Revision of a Hub tree listing")
+@click.option(
+    "--fraction", default=1.0, type=click.FloatRange(0, 1), help="Sampled fraction"
+)
+@click.option("--max-files", default=None, type=int, help="Sample at most N objects")
+@click.option("--max-bytes", default=None, type=int, help="Sample at most N bytes")
+@click.option("--include", multiple=True, help="Only sample the matching keys")
+@click.option("--exclude", multiple=True, help="Skip the matching keys")
+@click.option("--seed", default=0, help="Seed of the key sampling", type=int)
+@click.option(
+    "--report",
+    default=None,
+    type=click.Path(dir_okay=False, writable=True),
+    help="Write a versioned .json or .parquet report of the result",
+)
+def remote(
+    manifest,
+    manifest_format,
+    inventory_fields,
+    repo,
+    revision,
+    fraction,
+    max_files,
+    max_bytes,
+    include,
+    exclude,
+    seed,
+    report,
+):
+    """Estimate a remote corpus by reading only the sampled objects of a manifest."""
+    if manifest_format == "hub-tree":
+        if repo is None:
+            raise click.UsageError("--repo is required for Hub tree listings")
+        entries = read_hub_tree(manifest, repo, revision=revision)
+    else:
+        entries = read_s3_inventory(manifest, fields=inventory_fields.split(","))
+    rules = SamplingRules(
+        fraction=fraction,
+        max_files=max_files,
+        max_bytes=max_bytes,
+        include=include,
+        exclude=exclude,
+        seed=seed,
+    )
+    try:
+        result = estimate_manifest(entries, rules)
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    print(
+        f"Sampled {result['sampled_objects']} of {result['objects']} objects "
+        f"({naturalsize(result['total_len'])} of "
+        f"{naturalsize(result['corpus_bytes'])})"
+    )
+    print(
+        f"Deduplication ratio: {result['dedup_ratio']:.2%}, "
+        f"estimated corpus size {naturalsize(result['corpus_chunk_bytes'])}"
+    )
+    if report:
+        write_report([result], report)
+
+
+@cli.command("audit")
+@click.argument("repo_or_dir")
+@click.option("--revision", default="main", help="Revision of the Hub repository")
+@click.option(
+    "--repo-type",
+    default="datasets",
+    type=click.Choice(["datasets", "models", "spaces"]),
+    help="Type of the Hub repository",
+)
+@click.option(
+    "--similarity-threshold",
+    default=SIMILARITY_THRESHOLD,
+    type=click.FloatRange(0, 1),
+    help="Cluster files sharing at least this fraction of their chunk bytes",
+)
+@click.option(
+    "--cdc-threshold",
+    default=CDC_THRESHOLD,
+    type=click.FloatRange(0, 1),
+    help="Consider parquet files below this CDC confidence as written without it",
+)
+def audit_command(
+    repo_or_dir, revision, repo_type, similarity_threshold, cdc_threshold
+):
+    """Audit a local directory or a Hub repository and recommend rewrites."""
+    try:
+        result = audit(
+            repo_or_dir,
+            revision=revision,
+            repo_type=repo_type,
+            similarity_threshold=similarity_threshold,
+            cdc_threshold=cdc_threshold,
+        )
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    formats = {}
+    for scan in result.files:
+        formats[scan.format] = formats.get(scan.format, 0) + 1
+    print(
+        f"Files: {len(result.files)} "
+        f"({', '.join(f'{n} {fmt}' for fmt, n in sorted(formats.items()))})"
+    )
+    stats = result.estimate
+    print(
+        f"Deduplication ratio: {stats['dedup_ratio']:.2%} "
+        f"({naturalsize(stats['chunk_bytes'])} / {naturalsize(stats['total_len'])})"
+    )
+    for i, cluster in enumerate(result.clusters, 1):
+        print(f"Cluster {i}: {', '.join(cluster)}")
+    if not result.recommendations:
+        print("No recommendations")
+    for recommendation in result.recommendations:
+        print(f"Recommendation: {recommendation}")
+        for path in recommendation.files:
+            print(f"  {path}")
+
+
+@cli.command("upload-plan")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option("--repo", required=True, help="Hub repository id, e.g. org/name")
+@click.option(
+    "--token",
+    default=None,
+    envvar="HF_TOKEN",
+    help="Hub token with write access, also read from HF_TOKEN",
+)
+@click.option(
+    "--repo-type",
+    default="datasets",
+    type=click.Choice(["datasets", "models", "spaces"]),
+    help="Type of the Hub repository",
+)
+@click.option("--revision", default="main", help="Revision of the Hub repository")
+def upload_plan(files, repo, token, repo_type, revision):
+    """Estimate the bytes a push would transfer given the chunks stored on the Hub."""
+    try:
+        result = estimate_upload(
+            files, repo, token=token, repo_type=repo_type, revision=revision
+        )
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    print(
+        f"Unique bytes: {naturalsize(result['xet_bytes'])} "
+        f"of {naturalsize(result['total_len'])}"
+    )
+    print(
+        f"Already stored: {naturalsize(result['stored_bytes'])} "
+        f"({result['shards']} shards from {result['queried_chunks']} queried chunks)"
+    )
+    print(f"Would transfer: {naturalsize(result['transfer_bytes'])}")
+
+
+@cli.command("upset")
+@click.argument("files", nargs=-1, type=click.Path(exists=True))
+@click.option(
+    "--output",
+    "-o",
+    help="Output file, the format is inferred from the .json or .csv suffix",
+    type=click.Path(dir_okay=False, writable=True),
+    required=True,
+)
+@click.option("--top-k", default=None, help="Keep the top K combinations", type=int)
+def upset_export(files, output, top_k):
+    """Export chunk file-membership combinations as UpSet plot data."""
+    rows = upset(files, top_k=top_k)
+    output = Path(output)
+    if output.suffix == ".json":
+        output.write_text(json.dumps(rows, indent=2))
+    elif output.suffix == ".csv":
+        with output.open("w", newline="") as f:
+            writer = csv.DictWriter(f, fieldnames=list(rows[0]) if rows else [])
+            writer.writeheader()
+            for row in rows:
+                writer.writerow({**row, "files": ";".join(row["files"])})
+    else:
+        raise click.BadParameter("Output must end with .json or .csv")
+
+
+@cli.command()
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+def similarity(files):
+    """Print the fractions of shared bytes between every pair of files as CSV.
+
+    Every row is the fraction of the unique bytes of a file which each of the
+    other files contains too.
+    """
+    matrix = similarity_matrix(files)
+    writer = csv.writer(sys.stdout)
+    writer.writerow(["", *files])
+    for path, row in zip(files, matrix):
+        writer.writerow([path, *(f"{fraction:.4f}" for fraction in row)])
+
+
+@cli.command()
+@click.argument("directories", nargs=-1, type=click.Path(exists=True, file_okay=False))
+@filter_options
+def marginal(directories, exclude, exclude_from, min_size, max_size):
+    """Rank datasets by the unique bytes each adds to a shared store.
+
+    Every directory is a dataset consisting of all files below it.
+    """
+    datasets = {}
+    for directory in directories:
+        files = sorted(p for p in Path(directory).rglob("*") if p.is_file())
+        datasets[directory] = apply_filters(
+            files, exclude, exclude_from, min_size, max_size, root=directory
+        )
+    for record in marginal_contributions(datasets):
+        print(
+            f"{record['dataset']}: marginal {naturalsize(record['marginal_bytes'])}, "
+            f"shapley {naturalsize(record['shapley_bytes'])}, "
+            f"standalone {naturalsize(record['unique_bytes'])}"
+        )
+
+
+@cli.command("leaderboard")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option("--top", default=10, help="Number of files to list", type=int)
+@click.option("--json", "as_json", is_flag=True, help="Print the ranking as JSON")
+def leaderboard_command(files, top, as_json):
+    """Rank the files by the duplicate bytes they contain."""
+    result = leaderboard(files, top=top)
+    if as_json:
+        print(json.dumps(result, indent=2))
+        return
+    print(
+        f"{naturalsize(result['duplicate_bytes'])} duplicate bytes out of "
+        f"{naturalsize(result['total_bytes'])} in {result['numfiles']} files"
+    )
+    for row in result["files"]:
+        print(
+            f"{row['rank']:>4}. {naturalsize(row['duplicate_bytes']):>10} "
+            f"{row['share']:>7.2%} {row['cumulative_share']:>7.2%}  {row['path']}"
+        )
+
+
+@cli.command("refcounts")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option("--top", default=10, help="Number of chunks to list", type=int)
+@click.option("--json", "as_json", is_flag=True, help="Print the report as JSON")
+def refcounts_command(files, top, as_json):
+    """Show how many times the chunks are referenced and the most duplicated."""
+    result = refcounts(files, top=top)
+    if as_json:
+        print(json.dumps(result, indent=2))
+        return
+    print(
+        f"{result['unique_chunks']} unique chunks, "
+        f"{naturalsize(result['saved_bytes'])} saved out of "
+        f"{naturalsize(result['total_bytes'])}"
+    )
+    for bucket in result["buckets"]:
+        lower, upper = bucket["min_occurrences"], bucket["max_occurrences"]
+        label = f"{lower}x" if lower == upper else f"{lower}-{upper}x"
+        print(
+            f"{label:>12} {bucket['unique_chunks']:>10} chunks "
+            f"{naturalsize(bucket['unique_bytes']):>10} "
+            f"saves {naturalsize(bucket['saved_bytes']):>10} "
+            f"{bucket['savings_share']:>7.2%}"
+        )
+    for chunk in result["top"]:
+        print(
+            f"{chunk['hash']:016x} {chunk['occurrences']:>8}x "
+            f"{naturalsize(chunk['size']):>10}  {', '.join(chunk['files'])}"
+        )
+
+
+@cli.command("plan")
+@click.argument("files", nargs=-1, type=click.Path(exists=True))
+@click.option(
+    "--memory-limit",
+    default=None,
+    help="Memory budget in bytes, defaults to the available memory",
+    type=int,
+)
+def plan_command(files, memory_limit):
+    """Estimate the chunk count and peak memory of a dedup run."""
+    result = plan(files, memory_limit=memory_limit)
+    print(
+        f"{result['numfiles']} files, {naturalsize(result['total_len'])}, "
+        f"~{result['expected_chunks']} chunks, "
+        f"peak memory ~{naturalsize(result['peak_memory'])}"
+    )
+    for warning in result["warnings"]:
+        print(f"Warning: {warning}", file=sys.stderr)
+
+
+@cli.command("compare-chunkers")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--chunker",
+    "chunkers",
+    multiple=True,
+    default=CHUNKERS,
+    type=click.Choice(CHUNKERS),
+    help="Chunking algorithm to compare, defaults to all of them",
+)
+@click.option(
+    "--target-chunk-size",
+    default=None,
+    help="Power of two average chunk size, defaults to 64 KiB",
+    type=int,
+)
+def compare_chunkers_command(files, chunkers, target_chunk_size):
+    """Compare the deduplication ratio of the chunking algorithms."""
+    try:
+        results = chunker_sensitivity(
+            files, chunkers=chunkers, target_chunk_size=target_chunk_size
+        )
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    for result in results:
+        print(
+            f"{result['chunker']:>8}: {result['dedup_ratio']:.2%} "
+            f"({naturalsize(result['chunk_bytes'])} / "
+            f"{naturalsize(result['total_len'])}), "
+            f"mean chunk {naturalsize(result['mean_chunk_size'])}"
+        )
+
+
+@cli.command("sweep")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--target-chunk-size",
+    "-t",
+    "targets",
+    multiple=True,
+    default=SWEEP_CHUNK_SIZES,
+    help="Power of two average chunk size to evaluate, can be repeated",
+    type=int,
+)
+@click.option("--json", "as_json", is_flag=True, help="Print the table as JSON")
+def sweep_command(files, targets, as_json):
+    """Compare the deduplication ratio at several chunk sizes in one pass."""
+    try:
+        results = chunk_size_sweep(files, targets=targets)
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    if as_json:
+        print(json.dumps(results, indent=2))
+        return
+    for result in results:
+        print(
+            f"{naturalsize(result['target_chunk_size']):>10}: "
+            f"{result['dedup_ratio']:.2%} "
+            f"({naturalsize(result['chunk_bytes'])} / "
+            f"{naturalsize(result['total_len'])}), "
+            f"{result['num_chunks']} chunks"
+        )
+
+
+@cli.command("hash-only")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--chunker",
+    default="gearhash",
+    type=click.Choice(CHUNKERS),
+    help="Chunking algorithm",
+)
+@click.option(
+    "--target-chunk-size",
+    default=None,
+    help="Power of two average chunk size, defaults to 64 KiB",
+    type=int,
+)
+def hash_only(files, chunker, target_chunk_size):
+    """Show the deduplication ratio only, tracking nothing but chunk hashes."""
+    try:
+        result = estimate_hash_only(
+            files, chunker=chunker, target_chunk_size=target_chunk_size
+        )
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    print(
+        f"Deduplication ratio: {result['dedup_ratio']:.2%} "
+        f"({naturalsize(result['chunk_bytes'])} / "
+        f"{naturalsize(result['total_len'])})"
+    )
+
+
+@cli.command("chunk-sizes")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--chunker",
+    default="gearhash",
+    type=click.Choice(CHUNKERS),
+    help="Chunking algorithm",
+)
+@click.option(
+    "--target-chunk-size",
+    default=None,
+    help="Power of two average chunk size, defaults to 64 KiB",
+    type=int,
+)
+@click.option("--bins", default=16, help="Number of histogram bins", type=int)
+def chunk_sizes_command(files, chunker, target_chunk_size, bins):
+    """Show the size distribution of the unique chunks."""
+    try:
+        result = chunk_size_stats(
+            files, bins=bins, chunker=chunker, target_chunk_size=target_chunk_size
+        )
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    print(
+        f"{result['num_chunks']} unique chunks, "
+        f"mean {naturalsize(result['mean'])}, "
+        f"median {naturalsize(result['median'])}, "
+        f"p95 {naturalsize(result['p95'])}"
+    )
+    histogram = result["histogram"]
+    peak = max(histogram["counts"])
+    for count, low, high in zip(
+        histogram["counts"], histogram["edges"], histogram["edges"][1:]
+    ):
+        bar = "#" * round(40 * count / peak)
+        print(f"{naturalsize(low):>10} - {naturalsize(high):>10} {count:>8} {bar}")
+
+
+@cli.command("recommend-chunking")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--chunk-overhead",
+    default=64,
+    help="Metadata bytes stored per chunk, penalizing small chunks",
+    type=int,
+)
+def recommend_chunking_command(files, chunk_overhead):
+    """Recommend a target chunk size from the duplicated runs of chunks."""
+    try:
+        result = recommend_chunk_size(files, chunk_overhead=chunk_overhead)
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    print(
+        f"{result['runs']} duplicated runs, "
+        f"{naturalsize(result['mean_run_bytes'])} long on average, "
+        f"{result['adjacent_fraction']:.0%} of the duplicate chunks adjacent"
+    )
+    print(
+        f"Recommended target chunk size: "
+        f"{naturalsize(result['target_chunk_size'], binary=True)} "
+        f"(mask {result['chunk_mask']:#018x}), predicted deduplication ratio "
+        f"{result['predicted_dedup_ratio']:.2%} against {result['dedup_ratio']:.2%} "
+        f"at {naturalsize(result['current_target_chunk_size'], binary=True)}"
+    )
+
+
+@cli.command()
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--queue-depth", default=64, help="Capacity of the channels between the stages"
+)
+@click.option(
+    "--workers",
+    default=None,
+    help="Workers split between the stages, defaults to the CPU count",
+    type=int,
+)
+@click.option(
+    "--ingest",
+    is_flag=True,
+    help="Also compare the throughput of buffered and memory mapped reads",
+)
+def profile(files, queue_depth, workers, ingest):
+    """Report the time spent in each stage of the chunking pipeline."""
+    result = stage_timings(files, queue_depth=queue_depth, workers=workers)
+    print(f"{naturalsize(result['total_len'])} in {result['wall_time']:.2f}s")
+    for stage in result["stages"]:
+        print(
+            f"{stage['stage']:>10}: {stage['utilization']:.0%} busy, "
+            f"{stage['busy']:.2f}s busy, {stage['blocked']:.2f}s blocked "
+            f"on {stage['workers']} workers"
+        )
+    if ingest:
+        throughput = ingest_throughput(files)
+        print(
+            f"Buffered reads: {naturalsize(throughput['buffered_throughput'])}/s, "
+            f"memory mapped: {naturalsize(throughput['mmap_throughput'])}/s"
+        )
+
+
+@cli.command("format-pairs")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+def format_pairs_command(files):
+    """Attribute the cross-file duplicate bytes to pairs of file formats."""
+    for record in format_pairs(files):
+        a, b = record["formats"]
+        print(
+            f"{a} <-> {b}: {naturalsize(record['duplicate_bytes'])} "
+            f"({record['fraction']:.2%})"
+        )
+
+
+@cli.command("cache-sim")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--cache-size",
+    "-c",
+    multiple=True,
+    required=True,
+    help="Cache size in bytes, can be repeated",
+    type=int,
+)
+def cache_sim(files, cache_size):
+    """Simulate the hit rates of an LRU chunk cache serving the files in order."""
+    for record in simulate_cache(files, list(cache_size)):
+        print(
+            f"{naturalsize(record['cache_size'])}: "
+            f"hit rate {record['hit_rate']:.2%}, "
+            f"byte hit rate {record['byte_hit_rate']:.2%}"
+        )
+
+
+@cli.command("pack-sim")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--block-size",
+    "-b",
+    multiple=True,
+    default=[64 * 1024],
+    help="Uncompressed size of the packed blocks in bytes, can be repeated",
+    type=int,
+)
+def pack_sim(files, block_size):
+    """Simulate the storage of the unique chunks packed into compressed blocks."""
+    for record in packing(files, block_size):
+        print(
+            f"{naturalsize(record['block_size'])} blocks: "
+            f"{naturalsize(record['stored_bytes'])} stored "
+            f"({naturalsize(record['overhead_bytes'])} overhead) "
+            f"for {naturalsize(record['chunk_bytes'])} of unique chunks"
+        )
+
+
+@cli.command("intra-chunk")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--sub-chunk-size",
+    "-s",
+    multiple=True,
+    default=[16 * 1024, 4 * 1024, 1024],
+    help="Average sub-chunk size in bytes (a power of two), can be repeated",
+    type=int,
+)
+def intra_chunk(files, sub_chunk_size):
+    """Estimate the redundancy left inside the unique chunks."""
+    for record in intra_chunk_redundancy(files, sub_chunk_size):
+        print(
+            f"{naturalsize(record['sub_chunk_size'])} sub-chunks: "
+            f"{naturalsize(record['redundant_bytes'])} redundant "
+            f"({record['redundant_fraction']:.2%} of the unique chunks)"
+        )
+
+
+@cli.command("zstd-dict")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--dict-size",
+    default=110 * 1024,
+    help="Maximum size of the trained dictionary in bytes",
+    type=int,
+)
+@click.option("--level", default=3, help="Zstd compression level", type=int)
+def zstd_dict(files, dict_size, level):
+    """Estimate the compression of the unique chunks with a zstd dictionary."""
+    try:
+        result = estimate_dictionary(files, dict_size=dict_size, level=level)
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    print(f"Unique chunks: {naturalsize(result['chunk_bytes'])}")
+    print(f"Compressed per chunk: {naturalsize(result['compressed_chunk_bytes'])}")
+    print(
+        f"Compressed with a dictionary: "
+        f"{naturalsize(result['dictionary_total_bytes'])} "
+        f"({naturalsize(result['dictionary_bytes'])} dictionary, "
+        f"{result['dictionary_gain']:.2%} smaller)"
+    )
+
+
+@cli.command("check-cdc")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--threshold",
+    default=0.5,
+    help="Report files below this confidence as not written with CDC",
+    type=float,
+)
+def check_cdc(files, threshold):
+    """Estimate whether parquet files were written with content defined chunking."""
+    for path in files:
+        result = cdc_confidence(path)
+        if result["confidence"] is None:
+            print(f"{path}: too few pages to tell")
+        else:
+            verdict = "CDC" if result["confidence"] >= threshold else "not CDC"
+            print(f"{path}: {verdict} (confidence {result['confidence']:.2f})")
+
+
+@cli.command("column-maps")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--image-format",
+    default="png",
+    type=click.Choice(["png", "bmp", "jpeg", "webp"]),
+    help="Raster format of the generated images",
+)
+@click.option(
+    "--image-size", default=256, help="Width of the images in pixels", type=int
+)
+@click.option(
+    "--captions", is_flag=True, help="Render the file and column names below the strips"
+)
+def column_maps_command(files, image_format, image_size, captions):
+    """Render per-column chunk ownership strips of parquet files."""
+    result = column_maps(
+        files, image_format=image_format, image_size=image_size, captions=captions
+    )
+    for path, columns in result.items():
+        print(f"{path}: {len(columns)} columns")
+        for i, column in enumerate(columns):
+            print(f"  {i}: {column}")
+
+
+@cli.command("export-store")
+@click.argument("files", nargs=-1, type=click.Path(exists=True))
+@click.option(
+    "--output",
+    "-o",
+    help="Output file, written as CBOR for .cbor and as JSON otherwise",
+    type=click.Path(dir_okay=False, writable=True),
+    required=True,
+)
+@click.option(
+    "--previous",
+    default=None,
+    help="Earlier snapshot to carry the chunk ages over from",
+    type=click.Path(exists=True, dir_okay=False),
+)
+@tag_option
+def export_store_command(files, output, previous, tags):
+    """Export the merged chunk store as a versioned JSON/CBOR document."""
+    try:
+        export_store(list(files), output, tags=tags, previous=previous)
+    except ValueError as e:
+        raise click.ClickException(str(e))
+
+
+@cli.command("export-manifest")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--output",
+    "-o",
+    help="Output file, written as Parquet for .parquet and as JSON lines otherwise",
+    type=click.Path(dir_okay=False, writable=True),
+    required=True,
+)
+@click.option(
+    "--format",
+    "manifest_format",
+    default=None,
+    type=click.Choice(["parquet", "jsonl"]),
+    help="Format of the manifest, overriding the extension of the output",
+)
+def export_manifest_command(files, output, manifest_format):
+    """Export one row per chunk occurrence for analysis in DuckDB or pandas."""
+    rows = export_manifest(files, output, format=manifest_format)
+    print(f"{output}: {rows} chunks")
+
+
+@cli.command("reconstruct")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--index",
+    "-i",
+    "file_index",
+    default=0,
+    help="Index of the file to rebuild among the files",
+    type=click.IntRange(0),
+)
+@click.option(
+    "--output",
+    "-o",
+    help="Path of the rebuilt file",
+    type=click.Path(dir_okay=False, writable=True),
+    required=True,
+)
+def reconstruct_command(files, file_index, output):
+    """Rebuild a file from the deduplicated chunks and verify it."""
+    try:
+        result = reconstruct(files, file_index, output)
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    if not result["verified"]:
+        raise click.ClickException(
+            f"{output} differs from {result['path']} at byte "
+            f"{result['first_mismatch']}"
+        )
+    print(
+        f"Rebuilt {result['path']} into {output} from the chunks of "
+        f"{len(files)} files: {naturalsize(result['bytes'])}, identical"
+    )
+
+
+@cli.command("diff-stores")
+@click.argument("old", type=click.Path(exists=True, dir_okay=False))
+@click.argument("new", type=click.Path(exists=True, dir_okay=False))
+def diff_stores_command(old, new):
+    """Attribute the growth between two exported chunk stores to the files."""
+    try:
+        diff = diff_snapshots(old, new)
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    print(
+        f"Added {diff['added_chunks']} chunks ({naturalsize(diff['added_bytes'])}), "
+        f"removed {diff['removed_chunks']} chunks "
+        f"({naturalsize(diff['removed_bytes'])})"
+    )
+    print(
+        f"Refcounts: {diff['increased_chunks']} chunks up, "
+        f"{diff['decreased_chunks']} down"
+    )
+    for f in diff["files"]:
+        if f["added_bytes"] or f["removed_bytes"]:
+            print(
+                f"{f['path']}: +{naturalsize(f['added_bytes'])} "
+                f"-{naturalsize(f['removed_bytes'])}"
+            )
+
+
+@cli.command("diff")
+@click.option(
+    "--old",
+    "old_files",
+    multiple=True,
+    required=True,
+    help="File of the old revision, repeatable",
+    type=click.Path(exists=True, dir_okay=False),
+)
+@click.option(
+    "--new",
+    "new_files",
+    multiple=True,
+    required=True,
+    help="File of the new revision, repeatable",
+    type=click.Path(exists=True, dir_okay=False),
+)
+@click.option("--json", "as_json", is_flag=True, help="Print the diff as JSON")
+def diff_command(old_files, new_files, as_json):
+    """Diff two revisions of a dataset and estimate the upload of the update."""
+    result = diff(old_files, new_files)
+    if as_json:
+        print(json.dumps(result, indent=2))
+        return
+    print(
+        f"Added {naturalsize(result['added_bytes'])}, "
+        f"removed {naturalsize(result['removed_bytes'])}, "
+        f"shared {naturalsize(result['shared_bytes'])}"
+    )
+    print(
+        f"Upload: {naturalsize(result['upload_bytes'])} compressed, "
+        f"{naturalsize(result['added_bytes'])} uncompressed, "
+        f"{result['upload_ratio']:.2%} of the "
+        f"{naturalsize(result['naive_upload_bytes'])} new revision uncompressed"
+    )
+
+
+@cli.command("shard-renames")
+@click.argument("old", type=click.Path(exists=True, file_okay=False))
+@click.argument("new", type=click.Path(exists=True, file_okay=False))
+@click.option(
+    "--min-similarity",
+    default=0.9,
+    help="Fraction of shared bytes pairing two differently named files",
+    type=click.FloatRange(0, 1),
+)
+def shard_renames_command(old, new, min_similarity):
+    """Explain the changes between two dataset revisions by renamed shards.
+
+    OLD and NEW are the directories of the revisions, their files are
+    compared by path relative to them.
+    """
+    old_files = sorted(p for p in Path(old).rglob("*") if p.is_file())
+    new_files = sorted(p for p in Path(new).rglob("*") if p.is_file())
+    result = shard_renames(
+        old_files, new_files, old, new, min_similarity=min_similarity
+    )
+    print(f"Unchanged: {result['unchanged']} files")
+    for rename in result["renamed"]:
+        print(f"Renamed: {rename['old']} -> {rename['new']}")
+    for pair in result["near_duplicates"]:
+        print(
+            f"Near-duplicate: {pair['old']} -> {pair['new']} "
+            f"({pair['similarity']:.2%})"
+        )
+    for name in result["modified"]:
+        print(f"Modified: {name}")
+    for split, (old_count, new_count) in result["resharded"].items():
+        print(f"Resharded {split}: {old_count} -> {new_count} shards")
+    for name in result["added"]:
+        print(f"Added: {name}")
+    for name in result["removed"]:
+        print(f"Removed: {name}")
+
+
+@cli.command("retention")
+@click.argument("snapshot", type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--days", default=90, help="Age of the retention policy", type=click.IntRange(0)
+)
+def retention_command(snapshot, days):
+    """Report the bytes of a snapshot not referenced in the given days."""
+    try:
+        ages = retention(snapshot, days=days)
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    print(
+        f"Referenced: {naturalsize(ages['live_bytes'])} in {ages['live_chunks']} "
+        f"chunks, {naturalsize(ages['aged_bytes'])} first seen over {days} days ago"
+    )
     print(
-        f"Xet deduplication ratio: {result['xet_dedup_ratio']:.2%} ({naturalsize(result['xet_bytes'])} / {naturalsize(result['total_len'])})"
+        f"Unreferenced: {naturalsize(ages['retained_bytes'])} in "
+        f"{ages['retained_chunks']} chunks, {naturalsize(ages['stale_bytes'])} "
+        f"not referenced in {days} days"
+    )
+
+
+@cli.command("verify-report")
+@click.argument("report", type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--fraction",
+    default=0.1,
+    help="Fraction of the inputs to re-check, at least one",
+    type=click.FloatRange(0, 1, min_open=True),
+)
+@click.option("--seed", default=None, help="Seed of the sampling", type=int)
+def verify_report_command(report, fraction, seed):
+    """Check a sample of the inputs of a report against the data on disk."""
+    try:
+        verification = verify_report(report, fraction=fraction, seed=seed)
+    except ValueError as e:
+        raise click.ClickException(str(e))
+    if verification["chunker_changed"]:
+        print(
+            "Warning: the chunker configuration changed since the report, "
+            "only the contents are checked",
+            file=sys.stderr,
+        )
+    for mismatch in verification["mismatches"]:
+        print(f"{mismatch['path']}: {mismatch['reason']}")
+    print(
+        f"Checked {verification['checked']} of {verification['total']} inputs, "
+        f"{len(verification['mismatches'])} mismatches"
+    )
+    if verification["mismatches"]:
+        raise click.ClickException("The report doesn't match the data")
+
+
+@cli.command("export-caibx")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+def export_caibx_command(files):
+    """Write a casync/desync chunk index next to each file as FILE.caibx."""
+    for path, count in zip(files, export_caibx(list(files))):
+        print(f"{path}.caibx: {count} chunks")
+
+
+@cli.command("export-hashes")
+@click.argument("files", nargs=-1, type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--key",
+    default=None,
+    envvar="DE_HASH_KEY",
+    help="Shared secret keying the chunk hashes, also read from DE_HASH_KEY",
+)
+def export_hashes_command(files, key):
+    """Write the chunk hash list of each file as FILE.hashes.
+
+    Without a key the hashes are plain xxh3 hashes, which anyone can compute
+    for a guessed content. With a key they are keyed BLAKE3 hashes, so only
+    the parties sharing the key can match them, e.g. to measure the overlap of
+    private datasets by estimating their hash lists together.
+    """
+    key = key.encode() if key is not None else None
+    for path, count in zip(files, export_hash_lists(list(files), key=key)):
+        print(f"{path}.hashes: {count} chunks")
+
+
+@cli.command("dot-plot")
+@click.argument("file_a", type=click.Path(exists=True, dir_okay=False))
+@click.argument("file_b", type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "--output",
+    "-o",
+    help="Output path without extension",
+    type=click.Path(dir_okay=False, writable=True),
+    required=True,
+)
+@click.option(
+    "--image-format",
+    default="png",
+    type=click.Choice(["png", "bmp", "jpeg", "webp"]),
+    help="Raster format of the generated image",
+)
+@click.option("--image-size", default=512, help="Size of the image in pixels", type=int)
+def dot_plot_command(file_a, file_b, output, image_format, image_size):
+    """Render a dot plot of the chunks shared between two files."""
+    dot_plot(
+        file_a, file_b, output, image_format=image_format, image_size=image_size
     )
 
 
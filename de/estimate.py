@@ -1,13 +1,66 @@
 from collections import defaultdict
-from dataclasses import dataclass
+from dataclasses import dataclass, fields
+from itertools import combinations, product
 from pathlib import Path
 from concurrent.futures import ThreadPoolExecutor, as_completed
+import hashlib
+import os
+import re
+import tarfile
+import tempfile
+import time
+import warnings
 
+import numpy as np
 import pyarrow as pa
+import pyarrow.parquet as pq
 from tqdm import tqdm
 
-from .core import estimate as _estimate_de, estimate_xet as _estimate_xet
-from .formats import FileFormat
+from .core import CHUNK_MASK, MIN_CHUNK_SIZE
+from .core import (
+    Estimator as _Estimator,
+    boundaries as _boundaries,
+    chunk_size_sweep as _chunk_size_sweep,
+    chunk_sizes as _chunk_sizes,
+    chunks as _chunks,
+    column_maps as _column_maps,
+    deletion_savings as _deletion_savings,
+    delta_chunks as _delta_chunks,
+    detect_incompressible as _detect_incompressible,
+    diff_revisions as _diff_revisions,
+    diff_stores as _diff_stores,
+    duplication as _duplication,
+    estimate_against_bloom as _estimate_against_bloom,
+    estimate_bounded as _estimate_bounded,
+    estimate_on_disk as _estimate_on_disk,
+    estimate_sketched as _estimate_sketched,
+    export_bloom as _export_bloom,
+    estimate as _estimate_de,
+    estimate_concatenated as _estimate_concatenated,
+    estimate_dictionary as _estimate_dictionary,
+    estimate_hash_only as _estimate_hash_only,
+    estimate_labeled as _estimate_labeled,
+    estimate_with_metric as _estimate_with_metric,
+    estimate_pages as _estimate_pages,
+    estimate_xet as _estimate_xet,
+    expand_paths as _expand_paths,
+    ingest_timing as _ingest_timing,
+    overlaps as _overlaps,
+    pack_chunks as _pack_chunks,
+    reconstruct as _reconstruct,
+    refcounts as _refcounts,
+    page_layout as _page_layout,
+    retention as _retention,
+    shifted_chunks as _shifted_chunks,
+    simulate_cache as _simulate_cache,
+    stage_timings as _stage_timings,
+    sub_chunk_redundancy as _sub_chunk_redundancy,
+)
+from .canonical import serialize
+from .fileutils import detect_format
+from .formats import FileFormat, ParquetCpp
+from .remote import URLStream, is_remote, is_url, open_input
+from .report import chunker_metadata
 
 
 @dataclass
@@ -21,23 +74,1891 @@ class EstimationResult:
     xet_bytes: int
     xet_dedup_ratio: float
     group: str = ""
+    chunker: dict | None = None
 
+    @classmethod
+    def from_estimate(cls, result: dict, **extra) -> "EstimationResult":
+        """The record of an estimate() result, leaving out the keys it
+        doesn't model such as the warnings and the per-file breakdown."""
+        known = {f.name for f in fields(cls)}
+        return cls(**{k: v for k, v in result.items() if k in known}, **extra)
 
-def estimate(paths):
+
+class DedupRegressionError(Exception):
+    """Raised when the measured deduplication falls below the required one."""
+
+    def __init__(self, result: dict, threshold: float):
+        self.result = result
+        self.threshold = threshold
+        super().__init__(
+            f"Deduplicated {1 - result['dedup_ratio']:.2%} of the bytes, "
+            f"below the required {threshold:.2%}"
+        )
+
+
+def check_dedup(result: dict, fail_if_dedup_below: float | None = None) -> None:
+    """Raise DedupRegressionError if less than the fail_if_dedup_below fraction
+    of the bytes is deduplicated, i.e. 1 - dedup_ratio is below it."""
+    if fail_if_dedup_below is not None:
+        if 1 - result["dedup_ratio"] < fail_if_dedup_below:
+            raise DedupRegressionError(result, fail_if_dedup_below)
+
+
+# Fraction of the unique chunks cut at the maximum chunk size above which the
+# chunk size distribution is reported as suspicious.
+FORCED_CUT_WARNING = 0.5
+# The options of estimate() supported with remote inputs, see _estimate_streamed.
+STREAMED_OPTIONS = frozenset(
+    [
+        "attribution",
+        "chunker",
+        "target_chunk_size",
+        "min_chunk_size",
+        "max_chunk_size",
+        "chunk_mask",
+        "codec",
+        "codec_level",
+        "progress",
+        "workers",
+    ]
+)
+
+
+# The options of estimate() supported by the bounded memory modes, which
+# render no images and keep no per-file state.
+BOUNDED_OPTIONS = frozenset(["progress", "decompress"])
+
+
+def _check_bounded_options(mode, size_maps, options):
+    unsupported = sorted(set(options) - BOUNDED_OPTIONS)
+    if size_maps:
+        unsupported.insert(0, "size_maps")
+    if unsupported:
+        raise ValueError(f"{mode} doesn't support {', '.join(unsupported)}")
+
+
+def _pass_progress(progress, passes, bytes_done=0, files_done=0):
+    """Report one of the passes reading the inputs to progress as part of
+    the whole run, after the bytes and files of the earlier passes, so its
+    counts keep growing until the last pass is done."""
+    if progress is None:
+        return None
+
+    def report(bytes, files, total_files):
+        progress(bytes_done + bytes, files_done + files, passes * total_files)
+
+    return report
+
+
+def _file_states(paths) -> list:
+    """Return the (size, mtime) of every input, None if it can't be stat'ed,
+    to tell the inputs which changed while they were read."""
+    states = []
+    for path in paths:
+        try:
+            stat = os.stat(path)
+        except OSError:
+            states.append(None)
+        else:
+            states.append((stat.st_size, stat.st_mtime_ns))
+    return states
+
+
+# The options of estimate() deciding where the chunks are cut.
+CHUNKER_OPTIONS = frozenset(
+    [
+        "chunker",
+        "target_chunk_size",
+        "min_chunk_size",
+        "max_chunk_size",
+        "chunk_mask",
+        "decompress",
+    ]
+)
+
+
+def _chunker_of(options) -> dict:
+    """The chunker parameters of a run with the options, see chunker_metadata()."""
+    chunker = {k: v for k, v in options.items() if k in CHUNKER_OPTIONS}
+    return chunker_metadata(**chunker)
+
+
+def _incompressible_warnings(paths, sniffed) -> list[str]:
+    """The warnings of the inputs the chunker sniffed as already compressed
+    or encrypted, whose chunks are counted uncompressed."""
+    return [
+        f"{path} is likely compressed or encrypted ({reason}), its chunks are "
+        "counted uncompressed"
+        for path, reason in zip(paths, sniffed)
+        if reason is not None
+    ]
+
+
+def _estimate_streamed(paths, compress, fail_if_dedup_below, **options) -> dict:
+    unsupported = sorted(set(options) - STREAMED_OPTIONS)
+    if unsupported:
+        raise ValueError(f"Remote inputs don't support {', '.join(unsupported)}")
+    if options.get("attribution") == "mtime" and any(map(is_url, paths)):
+        # the objects of object stores have their last modification time
+        raise ValueError("The mtime attribution doesn't support HTTP(S) URLs")
+    # the streams are chunked as they arrive on the rayon pool
+    options.pop("workers", None)
+    stats, per_input = _estimate_labeled(
+        [(path, open_input(path)) for path in paths], **options
+    )
+    total_bytes, chunk_bytes, compressed_chunk_bytes = stats
+    sniffed = [reason for *_, reason in per_input]
+    result = {
+        "numfiles": len(paths),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "compressed_chunk_bytes": compressed_chunk_bytes if compress else None,
+        "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+        "xet_bytes": None,
+        "xet_dedup_ratio": None,
+        "chunker": _chunker_of(options),
+        "warnings": [
+            f"{path} is empty"
+            for path, (total, *_) in zip(paths, per_input)
+            if total == 0
+        ]
+        + _incompressible_warnings(paths, sniffed),
+        # the same breakdown as the one of the local files, see estimate()
+        "files": [
+            {
+                "path": path,
+                "total_bytes": total,
+                "unique_bytes": exclusive,
+                "shared_bytes": shared,
+                "owned_bytes": owned,
+                "compressed_bytes": compressed if compress else None,
+            }
+            for path, (total, _, owned, (exclusive, shared, compressed), _) in zip(
+                paths, per_input
+            )
+        ],
+    }
+    check_dedup(result, fail_if_dedup_below)
+    return result
+
+
+def estimate(
+    paths,
+    size_maps=False,
+    fail_if_dedup_below=None,
+    max_tracked_chunks=None,
+    index_dir=None,
+    index_partitions=256,
+    memory_budget=None,
+    deterministic=False,
+    compress=True,
+    **options,
+):
+    """Estimate the deduplication of the given files and render their images.
+
+    The paths can be files, directories and glob patterns like
+    data/**/*.parquet, expanded in sorted order without the hidden files,
+    .hashes lists of (hash, size) chunk records, which render no images, and
+    http(s)://, s3://, gs://, az:// or file:// URLs, which are streamed like
+    with estimate_labeled() and only support the attribution, chunker and
+    codec options.
+
+    The image options image_format, quality, image_size, thumbnail_size,
+    captions, self_maps and tags are forwarded to the renderer. attribution
+    decides which file owns the shared chunks: "order" (default), "mtime",
+    "largest" or a list of file indices. stream_images writes the size and
+    self maps as each file is chunked, sidecars writes a .blocks.json of
+    every image, and poster replaces the per-file images by a single grid
+    of one strip per file.
+
+    cache_path caches the chunk recipes so unchanged or appended files are
+    not fully re-chunked on the next run. mmap chunks memory mapped files in
+    ranges cut on all the cores, see ingest_throughput(), and can't be
+    combined with cache_path. deterministic runs a single worker per stage
+    and rejects the "mtime" attribution. progress is called from the worker
+    threads with the bytes, the files done and the total files.
+
+    The chunker, "gearhash" (default), "fastcdc", "rabin" or "fixed", cuts
+    64 KiB average chunks unless target_chunk_size or chunk_mask is given,
+    with min_chunk_size and max_chunk_size defaulting to 1/8 and twice the
+    target. decompress chunks gzip, zstd and xz inputs decompressed. The
+    unique chunks are compressed with codec, "lz4" (default), "zstd",
+    "gzip", "brotli" or "none", at codec_level; compress=False skips it.
+
+    max_tracked_chunks samples the chunks past that many unique ones,
+    index_dir spills the chunk index to index_partitions files under it, and
+    memory_budget counts the chunks with a Bloom filter and a HyperLogLog
+    sketch of that many bytes. Only one can be given, and they render no
+    images, skip the Xet pass and only support the progress and decompress
+    options.
+
+    The files list of the result breaks the deduplication down per input in
+    the exact and remote modes, and its warnings list the non-fatal
+    anomalies of the run. DedupRegressionError is raised if less than
+    fail_if_dedup_below of the bytes is deduplicated.
+    """
+    string_paths = _expand_paths(list(map(str, paths)))
+    modes = {
+        "max_tracked_chunks": max_tracked_chunks,
+        "index_dir": index_dir,
+        "memory_budget": memory_budget,
+    }
+    bounded = [name for name, mode in modes.items() if mode is not None]
+    if len(bounded) > 1:
+        raise ValueError(
+            "Pass only one of max_tracked_chunks, index_dir and memory_budget"
+        )
+    if bounded:
+        _check_bounded_options(bounded[0], size_maps, options)
+    if deterministic:
+        if options.get("attribution") == "mtime":
+            raise ValueError("The mtime attribution is not deterministic")
+        options["workers"] = 1
+    if options.get("mmap") and options.get("cache_path") is not None:
+        # the files of a cached run are read through the cache
+        raise ValueError("Pass either mmap or cache_path")
+    if not compress:
+        if options.get("codec", "none") != "none":
+            raise ValueError("Pass either compress=False or a codec")
+        options["codec"] = "none"
+    if any(map(is_remote, string_paths)):
+        if size_maps or bounded:
+            raise ValueError(
+                "Remote inputs don't support size_maps, max_tracked_chunks, "
+                "index_dir and memory_budget"
+            )
+        return _estimate_streamed(
+            string_paths, compress, fail_if_dedup_below, **options
+        )
+    before = _file_states(string_paths)
+    num_chunks = forced_cuts = 0
+    files = None
+    progress = options.pop("progress", None)
+    decompress = options.get("decompress", False)
+    hash_lists = any(path.endswith(".hashes") for path in string_paths)
+    if memory_budget is not None:
+        stats, error_bound, sniffed = _estimate_sketched(
+            string_paths,
+            memory_budget,
+            compress=compress,
+            decompress=decompress,
+            progress=progress,
+        )
+        total_bytes, chunk_bytes, compressed_chunk_bytes = stats
+    elif index_dir is not None:
+        stats, sniffed = _estimate_on_disk(
+            string_paths,
+            index_dir,
+            index_partitions,
+            compress=compress,
+            decompress=decompress,
+            progress=progress,
+        )
+        total_bytes, chunk_bytes, compressed_chunk_bytes = stats
+    elif max_tracked_chunks is None:
+        if progress is not None:
+            # the Xet pass reads the files a second time
+            passes = 1 if hash_lists else 2
+            options["progress"] = _pass_progress(progress, passes)
+        stats, (num_chunks, forced_cuts), files = _estimate_de(
+            string_paths, size_maps=size_maps, **options
+        )
+        total_bytes, chunk_bytes, compressed_chunk_bytes = stats
+        sniffed = [file[-1] for file in files]
+    else:
+        stats, shift, sniffed = _estimate_bounded(
+            string_paths,
+            max_tracked_chunks,
+            compress=compress,
+            decompress=decompress,
+            progress=progress,
+        )
+        total_bytes, chunk_bytes, compressed_chunk_bytes = stats
+    if not bounded and not hash_lists:
+        xet_bytes = _estimate_xet(
+            string_paths,
+            decompress=decompress,
+            progress=_pass_progress(progress, 2, total_bytes, len(string_paths)),
+        )
+    else:
+        # the Xet pass tracks every unique chunk, past any memory bound, and
+        # has no bytes to chunk for the hash lists
+        xet_bytes = None
+    if not compress:
+        compressed_chunk_bytes = None
+    result = {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "compressed_chunk_bytes": compressed_chunk_bytes,
+        "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+        "xet_bytes": xet_bytes,
+        "xet_dedup_ratio": (
+            (xet_bytes / total_bytes if total_bytes else 1.0)
+            if xet_bytes is not None
+            else None
+        ),
+        "chunker": _chunker_of(options),
+        "warnings": [],
+    }
+    if files is not None:
+        result["files"] = []
+        for path, (total, unique, shared, owned, compressed, digest, _) in zip(
+            string_paths, files
+        ):
+            entry = {
+                "path": path,
+                "total_bytes": total,
+                "unique_bytes": unique,
+                "shared_bytes": shared,
+                "owned_bytes": owned,
+                "compressed_bytes": compressed if compress else None,
+            }
+            if digest is not None:
+                # reused by write_report() instead of chunking the file again
+                entry["chunks"], entry["chunk_digest"] = digest
+            result["files"].append(entry)
+    for path, state, now in zip(string_paths, before, _file_states(string_paths)):
+        if state != now:
+            result["warnings"].append(
+                f"{path} changed while it was read, its counts may be truncated"
+            )
+        elif state is not None and state[0] == 0:
+            result["warnings"].append(f"{path} is empty")
+    result["warnings"].extend(_incompressible_warnings(string_paths, sniffed))
+    if forced_cuts > FORCED_CUT_WARNING * num_chunks:
+        result["warnings"].append(
+            f"{forced_cuts / num_chunks:.0%} of the unique chunks were cut at "
+            "the maximum chunk size, the inputs may be low entropy or padded"
+        )
+    if max_tracked_chunks is not None:
+        result["approximate"] = shift > 0
+        result["sample_rate"] = 2.0**-shift
+        if shift:
+            message = (
+                f"More than {max_tracked_chunks} unique chunks, counted one in "
+                f"{2**shift} chunks so the results are approximate"
+            )
+            result["warnings"].append(message)
+            warnings.warn(message, RuntimeWarning, stacklevel=2)
+    if memory_budget is not None:
+        result["approximate"] = True
+        result["error_bound"] = error_bound
+    check_dedup(result, fail_if_dedup_below)
+    return result
+
+
+class Estimator(_Estimator):
+    """Deduplication state fed one input at a time, for files arriving from a
+    pipeline which can't be passed to estimate() at once.
+
+    add_file() chunks a file and add_bytes() an object exposing the buffer
+    protocol in place or a file-like object read to the end, both returning
+    the index of the input. add_files() chunks a batch of files in parallel,
+    merging each as soon as it is chunked, and returns their indices, the
+    same as adding them one at a time in order. stats() and segments() can
+    be queried at any point: the stats of all the inputs added so far and,
+    for every chunk in input order, the index of the input first containing
+    it. The chunker options are the ones of estimate().
+
+    save(path) writes the state as a compact binary index and
+    Estimator(index=path) loads it back, so later runs only chunk the new
+    files and merge them against the index. The loaded estimator keeps the
+    chunker parameters of the index, passing others raises a ValueError. The
+    index doesn't keep the chunk order, so segments() only covers the inputs
+    added after loading it.
+
+    Several processes, e.g. data-prep workers on one machine, can update the
+    same index: save() locks it through a {path}.lock file and replaces it
+    atomically, and when another worker saved inputs to it in the meantime
+    the inputs added since this estimator was loaded or last saved are merged
+    into those instead of overwriting them. The estimator then holds the
+    merged state. save() returns a report of the numfiles of the saved index
+    and of the conflict: the merged_inputs of the other workers, zero when
+    the index was not updated meanwhile. stats() adds up the conflicts, the
+    saves of this estimator which merged inputs of other workers, and their
+    merged_inputs.
+
+    who_has(path, offset) maps a byte of an input, given by its label or
+    path, back to its chunk: the result holds the chunk hash, the offset and
+    size of the chunk in that input and its occurrences, the index, label
+    and offset of every copy of the same bytes in the inputs, including
+    itself. It is None past the end of the input. The occurrences are found
+    by walking the chunks of all the inputs and the inputs loaded from an
+    index are left out, querying one of them raises a ValueError.
+
+    export_manifest(path, format=None) writes one row per chunk occurrence
+    of the inputs, its hash, file label, file_index, offset, size,
+    compressed size and first_seen_in input, as "parquet" or "jsonl" for
+    analysis in DuckDB or pandas, by default parquet for a .parquet path and
+    JSON lines otherwise. The hashes are uint64 in parquet and 16 hex digits
+    in JSON. Like who_has() it leaves out the inputs loaded from an index and
+    it returns the number of rows.
+    """
+
+    def add_file(self, path) -> int:
+        if is_url(path):
+            return self.add_bytes(URLStream(path), label=path)
+        return super().add_file(str(path))
+
+    def add_files(self, paths) -> list[int]:
+        paths = list(paths)
+        if any(map(is_url, paths)):
+            # the objects are streamed, one at a time like in add_file()
+            return [self.add_file(path) for path in paths]
+        return super().add_files(list(map(str, paths)))
+
+    def save(self, path) -> dict:
+        merged_inputs = super().save(path)
+        return {
+            "numfiles": len(self),
+            "conflict": merged_inputs > 0,
+            "merged_inputs": merged_inputs,
+        }
+
+    def who_has(self, path, offset) -> dict | None:
+        found = super().who_has(str(path), offset)
+        if found is None:
+            return None
+        hash, occurrences = found
+        labels = self.labels()
+        index = labels.index(str(path))
+        size = occurrences[0][2]
+        start = next(
+            start
+            for input, start, _ in occurrences
+            if input == index and start <= offset < start + size
+        )
+        return {
+            "hash": hash,
+            "offset": start,
+            "size": size,
+            "occurrences": [
+                {"index": input, "label": labels[input], "offset": start}
+                for input, start, _ in occurrences
+            ],
+        }
+
+    def stats(self) -> dict:
+        total_bytes, chunk_bytes, compressed_chunk_bytes = super().stats()
+        conflicts, merged_inputs = super().conflicts()
+        return {
+            "numfiles": len(self),
+            "total_len": total_bytes,
+            "chunk_bytes": chunk_bytes,
+            "compressed_chunk_bytes": compressed_chunk_bytes,
+            "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+            "conflicts": conflicts,
+            "merged_inputs": merged_inputs,
+        }
+
+
+def export_manifest(paths, path, format=None, **params) -> int:
+    """Write the chunk occurrences of the files to a Parquet or JSON lines
+    manifest, see Estimator.export_manifest(). The chunker and codec options
+    are the ones of estimate(). Returns the number of rows."""
+    estimator = Estimator(**params)
+    for p in paths:
+        estimator.add_file(p)
+    return estimator.export_manifest(str(path), format=format)
+
+
+def reconstruct(paths, file_index, out_path, **params) -> dict:
+    """Rebuild a file from the deduplicated chunks and verify it, proving the
+    chunking is lossless.
+
+    The file at file_index is chunked first, then all the files are chunked
+    keeping the payloads of its chunks only, each taken from the first file
+    containing it, so the memory holds at most the size of the file. The
+    file is written to out_path by concatenating the payloads of its chunks
+    in order, then compared byte for byte with the file. The result holds the
+    bytes written, whether the rebuilt file was verified identical and
+    otherwise the offset of the first_mismatch. The chunker options are the
+    ones of estimate(); hash lists are not supported.
+    """
+    string_paths = list(map(str, paths))
+    written, mismatch = _reconstruct(
+        string_paths, file_index, str(out_path), **params
+    )
+    return {
+        "path": string_paths[file_index],
+        "output": str(out_path),
+        "bytes": written,
+        "verified": mismatch is None,
+        "first_mismatch": mismatch,
+    }
+
+
+def estimate_labeled(inputs, image_base=None, **options) -> dict:
+    """Estimate the deduplication of labeled inputs which may only exist
+    behind Python abstractions, e.g. database blobs or API responses.
+
+    inputs is an iterable of (label, source) pairs where the source is a file
+    path, an HTTP(S) URL streamed like in estimate(), an object exposing the
+    buffer protocol like bytes, or a file-like object with a read() method
+    returning bytes. A file-like object is read to the end in blocks without
+    holding the GIL in between, e.g. a network stream which never lands on
+    disk.
+
+    Besides the global stats, the inputs list of the result carries for
+    every label its total bytes, its unique bytes and its owned bytes, the
+    unique bytes attributed to it rather than to another input. With
+    image_base the merged chunk ownership image is written to image_base
+    with the extension of the image format, and a sidecar mapping its blocks
+    to the labels to {image_base}.blocks.json. The image options
+    image_format, quality, image_size and captions, the attribution, the
+    chunker options and progress are the ones of estimate(), the progress
+    being reported once per input.
+    """
+    labels, sources = [], []
+    for label, source in inputs:
+        if isinstance(source, os.PathLike):
+            source = os.fspath(source)
+        labels.append(str(label))
+        source = open_input(source)
+        sources.append(source)
+    if image_base is not None:
+        image_base = str(image_base)
+    stats, per_input = _estimate_labeled(
+        list(zip(labels, sources)), image_base=image_base, **options
+    )
+    total_bytes, chunk_bytes, compressed_chunk_bytes = stats
+    return {
+        "numfiles": len(labels),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "compressed_chunk_bytes": compressed_chunk_bytes,
+        "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+        "inputs": [
+            {
+                "label": label,
+                "total_bytes": total,
+                "unique_bytes": unique,
+                "owned_bytes": owned,
+            }
+            for label, (total, unique, owned, _, _) in zip(labels, per_input)
+        ],
+    }
+
+
+def estimate_with_metric(paths, on_chunk, **options) -> dict:
+    """Estimate the deduplication calling on_chunk(hash, data) once with the
+    bytes of every unique chunk during the same pass, to compute custom
+    metrics like domain-specific signatures, PII detectors or token counts
+    without re-reading the corpus.
+
+    The hash is the xxh3-64 digest of the chunk as an int. The callback is
+    called from the chunking threads one at a time while holding the GIL, so
+    it can accumulate into plain Python objects but slows the run down; an
+    exception it raises aborts the run. Hash list inputs have no bytes to
+    pass. The chunker and codec options are the ones of estimate().
+    """
     string_paths = list(map(str, paths))
-    total_bytes, chunk_bytes, compressed_chunk_bytes = _estimate_de(string_paths)
-    xet_bytes = _estimate_xet(string_paths)
+    total_bytes, chunk_bytes, compressed_chunk_bytes = _estimate_with_metric(
+        string_paths, on_chunk, **options
+    )
     return {
         "numfiles": len(string_paths),
         "total_len": total_bytes,
         "chunk_bytes": chunk_bytes,
         "compressed_chunk_bytes": compressed_chunk_bytes,
-        "dedup_ratio": chunk_bytes / total_bytes,
-        "xet_bytes": xet_bytes,
-        "xet_dedup_ratio": xet_bytes / total_bytes,
+        "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+    }
+
+
+def estimate_hash_only(paths, **params) -> dict:
+    """Estimate the chunk bytes as fast as possible for when the headline
+    dedup_ratio is all that's needed.
+
+    Only the set of chunk hashes is kept: no chunk order, per chunk metadata
+    or images, and no chunk is compressed, so the result has no
+    compressed_chunk_bytes. Like the Xet estimate but for the chunkers of
+    estimate(), whose chunker options are the params, the chunk_bytes are the
+    same as those of estimate().
+    """
+    string_paths = list(map(str, paths))
+    total_bytes, chunk_bytes = _estimate_hash_only(string_paths, **params)
+    return {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
     }
 
 
+def estimate_dictionary(paths, dict_size=110 * 1024, level=3, **options) -> dict:
+    """Quantify the benefit of compressing the unique chunks with a shared
+    zstd dictionary, which mostly helps small chunks.
+
+    The dictionary of at most dict_size bytes is trained on a deterministic
+    sample of about a hundred times its size of the unique chunks, then every
+    unique chunk is compressed on its own with it at the zstd level. The
+    files are chunked twice, once per step. Reports the dictionary-compressed
+    bytes next to the per-chunk compressed estimate of estimate(), whose
+    codec is picked with the same options; pass codec="zstd" and the same
+    codec_level to compare against zstd without a dictionary. The dictionary
+    itself is stored once and is counted in dictionary_total_bytes.
+    """
+    string_paths = list(map(str, paths))
+    stats, dictionary_compressed, dictionary_bytes = _estimate_dictionary(
+        string_paths, dict_size, level, **options
+    )
+    total_bytes, chunk_bytes, compressed_chunk_bytes = stats
+    dictionary_total = dictionary_compressed + dictionary_bytes
+    return {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "compressed_chunk_bytes": compressed_chunk_bytes,
+        "dictionary_bytes": dictionary_bytes,
+        "dictionary_compressed_bytes": dictionary_compressed,
+        "dictionary_total_bytes": dictionary_total,
+        "dictionary_gain": (
+            1 - dictionary_total / compressed_chunk_bytes
+            if compressed_chunk_bytes
+            else 0.0
+        ),
+    }
+
+
+def estimate_concatenated(path, boundaries, names=None, ends=None, **options):
+    """Estimate the deduplication of the members of a single concatenated file.
+
+    boundaries are the ascending byte offsets where the members start, e.g.
+    tar member or multipart body offsets; each member extends to the next
+    boundary, or to its offset in ends if given, the bytes up to the next
+    member, e.g. headers or padding, being skipped. The file is read once as
+    a single stream instead of opening a reader per member, while chunks are
+    still attributed to single members. The chunker, codec and decompress
+    options are the ones of estimate().
+    """
+    boundaries = list(boundaries)
+    ends = list(ends) if ends is not None else None
+    members, (total_bytes, chunk_bytes, compressed_chunk_bytes) = (
+        _estimate_concatenated(str(path), boundaries, ends, **options)
+    )
+    names = list(names) if names is not None else list(map(str, boundaries))
+    return {
+        "numfiles": len(members),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "compressed_chunk_bytes": compressed_chunk_bytes,
+        "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+        "members": [
+            {
+                "name": name,
+                "offset": offset,
+                "total_bytes": total,
+                "unique_bytes": unique,
+            }
+            for name, offset, (total, unique) in zip(names, boundaries, members)
+        ],
+    }
+
+
+def tar_boundaries(path) -> tuple[list[int], list[str], list[int]]:
+    """Return the data offsets, the names and the data end offsets of the
+    regular file members of an uncompressed tar archive, to be passed to
+    estimate_concatenated() so the headers and padding are left out."""
+    with tarfile.open(path, "r:") as tar:
+        members = [m for m in tar.getmembers() if m.isfile()]
+    return (
+        [m.offset_data for m in members],
+        [m.name for m in members],
+        [m.offset_data + m.size for m in members],
+    )
+
+
+# Approximate heap bytes of a unique chunk in a store: the hash map slot, the
+# chunk record and its seen_in list.
+CHUNK_ENTRY_BYTES = 128
+# Bytes of a chunk reference in the ordered chunk list of a store.
+ORDER_ENTRY_BYTES = 8
+# Read buffer and pending chunk of every chunking thread.
+THREAD_BUFFER_BYTES = 1024 * 1024 + 128 * 1024
+# Average length of a "<hash> <size>" record of a hash list input.
+HASH_LIST_RECORD_BYTES = 24
+# Fraction of the available memory a run may use before warning.
+MEMORY_HEADROOM = 0.8
+
+
+def stage_timings(paths, queue_depth=64, workers=None) -> dict:
+    """Chunk the files through the staged pipeline of estimate() and report
+    where the time goes.
+
+    The reader, chunker, hasher, compressor and aggregator stages run on their
+    own workers connected by channels holding at most queue_depth messages.
+    The first four split the workers, which default to the CPU count,
+    evenly with at least one each, and the aggregator runs on one more.
+    For every stage the busy seconds are spent processing and the blocked
+    seconds waiting for the next stage to accept the results, summed over its
+    workers; the stage busy for most of the wall time is the bottleneck.
+    """
+    string_paths = list(map(str, paths))
+    stats, stages, wall = _stage_timings(
+        string_paths, queue_depth=queue_depth, workers=workers
+    )
+    total_bytes, chunk_bytes, compressed_chunk_bytes = stats
+    return {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "compressed_chunk_bytes": compressed_chunk_bytes,
+        "wall_time": wall,
+        "stages": [
+            {
+                "stage": name,
+                "workers": n,
+                "items": items,
+                "busy": busy,
+                "blocked": blocked,
+                "utilization": busy / (n * wall) if wall else 0.0,
+            }
+            for name, n, items, busy, blocked in stages
+        ],
+    }
+
+
+def ingest_throughput(paths) -> dict:
+    """Chunk the files read through a buffer and then memory mapped, like
+    estimate() without and with mmap, and report the bytes per second of
+    each pass.
+
+    The second pass may read the files from the page cache filled by the
+    first one, so files larger than the memory, or a cache dropped between
+    runs, are needed to compare the reads from the storage.
+    """
+    string_paths = list(map(str, paths))
+    total_bytes, buffered = _ingest_timing(string_paths, mmap=False)
+    _, mapped = _ingest_timing(string_paths, mmap=True)
+    return {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "buffered_throughput": total_bytes / buffered if buffered else 0.0,
+        "mmap_throughput": total_bytes / mapped if mapped else 0.0,
+    }
+
+
+def available_memory() -> int | None:
+    """Return the currently available physical memory, if the OS reports it."""
+    try:
+        return os.sysconf("SC_PAGE_SIZE") * os.sysconf("SC_AVPHYS_PAGES")
+    except (AttributeError, OSError, ValueError):
+        return None
+
+
+def plan(
+    paths,
+    store_data=False,
+    memory_limit=None,
+    max_tracked_chunks=None,
+    index_dir=None,
+    index_partitions=256,
+    memory_budget=None,
+    **options,
+) -> dict:
+    """Estimate the chunk count and peak memory of a run before launching it.
+
+    The paths and options are those of estimate(): the directories and glob
+    patterns are expanded, the chunks are counted with the chunker options,
+    and the other options, which don't change the chunk index, are ignored.
+    Remote inputs have no size to stat, so they are left out of the counts
+    with a warning.
+
+    The estimate assumes no deduplication, which is the worst case: in the
+    default exact mode every per-file store and the merged store hold all
+    chunks, and with store_data both hold a copy of the chunk payloads. With
+    max_tracked_chunks, index_dir or memory_budget the chunk index is bounded
+    the way estimate() bounds it. Warnings are returned if the peak exceeds
+    the headroom of memory_limit, which defaults to the available physical
+    memory, suggesting the bounded modes when none is used.
+    """
+    modes = {
+        "max_tracked_chunks": max_tracked_chunks,
+        "index_dir": index_dir,
+        "memory_budget": memory_budget,
+    }
+    bounded = [name for name, mode in modes.items() if mode is not None]
+    if len(bounded) > 1:
+        raise ValueError(
+            "Pass only one of max_tracked_chunks, index_dir and memory_budget"
+        )
+    if bounded:
+        _check_bounded_options(bounded[0], False, options)
+    chunker = _chunker_of(options)
+    target = 2 ** bin(chunker["mask"]).count("1")
+    if chunker["algorithm"] == "fixed":
+        average_chunk = min(target, chunker["max_size"])
+    else:
+        average_chunk = chunker["min_size"] + target
+
+    string_paths = _expand_paths(list(map(str, paths)))
+    remote = [path for path in string_paths if is_remote(path)]
+    total_bytes, num_chunks = 0, 0
+    for path in string_paths:
+        if is_remote(path):
+            continue
+        size = os.stat(path).st_size
+        total_bytes += size
+        if path.endswith(".hashes"):
+            num_chunks += size // HASH_LIST_RECORD_BYTES
+        else:
+            num_chunks += -(-size // average_chunk)
+
+    if max_tracked_chunks is not None:
+        peak_memory = min(num_chunks, max_tracked_chunks) * CHUNK_ENTRY_BYTES
+    elif index_dir is not None:
+        peak_memory = -(-num_chunks // index_partitions) * CHUNK_ENTRY_BYTES
+    elif memory_budget is not None:
+        peak_memory = memory_budget
+    else:
+        peak_memory = 2 * num_chunks * (CHUNK_ENTRY_BYTES + ORDER_ENTRY_BYTES)
+        if store_data:
+            peak_memory += 2 * total_bytes
+    peak_memory += (os.cpu_count() or 1) * THREAD_BUFFER_BYTES
+
+    limit = memory_limit if memory_limit is not None else available_memory()
+    warnings = []
+    if remote:
+        warnings.append(
+            f"{len(remote)} remote inputs have no known size and are not counted"
+        )
+    if limit is not None and peak_memory > MEMORY_HEADROOM * limit:
+        warnings.append(
+            f"estimated peak memory of {peak_memory} bytes exceeds "
+            f"{MEMORY_HEADROOM:.0%} of the {limit} bytes available"
+        )
+        if not bounded:
+            if store_data:
+                warnings.append("run without storing the chunk data")
+            warnings.append(
+                "split the inputs into smaller runs or bound the chunk index "
+                "with max_tracked_chunks, index_dir or memory_budget"
+            )
+    return {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "expected_chunks": num_chunks,
+        "peak_memory": peak_memory,
+        "memory_limit": limit,
+        "warnings": warnings,
+    }
+
+
+def incompressible_warnings(paths) -> list[str]:
+    """Describe the inputs which look already compressed or encrypted.
+
+    Chunking such files rarely finds any duplication, so a near 0% dedup on
+    them is expected rather than a problem with the estimate.
+    """
+    string_paths = list(map(str, paths))
+    return [
+        f"{path} is likely compressed or encrypted: {reason}"
+        for path, reason in zip(string_paths, _detect_incompressible(string_paths))
+        if reason is not None
+    ]
+
+
+def estimate_pages(paths):
+    """Estimate the deduplication of the decompressed parquet page payloads.
+
+    Comparing against estimate() shows how much deduplication the page
+    compression destroys and would be recoverable by deduplicating after
+    decompression.
+    """
+    string_paths = list(map(str, paths))
+    total_bytes, chunk_bytes, compressed_chunk_bytes = _estimate_pages(string_paths)
+    return {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "compressed_chunk_bytes": compressed_chunk_bytes,
+        "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+    }
+
+
+def estimate_values(paths, **options) -> dict:
+    """Estimate the deduplication of the decoded values of parquet files.
+
+    Every column is decoded and serialized in the canonical form of the
+    canonical module, which only depends on the logical values, and chunked
+    as its own input. The result is stable across Arrow versions, platforms,
+    parquet writers, encodings and row group layouts, so it measures how much
+    of the data itself is duplicated, the upper bound of what any physical
+    layout can deduplicate. A column is decoded whole, so the memory holds
+    one column at a time. The chunker and codec options are the ones of
+    estimate().
+    """
+    estimator = Estimator(**options)
+    string_paths = list(map(str, paths))
+    for path in string_paths:
+        parquet = pq.ParquetFile(path)
+        for name in parquet.schema_arrow.names:
+            column = parquet.read(columns=[name]).column(0)
+            estimator.add_bytes(serialize(column), label=f"{path}:{name}")
+    result = estimator.stats()
+    # the estimator is never saved to an index
+    del result["conflicts"], result["merged_inputs"]
+    result["numcolumns"] = result["numfiles"]
+    result["numfiles"] = len(string_paths)
+    return result
+
+
+def estimate_shifted(paths, window=512):
+    """Estimate the deduplication lost to misaligned chunk boundaries.
+
+    Every new chunk is matched against the window sized blocks of the chunks
+    before it at every byte offset, using a weak rolling checksum to find the
+    candidates and a strong hash to confirm them. The shifted_bytes are found
+    at a different offset than in the earlier chunk, i.e. the same content was
+    cut at different boundaries; the aligned_dedup_ratio is the dedup ratio if
+    they were deduplicated too. Hash lists are not supported.
+    """
+    string_paths = list(map(str, paths))
+    total_bytes, chunk_bytes, shifted_bytes = _shifted_chunks(
+        string_paths, window=window
+    )
+    return {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "shifted_bytes": shifted_bytes,
+        "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+        "aligned_dedup_ratio": (
+            (chunk_bytes - shifted_bytes) / total_bytes if total_bytes else 1.0
+        ),
+    }
+
+
+def estimate_delta(paths, level=3):
+    """Estimate the savings of delta-compressing the near-duplicate chunks.
+
+    Chunks differing by a few bytes, like a parquet page rewritten with one
+    changed value, hash differently and don't deduplicate at all. Every new
+    unique chunk is looked up among the chunks before it by its
+    super-features, hashes of the maxima of transforms of a rolling hash over
+    the chunk which similar chunks very likely share, and the similar_chunks
+    found are zstd compressed at level both on their own, the
+    compressed_bytes, and with their similar chunk as the dictionary, the
+    delta_bytes. The delta_savings are the bytes saved on top of compressing
+    them; the delta_dedup_ratio is the dedup ratio if the similar chunks were
+    stored as deltas and the others as is. The data of the unique chunks is
+    held in memory and hash lists are not supported.
+    """
+    string_paths = list(map(str, paths))
+    stats = _delta_chunks(string_paths, level=level)
+    total_bytes, chunk_bytes, similar_chunks, similar_bytes, compressed, delta = stats
+    return {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "chunk_bytes": chunk_bytes,
+        "similar_chunks": similar_chunks,
+        "similar_bytes": similar_bytes,
+        "compressed_bytes": compressed,
+        "delta_bytes": delta,
+        "delta_savings": compressed - delta,
+        "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+        "delta_dedup_ratio": (
+            (chunk_bytes - similar_bytes + delta) / total_bytes if total_bytes else 1.0
+        ),
+    }
+
+
+# Both Arrow parquet writers check the page size limit after every batch of
+# this many values, so fixed size pages hold a multiple of it.
+WRITE_BATCH_SIZE = 1024
+# Coefficient of variation of the page sizes above which the sizes are
+# considered fully content defined.
+CDC_SIZE_CV = 0.25
+
+
+def cdc_confidence(path) -> dict:
+    """Score how likely a parquet file was written with content defined chunking.
+
+    Without CDC, pages are cut once they exceed the page size limit, so all but
+    the last page of a column chunk have similar sizes and hold a multiple of
+    the write batch size values. CDC cuts pages at content defined boundaries
+    instead, giving widely varying sizes and unaligned value counts. The
+    confidence averages both signals; it is None if no column chunk has enough
+    pages to tell.
+    """
+    sizes_cv, aligned, weights = [], 0, 0
+    for pages in _page_layout(str(path)):
+        # the last page is cut by the end of the column chunk
+        pages = pages[:-1]
+        if len(pages) < 2:
+            continue
+        sizes = [size for size, _ in pages]
+        mean = sum(sizes) / len(sizes)
+        std = (sum((s - mean) ** 2 for s in sizes) / len(sizes)) ** 0.5
+        sizes_cv.append((std / mean if mean else 0.0, len(pages)))
+        aligned += sum(1 for _, n in pages if n % WRITE_BATCH_SIZE == 0)
+        weights += len(pages)
+
+    if not weights:
+        return {"path": str(path), "pages": 0, "confidence": None}
+    size_cv = sum(cv * n for cv, n in sizes_cv) / weights
+    aligned_fraction = aligned / weights
+    confidence = (min(size_cv / CDC_SIZE_CV, 1.0) + 1 - aligned_fraction) / 2
+    return {
+        "path": str(path),
+        "pages": weights,
+        "size_cv": size_cv,
+        "aligned_fraction": aligned_fraction,
+        "confidence": confidence,
+    }
+
+
+def column_maps(paths, **options) -> dict[str, list[str]]:
+    """Render one strip per parquet column showing which file first stored the
+    chunks along that column's pages, written to {path}.columns.{ext}.
+
+    Columns are matched across files by their dotted path, so a column whose
+    encoding changes between versions of a file stands out as a strip owned
+    by the file itself while the stable columns keep the older file's color.
+    The image_format, quality, image_size and captions options are forwarded
+    to the renderer. Returns the column paths of each file in strip order.
+    """
+    string_paths = list(map(str, paths))
+    return dict(zip(string_paths, _column_maps(string_paths, **options)))
+
+
+def diff_snapshots(old_path, new_path) -> dict:
+    """Diff two chunk store documents written by export_store, e.g. nightly
+    snapshots of a growing corpus.
+
+    Reports the unique chunks added and removed, the chunks shared by both
+    snapshots whose occurrence counts changed and the resulting change of the
+    bytes referencing them. The added bytes are attributed to the file first
+    containing the chunk in the new snapshot and the removed bytes to the file
+    first containing it in the old one, so the growth can be traced back to
+    specific files or datasets.
+    """
+    summary, files = _diff_stores(str(old_path), str(new_path))
+    (
+        added_chunks,
+        added_bytes,
+        removed_chunks,
+        removed_bytes,
+        increased_chunks,
+        decreased_chunks,
+        referenced_bytes_delta,
+    ) = summary
+    return {
+        "added_chunks": added_chunks,
+        "added_bytes": added_bytes,
+        "removed_chunks": removed_chunks,
+        "removed_bytes": removed_bytes,
+        "growth_bytes": added_bytes - removed_bytes,
+        "increased_chunks": increased_chunks,
+        "decreased_chunks": decreased_chunks,
+        "referenced_bytes_delta": referenced_bytes_delta,
+        "files": [
+            {
+                "path": path,
+                "added_chunks": added_chunks,
+                "added_bytes": added_bytes,
+                "removed_chunks": removed_chunks,
+                "removed_bytes": removed_bytes,
+            }
+            for path, added_chunks, added_bytes, removed_chunks, removed_bytes in files
+        ],
+    }
+
+
+def diff(old_paths, new_paths, **params) -> dict:
+    """Diff two revisions of a dataset, each a list of files, at the chunk
+    level.
+
+    The unique chunks of each revision are compared: the added_bytes are only
+    in the new revision, the removed_bytes only in the old one and the
+    shared_bytes in both. The added chunks are what a content defined
+    chunking store holding the old revision would upload for the update,
+    upload_bytes once compressed, against the naive_upload_bytes of
+    uploading the new revision whole. The upload_ratio compares the
+    uncompressed added_bytes to the naive_upload_bytes. The chunker and codec
+    options are the ones of estimate().
+    """
+    (old_total, new_total), added, removed, shared = _diff_revisions(
+        list(map(str, old_paths)), list(map(str, new_paths)), **params
+    )
+    added_chunks, added_bytes, upload_bytes = added
+    removed_chunks, removed_bytes = removed
+    shared_chunks, shared_bytes = shared
+    return {
+        "old_total_len": old_total,
+        "new_total_len": new_total,
+        "added_chunks": added_chunks,
+        "added_bytes": added_bytes,
+        "removed_chunks": removed_chunks,
+        "removed_bytes": removed_bytes,
+        "shared_chunks": shared_chunks,
+        "shared_bytes": shared_bytes,
+        "upload_bytes": upload_bytes,
+        "naive_upload_bytes": new_total,
+        "upload_ratio": added_bytes / new_total if new_total else 1.0,
+    }
+
+
+def retention(snapshot_path, days=90, now=None) -> dict:
+    """Summarize the chunk ages of a snapshot written by export_store with a
+    previous snapshot or a timestamp, e.g. for retention policy planning.
+
+    The cutoff is the given number of days before now, a unix time which
+    defaults to the current time. The live chunks are referenced by the files
+    of the snapshot, the aged ones among them were first seen before the
+    cutoff. The retained chunks are no longer referenced by any file, the
+    stale ones among them haven't been referenced since the cutoff and could
+    be deleted under a retention policy of that many days.
+    """
+    now = time.time() if now is None else now
+    cutoff = max(0, int(now - days * 86400))
+    live, aged, retained, stale = _retention(str(snapshot_path), cutoff)
+    result = {"cutoff": cutoff}
+    for name, (chunks, size) in zip(
+        ["live", "aged", "retained", "stale"], [live, aged, retained, stale]
+    ):
+        result[f"{name}_chunks"] = chunks
+        result[f"{name}_bytes"] = size
+    return result
+
+
+def export_bloom(paths, output_path, fpp=0.01):
+    """Write a bloom filter over the chunk hashes of the given files.
+
+    The filter can be shared with another party to estimate the mutual
+    deduplication with estimate_against_bloom() without exchanging the exact
+    chunk hashes; a higher fpp reveals less but makes the estimate coarser.
+    """
+    _export_bloom(list(map(str, paths)), str(output_path), fpp=fpp)
+
+
+def estimate_against_bloom(paths, bloom_path):
+    """Estimate how many unique bytes of the given files are shared with the
+    files summarized by a bloom filter written by export_bloom().
+
+    The matched bytes are an upper bound since false positives count as
+    matches.
+    """
+    string_paths = list(map(str, paths))
+    unique_bytes, matched_bytes = _estimate_against_bloom(string_paths, str(bloom_path))
+    return {
+        "numfiles": len(string_paths),
+        "chunk_bytes": unique_bytes,
+        "matched_bytes": matched_bytes,
+        "shared_ratio": matched_bytes / unique_bytes if unique_bytes else 0.0,
+    }
+
+
+def duplication(paths):
+    """Report the byte-weighted duplication factor (total / unique bytes) per
+    power-of-two chunk size bucket and per file.
+
+    A factor of 2 means the bytes are stored twice on average, separating
+    two-way duplication from chunks repeated thousands of times.
+
+    The deduplicated bytes are split into intra_file_bytes, repeats within the
+    same file, and inter_file_bytes, chunks already stored by another file.
+    The latter are counted in the file holding the copy, the first file
+    containing a chunk owns it. Self-deduplicating files call for a different
+    layout, files duplicated across shards for a different sharding.
+    """
+    string_paths = list(map(str, paths))
+    buckets, files = _duplication(string_paths)
+    return {
+        "intra_file_bytes": sum(total - unique for total, unique, _ in files),
+        "inter_file_bytes": sum(inter for _, _, inter in files),
+        "buckets": [
+            {
+                "min_size": bucket,
+                "total_bytes": total,
+                "unique_bytes": unique,
+                "unique_chunks": count,
+                "duplication_factor": total / unique if unique else 1.0,
+            }
+            for bucket, total, unique, count in buckets
+        ],
+        "files": [
+            {
+                "path": path,
+                "total_bytes": total,
+                "unique_bytes": unique,
+                "duplication_factor": total / unique if unique else 1.0,
+                "intra_file_bytes": total - unique,
+                "inter_file_bytes": inter,
+            }
+            for path, (total, unique, inter) in zip(string_paths, files)
+        ],
+    }
+
+
+def leaderboard(paths, top: int | None = None) -> dict:
+    """Rank the files by the duplicate bytes they contain, to prioritize the
+    cleanup of the files wasting the most storage.
+
+    The duplicate_bytes of a file are its intra_file_bytes and
+    inter_file_bytes of duplication(), the bytes it would no longer store
+    once deduplicated. The files are ordered by descending duplicate bytes
+    and truncated to the top ones if given, each with its share of the
+    duplicate bytes of the whole corpus and the cumulative_share of the files
+    ranked up to it, e.g. the ten first files reclaim their cumulative_share
+    of the waste.
+    """
+    string_paths = list(map(str, paths))
+    _, files = _duplication(string_paths)
+    rows = [
+        {
+            "path": path,
+            "total_bytes": total,
+            "duplicate_bytes": total - unique + inter,
+            "intra_file_bytes": total - unique,
+            "inter_file_bytes": inter,
+        }
+        for path, (total, unique, inter) in zip(string_paths, files)
+    ]
+    rows.sort(key=lambda row: (-row["duplicate_bytes"], row["path"]))
+    duplicate_bytes = sum(row["duplicate_bytes"] for row in rows)
+    # without any duplicate the shares are all zero
+    denominator = duplicate_bytes or 1
+    cumulative = 0
+    for rank, row in enumerate(rows, 1):
+        cumulative += row["duplicate_bytes"]
+        row["rank"] = rank
+        row["share"] = row["duplicate_bytes"] / denominator
+        row["cumulative_share"] = cumulative / denominator
+    return {
+        "numfiles": len(string_paths),
+        "total_bytes": sum(row["total_bytes"] for row in rows),
+        "duplicate_bytes": duplicate_bytes,
+        "files": rows[:top],
+    }
+
+
+def _refcount_bucket(occurrences: int) -> int:
+    # 1, 2-9, 10-99, 100-999...
+    if occurrences < 10:
+        return min(occurrences, 2)
+    return 10 ** (len(str(occurrences)) - 1)
+
+
+def refcounts(paths, top: int = 10) -> dict:
+    """Report how many times the unique chunks are referenced and the most
+    duplicated chunks, telling whether the savings come from a few massive
+    duplicates or from broad low-level redundancy.
+
+    The distribution lists every reference count with its unique chunks and
+    bytes, the buckets group them into 1, 2-9, 10-99, 100-999... references
+    with the saved_bytes, the bytes not stored thanks to the repeats, and
+    their share of all the saved bytes. The top chunks are the ones with the
+    most references, the largest first among equal counts, with the paths of
+    the files containing them.
+    """
+    string_paths = list(map(str, paths))
+    counts, chunks = _refcounts(string_paths, top)
+    buckets = {}
+    for occurrences, count, size in counts:
+        bucket = buckets.setdefault(
+            _refcount_bucket(occurrences),
+            {"unique_chunks": 0, "unique_bytes": 0, "saved_bytes": 0},
+        )
+        bucket["unique_chunks"] += count
+        bucket["unique_bytes"] += size
+        bucket["saved_bytes"] += size * (occurrences - 1)
+    saved_bytes = sum(bucket["saved_bytes"] for bucket in buckets.values())
+    total_bytes = sum(size * occurrences for occurrences, _, size in counts)
+    return {
+        "numfiles": len(string_paths),
+        "total_bytes": total_bytes,
+        "unique_chunks": sum(count for _, count, _ in counts),
+        "saved_bytes": saved_bytes,
+        "distribution": [
+            {"occurrences": occurrences, "unique_chunks": count, "unique_bytes": size}
+            for occurrences, count, size in counts
+        ],
+        "buckets": [
+            {
+                "min_occurrences": lower,
+                "max_occurrences": {1: 1, 2: 9}.get(lower, lower * 10 - 1),
+                **bucket,
+                "savings_share": (
+                    bucket["saved_bytes"] / saved_bytes if saved_bytes else 0.0
+                ),
+            }
+            for lower, bucket in sorted(buckets.items())
+        ],
+        "top": [
+            {
+                "hash": hash,
+                "size": size,
+                "occurrences": occurrences,
+                "saved_bytes": size * (occurrences - 1),
+                "files": [string_paths[i] for i in files],
+            }
+            for hash, size, occurrences, files in chunks
+        ],
+    }
+
+
+def format_pairs(paths) -> list[dict]:
+    """Attribute the cross-file duplicate bytes to the pairs of file formats
+    sharing them, e.g. parquet/parquet or json/parquet.
+
+    A chunk found in several files is stored once in the file first containing
+    it and is a duplicate in each of the other files, the duplicate bytes are
+    attributed to the (sorted) formats of the owning and the duplicate file.
+    Repeats within a single file are not counted. Records are sorted by
+    descending duplicate bytes.
+    """
+    string_paths = list(map(str, paths))
+    formats = [detect_format(path) for path in string_paths]
+    pairs = defaultdict(int)
+    seen = set()
+    for hash, chunk in _chunks(string_paths):
+        # the chunks are listed once per occurrence
+        if hash in seen:
+            continue
+        seen.add(hash)
+        owner = formats[chunk["first_seen_in"]]
+        for i in chunk["seen_in"]:
+            if i != chunk["first_seen_in"]:
+                pairs[tuple(sorted((owner, formats[i])))] += chunk["size"]
+    total = sum(pairs.values())
+    return [
+        {
+            "formats": pair,
+            "duplicate_bytes": size,
+            "fraction": size / total,
+        }
+        for pair, size in sorted(pairs.items(), key=lambda item: -item[1])
+    ]
+
+
+def deletion_savings(paths, deleted):
+    """Estimate the bytes freed by deleting a subset of the given files.
+
+    Chunks referenced only by the deleted files are reclaimable, chunks also
+    referenced by the remaining files are shared and stay in storage.
+    """
+    string_paths = list(map(str, paths))
+    reclaimable, shared = _deletion_savings(string_paths, list(map(str, deleted)))
+    return {
+        "numfiles": len(string_paths),
+        "numdeleted": len(deleted),
+        "reclaimable_bytes": reclaimable,
+        "shared_bytes": shared,
+    }
+
+
+def boundaries(path) -> tuple[np.ndarray, np.ndarray]:
+    """Return the end offsets and the xxh3 hashes of the chunks of a file.
+
+    The file is only cut and hashed, without tracking the unique chunks or
+    compressing them, so this is the fastest way to get the chunk boundaries.
+    Both are read-only uint64 arrays viewing the bytes returned by the core.
+    """
+    offsets, hashes = _boundaries(str(path))
+    return np.frombuffer(offsets, dtype="<u8"), np.frombuffer(hashes, dtype="<u8")
+
+
+def chunk_size_stats(paths, bins=32, **params) -> dict:
+    """Summarize the size distribution of the unique chunks of the corpus, to
+    confirm the chunker parameters produce the intended chunk sizes.
+
+    The params are the chunker options of estimate(). The result holds the
+    num_chunks, the min, max, mean, median and p95 chunk sizes in bytes and a
+    histogram of the chunk sizes as its counts and bin edges. bins is passed
+    to np.histogram: a number of equal width bins between the smallest and
+    the largest chunk, 32 by default, or the list of edges.
+    """
+    sizes = np.frombuffer(_chunk_sizes(list(map(str, paths)), **params), dtype="<u8")
+    if not len(sizes):
+        raise ValueError("No chunks to summarize")
+    counts, edges = np.histogram(sizes, bins=bins)
+    return {
+        "num_chunks": len(sizes),
+        "min": int(sizes[0]),
+        "max": int(sizes[-1]),
+        "mean": float(sizes.mean()),
+        "median": float(np.median(sizes)),
+        "p95": float(np.percentile(sizes, 95)),
+        "histogram": {"counts": counts.tolist(), "edges": edges.tolist()},
+    }
+
+
+# Candidate target chunk sizes of the recommendation, 4 KiB to 4 MiB.
+CANDIDATE_CHUNK_SIZES = tuple(2**bits for bits in range(12, 23))
+
+
+def _mask(target: int) -> int:
+    bits = target.bit_length() - 1
+    return ((1 << bits) - 1) << (64 - bits)
+
+
+def recommend_chunk_size(
+    paths, chunk_overhead=64, candidates=CANDIDATE_CHUNK_SIZES
+) -> dict:
+    """Recommend a target chunk size for the corpus from its chunk matches.
+
+    The files are chunked with the default parameters and the duplicate chunks
+    (already seen in the same or an earlier file) are grouped into runs of
+    adjacent duplicates. Content defined chunking loses about one average chunk
+    of every duplicated run at its edges, so a run of L bytes found with the
+    current target T0 is predicted to keep max(0, L + T0 - T) duplicate bytes
+    with target T. The recommended target minimizes the predicted unique bytes
+    plus chunk_overhead bytes of metadata per chunk: long runs favor large
+    chunks, short scattered matches favor small ones.
+
+    Besides the recommended target, mask and min/max sizes (1/8 and twice the
+    target like the defaults) and its predicted_dedup_ratio, the result holds
+    the measured dedup_ratio, the number of runs, their byte-weighted mean
+    length and the adjacent_fraction of duplicate chunks following another
+    duplicate chunk.
+    """
+    current = 2 ** bin(CHUNK_MASK).count("1")
+    seen = set()
+    runs, adjacent, duplicates, total = [], 0, 0, 0
+    for path in paths:
+        offsets, hashes = boundaries(path)
+        sizes = np.diff(offsets, prepend=0)
+        total += int(offsets[-1]) if len(offsets) else 0
+        run = 0
+        for hash, size in zip(hashes.tolist(), sizes.tolist()):
+            if hash in seen:
+                duplicates += 1
+                adjacent += run > 0
+                run += size
+                continue
+            seen.add(hash)
+            if run:
+                runs.append(run)
+            run = 0
+        if run:
+            runs.append(run)
+    if not total:
+        raise ValueError("No data to recommend a chunk size for")
+
+    def cost(target):
+        kept = sum(max(0, length + current - target) for length in runs)
+        unique = total - min(kept, total)
+        return unique + chunk_overhead * total / target, unique
+
+    best = min(candidates, key=lambda target: cost(target)[0])
+    duplicate_bytes = sum(runs)
+    return {
+        "target_chunk_size": best,
+        "chunk_mask": _mask(best),
+        "min_chunk_size": best // 8,
+        "max_chunk_size": best * 2,
+        "predicted_dedup_ratio": cost(best)[1] / total,
+        "current_target_chunk_size": current,
+        "dedup_ratio": (total - duplicate_bytes) / total,
+        "runs": len(runs),
+        "mean_run_bytes": (
+            sum(length**2 for length in runs) / duplicate_bytes if runs else 0.0
+        ),
+        "adjacent_fraction": adjacent / duplicates if duplicates else 0.0,
+    }
+
+
+def chunk_table(paths, store_data=False, attribution=None, **params) -> pa.Table:
+    """Return the merged chunks in order as an Arrow table.
+
+    Unlike chunks(), the table is transferred as a single Arrow IPC stream
+    instead of one Python object per chunk, so it scales to millions of chunks
+    and converts to pandas cheaply. The data column is large_binary, so it
+    holds more than 2 GiB of chunks, and null unless store_data is set. The
+    chunker options of estimate() are accepted as well. Like with chunks(),
+    the inputs can be paths, binary data held in memory exposing the buffer
+    protocol, e.g. bytes, bytearray, numpy arrays or Arrow buffers, which is
+    chunked in place, or file-like objects.
+    """
+    ipc = _chunks(
+        [os.fspath(path) if isinstance(path, os.PathLike) else path for path in paths],
+        store_data=store_data,
+        attribution=attribution,
+        arrow=True,
+        **params,
+    )
+    return pa.ipc.open_stream(ipc).read_all()
+
+
+CHUNKERS = ("gearhash", "fastcdc", "rabin", "fixed")
+CODECS = ("lz4", "zstd", "gzip", "brotli", "none")
+
+
+def chunker_sensitivity(paths, chunkers=CHUNKERS, **params) -> list[dict]:
+    """Estimate the deduplication of the files with each chunking algorithm.
+
+    The target, minimum and maximum chunk sizes (see estimate()) are shared
+    by all the chunkers, so the differences in the dedup ratios come from how
+    the algorithms place the boundaries; fixed-size blocks show what content
+    defined chunking gains at all.
+    """
+    string_paths = list(map(str, paths))
+    results = []
+    for chunker in chunkers:
+        table = chunk_table(string_paths, chunker=chunker, **params)
+        _, first = np.unique(table.column("hash").to_numpy(), return_index=True)
+        sizes = table.column("size").to_numpy()
+        total_bytes = int(sizes.sum())
+        chunk_bytes = int(sizes[first].sum())
+        # the last chunk of a file may be empty
+        count = int((sizes > 0).sum())
+        results.append(
+            {
+                "chunker": chunker,
+                "total_len": total_bytes,
+                "chunk_bytes": chunk_bytes,
+                "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+                "unique_chunks": len(first),
+                "mean_chunk_size": total_bytes / count if count else 0.0,
+            }
+        )
+    return results
+
+
+# Target chunk sizes of the sweep, 8 KiB to 1 MiB.
+SWEEP_CHUNK_SIZES = tuple(2**bits for bits in range(13, 21))
+
+
+def chunk_size_sweep(paths, targets=SWEEP_CHUNK_SIZES) -> list[dict]:
+    """Estimate the deduplication of the files at several target chunk sizes.
+
+    Every file is read once: the gear rolling hash of the default chunker
+    doesn't depend on where the chunks are cut, so all the targets, powers of
+    two with the default 1/8 and twice the target minimum and maximum, are
+    cut from the same hash stream and give the same chunks as estimate()
+    with their target_chunk_size. One record per target, in the given order,
+    for the curve of the dedup_ratio against the target_chunk_size. Hash
+    lists are not supported.
+    """
+    string_paths = list(map(str, paths))
+    targets = list(targets)
+    total_bytes, stats = _chunk_size_sweep(string_paths, targets)
+    return [
+        {
+            "target_chunk_size": target,
+            "total_len": total_bytes,
+            "chunk_bytes": chunk_bytes,
+            "dedup_ratio": chunk_bytes / total_bytes if total_bytes else 1.0,
+            "num_chunks": num_chunks,
+            "unique_chunks": unique_chunks,
+            "mean_chunk_size": total_bytes / num_chunks if num_chunks else 0.0,
+        }
+        for target, (num_chunks, unique_chunks, chunk_bytes) in zip(targets, stats)
+    ]
+
+
+MAX_OVERLAP_FILES = 5
+
+
+def overlap(paths) -> dict[tuple[str, ...], int]:
+    """Decompose the unique chunk bytes of a few files into disjoint regions.
+
+    Maps every non-empty combination of the paths to the bytes contained in
+    exactly those files (only-A, only-B, A∩B, ...), including empty regions,
+    as needed to draw Venn diagrams.
+    """
+    string_paths = list(map(str, paths))
+    if len(string_paths) > MAX_OVERLAP_FILES:
+        raise ValueError(
+            f"Overlap decomposition supports at most {MAX_OVERLAP_FILES} files, "
+            f"got {len(string_paths)}"
+        )
+    membership = {tuple(seen_in): size for seen_in, size, _ in _overlaps(string_paths)}
+    return {
+        tuple(string_paths[i] for i in combo): membership.get(combo, 0)
+        for r in range(1, len(string_paths) + 1)
+        for combo in combinations(range(len(string_paths)), r)
+    }
+
+
+def upset(paths, top_k: int | None = None) -> list[dict]:
+    """Aggregate chunk file-membership by combination for UpSet plots.
+
+    Returns one record per combination of files sharing chunks, ordered by
+    descending bytes and truncated to the top_k combinations if given. The
+    membership field is a bitset string with one character per input path.
+    """
+    string_paths = list(map(str, paths))
+    rows = []
+    for seen_in, size, count in _overlaps(string_paths)[:top_k]:
+        members = set(seen_in)
+        rows.append(
+            {
+                "membership": "".join(
+                    "1" if i in members else "0" for i in range(len(string_paths))
+                ),
+                "files": [string_paths[i] for i in seen_in],
+                "degree": len(seen_in),
+                "bytes": size,
+                "chunks": count,
+            }
+        )
+    return rows
+
+
+def similarity_matrix(paths) -> np.ndarray:
+    """Return the N×N matrix of the bytes shared between every pair of files.
+
+    Entry [i, j] is the fraction of the unique bytes of file i which file j
+    contains too, so the diagonal is 1 and the matrix is not symmetric when
+    the files differ in size: a small file fully contained in a large one is
+    1 on its row and small on the row of the large file. The rows of empty
+    files are 0.
+    """
+    string_paths = list(map(str, paths))
+    shared = np.zeros((len(string_paths), len(string_paths)), dtype=np.int64)
+    for seen_in, size, _ in _overlaps(string_paths):
+        shared[np.ix_(seen_in, seen_in)] += size
+    unique = shared.diagonal()[:, None]
+    return np.divide(shared, unique, out=np.zeros(shared.shape), where=unique > 0)
+
+
+# Shard file names like train-00001-of-00100.parquet.
+SHARD_PATTERN = re.compile(r"^(?P<split>.+)-(?P<index>\d+)-of-(?P<count>\d+)")
+
+
+def _content_digest(path) -> str:
+    sha = hashlib.sha256()
+    with open(path, "rb") as f:
+        while block := f.read(1 << 20):
+            sha.update(block)
+    return sha.hexdigest()
+
+
+def _relative_names(paths, root=None) -> list[str]:
+    if root is None:
+        return [Path(p).name for p in paths]
+    return [str(Path(p).relative_to(root)) for p in paths]
+
+
+def _shard_counts(names) -> dict[str, int]:
+    """Return the shard count of every split among the shard file names."""
+    counts = {}
+    for name in names:
+        match = SHARD_PATTERN.match(Path(name).name)
+        if match:
+            counts[match["split"]] = int(match["count"])
+    return counts
+
+
+def shard_renames(
+    old_paths, new_paths, old_root=None, new_root=None, min_similarity=0.9
+) -> dict:
+    """Explain the differences between two revisions of a dataset by renamed
+    and re-sharded files, e.g. train-00001-of-00100.parquet becoming
+    train-00001-of-00120.parquet with the same content.
+
+    The files are named by their path relative to old_root and new_root if
+    given, by their file name otherwise. Files whose content is identical
+    under another name are reported as renamed, the remaining ones are paired
+    as near-duplicates when both share at least min_similarity of their
+    unique chunk bytes with each other, or listed as modified if they have
+    the same name. The resharded map lists the splits whose shard count in
+    the names changed, and the files left unpaired are reported as added or
+    removed. The unchanged count covers the identical files under the same
+    name.
+    """
+    old_paths, new_paths = list(map(str, old_paths)), list(map(str, new_paths))
+    old_names = _relative_names(old_paths, old_root)
+    new_names = _relative_names(new_paths, new_root)
+
+    old_digests = defaultdict(list)
+    for i, path in enumerate(old_paths):
+        old_digests[_content_digest(path)].append(i)
+    result = {"unchanged": 0, "renamed": [], "near_duplicates": [], "modified": []}
+    paired_old, unpaired_new = set(), []
+    for j, path in enumerate(new_paths):
+        # prefer an identical file under the same name
+        digest = _content_digest(path)
+        candidates = [i for i in old_digests[digest] if i not in paired_old]
+        same_name = [i for i in candidates if old_names[i] == new_names[j]]
+        if same_name:
+            paired_old.add(same_name[0])
+            result["unchanged"] += 1
+        elif candidates:
+            paired_old.add(candidates[0])
+            result["renamed"].append(
+                {"old": old_names[candidates[0]], "new": new_names[j]}
+            )
+        else:
+            unpaired_new.append(j)
+
+    unpaired_old = [i for i in range(len(old_paths)) if i not in paired_old]
+    if unpaired_old and unpaired_new:
+        paths = [old_paths[i] for i in unpaired_old]
+        paths += [new_paths[j] for j in unpaired_new]
+        matrix = similarity_matrix(paths)
+        n = len(unpaired_old)
+        # both directions have to match, so a shard split in two doesn't pair
+        # with either half
+        similarity = np.minimum(matrix[:n, n:], matrix[n:, :n].T)
+        pairs = sorted(
+            ((similarity[a, b], a, b) for a, b in np.ndindex(similarity.shape)),
+            reverse=True,
+        )
+        matched_old, matched_new = set(), set()
+        for score, a, b in pairs:
+            if score < min_similarity:
+                break
+            if a in matched_old or b in matched_new:
+                continue
+            matched_old.add(a)
+            matched_new.add(b)
+            old_name, new_name = old_names[unpaired_old[a]], new_names[unpaired_new[b]]
+            if old_name == new_name:
+                result["modified"].append(old_name)
+            else:
+                result["near_duplicates"].append(
+                    {"old": old_name, "new": new_name, "similarity": float(score)}
+                )
+        unpaired_old = [i for a, i in enumerate(unpaired_old) if a not in matched_old]
+        unpaired_new = [j for b, j in enumerate(unpaired_new) if b not in matched_new]
+
+    old_counts, new_counts = _shard_counts(old_names), _shard_counts(new_names)
+    result["resharded"] = {
+        split: [old_counts[split], new_counts[split]]
+        for split in sorted(old_counts.keys() & new_counts.keys())
+        if old_counts[split] != new_counts[split]
+    }
+    result["added"] = [new_names[j] for j in unpaired_new]
+    result["removed"] = [old_names[i] for i in unpaired_old]
+    return result
+
+
+def marginal_contributions(datasets: dict[str, list]) -> list[dict]:
+    """Rank datasets sharing a store by the unique bytes each one adds.
+
+    The marginal bytes are the leave-one-out contribution: the chunks no other
+    dataset contains, i.e. the bytes freed by dropping the dataset. The shapley
+    bytes split every chunk equally among the datasets containing it, which is
+    the exact Shapley value of the unique bytes and sums up to the total
+    unique bytes. Records are ordered from the cheapest dataset to host
+    incrementally to the most expensive one.
+    """
+    names, string_paths = [], []
+    for name, paths in datasets.items():
+        for path in paths:
+            names.append(name)
+            string_paths.append(str(path))
+
+    records = {
+        name: {
+            "dataset": name,
+            "unique_bytes": 0,
+            "marginal_bytes": 0,
+            "shapley_bytes": 0.0,
+        }
+        for name in datasets
+    }
+    for seen_in, size, _ in _overlaps(string_paths):
+        owners = {names[i] for i in seen_in}
+        for name in owners:
+            records[name]["unique_bytes"] += size
+            records[name]["shapley_bytes"] += size / len(owners)
+        if len(owners) == 1:
+            records[owners.pop()]["marginal_bytes"] += size
+    return sorted(
+        records.values(), key=lambda r: (r["marginal_bytes"], r["shapley_bytes"])
+    )
+
+
+def simulate_cache(paths, cache_sizes) -> list[dict]:
+    """Simulate an LRU chunk cache serving the files in the given order.
+
+    Every file is requested chunk by chunk, e.g. the upload or download
+    sequence of a dataset, and a chunk hits if it is still cached from an
+    earlier request. Returns one record per cache size in bytes with the
+    request and byte hit rates, to size CDN or edge caches.
+    """
+    string_paths = list(map(str, paths))
+    cache_sizes = [cache_sizes] if isinstance(cache_sizes, int) else list(cache_sizes)
+    simulations = _simulate_cache(string_paths, cache_sizes)
+    records = []
+    for cache_size, files in zip(cache_sizes, simulations):
+        requests = sum(f[0] for f in files)
+        hits = sum(f[1] for f in files)
+        requested_bytes = sum(f[2] for f in files)
+        hit_bytes = sum(f[3] for f in files)
+        records.append(
+            {
+                "cache_size": cache_size,
+                "requests": requests,
+                "hits": hits,
+                "hit_rate": hits / requests if requests else 0.0,
+                "requested_bytes": requested_bytes,
+                "hit_bytes": hit_bytes,
+                "byte_hit_rate": (
+                    hit_bytes / requested_bytes if requested_bytes else 0.0
+                ),
+                "files": [
+                    {"path": path, "requests": r, "hits": h}
+                    for path, (r, h, _, _) in zip(string_paths, files)
+                ],
+            }
+        )
+    return records
+
+
+def packing(paths, block_sizes=(64 * 1024,)) -> list[dict]:
+    """Simulate packing the unique chunks into independently compressed lz4
+    frames of each block size, e.g. the 64KiB frames of a container format.
+
+    Unlike the per-chunk compressed bytes reported by estimate(), the stored
+    bytes account for compressing across chunk boundaries and for the frame
+    headers and the chunk index of the container. One record per block size.
+    """
+    string_paths = list(map(str, paths))
+    records = []
+    for block_size in block_sizes:
+        chunks, blocks, raw, compressed, overhead = _pack_chunks(
+            string_paths, block_size
+        )
+        records.append(
+            {
+                "block_size": block_size,
+                "chunks": chunks,
+                "blocks": blocks,
+                "chunk_bytes": raw,
+                "compressed_bytes": compressed,
+                "overhead_bytes": overhead,
+                "stored_bytes": compressed + overhead,
+                "storage_ratio": (compressed + overhead) / raw if raw else 0.0,
+            }
+        )
+    return records
+
+
+def intra_chunk_redundancy(paths, sizes=(16 * 1024, 4 * 1024, 1024)) -> list[dict]:
+    """Estimate the redundancy left inside the unique chunks.
+
+    Every unique chunk is cut again by a content defined chunker with a much
+    smaller average size (a power of two of at least 64 bytes) and the bytes
+    of the sub-chunks seen before are counted as redundant. A large redundant
+    fraction indicates that a smaller target chunk size would unlock more
+    deduplication. One record per sub-chunk size.
+    """
+    string_paths = list(map(str, paths))
+    sizes = list(sizes)
+    records = []
+    for size, stats in zip(sizes, _sub_chunk_redundancy(string_paths, sizes)):
+        sub_chunks, unique_sub_chunks, chunk_bytes, redundant_bytes = stats
+        records.append(
+            {
+                "sub_chunk_size": size,
+                "sub_chunks": sub_chunks,
+                "unique_sub_chunks": unique_sub_chunks,
+                "chunk_bytes": chunk_bytes,
+                "redundant_bytes": redundant_bytes,
+                "redundant_fraction": (
+                    redundant_bytes / chunk_bytes if chunk_bytes else 0.0
+                ),
+            }
+        )
+    return records
+
+
 def compare_formats_tables(
     formats: list[FileFormat],
     tables: dict[str, dict[str, Path | pa.Table]],
@@ -79,7 +2000,7 @@ def compare_formats_tables(
         estimates = dict(zip(keys, results))
 
     return [
-        EstimationResult(format=fmt, group=table_name, **data)
+        EstimationResult.from_estimate(data, format=fmt, group=table_name)
         for (table_name, fmt), data in estimates.items()
     ]
 
@@ -114,6 +2035,119 @@ def compare_formats(
         estimates = executor.map(estimate, path_pairs)
 
     return [
-        EstimationResult(format=contenders[name], group="param-impact", **data)
+        EstimationResult.from_estimate(
+            data, format=contenders[name], group="param-impact"
+        )
         for name, data in zip(names, estimates)
     ]
+
+
+def experiment(
+    src: Path | str | list[Path | str],
+    configs: dict[str, list],
+    directory: Path | str | None = None,
+    max_workers: int | None = None,
+) -> pa.Table:
+    """Rewrite the source parquet file(s) under every combination of the given
+    parquet-cpp settings and estimate deduplication across the outputs.
+
+    configs maps ParquetCpp field names (e.g. compression, use_cdc,
+    row_group_size) to the list of values to sweep. Files are written to a
+    temporary directory unless directory is given. One row per combination.
+    """
+    sources = [Path(p) for p in src] if isinstance(src, list) else [Path(src)]
+    keys = list(configs.keys())
+    formats = [
+        ParquetCpp(**{"use_cdc": False, **dict(zip(keys, values))})
+        for values in product(*configs.values())
+    ]
+    tables = {"experiment": {p.stem: p for p in sources}}
+
+    if directory is None:
+        with tempfile.TemporaryDirectory() as tmp:
+            results = compare_formats_tables(formats, tables, tmp, max_workers)
+    else:
+        results = compare_formats_tables(formats, tables, directory, max_workers)
+
+    rows = []
+    for r in sorted(results, key=lambda r: formats.index(r.format)):
+        row = {key: getattr(r.format, key) for key in keys}
+        row["use_cdc"] = bool(r.format.use_cdc)
+        row.update(
+            numfiles=r.numfiles,
+            total_len=r.total_len,
+            chunk_bytes=r.chunk_bytes,
+            compressed_chunk_bytes=r.compressed_chunk_bytes,
+            dedup_ratio=r.dedup_ratio,
+            xet_bytes=r.xet_bytes,
+            xet_dedup_ratio=r.xet_dedup_ratio,
+        )
+        rows.append(row)
+    return pa.Table.from_pylist(rows)
+
+
+DEFAULT_CODECS: list[tuple[str, int | None]] = [
+    ("none", None),
+    ("snappy", None),
+    ("zstd", 1),
+    ("zstd", 3),
+    ("zstd", 9),
+    ("zstd", 19),
+]
+
+
+def codec_sensitivity(
+    original: Path | str,
+    updated: Path | str,
+    directory: Path | str,
+    codecs: list[tuple[str, int | None]] = DEFAULT_CODECS,
+    use_cdc: bool = False,
+) -> list[EstimationResult]:
+    """Recompress both versions of a file under each codec and estimate the
+    deduplication between them. One record per (codec, level) pair.
+
+    Since the data is identical across codecs, differences in dedup ratio are
+    caused by how each codec's framing affects chunk stability.
+    """
+    formats: list[FileFormat] = [
+        ParquetCpp(use_cdc=use_cdc, compression=codec, compression_level=level)
+        for codec, level in codecs
+    ]
+    tables: dict[str, dict[str, Path | pa.Table]] = {
+        "codec-sensitivity": {"original": Path(original), "updated": Path(updated)}
+    }
+    return compare_formats_tables(formats, tables, directory)
+
+
+def row_order_churn(
+    original: Path | str,
+    updated: Path | str,
+    sort_by: str | list[str],
+    directory: Path | str,
+    fmt: FileFormat | None = None,
+) -> list[EstimationResult]:
+    """Quantify how much of the dedup between two file versions is lost to
+    row-order churn by sorting both on a stable key.
+
+    Returns one record per group: "unsorted" compares the versions as they
+    are, "sorted" compares both sorted by the key, and "reordered" compares
+    the original against its own sorted rewrite. A large gain from unsorted
+    to sorted means the poor dedup is caused by rows moving around rather
+    than by changed content.
+    """
+    fmt = fmt or ParquetCpp(use_cdc=True)
+    keys = [sort_by] if isinstance(sort_by, str) else list(sort_by)
+
+    def sort(table):
+        return table.sort_by([(key, "ascending") for key in keys])
+
+    original_table = pq.read_table(original)
+    updated_table = pq.read_table(updated)
+    sorted_original = sort(original_table)
+    tables: dict[str, dict[str, Path | pa.Table]] = {
+        "unsorted": {"original": original_table, "updated": updated_table},
+        "sorted": {"original": sorted_original, "updated": sort(updated_table)},
+        "reordered": {"original": original_table, "sorted": sorted_original},
+    }
+    results = compare_formats_tables([fmt], tables, directory)
+    return sorted(results, key=lambda r: list(tables).index(r.group))
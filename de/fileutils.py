@@ -1,7 +1,11 @@
+import ctypes
+import fnmatch
 import json
+import struct
 import subprocess
 import os
 from pathlib import Path
+import sys
 
 
 def checkout_file_revisions(
@@ -74,3 +78,266 @@ def get_page_chunk_sizes(paths):
                 for page in column["pages"]:
                     if page["page_type"].startswith("data"):
                         yield page["uncompressed_bytes"], page["num_values"]
+
+
+def resolve_paths(
+    paths, follow_symlinks=True, dedupe_hardlinks=False, canonicalize=False
+) -> list[Path]:
+    """
+    Apply the symlink, hard link and canonicalization policy to the input paths.
+    Symlinks are skipped unless follow_symlinks is set, later links to an already
+    seen inode are dropped with dedupe_hardlinks, and canonicalize resolves the
+    real paths and sorts them, so the same tree yields the same report regardless
+    of the symlinked mount point or the argument order.
+    """
+    resolved = []
+    seen = set()
+    for path in map(Path, paths):
+        if path.is_symlink() and not follow_symlinks:
+            continue
+        if dedupe_hardlinks:
+            stat = path.stat()
+            if (stat.st_dev, stat.st_ino) in seen:
+                continue
+            seen.add((stat.st_dev, stat.st_ino))
+        resolved.append(Path(os.path.realpath(path)) if canonicalize else path)
+
+    if canonicalize:
+        resolved.sort(key=lambda p: os.path.normcase(p.as_posix()))
+    return resolved
+
+
+# FS_IOC_FIEMAP and the extent flags of linux/fiemap.h
+_FS_IOC_FIEMAP = 0xC020660B
+_FIEMAP_EXTENT_LAST = 0x1
+_FIEMAP_EXTENT_SHARED = 0x2000
+_FIEMAP_HEADER = struct.Struct("=QQIIII")
+_FIEMAP_EXTENT = struct.Struct("=QQQ16xI12x")
+_FIEMAP_BATCH = 256
+
+
+def _shared_bytes_linux(path) -> int:
+    # extents shared with other files, i.e. reflinks on btrfs, xfs and others
+    import fcntl
+
+    shared, start = 0, 0
+    buffer = bytearray(_FIEMAP_HEADER.size + _FIEMAP_BATCH * _FIEMAP_EXTENT.size)
+    with open(path, "rb") as f:
+        while True:
+            # map the extents from start to the end of the file
+            header = (start, 2**64 - 1 - start, 0, 0, _FIEMAP_BATCH, 0)
+            _FIEMAP_HEADER.pack_into(buffer, 0, *header)
+            fcntl.ioctl(f.fileno(), _FS_IOC_FIEMAP, buffer)
+            mapped = _FIEMAP_HEADER.unpack_from(buffer)[3]
+            if not mapped:
+                return shared
+            for i in range(mapped):
+                offset = _FIEMAP_HEADER.size + i * _FIEMAP_EXTENT.size
+                logical, _, length, flags = _FIEMAP_EXTENT.unpack_from(buffer, offset)
+                if flags & _FIEMAP_EXTENT_SHARED:
+                    shared += length
+                if flags & _FIEMAP_EXTENT_LAST:
+                    return shared
+            start = logical + length
+
+
+# getattrlist(2) request of ATTR_CMNEXT_PRIVATESIZE, the allocated bytes of a
+# file not shared with its APFS clones
+_ATTR_BIT_MAP_COUNT = 5
+_ATTR_CMNEXT_PRIVATESIZE = 0x8
+_FSOPT_ATTR_CMN_EXTENDED = 0x20
+
+
+def _shared_bytes_macos(path, allocated) -> int:
+    libc = ctypes.CDLL(None, use_errno=True)
+    attrlist = struct.pack(
+        "=HH5I", _ATTR_BIT_MAP_COUNT, 0, 0, 0, 0, 0, _ATTR_CMNEXT_PRIVATESIZE
+    )
+    buffer = ctypes.create_string_buffer(16)
+    if libc.getattrlist(
+        os.fsencode(path), attrlist, buffer, len(buffer), _FSOPT_ATTR_CMN_EXTENDED
+    ):
+        raise OSError(ctypes.get_errno(), os.strerror(ctypes.get_errno()), path)
+    _, private = struct.unpack_from("=Iq", buffer.raw)
+    return max(allocated - private, 0)
+
+
+def _allocated_bytes_windows(path) -> int:
+    # the allocated size of sparse and NTFS compressed files
+    kernel32 = ctypes.WinDLL("kernel32", use_last_error=True)
+    kernel32.GetCompressedFileSizeW.restype = ctypes.c_uint32
+    high = ctypes.c_uint32()
+    low = kernel32.GetCompressedFileSizeW(str(path), ctypes.byref(high))
+    if low == 0xFFFFFFFF and ctypes.get_last_error():
+        raise ctypes.WinError(ctypes.get_last_error())
+    return high.value << 32 | low
+
+
+def filesystem_sharing(path) -> tuple[int, int]:
+    """
+    Return the (sparse, shared) bytes of a file which the filesystem already
+    avoids storing: the holes of sparse files (which read as zeros) and the
+    blocks shared with clones, i.e. APFS clones on macOS and reflinks on Linux.
+    The clones of Windows ReFS are not detected, and the shared bytes are zero
+    where the filesystem doesn't tell.
+    """
+    stat = os.stat(path)
+    if sys.platform == "win32":
+        allocated = _allocated_bytes_windows(path)
+    else:
+        allocated = stat.st_blocks * 512
+    sparse = max(stat.st_size - allocated, 0)
+    try:
+        if sys.platform == "darwin":
+            shared = _shared_bytes_macos(path, allocated)
+        elif sys.platform.startswith("linux"):
+            shared = _shared_bytes_linux(path)
+        else:
+            shared = 0
+    except OSError:
+        # e.g. filesystems without FIEMAP support like tmpfs
+        shared = 0
+    return sparse, min(shared, allocated)
+
+
+def filesystem_savings(paths) -> dict:
+    """
+    Sum the sparse and clone shared bytes of the files, see filesystem_sharing.
+    Content dedup counts these bytes as savings although the filesystem already
+    stores them once (or not at all), so they are reported separately to avoid
+    double counting them on copy-on-write filesystems.
+    """
+    sparse_bytes, cloned_bytes, files = 0, 0, 0
+    for path in paths:
+        sparse, shared = filesystem_sharing(path)
+        sparse_bytes += sparse
+        cloned_bytes += shared
+        files += bool(sparse or shared)
+    return {"sparse_bytes": sparse_bytes, "cloned_bytes": cloned_bytes, "files": files}
+
+
+class IgnoreRules:
+    """
+    Exclude patterns with .gitignore semantics: blank lines and # comments are
+    skipped, a leading ! re-includes, a trailing / only matches directories, a
+    pattern containing another / is anchored at the root and any other pattern
+    matches a path component at any depth. The last matching pattern wins.
+    """
+
+    def __init__(self, patterns=()):
+        self.rules = []
+        for pattern in patterns:
+            pattern = pattern.strip()
+            if not pattern or pattern.startswith("#"):
+                continue
+            negate = pattern.startswith("!")
+            pattern = pattern.removeprefix("!")
+            dir_only = pattern.endswith("/")
+            pattern = pattern.rstrip("/")
+            anchored = "/" in pattern
+            # anchored patterns are matched component by component so that
+            # their wildcards don't cross a separator
+            pattern = pattern.lstrip("/").split("/") if anchored else pattern
+            self.rules.append((negate, dir_only, anchored, pattern))
+
+    @classmethod
+    def from_file(cls, path) -> "IgnoreRules":
+        return cls(Path(path).read_text().splitlines())
+
+    def ignored(self, path, root=None) -> bool:
+        path = Path(path)
+        if root is not None and path.is_relative_to(root):
+            path = path.relative_to(root)
+        parts = path.as_posix().split("/")
+        result = False
+        for negate, dir_only, anchored, pattern in self.rules:
+            # directory patterns can only match the parents of a file
+            end = len(parts) - 1 if dir_only else len(parts)
+            if anchored:
+                matched = any(
+                    _match_components(parts[: i + 1], pattern) for i in range(end)
+                )
+            else:
+                matched = any(fnmatch.fnmatchcase(c, pattern) for c in parts[:end])
+            if matched:
+                result = not negate
+        return result
+
+
+def _match_components(parts, pattern) -> bool:
+    """Whether the path components match the pattern ones, a ** component
+    matching any number of path components and the others exactly one."""
+    if not pattern:
+        return not parts
+    if pattern[0] == "**":
+        return any(
+            _match_components(parts[i:], pattern[1:]) for i in range(len(parts) + 1)
+        )
+    return bool(parts) and (
+        fnmatch.fnmatchcase(parts[0], pattern[0])
+        and _match_components(parts[1:], pattern[1:])
+    )
+
+
+def filter_paths(
+    paths, rules=None, min_size=None, max_size=None, root=None
+) -> list[Path]:
+    """
+    Drop the paths matching the ignore rules, relative to root if given, and the
+    files smaller than min_size or larger than max_size bytes.
+    """
+    selected = []
+    for path in map(Path, paths):
+        if rules is not None and rules.ignored(path, root):
+            continue
+        size = path.stat().st_size
+        if min_size is not None and size < min_size:
+            continue
+        if max_size is not None and size > max_size:
+            continue
+        selected.append(path)
+    return selected
+
+
+# magic bytes at the start of the file, checked before the extensions
+_FORMAT_MAGIC = [
+    (b"PAR1", "parquet"),
+    (b"ARROW1", "arrow"),
+    (b"SQLite format 3\x00", "sqlite"),
+    (b"PK\x03\x04", "zip"),
+    (b"\x1f\x8b", "gzip"),
+    (b"\x28\xb5\x2f\xfd", "zstd"),
+]
+_FORMAT_EXTENSIONS = {
+    ".parquet": "parquet",
+    ".arrow": "arrow",
+    ".feather": "arrow",
+    ".json": "json",
+    ".jsonl": "json",
+    ".ndjson": "json",
+    ".csv": "csv",
+    ".tsv": "csv",
+    ".txt": "text",
+    ".sqlite": "sqlite",
+    ".db": "sqlite",
+}
+
+
+def detect_format(path) -> str:
+    """
+    Classify a file as parquet, arrow, json, csv, text, sqlite, one of the
+    common compression or archive formats, or binary. The magic bytes take
+    precedence over the extension; files without either are json if they start
+    with { or [ and binary otherwise.
+    """
+    path = Path(path)
+    with path.open("rb") as f:
+        head = f.read(16)
+    for magic, name in _FORMAT_MAGIC:
+        if head.startswith(magic):
+            return name
+    if path.suffix.lower() in _FORMAT_EXTENSIONS:
+        return _FORMAT_EXTENSIONS[path.suffix.lower()]
+    if head.lstrip()[:1] in (b"{", b"["):
+        return "json"
+    return "binary"
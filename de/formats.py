@@ -18,6 +18,14 @@ class CdcParams:
     norm_level: int
 
 
+@dataclass(frozen=True)
+class EncryptionKeys:
+    """Parquet modular encryption keys, columns without a key use the footer key."""
+
+    footer_key: bytes
+    column_keys: tuple[tuple[str, bytes], ...] = ()
+
+
 @dataclass(frozen=True)
 class FileFormat:
     """Base class for file format handlers."""
@@ -54,6 +62,7 @@ class ParquetCpp(FileFormat):
     suffix = "parquet"
     use_cdc: bool | CdcParams
     compression: Optional[str] = None
+    compression_level: Optional[int] = None
     use_dictionary: bool = True
     data_page_size: Optional[int] = None
     row_group_size: Optional[int] = None
@@ -63,6 +72,8 @@ class ParquetCpp(FileFormat):
         parts = []
         if self.compression is not None:
             parts.append(self.compression)
+        if self.compression_level is not None:
+            parts.append(f"l={self.compression_level}")
         if self.use_cdc:
             parts.append("cdc")
         if self.row_group_size is not None:
@@ -86,6 +97,8 @@ class ParquetCpp(FileFormat):
             "use_dictionary": self.use_dictionary,
             "compression": self.compression,
         }
+        if self.compression_level is not None:
+            kwargs["compression_level"] = self.compression_level
         if isinstance(self.use_cdc, CdcParams):
             kwargs["use_content_defined_chunking"] = asdict(self.use_cdc)
         else:
@@ -136,6 +149,7 @@ class ParquetRs(FileFormat):
     suffix = "parquet"
     use_cdc: bool | CdcParams
     compression: Optional[str] = None
+    encryption: Optional[EncryptionKeys] = None
 
     @property
     def paramstem(self) -> str:
@@ -144,24 +158,34 @@ class ParquetRs(FileFormat):
             parts.append(self.compression)
         if self.use_cdc:
             parts.append("cdc")
+        if self.encryption is not None:
+            parts.append("encrypted")
         return " ".join(parts)
 
-    def write(self, name: str, src: pa.Table | Path, directory: Path, **kwargs) -> Path:
+    def _rewrite_kwargs(self) -> dict:
+        kwargs: dict = {"cdc": bool(self.use_cdc), "compression": self.compression}
+        if self.encryption is not None:
+            kwargs["footer_key"] = self.encryption.footer_key
+            kwargs["column_keys"] = dict(self.encryption.column_keys)
+        return kwargs
+
+    def write(
+        self, name: str, src: pa.Table | Path | str, directory: Path, **kwargs
+    ) -> Path:
+        """The src can also be a str path or the URL of a cloud object, e.g.
+        s3://bucket/file.parquet, read with ranged requests."""
         if isinstance(self.use_cdc, CdcParams):
             raise ValueError("CDC parameters are not supported by ParquetRs format.")
 
-        use_cdc = bool(self.use_cdc)
         dest = self.derive_path(name, directory)
-        if isinstance(src, Path):
-            rewrite_to_parquet_rs(
-                str(src), str(dest), cdc=use_cdc, compression=self.compression
-            )
+        kwargs = self._rewrite_kwargs()
+        if isinstance(src, (Path, str)):
+            # a str is passed unchanged, Path would mangle the // of URLs
+            rewrite_to_parquet_rs(str(src), str(dest), **kwargs)
         else:
             with tempfile.NamedTemporaryFile(suffix=".parquet", delete=False) as tmp:
                 pq.write_table(src, tmp.name)
-                rewrite_to_parquet_rs(
-                    tmp.name, str(dest), cdc=use_cdc, compression=self.compression
-                )
+                rewrite_to_parquet_rs(tmp.name, str(dest), **kwargs)
         return dest
 
 
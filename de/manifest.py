@@ -0,0 +1,246 @@
+"""Manifest-driven estimation of remote corpora.
+
+A manifest lists the objects of a corpus with their sizes without reading
+them: an S3 inventory CSV or the JSON tree listing of a Hugging Face Hub
+repository. Sampling rules pick a subset of the objects, only the sampled
+objects are downloaded and estimated, and the dedup ratio of the sample is
+extrapolated to the whole corpus.
+"""
+
+import csv
+from dataclasses import dataclass
+import fnmatch
+import hashlib
+import json
+import os
+from pathlib import Path
+import shutil
+import tempfile
+from urllib.parse import quote, unquote
+from urllib.request import Request, urlopen
+
+from .estimate import estimate, estimate_labeled
+from .fileutils import IgnoreRules
+from .remote import URLStream
+
+S3_INVENTORY_FIELDS = ("bucket", "key", "size")
+# The Hub or a mirror of it, overridden like huggingface_hub does.
+HUB_ENDPOINT = os.environ.get("HF_ENDPOINT", "https://huggingface.co")
+
+
+@dataclass(frozen=True)
+class ManifestEntry:
+    key: str
+    url: str
+    size: int
+
+
+def read_s3_inventory(path, fields=S3_INVENTORY_FIELDS) -> list[ManifestEntry]:
+    """Read an S3 inventory CSV file, which has no header row; fields names
+    its columns in the order configured for the inventory and must contain
+    bucket, key and size. The keys are URL-encoded in the inventory."""
+    fields = list(fields)
+    bucket, key, size = (fields.index(name) for name in ("bucket", "key", "size"))
+    entries = []
+    with open(path, newline="") as f:
+        for row in csv.reader(f):
+            if not row or not row[size]:
+                continue
+            object_key = unquote(row[key])
+            entries.append(
+                ManifestEntry(
+                    key=object_key,
+                    url=f"s3://{row[bucket]}/{object_key}",
+                    size=int(row[size]),
+                )
+            )
+    return entries
+
+
+def _hub_entries(items, repo_id, revision, repo_type) -> list[ManifestEntry]:
+    prefix = "" if repo_type == "models" else f"{repo_type}/"
+    base = f"{HUB_ENDPOINT}/{prefix}{repo_id}/resolve/{quote(revision)}"
+    return [
+        ManifestEntry(
+            key=item["path"],
+            url=f"{base}/{quote(item['path'])}",
+            size=item.get("lfs", {}).get("size", item["size"]),
+        )
+        for item in items
+        if item.get("type") == "file"
+    ]
+
+
+def read_hub_tree(
+    path, repo_id, revision="main", repo_type="datasets"
+) -> list[ManifestEntry]:
+    """Read the JSON listing of the Hub tree API of a repository, i.e. the
+    response of /api/{repo_type}/{repo_id}/tree/{revision}?recursive=true,
+    keeping the files."""
+    items = json.loads(Path(path).read_text())
+    return _hub_entries(items, repo_id, revision, repo_type)
+
+
+def _auth_headers(token) -> dict:
+    return {"Authorization": f"Bearer {token}"} if token else {}
+
+
+def list_hub_tree(
+    repo_id, revision="main", repo_type="datasets", token=None
+) -> list[ManifestEntry]:
+    """List the files of a Hub repository with the tree API, following the
+    pages of the listing. The token is only needed for private or gated
+    repositories."""
+    url = (
+        f"{HUB_ENDPOINT}/api/{repo_type}/{repo_id}/tree/"
+        f"{quote(revision, safe='')}?recursive=true"
+    )
+    items = []
+    while url:
+        with urlopen(Request(url, headers=_auth_headers(token))) as response:
+            items.extend(json.load(response))
+            # the next page is linked as <url>; rel="next"
+            links = response.headers.get("Link", "")
+        url = next(
+            (
+                link.split(";")[0].strip(" <>")
+                for link in links.split(",")
+                if 'rel="next"' in link
+            ),
+            None,
+        )
+    return _hub_entries(items, repo_id, revision, repo_type)
+
+
+@dataclass(frozen=True)
+class SamplingRules:
+    """Which objects of a manifest to read. The include and exclude patterns
+    use .gitignore semantics on the keys, the fraction samples the objects by
+    the hash of their key so repeated runs with the same seed read the same
+    objects, and max_files and max_bytes cap the sample in manifest order."""
+
+    fraction: float = 1.0
+    max_files: int | None = None
+    max_bytes: int | None = None
+    include: tuple[str, ...] = ()
+    exclude: tuple[str, ...] = ()
+    seed: int = 0
+
+    def _key_hash(self, key: str) -> int:
+        digest = hashlib.blake2b(f"{self.seed}:{key}".encode(), digest_size=8)
+        return int.from_bytes(digest.digest(), "big")
+
+    def sample(self, entries) -> list[ManifestEntry]:
+        include = IgnoreRules(self.include)
+        exclude = IgnoreRules(self.exclude)
+        threshold = self.fraction * 2**64
+        sampled, total = [], 0
+        for entry in entries:
+            if self.include and not include.ignored(entry.key):
+                continue
+            if exclude.ignored(entry.key):
+                continue
+            if self._key_hash(entry.key) >= threshold:
+                continue
+            if self.max_files is not None and len(sampled) >= self.max_files:
+                break
+            if self.max_bytes is not None and total + entry.size > self.max_bytes:
+                continue
+            sampled.append(entry)
+            total += entry.size
+        return sampled
+
+
+def _https_url(url: str) -> str:
+    if url.startswith("s3://"):
+        bucket, _, key = url.removeprefix("s3://").partition("/")
+        return f"https://{bucket}.s3.amazonaws.com/{quote(key)}"
+    return url
+
+
+def fetch(url: str, dest: Path) -> None:
+    """Download an object, s3:// URLs are read with boto3 if it is installed
+    (to use the configured credentials) and anonymously over HTTPS otherwise."""
+    if url.startswith("s3://"):
+        try:
+            import boto3
+        except ImportError:
+            pass
+        else:
+            bucket, _, key = url.removeprefix("s3://").partition("/")
+            boto3.client("s3").download_file(bucket, key, str(dest))
+            return
+    with urlopen(_https_url(url)) as response, open(dest, "wb") as f:
+        shutil.copyfileobj(response, f)
+
+
+def estimate_manifest(entries, rules=SamplingRules(), fetcher=fetch) -> dict:
+    """Estimate the dedup of a remote corpus from the sampled objects of its
+    manifest. The objects are downloaded to a temporary directory one after
+    the other with fetcher(url, dest) and removed after the estimation.
+
+    The corpus_chunk_bytes extrapolate the dedup ratio of the sample to the
+    total size listed in the manifest, which assumes the sample is as
+    redundant as the corpus; cross-object duplicates between sampled and
+    unsampled objects are not seen, so the sample ratio is pessimistic for
+    small fractions.
+    """
+    entries = list(entries)
+    sampled = rules.sample(entries)
+    if not sampled:
+        raise ValueError("The sampling rules didn't select any object")
+    with tempfile.TemporaryDirectory() as tmp:
+        paths = []
+        for i, entry in enumerate(sampled):
+            # keep the suffix since hash lists are recognized by their extension
+            path = Path(tmp) / f"{i:08d}{Path(entry.key).suffix}"
+            fetcher(entry.url, path)
+            paths.append(path)
+        result = estimate(paths)
+    corpus_bytes = sum(entry.size for entry in entries)
+    return {
+        **result,
+        "objects": len(entries),
+        "sampled_objects": len(sampled),
+        "corpus_bytes": corpus_bytes,
+        "corpus_chunk_bytes": round(result["dedup_ratio"] * corpus_bytes),
+        "sampled": [entry.key for entry in sampled],
+    }
+
+
+def estimate_hub_repo(
+    repo_id,
+    revision=None,
+    allow_patterns=None,
+    repo_type="datasets",
+    token=None,
+    **options,
+) -> dict:
+    """Estimate the dedup of the files of a Hub repository at a revision, the
+    main branch by default, streaming them without a local copy.
+
+    The files are listed with the tree API and, if allow_patterns is given, a
+    pattern or a list of them, only the files whose path matches one of them
+    are kept, with the fnmatch semantics of huggingface_hub, e.g. "*.parquet"
+    or "data/train-*". The files are then streamed from the resolve endpoint
+    and chunked like the inputs of estimate_labeled(), labeled with their
+    path in the repository, and the options are the ones of
+    estimate_labeled(). The token, sent to the Hub only, gives access to
+    private and gated repositories.
+    """
+    revision = revision or "main"
+    if isinstance(allow_patterns, str):
+        allow_patterns = [allow_patterns]
+    entries = [
+        entry
+        for entry in list_hub_tree(repo_id, revision, repo_type, token=token)
+        if allow_patterns is None
+        or any(fnmatch.fnmatch(entry.key, pattern) for pattern in allow_patterns)
+    ]
+    if not entries:
+        raise ValueError(f"No files of {repo_id} at {revision} match {allow_patterns}")
+    headers = _auth_headers(token)
+    result = estimate_labeled(
+        [(entry.key, URLStream(entry.url, headers)) for entry in entries], **options
+    )
+    return {**result, "repo_id": repo_id, "revision": revision}
@@ -0,0 +1,83 @@
+"""Remote inputs streamed into the chunker.
+
+The body of an HTTP(S) URL is chunked as it arrives, read in blocks by the
+Rust side like any file-like input, so estimating files hosted on the Hub or
+a CDN doesn't need local storage for them. Objects of cloud object stores,
+s3://, gs:// or az:// URLs, are read by the Rust side itself with ranged
+requests and the credentials of the environment. file:// URLs go through the
+same reader, e.g. to try it out without a cloud account.
+"""
+
+from urllib.parse import urlsplit
+from urllib.request import HTTPRedirectHandler, Request, build_opener
+
+URL_SCHEMES = ("http://", "https://")
+OBJECT_STORE_SCHEMES = (
+    "s3://",
+    "s3a://",
+    "gs://",
+    "az://",
+    "azure://",
+    "abfs://",
+    "abfss://",
+    "file://",
+)
+
+
+class _RedirectHandler(HTTPRedirectHandler):
+    """Drop the credentials of a request redirected to another host, e.g.
+    from the Hub to the presigned URL of its storage, which rejects them."""
+
+    def redirect_request(self, req, fp, code, msg, headers, newurl):
+        request = super().redirect_request(req, fp, code, msg, headers, newurl)
+        moved = urlsplit(newurl).hostname != urlsplit(req.full_url).hostname
+        if request is not None and moved:
+            request.remove_header("Authorization")
+        return request
+
+
+_opener = build_opener(_RedirectHandler)
+
+
+def is_url(path) -> bool:
+    return isinstance(path, str) and path.startswith(URL_SCHEMES)
+
+
+def is_remote(path) -> bool:
+    """Whether the path is an HTTP(S) URL or the URL of a cloud object."""
+    return isinstance(path, str) and path.startswith(URL_SCHEMES + OBJECT_STORE_SCHEMES)
+
+
+class URLStream:
+    """File-like body of a URL, requested on the first read and closed once
+    read to the end, so that a long list of URLs only holds a connection for
+    the ones being chunked."""
+
+    def __init__(self, url, headers=None):
+        self.url = url
+        self.headers = dict(headers or {})
+        self._response = None
+        self._closed = False
+
+    def read(self, size=-1) -> bytes:
+        if self._closed:
+            return b""
+        if self._response is None:
+            request = Request(self.url, headers=self.headers)
+            self._response = _opener.open(request)
+        data = self._response.read(size)
+        if not data:
+            self.close()
+        return data
+
+    def close(self):
+        if self._response is not None:
+            self._response.close()
+            self._response = None
+        self._closed = True
+
+
+def open_input(path):
+    """Return a URLStream for an HTTP(S) URL and the path unchanged otherwise,
+    including the URLs of cloud objects which the Rust side reads."""
+    return URLStream(path) if is_url(path) else path
@@ -0,0 +1,262 @@
+"""Versioned JSON and parquet reports.
+
+A report stores the result rows together with the metadata needed to compare
+reports produced by different tool versions:
+
+    {
+      "format": "de-report",
+      "version": 1,
+      "tool_version": <version of the de package>,
+      "chunker": {"algorithm": <chunker of the run>, "mask": ...,
+                  "min_size": ..., "max_size": ..., "hash": "xxh3-64",
+                  "decompress": true if the inputs were decompressed},
+      "inputs": [{"path": ..., "size": ..., "sha256": ..., "chunks": ...,
+                  "chunk_digest": ...}, ...],
+      "tags": {<key>: <value>, ...},
+      "approximate": <whether any result comes from sampled counts>
+    }
+
+JSON reports hold the metadata and a "results" list, parquet reports hold one
+row per result and the metadata as JSON under the de.report schema metadata
+key. The version is bumped on incompatible changes to either layout; readers
+load every version in SUPPORTED_VERSIONS and reject the others.
+
+The chunk_digest of an input is the sha256 of its chunk hashes in order, as
+little-endian uint64, so verify_report() can tell whether archived data still
+chunks the same without storing every hash. It is computed with the
+chunker parameters the results were estimated with, from the estimate()
+results when they carry it. Reports written before it was
+added only carry the size and sha256 of the inputs.
+"""
+
+from dataclasses import asdict, is_dataclass
+import hashlib
+import json
+from pathlib import Path
+import random
+
+import pyarrow as pa
+import pyarrow.parquet as pq
+
+from .core import __version__, boundaries, chunker_params
+from .formats import FileFormat
+
+REPORT_FORMAT = "de-report"
+REPORT_VERSION = 1
+SUPPORTED_VERSIONS = {1}
+METADATA_KEY = b"de.report"
+
+
+def _digest(path: Path) -> str:
+    sha = hashlib.sha256()
+    with path.open("rb") as f:
+        while block := f.read(1 << 20):
+            sha.update(block)
+    return sha.hexdigest()
+
+
+def chunker_metadata(
+    chunker="gearhash",
+    target_chunk_size=None,
+    min_chunk_size=None,
+    max_chunk_size=None,
+    chunk_mask=None,
+    decompress=False,
+) -> dict:
+    """The chunker parameters the chunker options of estimate() resolve to,
+    as recorded in the reports."""
+    algorithm, mask, min_size, max_size = chunker_params(
+        chunker, target_chunk_size, min_chunk_size, max_chunk_size, chunk_mask
+    )
+    metadata = {
+        "algorithm": algorithm,
+        "mask": mask,
+        "min_size": min_size,
+        "max_size": max_size,
+        "hash": "xxh3-64",
+    }
+    if decompress:
+        metadata["decompress"] = True
+    return metadata
+
+
+def _chunk_digest(path: Path, chunker: dict) -> tuple[int, str]:
+    _, hashes = boundaries(
+        str(path),
+        chunker["algorithm"],
+        min_chunk_size=chunker["min_size"],
+        max_chunk_size=chunker["max_size"],
+        chunk_mask=chunker["mask"],
+        decompress=chunker.get("decompress", False),
+    )
+    return len(hashes) // 8, hashlib.sha256(hashes).hexdigest()
+
+
+def _input(path: Path, chunker: dict, digests: dict) -> dict:
+    entry = {"path": str(path), "size": path.stat().st_size, "sha256": _digest(path)}
+    if str(path) in digests:
+        entry["chunks"], entry["chunk_digest"] = digests[str(path)]
+        return entry
+    try:
+        entry["chunks"], entry["chunk_digest"] = _chunk_digest(path, chunker)
+    except ValueError:
+        # hash lists have no chunk boundaries
+        pass
+    return entry
+
+
+def report_metadata(inputs=(), tags=None, chunker=None, digests=None) -> dict:
+    """Describe the tool, the chunker configuration and the input files, and
+    carry the free-form tags of the run, e.g. an experiment id or the revision
+    of the dataset. The chunker defaults to the default chunker parameters
+    and digests maps input paths to their (chunks, chunk_digest) when they
+    are already known, the other inputs are chunked again."""
+    chunker = chunker if chunker is not None else chunker_metadata()
+    digests = digests or {}
+    return {
+        "format": REPORT_FORMAT,
+        "version": REPORT_VERSION,
+        "tool_version": __version__,
+        "chunker": chunker,
+        "inputs": [_input(path, chunker, digests) for path in map(Path, inputs)],
+        "tags": dict(tags or {}),
+    }
+
+
+def _row(result) -> dict:
+    if not is_dataclass(result):
+        return dict(result)
+    row = asdict(result)
+    if row.get("chunker", {}) is None:
+        # a record built by hand, of the default chunker
+        del row["chunker"]
+    fmt = getattr(result, "format", None)
+    if isinstance(fmt, FileFormat):
+        row["format"] = f"{fmt.name} {fmt.paramstem}".strip()
+    return row
+
+
+def write_report(results, path, inputs=(), tags=None) -> None:
+    """Write the result rows (dicts or dataclasses such as EstimationResult) as
+    a parquet report for .parquet paths and as a JSON report otherwise.
+
+    The chunker of the report is the one recorded in the results of
+    estimate(), which all need the same one, and the chunk digests of the
+    inputs are taken from their files lists when they have them instead of
+    chunking the inputs again.
+    """
+    path = Path(path)
+    rows = [_row(result) for result in results]
+    chunkers = {
+        json.dumps(row.pop("chunker"), sort_keys=True)
+        for row in rows
+        if "chunker" in row
+    }
+    if len(chunkers) > 1:
+        raise ValueError("The results were computed with different chunkers")
+    chunker = json.loads(chunkers.pop()) if chunkers else None
+    digests = {
+        entry["path"]: (entry["chunks"], entry["chunk_digest"])
+        for row in rows
+        for entry in row.get("files") or []
+        if "chunk_digest" in entry
+    }
+    metadata = report_metadata(inputs, tags, chunker, digests)
+    metadata["approximate"] = any(row.get("approximate", False) for row in rows)
+    if path.suffix == ".parquet":
+        table = pa.Table.from_pylist(rows)
+        table = table.replace_schema_metadata({METADATA_KEY: json.dumps(metadata)})
+        pq.write_table(table, path)
+    else:
+        path.write_text(json.dumps({**metadata, "results": rows}, indent=2))
+
+
+def load_report(path) -> tuple[dict, list[dict]]:
+    """Load a report written by write_report() as (metadata, rows).
+
+    Raises ValueError if the file is not a report or its version is not
+    supported by this tool version.
+    """
+    path = Path(path)
+    if path.suffix == ".parquet":
+        table = pq.read_table(path)
+        raw = (table.schema.metadata or {}).get(METADATA_KEY)
+        if raw is None:
+            raise ValueError(f"{path} is not a {REPORT_FORMAT} file")
+        metadata = json.loads(raw)
+        rows = table.to_pylist()
+    else:
+        metadata = json.loads(path.read_text())
+        rows = metadata.pop("results", [])
+
+    if metadata.get("format") != REPORT_FORMAT:
+        raise ValueError(f"{path} is not a {REPORT_FORMAT} file")
+    if metadata.get("version") not in SUPPORTED_VERSIONS:
+        raise ValueError(
+            f"{path} has report version {metadata.get('version')}, "
+            f"supported versions are {sorted(SUPPORTED_VERSIONS)}"
+        )
+    return metadata, rows
+
+
+def _mismatch(entry: dict, path: Path, chunker: dict | None) -> str | None:
+    if not path.is_file():
+        return "missing"
+    if path.stat().st_size != entry["size"]:
+        return f"size {path.stat().st_size} != {entry['size']}"
+    if _digest(path) != entry["sha256"]:
+        return "sha256 mismatch"
+    if "chunk_digest" in entry and chunker is not None:
+        chunks, chunk_digest = _chunk_digest(path, chunker)
+        if chunk_digest != entry["chunk_digest"]:
+            return f"chunks differ ({chunks} chunks, {entry['chunks']} reported)"
+    return None
+
+
+def verify_report(path, fraction=0.1, seed=None, root=None) -> dict:
+    """Check a sample of the inputs of a report against the data on disk.
+
+    A fraction of the inputs (at least one) is drawn at random and each one
+    is compared to its reported size and sha256, then re-chunked with the
+    chunker parameters of the report and compared to its reported chunk
+    digest, detecting silent corruption or drift of archived corpora.
+    Relative input paths are resolved against root, which defaults to the
+    working directory. Nothing is written.
+
+    Returns the number of checked and total inputs, the mismatches as
+    {"path", "reason"} records and whether the chunker of the report can't
+    be reproduced by this tool version, in which case the chunk digests
+    can't be compared and only the contents are checked.
+    """
+    if not 0 < fraction <= 1:
+        raise ValueError(f"The fraction must be in (0, 1], got {fraction}")
+    metadata, _ = load_report(path)
+    inputs = metadata.get("inputs", [])
+    count = min(len(inputs), max(1, round(fraction * len(inputs))))
+    sample = random.Random(seed).sample(inputs, count)
+    chunker = metadata.get("chunker")
+    try:
+        reproduced = chunker_metadata(
+            chunker["algorithm"],
+            min_chunk_size=chunker["min_size"],
+            max_chunk_size=chunker["max_size"],
+            chunk_mask=chunker["mask"],
+            decompress=chunker.get("decompress", False),
+        )
+    except (KeyError, TypeError, ValueError):
+        reproduced = None
+    chunker_changed = reproduced != chunker
+    root = Path(root) if root is not None else Path()
+    mismatches = []
+    for entry in sample:
+        reason = _mismatch(
+            entry, root / entry["path"], None if chunker_changed else chunker
+        )
+        if reason is not None:
+            mismatches.append({"path": entry["path"], "reason": reason})
+    return {
+        "checked": len(sample),
+        "total": len(inputs),
+        "mismatches": mismatches,
+        "chunker_changed": chunker_changed,
+    }
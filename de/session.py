@@ -0,0 +1,208 @@
+"""Persistent sessions serving several teams from one process.
+
+A session keeps the deduplication state of named namespaces in a directory,
+one index per namespace, so that a long-running service can add the inputs
+of several teams as they arrive without mixing their chunk sets, and reopen
+them after a restart. Every namespace may have a quota on its input bytes and
+on its number of inputs, and the overlap of two namespaces tells how much
+storage they would save by sharing their chunks.
+
+    root/
+      session.json        the namespaces, their quotas and chunker options
+      {namespace}.index   the index of every namespace, see Estimator.save()
+"""
+
+from contextlib import ExitStack, contextmanager
+import json
+import os
+from pathlib import Path
+import re
+import threading
+
+from .estimate import Estimator
+from .remote import is_url
+
+SESSION_FILE = "session.json"
+# Namespaces name files of the session directory, so they can't hold a
+# separator or start with a dot.
+NAMESPACE_PATTERN = re.compile(r"[A-Za-z0-9_][A-Za-z0-9_.-]*")
+
+
+class QuotaExceededError(Exception):
+    """Raised when an input would take a namespace over its quota."""
+
+
+class Session:
+    """Namespaces of deduplication state persisted under root.
+
+    The chunker options, the ones of estimate(), apply to every namespace of
+    the session and are fixed when it is created; reopening a session uses
+    the stored ones. The methods can be called from several threads, the
+    inputs of a namespace are added one at a time while the namespaces are
+    independent.
+    """
+
+    def __init__(self, root, **params):
+        self.root = Path(root)
+        self.root.mkdir(parents=True, exist_ok=True)
+        self._lock = threading.Lock()
+        self._estimators = {}
+        self._locks = {}
+        config = self.root / SESSION_FILE
+        if config.exists():
+            state = json.loads(config.read_text())
+            if params and params != state["params"]:
+                raise ValueError(f"{self.root} was created with other chunker options")
+            self.params = state["params"]
+            self.quotas = state["namespaces"]
+        else:
+            self.params = params
+            self.quotas = {}
+            self._write_config()
+
+    def _write_config(self):
+        config = self.root / SESSION_FILE
+        state = {"params": self.params, "namespaces": self.quotas}
+        tmp = config.with_suffix(".tmp")
+        tmp.write_text(json.dumps(state, indent=2, sort_keys=True))
+        os.replace(tmp, config)
+
+    def _index(self, namespace) -> Path:
+        return self.root / f"{namespace}.index"
+
+    def create_namespace(self, namespace, max_bytes=None, max_inputs=None):
+        """Create an empty namespace, limited to max_bytes of inputs and to
+        max_inputs inputs if given."""
+        if not NAMESPACE_PATTERN.fullmatch(namespace):
+            raise ValueError(f"Invalid namespace name {namespace!r}")
+        with self._lock:
+            if namespace in self.quotas:
+                raise ValueError(f"Namespace {namespace} already exists")
+            self.quotas[namespace] = {"max_bytes": max_bytes, "max_inputs": max_inputs}
+            self._write_config()
+
+    def drop_namespace(self, namespace):
+        """Remove a namespace and its index."""
+        with self._namespace(namespace):
+            with self._lock:
+                del self.quotas[namespace]
+                self._estimators.pop(namespace, None)
+                # the threads waiting for it find the namespace gone
+                del self._locks[namespace]
+                self._write_config()
+            self._index(namespace).unlink(missing_ok=True)
+
+    def namespaces(self) -> list[str]:
+        return sorted(self.quotas)
+
+    @contextmanager
+    def _namespace(self, namespace):
+        """Hold the lock of a namespace, raising KeyError if it doesn't
+        exist or was dropped while waiting for it."""
+        with self._lock:
+            if namespace not in self.quotas:
+                raise KeyError(f"No namespace {namespace}")
+            lock = self._locks.setdefault(namespace, threading.Lock())
+        with lock:
+            with self._lock:
+                if self._locks.get(namespace) is not lock:
+                    raise KeyError(f"No namespace {namespace}")
+            yield
+
+    def _estimator(self, namespace) -> Estimator:
+        """The state of a namespace, loaded on first use, with its lock held."""
+        estimator = self._estimators.get(namespace)
+        if estimator is None:
+            index = self._index(namespace)
+            if index.exists():
+                estimator = Estimator(index=index)
+            else:
+                estimator = Estimator(**self.params)
+            self._estimators[namespace] = estimator
+        return estimator
+
+    def _check_quota(self, namespace, estimator, size):
+        quota = self.quotas[namespace]
+        if quota["max_inputs"] is not None and len(estimator) >= quota["max_inputs"]:
+            raise QuotaExceededError(
+                f"Namespace {namespace} is limited to {quota['max_inputs']} inputs"
+            )
+        if quota["max_bytes"] is None:
+            return
+        if size is None:
+            raise QuotaExceededError(
+                f"Namespace {namespace} has a max_bytes quota, which can't be "
+                "checked for inputs of unknown size"
+            )
+        total = estimator.stats()["total_len"]
+        if total + size > quota["max_bytes"]:
+            raise QuotaExceededError(
+                f"Namespace {namespace} is limited to {quota['max_bytes']} bytes, "
+                f"{total} are used"
+            )
+
+    def add_file(self, namespace, path) -> int:
+        """Chunk a file or URL into a namespace, returning its input index
+        in the namespace. QuotaExceededError is raised before reading it if
+        it would exceed the quota, URLs being of unknown size."""
+        size = None if is_url(path) else os.path.getsize(path)
+        with self._namespace(namespace):
+            estimator = self._estimator(namespace)
+            self._check_quota(namespace, estimator, size)
+            return estimator.add_file(path)
+
+    def add_bytes(self, namespace, data, label=None) -> int:
+        """Chunk a buffer or a file-like object into a namespace like
+        Estimator.add_bytes(), file-like objects being of unknown size."""
+        size = None if hasattr(data, "read") else memoryview(data).nbytes
+        with self._namespace(namespace):
+            estimator = self._estimator(namespace)
+            self._check_quota(namespace, estimator, size)
+            return estimator.add_bytes(data, label=label)
+
+    def stats(self, namespace) -> dict:
+        """The stats of the inputs of a namespace along with its quota."""
+        with self._namespace(namespace):
+            stats = self._estimator(namespace).stats()
+        return {**stats, **self.quotas[namespace]}
+
+    def overlap(self, first, second) -> dict:
+        """The unique chunks of the first namespace also found in the second
+        one, and the share of the unique bytes of each namespace they make
+        up, the first_share and second_share."""
+        with ExitStack() as stack:
+            # the locks are taken in name order so two overlaps can't deadlock
+            for name in sorted({first, second}):
+                stack.enter_context(self._namespace(name))
+            a, b = self._estimator(first), self._estimator(second)
+            chunks, shared_bytes, compressed = a.shared(b)
+            a_bytes = a.stats()["chunk_bytes"]
+            b_bytes = b.stats()["chunk_bytes"]
+        return {
+            "shared_chunks": chunks,
+            "shared_bytes": shared_bytes,
+            "shared_compressed_bytes": compressed,
+            "first_share": shared_bytes / a_bytes if a_bytes else 0.0,
+            "second_share": shared_bytes / b_bytes if b_bytes else 0.0,
+        }
+
+    def save(self, namespace=None) -> dict:
+        """Save the index of a namespace, or of every loaded one, returning
+        the save report of every namespace saved, see Estimator.save()."""
+        if namespace is None:
+            with self._lock:
+                names = sorted(self._estimators)
+        else:
+            names = [namespace]
+        reports = {}
+        for name in names:
+            try:
+                with self._namespace(name):
+                    estimator = self._estimators.get(name)
+                    if estimator is not None:
+                        reports[name] = estimator.save(self._index(name))
+            except KeyError:
+                # a loaded namespace dropped since is not saved
+                if namespace is not None:
+                    raise
+        return reports
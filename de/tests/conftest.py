@@ -0,0 +1,12 @@
+import os
+
+import pytest
+
+
+@pytest.fixture
+def duplicate_files(tmp_path):
+    """Two files a and b of the same random bytes, deduplicating by half."""
+    data = os.urandom(256 * 1024)
+    (tmp_path / "a").write_bytes(data)
+    (tmp_path / "b").write_bytes(data)
+    return [str(tmp_path / "a"), str(tmp_path / "b")]
@@ -0,0 +1,83 @@
+import os
+import shutil
+
+import pyarrow as pa
+import pyarrow.parquet as pq
+import pytest
+
+from de.audit import audit, scan_footer, similarity_clusters
+from de.manifest import ManifestEntry
+
+
+@pytest.fixture
+def corpus(tmp_path):
+    root = tmp_path / "corpus"
+    (root / "data").mkdir(parents=True)
+    n = 100_000
+    table = pa.table({"id": range(n), "text": [f"row {i} " * 4 for i in range(n)]})
+    edited = pa.concat_tables([table.slice(0, 1000), table]).combine_chunks()
+    pq.write_table(table, root / "data" / "v1.parquet", compression="snappy")
+    pq.write_table(edited, root / "data" / "v2.parquet", compression="snappy")
+    data = os.urandom(256 * 1024)
+    (root / "a.bin").write_bytes(data)
+    (root / "b.bin").write_bytes(data)
+    (root / "c.jsonl").write_bytes(b'{"a": 1}\n' * 1000)
+    return root
+
+
+class TestScanFooter:
+    def test_parquet(self, corpus):
+        scan = scan_footer(corpus / "data" / "v1.parquet")
+        assert scan.format == "parquet"
+        assert scan.codecs == ("SNAPPY",)
+        assert scan.cdc_confidence < 0.5
+
+    def test_other_formats(self, corpus):
+        scan = scan_footer(corpus / "c.jsonl")
+        assert (scan.format, scan.codecs, scan.cdc_confidence) == ("json", (), None)
+
+
+class TestSimilarityClusters:
+    def test_identical_files_cluster(self, corpus):
+        paths = [corpus / "a.bin", corpus / "c.jsonl", corpus / "b.bin"]
+        clusters = similarity_clusters(paths)
+        assert clusters == [[str(corpus / "a.bin"), str(corpus / "b.bin")]]
+
+
+class TestAudit:
+    def test_directory(self, corpus):
+        result = audit(corpus)
+        assert len(result.files) == 5
+        assert result.estimate["numfiles"] == 5
+        assert [str(corpus / "a.bin"), str(corpus / "b.bin")] in result.clusters
+        [recommendation] = result.recommendations
+        assert recommendation.action == "enable CDC + zstd"
+        assert recommendation.files == [
+            str(corpus / "data" / "v1.parquet"),
+            str(corpus / "data" / "v2.parquet"),
+        ]
+        assert recommendation.saving > 0
+        assert str(recommendation).startswith(
+            "enable CDC + zstd on these 2 files for an estimated "
+        )
+
+    def test_writes_nothing_next_to_the_files(self, corpus):
+        before = sorted(corpus.rglob("*"))
+        first = audit(corpus)
+        assert sorted(corpus.rglob("*")) == before
+        assert audit(corpus).estimate == first.estimate
+
+    def test_repository_paths_are_relative(self, corpus, monkeypatch):
+        keys = ["a.bin", "b.bin", "c.jsonl"]
+        entries = [ManifestEntry(key, f"hub://{key}", 0) for key in keys]
+        monkeypatch.setattr("de.audit.list_hub_tree", lambda *args, **kw: entries)
+
+        def fetch(url, dest):
+            # the previous files are removed once audited
+            assert list(dest.parent.iterdir()) == []
+            shutil.copyfile(corpus / url.removeprefix("hub://"), dest)
+
+        result = audit("org/name", fetcher=fetch)
+        assert [scan.path for scan in result.files] == keys
+        assert result.clusters == [["a.bin", "b.bin"]]
+        assert result.recommendations == []
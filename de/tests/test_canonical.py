@@ -0,0 +1,88 @@
+from decimal import Decimal
+import json
+from pathlib import Path
+
+import numpy as np
+import pyarrow as pa
+import pytest
+
+from de.canonical import serialize
+
+GOLDEN = json.loads((Path(__file__).parent / "golden" / "canonical.json").read_text())
+
+ARRAYS = {
+    "int32": pa.array([1, None, -2], pa.int32()),
+    "uint16": pa.array([65535, 0], pa.uint16()),
+    "float64": pa.array([0.5, float("nan"), -0.0]),
+    "float64_nan_payload": pa.array(
+        np.frombuffer(bytes.fromhex("010000000000f07f"), dtype="<f8")
+    ),
+    "bool": pa.array([True, False, None]),
+    "string": pa.array(["ab", None, ""]),
+    "decimal128": pa.array([Decimal("1.23"), Decimal("-0.01")], pa.decimal128(5, 2)),
+    "timestamp_ms": pa.array([1000, None], pa.timestamp("ms")),
+    "date32": pa.array([2], pa.date32()),
+    "fixed_size_binary": pa.array([b"ab", None], pa.binary(2)),
+    "list_int8": pa.array([[1, 2], None, []], pa.list_(pa.int8())),
+    "fixed_size_list_int8": pa.array([[1, None], None], pa.list_(pa.int8(), 2)),
+    "struct": pa.array(
+        [{"a": 1, "b": "x"}, None, {"a": None, "b": "yz"}],
+        pa.struct([("a", pa.int16()), ("b", pa.string())]),
+    ),
+    "map": pa.array([[("k", 1)]], pa.map_(pa.string(), pa.int8())),
+    "null": pa.array([None, None]),
+}
+
+
+@pytest.mark.parametrize("name", sorted(ARRAYS))
+def test_golden(name):
+    assert serialize(ARRAYS[name]).hex() == GOLDEN[name]
+
+
+def test_chunked_and_sliced():
+    expected = serialize(ARRAYS["int32"])
+    chunked = pa.chunked_array([[1], [None, -2]], pa.int32())
+    assert serialize(chunked) == expected
+    assert serialize(pa.array([7, 1, None, -2], pa.int32())[1:]) == expected
+    structs = pa.array([{"a": 5, "b": "z"}], ARRAYS["struct"].type)
+    sliced = pa.concat_arrays([structs, ARRAYS["struct"]])[1:]
+    assert serialize(sliced) == serialize(ARRAYS["struct"])
+    lists = pa.array([[9], [1, 2], None, []], pa.list_(pa.int8()))[1:]
+    assert serialize(lists) == serialize(ARRAYS["list_int8"])
+
+
+def test_encodings_and_variants():
+    strings = ARRAYS["string"]
+    expected = serialize(strings)
+    assert serialize(strings.cast(pa.large_string())) == expected
+    assert serialize(strings.cast(pa.string_view())) == expected
+    assert serialize(strings.cast(pa.binary())) == expected
+    assert serialize(strings.dictionary_encode()) == expected
+    lists = ARRAYS["list_int8"]
+    assert serialize(lists.cast(pa.large_list(pa.int8()))) == serialize(lists)
+    assert serialize(lists.cast(pa.list_view(pa.int8()))) == serialize(lists)
+    assert serialize(lists.cast(pa.large_list_view(pa.int8()))) == serialize(lists)
+
+
+def test_out_of_order_list_views():
+    values = pa.array([1, 2, 3], pa.int8())
+    views = pa.ListViewArray.from_arrays([1, 0, 0], [2, 1, 3], values)
+    lists = pa.array([[2, 3], [1], [1, 2, 3]], pa.list_(pa.int8()))
+    assert serialize(views) == serialize(lists)
+
+
+def test_gathers_in_slices(monkeypatch):
+    expected = [serialize(array) for array in ARRAYS.values()]
+    monkeypatch.setattr("de.canonical._GATHER_BYTES", 3)
+    assert [serialize(array) for array in ARRAYS.values()] == expected
+
+
+def test_empty():
+    assert serialize(pa.array([], pa.int64())) == b""
+    assert serialize(pa.chunked_array([], pa.string())) == b""
+
+
+def test_unsupported_type():
+    intervals = pa.array([(1, 2, 3)], pa.month_day_nano_interval())
+    with pytest.raises(TypeError, match="Can't serialize"):
+        serialize(intervals)
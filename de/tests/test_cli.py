@@ -1,9 +1,18 @@
+import csv
+import hashlib
+import json
+import os
+import struct
+
 import pyarrow as pa
 import pyarrow.parquet as pq
 import pytest
 from click.testing import CliRunner
+from PIL import Image
 
 from de.cli import cli
+from de.core import chunks
+from de.estimate import estimate
 
 
 @pytest.fixture
@@ -170,3 +179,675 @@ class TestStatsCommand:
         )
         assert result.exit_code == 0, result.output
         assert len(capture_results["results"]) > 0
+
+
+class TestCodecImpactCommand:
+    def test_one_row_per_codec(self, runner, tmp_path, capture_results):
+        pq.write_table(pa.table({"a": list(range(100))}), tmp_path / "v1.parquet")
+        pq.write_table(pa.table({"a": list(range(101))}), tmp_path / "v2.parquet")
+        result = runner.invoke(
+            cli,
+            [
+                "codec-impact",
+                str(tmp_path / "v1.parquet"),
+                str(tmp_path / "v2.parquet"),
+                "--target-dir",
+                str(tmp_path / "out"),
+            ],
+        )
+        assert result.exit_code == 0, result.output
+        rows = capture_results["results"]
+        assert len(rows) == 6
+        assert all(r.group == "codec-sensitivity" for r in rows)
+        assert all(r.numfiles == 2 for r in rows)
+
+
+class TestUpsetCommand:
+    @pytest.fixture
+    def files(self, tmp_path):
+        shared = os.urandom(64 * 1024)
+        for name in ["a", "b", "c"]:
+            (tmp_path / name).write_bytes(shared + os.urandom(64 * 1024))
+        return [str(tmp_path / name) for name in ["a", "b", "c"]]
+
+    def test_json_output(self, runner, tmp_path, files):
+        output = tmp_path / "upset.json"
+        result = runner.invoke(cli, ["upset", *files, "-o", str(output)])
+        assert result.exit_code == 0, result.output
+        rows = json.loads(output.read_text())
+        assert {r["membership"] for r in rows} >= {"100", "010", "001"}
+
+    def test_csv_output(self, runner, tmp_path, files):
+        output = tmp_path / "upset.csv"
+        result = runner.invoke(
+            cli, ["upset", *files, "-o", str(output), "--top-k", "2"]
+        )
+        assert result.exit_code == 0, result.output
+        assert len(output.read_text().splitlines()) == 3
+
+
+class TestShardRenamesCommand:
+    def test_prints_renames(self, runner, tmp_path):
+        data = os.urandom(64 * 1024)
+        for directory in ["old", "new"]:
+            (tmp_path / directory).mkdir()
+        (tmp_path / "old" / "train-00000-of-00001.bin").write_bytes(data)
+        (tmp_path / "new" / "train-00000-of-00002.bin").write_bytes(data)
+        (tmp_path / "new" / "train-00001-of-00002.bin").write_bytes(os.urandom(1024))
+        result = runner.invoke(
+            cli, ["shard-renames", str(tmp_path / "old"), str(tmp_path / "new")]
+        )
+        assert result.exit_code == 0, result.output
+        assert (
+            "Renamed: train-00000-of-00001.bin -> train-00000-of-00002.bin"
+            in result.output
+        )
+        assert "Resharded train: 1 -> 2 shards" in result.output
+        assert "Added: train-00001-of-00002.bin" in result.output
+
+
+class TestSimilarityCommand:
+    def test_prints_matrix(self, runner, tmp_path):
+        for name in ["a", "b"]:
+            (tmp_path / name).write_bytes(os.urandom(64 * 1024))
+        files = [str(tmp_path / name) for name in ["a", "b"]]
+        result = runner.invoke(cli, ["similarity", *files])
+        assert result.exit_code == 0, result.output
+        rows = list(csv.reader(result.output.splitlines()))
+        assert rows == [
+            ["", *files],
+            [files[0], "1.0000", "0.0000"],
+            [files[1], "0.0000", "1.0000"],
+        ]
+
+
+class TestChunkSizesCommand:
+    def test_prints_histogram(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(1024 * 1024))
+        result = runner.invoke(
+            cli,
+            ["chunk-sizes", str(tmp_path / "a"), "--chunker", "fixed", "--bins", "4"],
+        )
+        assert result.exit_code == 0, result.output
+        lines = result.output.splitlines()
+        assert lines[0].startswith("16 unique chunks")
+        assert len(lines) == 5
+
+
+class TestHashOnlyCommand:
+    def test_prints_ratio(self, runner, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        result = runner.invoke(
+            cli, ["hash-only", str(tmp_path / "a"), str(tmp_path / "b")]
+        )
+        assert result.exit_code == 0, result.output
+        assert result.output.startswith("Deduplication ratio: 50.00%")
+
+
+class TestSweepCommand:
+    def test_prints_table(self, runner, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        files = [str(tmp_path / "a"), str(tmp_path / "b")]
+        result = runner.invoke(cli, ["sweep", "-t", "8192", "-t", "65536", *files])
+        assert result.exit_code == 0, result.output
+        lines = result.output.splitlines()
+        assert len(lines) == 2
+        assert all("50.00%" in line for line in lines)
+        result = runner.invoke(cli, ["sweep", "--json", *files])
+        assert result.exit_code == 0, result.output
+        assert len(json.loads(result.output)) == 8
+
+
+class TestLeaderboardCommand:
+    def test_prints_and_dumps_ranking(self, runner, tmp_path):
+        data = os.urandom(256 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        (tmp_path / "c").write_bytes(os.urandom(256 * 1024))
+        files = [str(tmp_path / name) for name in "abc"]
+        result = runner.invoke(cli, ["leaderboard", *files, "--top", "1"])
+        assert result.exit_code == 0, result.output
+        lines = result.output.splitlines()
+        assert len(lines) == 2
+        assert lines[1].endswith(files[1])
+        result = runner.invoke(cli, ["leaderboard", *files, "--json"])
+        assert result.exit_code == 0, result.output
+        ranking = json.loads(result.output)
+        assert [row["path"] for row in ranking["files"]] == [files[1], *files[::2]]
+
+
+class TestRefcountsCommand:
+    def test_prints_and_dumps_report(self, runner, duplicate_files):
+        files = duplicate_files
+        result = runner.invoke(cli, ["refcounts", *files, "--top", "1"])
+        assert result.exit_code == 0, result.output
+        lines = result.output.splitlines()
+        assert lines[-1].endswith(", ".join(files))
+        result = runner.invoke(cli, ["refcounts", *files, "--json"])
+        assert result.exit_code == 0, result.output
+        report = json.loads(result.output)
+        assert report["saved_bytes"] == os.path.getsize(files[0])
+        assert all(chunk["occurrences"] == 2 for chunk in report["top"])
+
+
+class TestMarginalCommand:
+    def test_ranks_directories(self, runner, tmp_path):
+        shared = os.urandom(256 * 1024)
+        (tmp_path / "x").mkdir()
+        (tmp_path / "y").mkdir()
+        (tmp_path / "x" / "a").write_bytes(shared)
+        (tmp_path / "y" / "b").write_bytes(shared + os.urandom(256 * 1024))
+        result = runner.invoke(
+            cli, ["marginal", str(tmp_path / "y"), str(tmp_path / "x")]
+        )
+        assert result.exit_code == 0, result.output
+        lines = result.output.splitlines()
+        assert lines[0].startswith(f"{tmp_path / 'x'}: marginal 0 Bytes")
+        assert lines[1].startswith(str(tmp_path / "y"))
+
+
+class TestPlanCommand:
+    def test_warns_above_limit(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(bytes(2**20))
+        result = runner.invoke(
+            cli, ["plan", str(tmp_path / "a"), "--memory-limit", "1024"]
+        )
+        assert result.exit_code == 0, result.output
+        assert "1 files" in result.output
+        assert "Warning: estimated peak memory" in result.output
+
+
+class TestCacheSimCommand:
+    def test_one_line_per_size(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        result = runner.invoke(
+            cli, ["cache-sim", str(tmp_path / "a"), "-c", "1024", "-c", "1048576"]
+        )
+        assert result.exit_code == 0, result.output
+        assert len(result.output.splitlines()) == 2
+
+
+class TestPackSimCommand:
+    def test_default_block_size(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        result = runner.invoke(cli, ["pack-sim", str(tmp_path / "a")])
+        assert result.exit_code == 0, result.output
+        assert result.output.startswith("65.5 kB blocks")
+
+
+class TestCheckCdcCommand:
+    def test_small_file_is_inconclusive(self, runner, tmp_path):
+        path = tmp_path / "small.parquet"
+        pq.write_table(pa.table({"a": [1, 2, 3]}), path)
+        result = runner.invoke(cli, ["check-cdc", str(path)])
+        assert result.exit_code == 0, result.output
+        assert result.output == f"{path}: too few pages to tell\n"
+
+
+class TestColumnMapsCommand:
+    def test_lists_columns(self, runner, tmp_path):
+        path = tmp_path / "a.parquet"
+        pq.write_table(pa.table({"a": [1, 2, 3], "b": ["x", "y", "z"]}), path)
+        result = runner.invoke(cli, ["column-maps", str(path)])
+        assert result.exit_code == 0, result.output
+        assert result.output == f"{path}: 2 columns\n  0: a\n  1: b\n"
+        assert (tmp_path / "a.parquet.columns.png").exists()
+
+
+class TestMarginalFilters:
+    def test_excluded_files_are_skipped(self, runner, tmp_path, monkeypatch):
+        captured = {}
+
+        def fake_marginal(datasets):
+            captured.update(datasets)
+            return []
+
+        monkeypatch.setattr("de.cli.marginal_contributions", fake_marginal)
+        (tmp_path / "x" / "logs").mkdir(parents=True)
+        (tmp_path / "x" / "data.bin").write_bytes(bytes(100))
+        (tmp_path / "x" / "small.bin").write_bytes(bytes(1))
+        (tmp_path / "x" / "logs" / "run.txt").write_bytes(bytes(100))
+        result = runner.invoke(
+            cli,
+            ["marginal", str(tmp_path / "x"), "--exclude", "logs/", "--min-size", "10"],
+        )
+        assert result.exit_code == 0, result.output
+        assert captured == {str(tmp_path / "x"): [tmp_path / "x" / "data.bin"]}
+
+
+class TestDedupFilters:
+    def test_patterns_apply_below_directories(self, runner, tmp_path):
+        (tmp_path / "x" / "sub").mkdir(parents=True)
+        (tmp_path / "x" / "a.log").write_bytes(os.urandom(1024))
+        (tmp_path / "x" / "sub" / "b.log").write_bytes(os.urandom(1024))
+        (tmp_path / "x" / "c.bin").write_bytes(os.urandom(1024))
+        result = runner.invoke(
+            cli, ["dedup", str(tmp_path / "x"), "--exclude", "/*.log"]
+        )
+        assert result.exit_code == 0, result.output
+        assert "/ 2.0 kB)" in result.output
+
+
+class TestDotPlotCommand:
+    def test_writes_image(self, runner, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(os.urandom(64 * 1024) + data)
+        result = runner.invoke(
+            cli,
+            [
+                "dot-plot",
+                str(tmp_path / "a"),
+                str(tmp_path / "b"),
+                "-o",
+                str(tmp_path / "plot"),
+            ],
+        )
+        assert result.exit_code == 0, result.output
+        assert (tmp_path / "plot.png").exists()
+
+
+class TestExportStoreCommand:
+    def test_json_document(self, runner, tmp_path, duplicate_files):
+        output = tmp_path / "store.json"
+        result = runner.invoke(
+            cli, ["export-store", *duplicate_files, "-o", str(output)]
+        )
+        assert result.exit_code == 0, result.output
+        doc = json.loads(output.read_text())
+        assert doc["format"] == "de-chunk-store"
+        assert doc["version"] == 1
+        sizes = list(map(os.path.getsize, duplicate_files))
+        assert [f["size"] for f in doc["files"]] == sizes
+        assert doc["files"][0]["order"] == doc["files"][1]["order"]
+        assert all(c["seen_in"] == [0, 1] for c in doc["chunks"])
+        assert [c["hash"] for c in doc["chunks"]] == sorted(
+            c["hash"] for c in doc["chunks"]
+        )
+
+    def test_tags(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(64 * 1024))
+        output = tmp_path / "store.json"
+        args = ["export-store", str(tmp_path / "a"), "-o", str(output)]
+        result = runner.invoke(cli, [*args, "--tag", "sha=abc123"])
+        assert result.exit_code == 0, result.output
+        assert json.loads(output.read_text())["tags"] == {"sha": "abc123"}
+
+
+class TestExportManifestCommand:
+    def test_writes_manifest(self, runner, tmp_path, duplicate_files):
+        output = tmp_path / "manifest.jsonl"
+        args = ["export-manifest", *duplicate_files, "-o", str(output)]
+        result = runner.invoke(cli, args)
+        assert result.exit_code == 0, result.output
+        rows = [json.loads(line) for line in output.read_text().splitlines()]
+        assert result.output == f"{output}: {len(rows)} chunks\n"
+        assert [row["file_index"] for row in rows].count(1) == len(rows) // 2
+        assert all(row["first_seen_in"] == 0 for row in rows)
+
+
+class TestDiffCommand:
+    def test_prints_and_dumps_diff(self, runner, tmp_path):
+        data = os.urandom(256 * 1024)
+        (tmp_path / "old").write_bytes(data)
+        (tmp_path / "new").write_bytes(data + os.urandom(256 * 1024))
+        args = ["diff", "--old", str(tmp_path / "old"), "--new", str(tmp_path / "new")]
+        result = runner.invoke(cli, args)
+        assert result.exit_code == 0, result.output
+        assert result.output.startswith("Added ")
+        assert "Upload: " in result.output
+        assert "% of the " in result.output
+        assert result.output.rstrip().endswith("new revision uncompressed")
+        result = runner.invoke(cli, [*args, "--json"])
+        assert result.exit_code == 0, result.output
+        assert json.loads(result.output)["shared_bytes"] > 0
+
+
+class TestReconstructCommand:
+    def test_rebuilds_and_verifies(self, runner, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(os.urandom(10) + data)
+        files = [str(tmp_path / "a"), str(tmp_path / "b")]
+        output = tmp_path / "out"
+        args = ["reconstruct", *files, "-i", "1", "-o", str(output)]
+        result = runner.invoke(cli, args)
+        assert result.exit_code == 0, result.output
+        assert result.output.endswith("identical\n")
+        assert output.read_bytes() == (tmp_path / "b").read_bytes()
+
+
+class TestRetentionCommand:
+    def test_reports_unreferenced_bytes(self, runner, tmp_path):
+        for name in ["a", "b"]:
+            (tmp_path / name).write_bytes(os.urandom(256 * 1024))
+        old, new = tmp_path / "old.json", tmp_path / "new.json"
+        args = ["export-store", str(tmp_path / "a"), "-o"]
+        assert runner.invoke(cli, [*args, str(old), str(tmp_path / "b")]).exit_code == 0
+        result = runner.invoke(cli, [*args, str(new), "--previous", str(old)])
+        assert result.exit_code == 0, result.output
+        result = runner.invoke(cli, ["retention", str(new), "--days", "0"])
+        assert result.exit_code == 0, result.output
+        assert "Unreferenced: 262.1 kB in " in result.output
+
+
+class TestRecommendChunkingCommand:
+    def test_prints_recommendation(self, runner, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        files = [str(tmp_path / "a"), str(tmp_path / "b")]
+        result = runner.invoke(cli, ["recommend-chunking", *files])
+        assert result.exit_code == 0, result.output
+        assert "1 duplicated runs" in result.output
+        assert "Recommended target chunk size: " in result.output
+
+
+class TestDedupTags:
+    def test_tags_in_report_and_images(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        report = tmp_path / "report.json"
+        result = runner.invoke(
+            cli,
+            [
+                "dedup",
+                str(tmp_path / "a"),
+                "--tag",
+                "experiment=e1",
+                "--tag",
+                "revision=v2=final",
+                "--report",
+                str(report),
+            ],
+        )
+        assert result.exit_code == 0, result.output
+        tags = {"experiment": "e1", "revision": "v2=final"}
+        assert json.loads(report.read_text())["tags"] == tags
+        with Image.open(tmp_path / "a.png") as image:
+            assert image.text == tags
+
+    def test_rejects_malformed_tag(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(b"abc")
+        result = runner.invoke(cli, ["dedup", str(tmp_path / "a"), "--tag", "e1"])
+        assert result.exit_code == 2
+        assert "'e1' is not KEY=VALUE" in result.output
+
+
+class TestDedupDeterministic:
+    def test_reruns_are_byte_identical(self, runner, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data[:256 * 1024] + os.urandom(256 * 1024))
+        outputs = []
+        for names in [["a", "b"], ["b", "a"]]:
+            report = tmp_path / "report.json"
+            args = [str(tmp_path / n) for n in names]
+            result = runner.invoke(
+                cli, ["dedup", "--deterministic", "--report", str(report), *args]
+            )
+            assert result.exit_code == 0, result.output
+            outputs.append(
+                [report.read_bytes()]
+                + [(tmp_path / f"{n}.png").read_bytes() for n in ["a", "b", "merged"]]
+            )
+        assert outputs[0] == outputs[1]
+
+    def test_rejects_mtime_attribution(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(b"abc")
+        args = ["dedup", "--deterministic", "--attribution", "mtime"]
+        result = runner.invoke(cli, [*args, str(tmp_path / "a")])
+        assert result.exit_code == 1
+        assert "not deterministic" in result.output
+
+
+class TestDedupCompression:
+    def test_rejects_codec_without_compression(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(b"abc")
+        args = ["dedup", "--no-compression", "--codec", "zstd"]
+        result = runner.invoke(cli, [*args, str(tmp_path / "a")])
+        assert result.exit_code == 1
+        assert "Pass either --no-compression or --codec" in result.output
+
+    def test_no_compression(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(64 * 1024))
+        result = runner.invoke(cli, ["dedup", "--no-compression", str(tmp_path / "a")])
+        assert result.exit_code == 0, result.output
+
+
+class TestDedupMmap:
+    def test_rejects_mmap_with_cache(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(b"abc")
+        args = ["dedup", "--mmap", "--cache", str(tmp_path / "cache")]
+        result = runner.invoke(cli, [*args, str(tmp_path / "a")])
+        assert result.exit_code == 1
+        assert "--mmap and --cache are mutually exclusive" in result.output
+
+
+class TestProfileCommand:
+    def test_compares_ingest_throughput(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        result = runner.invoke(cli, ["profile", "--ingest", str(tmp_path / "a")])
+        assert result.exit_code == 0, result.output
+        assert "reader:" in result.output
+        assert "Buffered reads:" in result.output
+
+
+class TestVerifyReportCommand:
+    def test_detects_changed_input(self, runner, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        report = tmp_path / "report.json"
+        args = ["dedup", str(tmp_path / "a"), "--report", str(report)]
+        assert runner.invoke(cli, args).exit_code == 0
+        verify = ["verify-report", str(report), "--fraction", "1"]
+        result = runner.invoke(cli, verify)
+        assert result.exit_code == 0, result.output
+        assert "Checked 1 of 1 inputs, 0 mismatches" in result.output
+
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        result = runner.invoke(cli, verify)
+        assert result.exit_code == 1
+        assert f"{tmp_path / 'a'}: sha256 mismatch" in result.output
+
+
+class TestExportCaibxCommand:
+    def test_index_layout(self, runner, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        result = runner.invoke(cli, ["export-caibx", str(tmp_path / "a")])
+        assert result.exit_code == 0, result.output
+
+        index = (tmp_path / "a.caibx").read_bytes()
+        size, kind, _, min_size, _, max_size = struct.unpack_from("<6Q", index)
+        assert (size, kind) == (48, 0x96824D9C7B129FF9)
+        assert (min_size, max_size) == (8192, 131072)
+        items = (len(index) - 48 - 16 - 40) // 40
+        assert result.output == f"{tmp_path / 'a'}.caibx: {items} chunks\n"
+        start = 0
+        for i in range(items):
+            end, chunk_id = struct.unpack_from("<Q32s", index, 64 + i * 40)
+            assert chunk_id == hashlib.new("sha512_256", data[start:end]).digest()
+            start = end
+        assert start == len(data)
+        tail = struct.unpack_from("<5Q", index, len(index) - 40)
+        assert tail == (0, 0, 48, 16 + items * 40 + 40, 0x4B4F050E5549ECD1)
+
+
+class TestExportHashesCommand:
+    @pytest.fixture
+    def files(self, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        return [str(tmp_path / "a"), str(tmp_path / "b")]
+
+    def test_plain_hashes(self, runner, files):
+        result = runner.invoke(cli, ["export-hashes", files[0]])
+        assert result.exit_code == 0, result.output
+        lines = open(f"{files[0]}.hashes").read().splitlines()
+        assert result.output == f"{files[0]}.hashes: {len(lines)} chunks\n"
+        hashes = [hash for hash, c in chunks([files[0]]) if c["size"]]
+        assert [line.split()[0] for line in lines] == [f"{h:016x}" for h in hashes]
+
+    def test_keyed_hashes_match_across_parties(self, runner, files):
+        result = runner.invoke(
+            cli, ["export-hashes", *files], env={"DE_HASH_KEY": "secret"}
+        )
+        assert result.exit_code == 0, result.output
+        a, b = (open(f"{path}.hashes").read() for path in files)
+        assert a == b
+        assert len(a.split()[0]) == 64
+        result = estimate([f"{path}.hashes" for path in files])
+        assert result["dedup_ratio"] == pytest.approx(0.5)
+
+        runner.invoke(cli, ["export-hashes", "--key", "other", files[1]])
+        assert open(f"{files[1]}.hashes").read().split()[0] != a.split()[0]
+
+
+class TestFormatPairsCommand:
+    def test_prints_pairs(self, runner, tmp_path):
+        data = os.urandom(256 * 1024)
+        (tmp_path / "a.jsonl").write_bytes(data)
+        (tmp_path / "b.csv").write_bytes(data)
+        result = runner.invoke(
+            cli, ["format-pairs", str(tmp_path / "a.jsonl"), str(tmp_path / "b.csv")]
+        )
+        assert result.exit_code == 0, result.output
+        assert result.output.startswith("csv <-> json: ")
+        assert result.output.endswith("(100.00%)\n")
+
+
+class TestAuditCommand:
+    def test_directory(self, runner, tmp_path):
+        data = os.urandom(256 * 1024)
+        (tmp_path / "a.bin").write_bytes(data)
+        (tmp_path / "b.bin").write_bytes(data)
+        result = runner.invoke(cli, ["audit", str(tmp_path)])
+        assert result.exit_code == 0, result.output
+        assert result.output.startswith("Files: 2 (2 binary)\n")
+        assert f"Cluster 1: {tmp_path / 'a.bin'}, {tmp_path / 'b.bin'}\n" in result.output
+        assert result.output.endswith("No recommendations\n")
+
+
+class TestDedupThreshold:
+    def test_passes(self, runner, duplicate_files):
+        args = ["dedup", "--fail-if-dedup-below", "0.4", *duplicate_files]
+        result = runner.invoke(cli, args)
+        assert result.exit_code == 0, result.output
+
+    def test_fails_below_threshold(self, runner, duplicate_files):
+        args = ["dedup", "--fail-if-dedup-below", "0.6", *duplicate_files]
+        result = runner.invoke(cli, args)
+        assert result.exit_code == 1
+        assert "below the required 60.00%" in result.output
+
+
+class TestDedupFilesystemSavings:
+    def test_reports_sparse_bytes(self, runner, tmp_path):
+        path = tmp_path / "sparse.bin"
+        with open(path, "wb") as f:
+            f.seek(16 * 1024 * 1024)
+            f.write(b"x")
+        result = runner.invoke(cli, ["dedup", str(path)])
+        assert result.exit_code == 0, result.output
+        assert "Already deduplicated by the filesystem: " in result.output
+        assert "in 1 files" in result.output
+
+
+class TestDedupDetectShifts:
+    def test_prints_shift_tolerant_ratio(self, runner, tmp_path):
+        data = os.urandom(5000)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(os.urandom(7) + data)
+        files = [str(tmp_path / "a"), str(tmp_path / "b")]
+        result = runner.invoke(cli, ["dedup", "--detect-shifts", *files])
+        assert result.exit_code == 0, result.output
+        assert "Shift-tolerant deduplication ratio: " in result.output
+
+
+class TestDedupDetectDeltas:
+    def test_prints_delta_compressed_ratio(self, runner, tmp_path):
+        data = bytearray(os.urandom(5000))
+        (tmp_path / "a").write_bytes(data)
+        data[2500] ^= 0xFF
+        (tmp_path / "b").write_bytes(data)
+        files = [str(tmp_path / "a"), str(tmp_path / "b")]
+        result = runner.invoke(cli, ["dedup", "--detect-deltas", *files])
+        assert result.exit_code == 0, result.output
+        assert "Delta-compressed deduplication ratio: " in result.output
+        assert "on 1 near-duplicate chunks" in result.output
+
+
+class TestDedupMaxTrackedChunks:
+    def test_warns_and_marks_report(self, runner, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        report = tmp_path / "report.json"
+        result = runner.invoke(
+            cli,
+            [
+                "dedup",
+                str(tmp_path / "a"),
+                str(tmp_path / "b"),
+                "--max-tracked-chunks",
+                "1",
+                "--report",
+                str(report),
+            ],
+        )
+        assert result.exit_code == 0, result.output
+        assert "Warning: More than 1 unique chunks" in result.output
+        assert json.loads(report.read_text())["approximate"] is True
+        assert not (tmp_path / "a.png").exists()
+
+
+class TestDedupPathPolicy:
+    @pytest.fixture
+    def captured_paths(self, monkeypatch):
+        captured = []
+
+        def fake_estimate(paths, **kwargs):
+            captured.extend(paths)
+            return {
+                "dedup_ratio": 1.0,
+                "chunk_bytes": 1,
+                "total_len": 1,
+                "xet_dedup_ratio": 1.0,
+                "xet_bytes": 1,
+                "warnings": [],
+            }
+
+        monkeypatch.setattr("de.cli.estimate", fake_estimate)
+        return captured
+
+    @pytest.fixture
+    def tree(self, tmp_path):
+        (tmp_path / "b").write_bytes(b"b")
+        (tmp_path / "a").write_bytes(b"a")
+        os.link(tmp_path / "a", tmp_path / "a-link")
+        (tmp_path / "a-sym").symlink_to(tmp_path / "a")
+        return tmp_path
+
+    def run(self, runner, tree, *options):
+        names = ["b", "a", "a-link", "a-sym"]
+        result = runner.invoke(
+            cli, ["dedup", *options, *(str(tree / n) for n in names)]
+        )
+        assert result.exit_code == 0, result.output
+
+    def test_skip_symlinks(self, runner, tree, captured_paths):
+        self.run(runner, tree, "--skip-symlinks")
+        assert [p.name for p in captured_paths] == ["b", "a", "a-link"]
+
+    def test_dedupe_hardlinks(self, runner, tree, captured_paths):
+        self.run(runner, tree, "--dedupe-hardlinks")
+        assert [p.name for p in captured_paths] == ["b", "a"]
+
+    def test_canonicalize(self, runner, tree, captured_paths):
+        self.run(runner, tree, "--canonicalize")
+        real = tree.resolve()
+        assert captured_paths == [real / "a", real / "a", real / "a-link", real / "b"]
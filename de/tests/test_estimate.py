@@ -1,15 +1,76 @@
+import gzip
+import io
+import itertools
+import json
+import lzma
+import mmap
+import os
+import random
+import signal
+import tarfile
+import threading
+import time
 from pathlib import Path
 from unittest.mock import patch
 
+import numpy as np
+from PIL import Image
 import pyarrow as pa
 import pyarrow.parquet as pq
 import pytest
 
 from de.estimate import (
+    Estimator,
+    DedupRegressionError,
     estimate,
+    boundaries,
+    cdc_confidence,
+    chunk_size_stats,
+    chunk_size_sweep,
+    chunk_table,
+    chunker_sensitivity,
+    column_maps,
     compare_formats_tables,
     compare_formats,
+    deletion_savings,
+    diff,
+    diff_snapshots,
+    duplication,
+    estimate_against_bloom,
+    estimate_concatenated,
+    estimate_labeled,
+    estimate_with_metric,
+    estimate_delta,
+    estimate_dictionary,
+    estimate_hash_only,
+    estimate_pages,
+    estimate_shifted,
+    estimate_values,
+    experiment,
+    export_bloom,
+    export_manifest,
+    format_pairs,
+    incompressible_warnings,
+    ingest_throughput,
+    intra_chunk_redundancy,
+    leaderboard,
+    marginal_contributions,
+    overlap,
+    packing,
+    plan,
+    reconstruct,
+    recommend_chunk_size,
+    refcounts,
+    retention,
+    row_order_churn,
+    shard_renames,
+    similarity_matrix,
+    simulate_cache,
+    stage_timings,
+    tar_boundaries,
+    upset,
 )
+from de.core import MAX_CHUNK_SIZE, MIN_CHUNK_SIZE, chunks, export_store
 from de.formats import ParquetCpp
 
 
@@ -25,9 +86,9 @@ def edit():
 
 class TestEstimateDe:
     def test_returns_expected_keys(self):
-        with patch("de.estimate._estimate_de", return_value=(300, 150, 75)), patch(
-            "de.estimate._estimate_xet", return_value=0
-        ):
+        with patch(
+            "de.estimate._estimate_de", return_value=((300, 150, 75), (0, 0), [])
+        ), patch("de.estimate._estimate_xet", return_value=0):
             result = estimate([Path("a.parquet")])
         assert result["total_len"] == 300
         assert result["chunk_bytes"] == 150
@@ -35,10 +96,1954 @@ class TestEstimateDe:
 
     def test_converts_paths_to_strings(self):
         with patch(
-            "de.estimate._estimate_de", return_value=(100, 50, 25)
+            "de.estimate._estimate_de", return_value=((100, 50, 25), (0, 0), [])
         ) as mock, patch("de.estimate._estimate_xet", return_value=10):
             estimate([Path("a.parquet"), Path("b.parquet")])
-        mock.assert_called_once_with(["a.parquet", "b.parquet"])
+        mock.assert_called_once_with(["a.parquet", "b.parquet"], size_maps=False)
+
+    def test_per_file_breakdown(self, tmp_path):
+        shared = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(shared)
+        (tmp_path / "b").write_bytes(shared + os.urandom(512 * 1024))
+        (tmp_path / "c").write_bytes(os.urandom(256 * 1024))
+        result = estimate([tmp_path / "a", tmp_path / "b", tmp_path / "c"])
+        a, b, c = result["files"]
+        assert a["path"] == str(tmp_path / "a")
+        assert a["total_bytes"] == 512 * 1024
+        assert a["shared_bytes"] == 0
+        assert b["shared_bytes"] > 0
+        assert a["unique_bytes"] + b["shared_bytes"] == 512 * 1024
+        assert c["unique_bytes"] == c["total_bytes"] == 256 * 1024
+        assert c["compressed_bytes"] > 0
+        assert a["owned_bytes"] == a["total_bytes"]
+        assert b["owned_bytes"] == b["total_bytes"] - b["shared_bytes"]
+        assert c["owned_bytes"] == c["total_bytes"]
+        assert a["unique_bytes"] + b["unique_bytes"] + c["unique_bytes"] <= (
+            result["chunk_bytes"]
+        )
+
+    def test_codecs(self, tmp_path):
+        rng = random.Random(0)
+        lines = (f"{{'id': {rng.random()}, 'label': 'x'}}\n" for _ in range(20_000))
+        (tmp_path / "a").write_text("".join(lines))
+        sizes = {
+            codec: estimate([tmp_path / "a"], codec=codec)["compressed_chunk_bytes"]
+            for codec in ["lz4", "zstd", "gzip", "none"]
+        }
+        assert sizes["none"] == estimate([tmp_path / "a"])["chunk_bytes"]
+        assert sizes["zstd"] < sizes["lz4"] < sizes["none"]
+        assert sizes["gzip"] < sizes["none"]
+        fast = estimate([tmp_path / "a"], codec="zstd", codec_level=1)
+        assert fast["compressed_chunk_bytes"] < sizes["none"]
+
+    def test_invalid_codec(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"data")
+        with pytest.raises(ValueError, match="Unknown codec"):
+            estimate([tmp_path / "a"], codec="lzma")
+        with pytest.raises(ValueError, match="between 0 and 9"):
+            estimate([tmp_path / "a"], codec="gzip", codec_level=12)
+
+    def test_skip_compression(self, tmp_path):
+        data = os.urandom(1 << 20)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data[:1000] + data)
+        paths = [tmp_path / "a", tmp_path / "b"]
+        expected = estimate(paths)
+        result = estimate(paths, compress=False)
+        assert result["compressed_chunk_bytes"] is None
+        assert result["chunk_bytes"] == expected["chunk_bytes"]
+        assert all(f["compressed_bytes"] is None for f in result["files"])
+        for mode in [dict(max_tracked_chunks=1000), dict(index_dir=tmp_path)]:
+            result = estimate(paths, compress=False, **mode)
+            assert result["compressed_chunk_bytes"] is None
+            assert result["chunk_bytes"] == expected["chunk_bytes"]
+        with pytest.raises(ValueError, match="compress=False or a codec"):
+            estimate(paths, compress=False, codec="zstd")
+
+    def test_directories_and_globs(self, tmp_path):
+        data = os.urandom(256 * 1024)
+        (tmp_path / "data" / "b").mkdir(parents=True)
+        (tmp_path / "data" / ".cache").mkdir()
+        (tmp_path / "data" / "b" / "y.parquet").write_bytes(data)
+        (tmp_path / "data" / "x.parquet").write_bytes(data)
+        (tmp_path / "data" / "notes.txt").write_bytes(b"notes")
+        (tmp_path / "data" / ".cache" / "z.parquet").write_bytes(data)
+        result = estimate([tmp_path / "data"])
+        assert [f["path"] for f in result["files"]] == [
+            str(tmp_path / "data" / "b" / "y.parquet"),
+            str(tmp_path / "data" / "notes.txt"),
+            str(tmp_path / "data" / "x.parquet"),
+        ]
+        result = estimate([f"{tmp_path}/data/**/*.parquet"])
+        assert [f["path"] for f in result["files"]] == [
+            str(tmp_path / "data" / "b" / "y.parquet"),
+            str(tmp_path / "data" / "x.parquet"),
+        ]
+        assert result["dedup_ratio"] == 0.5
+        with pytest.raises(FileNotFoundError, match="No files match"):
+            estimate([f"{tmp_path}/data/*.csv"])
+
+    def test_no_warnings(self, tmp_path):
+        # 4 bits per byte, compressible unlike random bytes
+        data = bytes(random.Random(0).choices(range(16), k=256 * 1024))
+        (tmp_path / "a").write_bytes(data)
+        assert estimate([tmp_path / "a"])["warnings"] == []
+
+    def test_warns_about_empty_inputs(self, tmp_path):
+        data = bytes(random.Random(0).choices(range(16), k=256 * 1024))
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(b"")
+        result = estimate([tmp_path / "a", tmp_path / "b"])
+        assert result["warnings"] == [f"{tmp_path / 'b'} is empty"]
+
+    def test_only_empty_inputs(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"")
+        result = estimate([tmp_path / "a"])
+        assert result["warnings"] == [f"{tmp_path / 'a'} is empty"]
+        assert result["dedup_ratio"] == 1.0
+        assert result["xet_dedup_ratio"] == 1.0
+
+    def test_warns_about_incompressible_inputs(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        (tmp_path / "b.zst").write_bytes(b"\x28\xb5\x2f\xfd" + bytes(1000))
+        paths = [tmp_path / "a", tmp_path / "b.zst"]
+        for options in [
+            dict(),
+            dict(max_tracked_chunks=10_000),
+            dict(index_dir=tmp_path),
+            dict(memory_budget=1 << 20),
+        ]:
+            result = estimate(paths, **options)
+            high_entropy, zstd = result["warnings"]
+            assert high_entropy.startswith(
+                f"{tmp_path / 'a'} is likely compressed or encrypted (high entropy"
+            )
+            assert zstd == (
+                f"{tmp_path / 'b.zst'} is likely compressed or encrypted (zstd "
+                "magic bytes), its chunks are counted uncompressed"
+            )
+            assert result["compressed_chunk_bytes"] == result["chunk_bytes"]
+
+    def test_warns_about_chunks_cut_at_max_size(self, tmp_path):
+        (tmp_path / "a").write_bytes(bytes(8 * MAX_CHUNK_SIZE))
+        (warning,) = estimate([tmp_path / "a"])["warnings"]
+        assert "cut at the maximum chunk size" in warning
+
+    def test_writes_size_maps(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        estimate([tmp_path / "a"], size_maps=True)
+        assert (tmp_path / "a.png").exists()
+        assert (tmp_path / "a.sizes.png").exists()
+
+    def test_sidecars(self, tmp_path):
+        shared = os.urandom(256 * 1024)
+        (tmp_path / "a").write_bytes(shared)
+        (tmp_path / "b").write_bytes(os.urandom(256 * 1024) + shared)
+        paths = [tmp_path / "a", tmp_path / "b"]
+        estimate(paths, sidecars=True, image_size=64)
+        sidecar = json.loads((tmp_path / "b.blocks.json").read_text())
+        assert sidecar["image"] == "b.png"
+        assert sidecar["width"] == 64
+        blocks = sidecar["blocks"]
+        assert len(blocks) == 64 * 64 // sidecar["block_height"]
+        assert {b["file"] for b in blocks} == {str(tmp_path / "b")}
+        assert blocks[0]["start"] == 0
+        assert blocks[-1]["end"] == 512 * 1024
+        assert blocks[-1]["owner"] == str(tmp_path / "a")
+        assert blocks[0]["owner"] == str(tmp_path / "b")
+        merged = json.loads((tmp_path / "merged.blocks.json").read_text())
+        assert merged["blocks"][0]["file"] == str(tmp_path / "a")
+        assert merged["blocks"][-1]["file"] == str(tmp_path / "b")
+
+    def test_poster(self, tmp_path):
+        shared = os.urandom(256 * 1024)
+        paths = []
+        for i in range(10):
+            path = tmp_path / f"{i}"
+            path.write_bytes(os.urandom(128 * 1024) + (shared if i % 2 else b""))
+            paths.append(path)
+        estimate(paths, poster=True, image_size=64)
+        assert not (tmp_path / "0.png").exists()
+        assert (tmp_path / "merged.png").exists()
+        with Image.open(tmp_path / "poster.png") as image:
+            assert image.size == (64, 10 * 4)
+            # the files sharing chunks with others come first
+            first, last = image.getpixel((0, 0)), image.getpixel((0, 39))
+            assert first != last
+
+    def test_stream_images(self, tmp_path):
+        for name in ["a", "b", "c"]:
+            (tmp_path / name).write_bytes(os.urandom(256 * 1024))
+        paths = [tmp_path / name for name in ["a", "b", "c"]]
+        streamed = estimate(paths, size_maps=True, self_maps=True, stream_images=True)
+        assert streamed == estimate(paths)
+        for name in ["a", "b", "c"]:
+            assert (tmp_path / f"{name}.png").exists()
+            assert (tmp_path / f"{name}.sizes.png").exists()
+            assert (tmp_path / f"{name}.self.png").exists()
+
+    def test_progress(self, tmp_path):
+        for name in ["a", "b", "c"]:
+            (tmp_path / name).write_bytes(os.urandom(3 * 1024 * 1024))
+        paths = [tmp_path / name for name in ["a", "b", "c"]]
+        for options in [{}, {"mmap": True}, {"cache_path": tmp_path / "cache"}]:
+            calls = []
+
+            def progress(*args):
+                calls.append(args)
+
+            result = estimate(paths, progress=progress, **options)
+            # the Xet pass reads the files a second time
+            assert calls[-1] == (2 * result["total_len"], 6, 6)
+            assert calls == sorted(calls)
+
+    def test_progress_of_bounded_modes(self, tmp_path):
+        for name in ["a", "b", "c"]:
+            (tmp_path / name).write_bytes(os.urandom(3 * 1024 * 1024))
+        paths = [tmp_path / name for name in ["a", "b", "c"]]
+        for options in [
+            dict(max_tracked_chunks=10_000),
+            dict(index_dir=tmp_path),
+            dict(memory_budget=1 << 20),
+        ]:
+            calls = []
+            result = estimate(
+                paths, progress=lambda *args: calls.append(args), **options
+            )
+            assert calls[-1] == (result["total_len"], 3, 3)
+            assert calls == sorted(calls)
+
+    def test_progress_error_aborts(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+
+        def progress(bytes, files, total_files):
+            raise KeyError("progress failed")
+
+        with pytest.raises(KeyError, match="progress failed"):
+            estimate([tmp_path / "a"], progress=progress)
+
+    @pytest.mark.parametrize("mode", ["pipeline", "mmap", "stream_images", "cache"])
+    def test_interrupt(self, tmp_path, mode):
+        options = {
+            "pipeline": {},
+            "mmap": {"mmap": True},
+            "stream_images": {"stream_images": True},
+            "cache": {"cache_path": str(tmp_path / "cache")},
+        }[mode]
+        for name in ["a", "b"]:
+            (tmp_path / name).write_bytes(os.urandom(256 * 1024))
+        calls = []
+
+        def progress(*args):
+            calls.append(args)
+            os.kill(os.getpid(), signal.SIGINT)
+            # leave the main thread the time to notice the signal
+            time.sleep(0.5)
+
+        with pytest.raises(KeyboardInterrupt):
+            estimate([tmp_path / "a", tmp_path / "b"], progress=progress, **options)
+        assert len(calls) == 1
+
+    def test_deterministic(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        result = estimate([tmp_path / "a"], deterministic=True)
+        assert result == estimate([tmp_path / "a"])
+        with pytest.raises(ValueError, match="not deterministic"):
+            estimate([tmp_path / "a"], deterministic=True, attribution="mtime")
+
+    def test_mmap(self, tmp_path):
+        data = os.urandom(3 * 1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data[1000:] + os.urandom(1000))
+        (tmp_path / "empty").write_bytes(b"")
+        paths = [tmp_path / name for name in ["a", "b", "empty"]]
+        for chunker in ["gearhash", "fastcdc"]:
+            mapped = estimate(paths, mmap=True, chunker=chunker)
+            assert mapped == estimate(paths, chunker=chunker)
+        assert (tmp_path / "a.png").exists()
+        with pytest.raises(ValueError, match="either mmap or cache_path"):
+            estimate(paths, mmap=True, cache_path=tmp_path / "cache")
+
+    def test_decompress(self, tmp_path):
+        data = b"".join(
+            b'{"id": %d, "value": "%s"}\n' % (i, os.urandom(16).hex().encode())
+            for i in range(50_000)
+        )
+        half = len(data) // 2
+        (tmp_path / "a.jsonl").write_bytes(data)
+        (tmp_path / "a.jsonl.gz").write_bytes(gzip.compress(data))
+        # concatenated members are one stream
+        members = gzip.compress(data[:half]) + gzip.compress(data[half:])
+        (tmp_path / "b.jsonl.gz").write_bytes(members)
+        (tmp_path / "a.jsonl.xz").write_bytes(lzma.compress(data))
+        paths = sorted(tmp_path.iterdir())
+        raw = estimate(paths)
+        assert raw["total_len"] == sum(path.stat().st_size for path in paths)
+        for options in [
+            dict(),
+            dict(mmap=True),
+            dict(max_tracked_chunks=10_000),
+            dict(index_dir=tmp_path),
+            dict(memory_budget=1 << 20),
+        ]:
+            result = estimate(paths, decompress=True, **options)
+            assert result["total_len"] == 4 * len(data)
+            assert result["chunk_bytes"] == len(data) < raw["chunk_bytes"]
+
+    @pytest.mark.parametrize("image_format,ext", [("webp", "webp"), ("jpeg", "jpg")])
+    def test_image_format(self, tmp_path, image_format, ext):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        estimate([tmp_path / "a"], image_format=image_format, quality=80)
+        assert (tmp_path / f"a.{ext}").exists()
+        assert (tmp_path / f"merged.{ext}").exists()
+
+    def test_thumbnail(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        estimate([tmp_path / "a"], image_size=512, thumbnail_size=64)
+        assert (tmp_path / "a.png").exists()
+        assert (tmp_path / "a.thumb.png").exists()
+        assert (tmp_path / "merged.thumb.png").exists()
+
+    def test_captions_extend_image_height(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        estimate([tmp_path / "a"], captions=True)
+        with Image.open(tmp_path / "a.png") as img:
+            assert img.width == 256
+            assert img.height > 256
+
+    def test_self_map_shows_repeats(self, tmp_path):
+        block = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(block + block)
+        estimate([tmp_path / "a"], self_maps=True)
+        with Image.open(tmp_path / "a.self.png") as img:
+            assert img.getpixel((0, 0)) == (0, 255, 0)
+            assert img.getpixel((128, 200)) == (255, 0, 0)
+
+    def test_cache_reused_for_unchanged_and_appended_files(self, tmp_path):
+        cache = tmp_path / "cache.json"
+        (tmp_path / "a").write_bytes(os.urandom(512 * 1024))
+        (tmp_path / "b").write_bytes(os.urandom(512 * 1024))
+        paths = [tmp_path / "a", tmp_path / "b"]
+        first = estimate(paths, cache_path=cache)
+        assert cache.exists()
+        assert estimate(paths, cache_path=cache) == first
+
+        with open(tmp_path / "b", "ab") as f:
+            f.write(os.urandom(256 * 1024))
+        cached = estimate(paths, cache_path=cache)
+        assert cached == estimate(paths)
+
+
+class TestStageTimings:
+    def test_matches_estimate(self, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data + os.urandom(300 * 1024))
+        (tmp_path / "c.hashes").write_text("aa 100\nbb 200\n")
+        paths = [tmp_path / "a", tmp_path / "b", tmp_path / "c.hashes"]
+        expected = estimate(paths)
+        result = stage_timings(paths, queue_depth=1, workers=8)
+        for key in ["total_len", "chunk_bytes", "compressed_chunk_bytes"]:
+            assert result[key] == expected[key]
+        assert [s["workers"] for s in result["stages"]] == [2, 2, 2, 2, 1]
+        assert [s["stage"] for s in result["stages"]] == [
+            "reader",
+            "chunker",
+            "hasher",
+            "compressor",
+            "aggregator",
+        ]
+        assert all(s["busy"] >= 0 for s in result["stages"])
+
+    def test_missing_file(self, tmp_path):
+        with pytest.raises(FileNotFoundError):
+            stage_timings([tmp_path / "missing"])
+
+
+class TestIngestThroughput:
+    def test_measures_both_reads(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(1024 * 1024))
+        (tmp_path / "b").write_bytes(b"")
+        result = ingest_throughput([tmp_path / "a", tmp_path / "b"])
+        assert result["numfiles"] == 2
+        assert result["total_len"] == 1024 * 1024
+        assert result["buffered_throughput"] > 0
+        assert result["mmap_throughput"] > 0
+
+
+class TestEstimateConcatenated:
+    def test_repeated_member_is_deduplicated(self, tmp_path):
+        data = os.urandom(256 * 1024)
+        (tmp_path / "stream").write_bytes(b"header" + data + data)
+        result = estimate_concatenated(
+            tmp_path / "stream", [6, 6 + len(data)], names=["a", "b"]
+        )
+        assert result["total_len"] == 2 * len(data)
+        assert result["chunk_bytes"] == len(data)
+        assert [m["name"] for m in result["members"]] == ["a", "b"]
+        assert [m["total_bytes"] for m in result["members"]] == [len(data)] * 2
+
+    def test_tar_members(self, tmp_path):
+        data = os.urandom(2 * 1024 * 1024)
+        for name in ["a", "b"]:
+            (tmp_path / name).write_bytes(data)
+        with tarfile.open(tmp_path / "archive.tar", "w") as tar:
+            tar.add(tmp_path / "a", arcname="a")
+            tar.add(tmp_path / "b", arcname="b")
+        boundaries, names, ends = tar_boundaries(tmp_path / "archive.tar")
+        result = estimate_concatenated(
+            tmp_path / "archive.tar", boundaries, names, ends=ends
+        )
+        assert names == ["a", "b"]
+        # the headers and the padding are left out
+        assert result["total_len"] == 2 * len(data)
+        assert result["dedup_ratio"] == 0.5
+
+    def test_chunker_options(self, tmp_path):
+        data = b"".join(b"line %d\n" % i for i in range(100_000))
+        a, b = gzip.compress(data, 1), gzip.compress(data, 9)
+        (tmp_path / "stream").write_bytes(a + b)
+        boundaries = [0, len(a)]
+        raw = estimate_concatenated(tmp_path / "stream", boundaries)
+        assert raw["total_len"] == len(a) + len(b)
+        result = estimate_concatenated(
+            tmp_path / "stream", boundaries, decompress=True, chunker="fixed"
+        )
+        assert result["total_len"] == 2 * len(data)
+        assert result["chunk_bytes"] == len(data)
+        with pytest.raises(ValueError):
+            estimate_concatenated(tmp_path / "stream", boundaries, chunk_mask=0)
+
+    def test_unordered_boundaries(self, tmp_path):
+        (tmp_path / "stream").write_bytes(b"abc")
+        with pytest.raises(ValueError):
+            estimate_concatenated(tmp_path / "stream", [2, 1])
+        with pytest.raises(ValueError):
+            estimate_concatenated(tmp_path / "stream", [0, 1], ends=[2, 3])
+
+
+class TestDedupThreshold:
+    def test_passes_above_threshold(self, duplicate_files):
+        result = estimate(duplicate_files, fail_if_dedup_below=0.4)
+        assert result["dedup_ratio"] == 0.5
+
+    def test_raises_below_threshold(self, duplicate_files):
+        with pytest.raises(DedupRegressionError) as exc:
+            estimate(duplicate_files, fail_if_dedup_below=0.6)
+        assert exc.value.result["dedup_ratio"] == 0.5
+
+
+class TestBoundedEstimate:
+    @pytest.fixture
+    def files(self, tmp_path):
+        data = os.urandom(2 * 1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        return [tmp_path / "a", tmp_path / "b"]
+
+    def test_exact_below_limit(self, files):
+        result = estimate(files, max_tracked_chunks=10_000)
+        assert result["approximate"] is False
+        assert result["sample_rate"] == 1.0
+        assert result["chunk_bytes"] == estimate(files)["chunk_bytes"]
+        assert result["xet_bytes"] is None
+
+    def test_rejects_ignored_options(self, files):
+        for options in [
+            dict(image_format="webp"),
+            dict(attribution="largest"),
+            dict(target_chunk_size=1 << 20),
+        ]:
+            with pytest.raises(ValueError, match="max_tracked_chunks doesn't"):
+                estimate(files, max_tracked_chunks=10_000, **options)
+
+    def test_switches_to_sampling(self, files):
+        with pytest.warns(RuntimeWarning, match="approximate"):
+            result = estimate(files, max_tracked_chunks=2)
+        assert result["approximate"] is True
+        assert result["sample_rate"] < 1
+        assert "approximate" in result["warnings"][-1]
+        assert result["total_len"] == 4 * 1024 * 1024
+        assert result["chunk_bytes"] <= result["total_len"]
+
+
+class TestOnDiskEstimate:
+    def test_matches_in_memory_estimate(self, tmp_path):
+        data = os.urandom(2 * 1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data[:1024 * 1024] + os.urandom(1024 * 1024))
+        files = [tmp_path / "a", tmp_path / "b"]
+        index_dir = tmp_path / "index"
+        index_dir.mkdir()
+        result = estimate(files, index_dir=index_dir, index_partitions=7)
+        expected = estimate(files)
+        for key in ["total_len", "chunk_bytes", "compressed_chunk_bytes"]:
+            assert result[key] == expected[key]
+        assert result["xet_bytes"] is None
+        assert list(index_dir.iterdir()) == []
+
+    def test_rejects_image_options(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"abc")
+        for options in [
+            dict(size_maps=True),
+            dict(cache_path=tmp_path / "cache"),
+            dict(attribution="largest"),
+        ]:
+            with pytest.raises(ValueError, match="index_dir doesn't support"):
+                estimate([tmp_path / "a"], index_dir=tmp_path, **options)
+
+    def test_rejects_sampling(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"abc")
+        with pytest.raises(ValueError, match="only one of"):
+            estimate([tmp_path / "a"], index_dir=tmp_path, max_tracked_chunks=10)
+
+
+class TestSketchedEstimate:
+    @pytest.fixture
+    def files(self, tmp_path):
+        data = os.urandom(4 * 1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data[: 2 * 1024 * 1024] + os.urandom(2 << 20))
+        return [tmp_path / "a", tmp_path / "b"]
+
+    def test_exact_while_filter_is_sparse(self, files):
+        result = estimate(files, memory_budget=1 << 20)
+        assert result["approximate"] is True
+        assert result["error_bound"] < 1e-3
+        assert result["chunk_bytes"] == estimate(files)["chunk_bytes"]
+        assert result["xet_bytes"] is None
+
+    def test_saturated_filter_within_bound(self, tmp_path):
+        hashes = [os.urandom(8).hex() for _ in range(30_000)]
+        (tmp_path / "a.hashes").write_text("".join(f"{h} 100\n" for h in hashes))
+        (tmp_path / "b.hashes").write_text(
+            "".join(f"{h} 100\n" for h in hashes[:10_000])
+        )
+        files = [tmp_path / "a.hashes", tmp_path / "b.hashes"]
+        result = estimate(files, memory_budget=1024)
+        assert result["error_bound"] > 1e-3
+        assert result["total_len"] == 4_000_000
+        exact = 3_000_000
+        assert abs(result["chunk_bytes"] - exact) <= 2 * result["error_bound"] * exact
+
+    def test_rejects_tiny_budget(self, files):
+        with pytest.raises(ValueError, match="at least"):
+            estimate(files, memory_budget=100)
+
+
+class TestHashListInput:
+    def test_hash_lists_are_merged(self, tmp_path):
+        (tmp_path / "a.hashes").write_text("# hash size\naa01 100\nbb02 50\n")
+        (tmp_path / "b.hashes").write_text("AA01,100\n\ncc03,25\n")
+        result = estimate(
+            [tmp_path / "a.hashes", tmp_path / "b.hashes"], size_maps=True
+        )
+        assert result["total_len"] == 275
+        assert result["chunk_bytes"] == 175
+        assert result["xet_bytes"] is None
+        assert sorted(p.name for p in tmp_path.glob("*.png")) == ["merged.png"]
+
+    def test_invalid_record(self, tmp_path):
+        (tmp_path / "a.hashes").write_text("aa01\n")
+        with pytest.raises(OSError):
+            estimate([tmp_path / "a.hashes"])
+
+
+class TestPlan:
+    def test_counts_chunks(self, tmp_path):
+        (tmp_path / "a").write_bytes(bytes(10 * 2**20))
+        (tmp_path / "b.hashes").write_text("0123456789abcdef 1000\n" * 100)
+        result = plan([tmp_path / "a", tmp_path / "b.hashes"], memory_limit=2**40)
+        assert result["numfiles"] == 2
+        assert 100 < result["expected_chunks"] < 300
+        assert result["warnings"] == []
+
+    def test_warns_above_limit(self, tmp_path):
+        (tmp_path / "a").write_bytes(bytes(2**20))
+        result = plan([tmp_path / "a"], store_data=True, memory_limit=2**20)
+        assert result["peak_memory"] > 2 * 2**20
+        assert len(result["warnings"]) == 3
+        assert "memory_budget" in result["warnings"][2]
+
+    def test_takes_the_inputs_and_options_of_estimate(self, tmp_path):
+        (tmp_path / "data").mkdir()
+        (tmp_path / "data" / "a").write_bytes(bytes(2**20))
+        paths = [tmp_path / "data", "https://example.com/b"]
+        result = plan(paths, memory_limit=2**40, target_chunk_size=2**12)
+        assert result["numfiles"] == 2
+        assert result["total_len"] == 2**20
+        assert 200 < result["expected_chunks"] < 256
+        assert len(result["warnings"]) == 1
+        assert "remote" in result["warnings"][0]
+
+    def test_bounds_the_chunk_index(self, tmp_path):
+        (tmp_path / "a").write_bytes(bytes(2**20))
+        exact = plan([tmp_path / "a"], memory_limit=2**20)
+        bounded = plan([tmp_path / "a"], memory_limit=2**20, max_tracked_chunks=1)
+        assert bounded["peak_memory"] < exact["peak_memory"]
+        assert len(bounded["warnings"]) == 1
+        with pytest.raises(ValueError):
+            plan([tmp_path / "a"], max_tracked_chunks=1, chunker="fixed")
+
+
+class TestIncompressibleWarnings:
+    def test_detects_magic_and_entropy(self, tmp_path):
+        (tmp_path / "a.zst").write_bytes(b"\x28\xb5\x2f\xfd" + b"\x00" * 100)
+        (tmp_path / "b.bin").write_bytes(os.urandom(64 * 1024))
+        (tmp_path / "c.txt").write_bytes(b"hello world\n" * 10_000)
+        warnings = incompressible_warnings(
+            [tmp_path / "a.zst", tmp_path / "b.bin", tmp_path / "c.txt"]
+        )
+        assert len(warnings) == 2
+        assert "zstd" in warnings[0]
+        assert "entropy" in warnings[1]
+
+
+class TestEstimatePages:
+    def test_compressed_copies_dedup_after_decompression(self, tmp_path):
+        table = pa.table({"a": list(range(100_000))})
+        pq.write_table(table, tmp_path / "a.parquet", compression="zstd")
+        pq.write_table(table, tmp_path / "b.parquet", compression="snappy")
+        result = estimate_pages([tmp_path / "a.parquet", tmp_path / "b.parquet"])
+        assert result["numfiles"] == 2
+        assert result["dedup_ratio"] <= 0.55
+
+
+class TestEstimateValues:
+    def test_stable_across_layouts(self, tmp_path):
+        table = pa.table(
+            {
+                "id": list(range(100_000)),
+                "name": [f"name {i % 1000}" for i in range(100_000)],
+            }
+        )
+        pq.write_table(table, tmp_path / "a.parquet", compression="zstd")
+        rewritten = table.cast(
+            pa.schema([("id", pa.int64()), ("name", pa.large_string())])
+        )
+        pq.write_table(
+            rewritten,
+            tmp_path / "b.parquet",
+            compression="snappy",
+            use_dictionary=False,
+            row_group_size=7_000,
+        )
+        result = estimate_values([tmp_path / "a.parquet", tmp_path / "b.parquet"])
+        assert result["numfiles"] == 2
+        assert result["numcolumns"] == 4
+        assert result["chunk_bytes"] * 2 == result["total_len"]
+
+
+class TestEstimateShifted:
+    def test_counts_content_at_shifted_boundaries(self, tmp_path):
+        # both files fit in a single chunk, so nothing deduplicates exactly
+        data = os.urandom(5000)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(os.urandom(7) + data)
+        result = estimate_shifted([tmp_path / "a", tmp_path / "b"], window=512)
+        assert result["chunk_bytes"] == result["total_len"] == 10007
+        assert result["shifted_bytes"] == 9 * 512
+        assert result["aligned_dedup_ratio"] == (10007 - 9 * 512) / 10007
+
+    def test_ignores_content_at_the_same_offset(self, tmp_path):
+        data = os.urandom(5000)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data[:4000] + os.urandom(1000))
+        result = estimate_shifted([tmp_path / "a", tmp_path / "b"], window=512)
+        assert result["shifted_bytes"] == 0
+
+    def test_empty_inputs(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"")
+        result = estimate_shifted([tmp_path / "a"])
+        assert result["dedup_ratio"] == result["aligned_dedup_ratio"] == 1.0
+
+
+class TestEstimateDelta:
+    def test_delta_compresses_near_duplicates(self, tmp_path):
+        # both files fit in a single chunk differing by one byte
+        data = bytearray(random.Random(0).randbytes(5000))
+        (tmp_path / "a").write_bytes(data)
+        data[2500] ^= 0xFF
+        (tmp_path / "b").write_bytes(data)
+        result = estimate_delta([tmp_path / "a", tmp_path / "b"])
+        assert result["chunk_bytes"] == result["total_len"] == 10000
+        assert result["similar_chunks"] == 1
+        assert result["similar_bytes"] == 5000
+        assert result["delta_bytes"] < result["compressed_bytes"] / 10
+        assert result["delta_savings"] > 4500
+        assert result["delta_dedup_ratio"] < 0.6
+
+    def test_ignores_unrelated_chunks(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(5000))
+        (tmp_path / "b").write_bytes(os.urandom(5000))
+        result = estimate_delta([tmp_path / "a", tmp_path / "b"])
+        assert result["similar_chunks"] == result["delta_savings"] == 0
+        assert result["delta_dedup_ratio"] == result["dedup_ratio"] == 1.0
+
+
+class TestColumnMaps:
+    def test_changed_column_is_owned_by_new_file(self, tmp_path):
+        stable = list(range(100_000))
+        for name, offset in [("a", 0), ("b", 1_000_000)]:
+            table = pa.table({"x": stable, "y": [v + offset for v in stable]})
+            pq.write_table(table, tmp_path / f"{name}.parquet", use_dictionary=False)
+        paths = [tmp_path / "a.parquet", tmp_path / "b.parquet"]
+        columns = column_maps(paths)
+        assert columns[str(paths[1])] == ["x", "y"]
+        with Image.open(tmp_path / "b.parquet.columns.png") as img:
+            assert img.size == (256, 16)
+            assert img.getpixel((128, 0)) == (0, 255, 0)
+            assert img.getpixel((128, 8)) == (255, 0, 0)
+
+    def test_captions_list_columns(self, tmp_path, table):
+        pq.write_table(table, tmp_path / "a.parquet")
+        column_maps([tmp_path / "a.parquet"], captions=True)
+        with Image.open(tmp_path / "a.parquet.columns.png") as img:
+            assert img.height > 16
+
+
+class TestCdcConfidence:
+    @pytest.fixture
+    def large_table(self):
+        return pa.table({"a": pa.array(range(2**21), pa.int64())})
+
+    def test_cdc_scores_higher(self, tmp_path, large_table):
+        plain = ParquetCpp(use_cdc=False, use_dictionary=False)
+        cdc = ParquetCpp(use_cdc=True, use_dictionary=False)
+        plain_result = cdc_confidence(plain.write("plain", large_table, tmp_path))
+        cdc_result = cdc_confidence(cdc.write("cdc", large_table, tmp_path))
+        assert plain_result["aligned_fraction"] == 1.0
+        assert plain_result["confidence"] < 0.5
+        assert cdc_result["confidence"] > 0.5
+
+    def test_single_page_is_inconclusive(self, tmp_path, table):
+        path = ParquetCpp(use_cdc=False).write("small", table, tmp_path)
+        assert cdc_confidence(path)["confidence"] is None
+
+
+class TestDiffSnapshots:
+    @pytest.fixture
+    def files(self, tmp_path):
+        for name in ["a", "b"]:
+            (tmp_path / name).write_bytes(os.urandom(512 * 1024))
+        (tmp_path / "c").write_bytes((tmp_path / "a").read_bytes())
+        return {name: str(tmp_path / name) for name in ["a", "b", "c"]}
+
+    def test_growth_attributed_to_new_file(self, tmp_path, files):
+        export_store([files["a"]], str(tmp_path / "old.json"))
+        export_store([files["a"], files["b"]], str(tmp_path / "new.cbor"))
+        diff = diff_snapshots(tmp_path / "old.json", tmp_path / "new.cbor")
+        assert diff["added_bytes"] == 512 * 1024
+        assert diff["removed_chunks"] == 0
+        assert diff["increased_chunks"] == 0
+        assert [(f["path"], f["added_bytes"]) for f in diff["files"]] == [
+            (files["a"], 0),
+            (files["b"], 512 * 1024),
+        ]
+
+        diff = diff_snapshots(tmp_path / "new.cbor", tmp_path / "old.json")
+        assert diff["removed_bytes"] == 512 * 1024
+        assert diff["growth_bytes"] == -512 * 1024
+        assert diff["files"][1]["path"] == files["b"]
+        assert diff["files"][1]["removed_bytes"] == 512 * 1024
+
+    def test_refcount_changes(self, tmp_path, files):
+        export_store([files["a"]], str(tmp_path / "old.json"))
+        export_store([files["a"], files["c"]], str(tmp_path / "new.json"))
+        diff = diff_snapshots(tmp_path / "old.json", tmp_path / "new.json")
+        assert diff["added_chunks"] == 0
+        assert diff["increased_chunks"] == len(chunks([files["a"]]))
+        assert diff["referenced_bytes_delta"] == 512 * 1024
+
+    def test_rejects_other_documents(self, tmp_path):
+        (tmp_path / "other.json").write_text('{"format": "other"}')
+        with pytest.raises(ValueError):
+            diff_snapshots(tmp_path / "other.json", tmp_path / "other.json")
+
+    def test_rejects_malformed_documents(self, tmp_path, files):
+        export_store([files["a"]], str(tmp_path / "old.json"))
+        export_store([files["a"], files["b"]], str(tmp_path / "new.json"))
+        document = json.loads((tmp_path / "new.json").read_text())
+        del document["files"][1]
+        (tmp_path / "new.json").write_text(json.dumps(document))
+        with pytest.raises(ValueError, match="first seen in input 1"):
+            diff_snapshots(tmp_path / "old.json", tmp_path / "new.json")
+
+
+class TestDiff:
+    def test_added_removed_and_shared_bytes(self, tmp_path):
+        kept, dropped, new = (os.urandom(512 * 1024) for _ in range(3))
+        (tmp_path / "kept").write_bytes(kept)
+        (tmp_path / "dropped").write_bytes(dropped)
+        (tmp_path / "new").write_bytes(new)
+        old_paths = [tmp_path / "kept", tmp_path / "dropped"]
+        new_paths = [tmp_path / "kept", tmp_path / "new"]
+        result = diff(old_paths, new_paths)
+        assert result["old_total_len"] == result["new_total_len"] == 1024 * 1024
+        assert result["added_bytes"] == result["removed_bytes"] == 512 * 1024
+        assert result["shared_bytes"] == 512 * 1024
+        assert result["upload_ratio"] == 0.5
+        assert result["naive_upload_bytes"] == 1024 * 1024
+        # random data doesn't compress
+        assert result["upload_bytes"] >= result["added_bytes"]
+
+    def test_appended_rows_upload_the_tail(self, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "old").write_bytes(data)
+        (tmp_path / "new").write_bytes(data + os.urandom(64 * 1024))
+        result = diff([tmp_path / "old"], [tmp_path / "new"], codec="none")
+        assert 64 * 1024 <= result["added_bytes"] < 256 * 1024
+        assert result["upload_bytes"] == result["added_bytes"]
+        assert result["removed_chunks"] <= 1
+
+    def test_identical_revisions(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(256 * 1024))
+        result = diff([tmp_path / "a"], [tmp_path / "a"])
+        assert result["added_chunks"] == result["removed_chunks"] == 0
+        assert result["upload_bytes"] == 0
+
+
+class TestEstimateLabeled:
+    def test_mixed_sources(self, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        inputs = [
+            ("blob", data),
+            ("response", io.BytesIO(data)),
+            ("file", tmp_path / "a"),
+            ("other", bytearray(os.urandom(256 * 1024))),
+        ]
+        result = estimate_labeled(inputs)
+        assert result["numfiles"] == 4
+        assert result["total_len"] == 3 * 512 * 1024 + 256 * 1024
+        assert result["chunk_bytes"] == 768 * 1024
+        records = result["inputs"]
+        assert [r["label"] for r in records] == ["blob", "response", "file", "other"]
+        assert records[0]["owned_bytes"] == 512 * 1024
+        assert records[1]["unique_bytes"] == 512 * 1024
+        assert records[1]["owned_bytes"] == 0
+        assert records[3]["owned_bytes"] == 256 * 1024
+
+    def test_image_and_sidecar(self, tmp_path):
+        data = os.urandom(256 * 1024)
+        inputs = [("first", data), ("second", data + os.urandom(256 * 1024))]
+        estimate_labeled(inputs, image_base=tmp_path / "labeled", image_size=64)
+        assert (tmp_path / "labeled.png").exists()
+        sidecar = json.loads((tmp_path / "labeled.blocks.json").read_text())
+        assert {b["file"] for b in sidecar["blocks"]} == {"first", "second"}
+        assert {b["owner"] for b in sidecar["blocks"]} == {"first", "second"}
+
+    def test_progress(self):
+        inputs = [("first", os.urandom(256 * 1024)), ("second", b"")]
+        calls = []
+        estimate_labeled(inputs, progress=lambda *args: calls.append(args))
+        assert calls[-1] == (256 * 1024, 2, 2)
+
+
+class TestEstimateWithMetric:
+    def test_sees_every_unique_chunk_once(self, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data + os.urandom(256 * 1024))
+        seen = {}
+
+        def on_chunk(hash, chunk):
+            assert hash not in seen
+            seen[hash] = len(chunk)
+
+        paths = [tmp_path / "a", tmp_path / "b"]
+        result = estimate_with_metric(paths, on_chunk)
+        assert result == {
+            key: value for key, value in estimate(paths).items() if key in result
+        }
+        assert sum(seen.values()) == result["chunk_bytes"]
+
+    def test_callback_error_aborts(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(4 * 1024 * 1024))
+        (tmp_path / "b").write_bytes(os.urandom(4 * 1024 * 1024))
+        calls = []
+
+        def on_chunk(hash, chunk):
+            calls.append(hash)
+            raise KeyError("metric failed")
+
+        with pytest.raises(KeyError, match="metric failed"):
+            estimate_with_metric([tmp_path / "a", tmp_path / "b"], on_chunk)
+        # the chunking stops at the first exception
+        assert len(calls) == 1
+
+
+class TestEstimateDictionary:
+    def test_dictionary_helps_small_similar_chunks(self, tmp_path):
+        # records sharing most of their structure, cut into small chunks
+        rng = np.random.default_rng(0)
+        words = [os.urandom(8).hex() for _ in range(64)]
+        records = [
+            f'{{"id": {i}, "name": "{rng.choice(words)}", '
+            f'"tags": ["{rng.choice(words)}", "{rng.choice(words)}"]}}\n'
+            for i in range(20000)
+        ]
+        (tmp_path / "a.jsonl").write_text("".join(records))
+        paths = [tmp_path / "a.jsonl"]
+        result = estimate_dictionary(
+            paths, dict_size=16 * 1024, target_chunk_size=4096, codec="zstd"
+        )
+        expected = estimate(paths, target_chunk_size=4096, codec="zstd")
+        for key in ["total_len", "chunk_bytes", "compressed_chunk_bytes"]:
+            assert result[key] == expected[key]
+        assert 0 < result["dictionary_bytes"] <= 16 * 1024
+        assert result["dictionary_total_bytes"] < result["compressed_chunk_bytes"]
+        assert result["dictionary_gain"] > 0
+
+    def test_invalid_level(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(64 * 1024))
+        with pytest.raises(ValueError, match="zstd level"):
+            estimate_dictionary([tmp_path / "a"], level=30)
+
+
+class TestEstimateHashOnly:
+    @pytest.mark.parametrize("chunker", ["gearhash", "fastcdc", "rabin", "fixed"])
+    def test_matches_estimate(self, tmp_path, chunker):
+        block = os.urandom(3 * 1024 * 1024)
+        (tmp_path / "a").write_bytes(block + os.urandom(1024) + block)
+        (tmp_path / "b").write_bytes(block[1000:])
+        (tmp_path / "c").write_bytes(b"")
+        paths = [tmp_path / "a", tmp_path / "b", tmp_path / "c"]
+        result = estimate_hash_only(paths, chunker=chunker)
+        expected = estimate(paths, chunker=chunker)
+        assert result["numfiles"] == 3
+        for key in ["total_len", "chunk_bytes", "dedup_ratio"]:
+            assert result[key] == expected[key]
+        assert "compressed_chunk_bytes" not in result
+
+    def test_empty(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"")
+        result = estimate_hash_only([tmp_path / "a"])
+        assert (result["total_len"], result["chunk_bytes"]) == (0, 0)
+        assert result["dedup_ratio"] == 1.0
+
+
+class TestReconstruct:
+    @pytest.mark.parametrize("chunker", ["gearhash", "fixed"])
+    def test_rebuilds_identical_file(self, tmp_path, chunker):
+        data = os.urandom(2 * 1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data[:1000] + b"x" + data + data[:5000])
+        paths = [tmp_path / "a", tmp_path / "b"]
+        result = reconstruct(paths, 1, tmp_path / "out", chunker=chunker)
+        assert result["verified"]
+        assert result["first_mismatch"] is None
+        assert result["bytes"] == len(data) + 6001
+        assert (tmp_path / "out").read_bytes() == (tmp_path / "b").read_bytes()
+
+    @pytest.mark.skipif(not hasattr(os, "mkfifo"), reason="needs named pipes")
+    def test_reports_mismatch(self, tmp_path):
+        # a pipe whose content changes after it was chunked twice, for the
+        # chunk order and the payloads, so the comparison sees other bytes
+        data = os.urandom(256 * 1024)
+        changed = data[:17] + bytes([data[17] ^ 1]) + data[18:]
+        os.mkfifo(tmp_path / "a")
+
+        def write():
+            for content in itertools.chain([data, data], itertools.repeat(changed)):
+                with open(tmp_path / "a", "wb") as f:
+                    f.write(content)
+
+        threading.Thread(target=write, daemon=True).start()
+        result = reconstruct([tmp_path / "a"], 0, tmp_path / "out")
+        assert not result["verified"]
+        assert result["first_mismatch"] == 17
+        assert (tmp_path / "out").read_bytes() == data
+
+    def test_invalid_index(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"data")
+        with pytest.raises(ValueError, match="No file 1"):
+            reconstruct([tmp_path / "a"], 1, tmp_path / "out")
+
+
+class TestChunkSizeSweep:
+    def test_matches_estimate(self, tmp_path):
+        block = os.urandom(3 * 1024 * 1024)
+        (tmp_path / "a").write_bytes(block + os.urandom(1024) + block)
+        (tmp_path / "b").write_bytes(block[1000:])
+        (tmp_path / "c").write_bytes(b"")
+        paths = [tmp_path / "a", tmp_path / "b", tmp_path / "c"]
+        targets = [8 * 1024, 64 * 1024, 1024 * 1024]
+        records = chunk_size_sweep(paths, targets=targets)
+        assert [r["target_chunk_size"] for r in records] == targets
+        for record in records:
+            expected = estimate(paths, target_chunk_size=record["target_chunk_size"])
+            for key in ["total_len", "chunk_bytes", "dedup_ratio"]:
+                assert record[key] == expected[key]
+        assert records[0]["num_chunks"] > records[-1]["num_chunks"]
+
+    def test_invalid_target(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"data")
+        with pytest.raises(ValueError, match="power of two"):
+            chunk_size_sweep([tmp_path / "a"], targets=[1000])
+
+
+class TestEstimator:
+    def test_matches_estimate(self, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data[:512 * 1024] + os.urandom(512 * 1024))
+        paths = [tmp_path / "a", tmp_path / "b"]
+        estimator = Estimator()
+        assert estimator.stats()["total_len"] == 0
+        assert estimator.add_file(paths[0]) == 0
+        assert estimator.stats()["chunk_bytes"] == len(data)
+        assert estimator.add_file(paths[1]) == 1
+        expected = estimate(paths)
+        stats = estimator.stats()
+        for key in ["numfiles", "total_len", "chunk_bytes", "compressed_chunk_bytes"]:
+            assert stats[key] == expected[key]
+        assert estimator.labels() == list(map(str, paths))
+
+    def test_add_files_matches_add_file(self, tmp_path):
+        shared = os.urandom(256 * 1024)
+        # the large first file is chunked last, so it is merged after the
+        # later ones sharing its chunks
+        (tmp_path / "a").write_bytes(os.urandom(8 * 1024 * 1024) + shared)
+        for name in ["b", "c", "d"]:
+            (tmp_path / name).write_bytes(shared + os.urandom(64 * 1024))
+        paths = [tmp_path / name for name in ["a", "b", "c", "d"]]
+        one_by_one = Estimator()
+        for path in paths:
+            one_by_one.add_file(path)
+        batched = Estimator()
+        batched.add_file(paths[0])
+        assert batched.add_files(paths[1:]) == [1, 2, 3]
+        assert batched.add_files(paths) == [4, 5, 6, 7]
+        for path in paths:
+            one_by_one.add_file(path)
+        assert batched.stats() == one_by_one.stats()
+        assert batched.segments() == one_by_one.segments()
+        assert batched.labels() == one_by_one.labels()
+        offset = 8 * 1024 * 1024 + 1000
+        assert batched.who_has(paths[0], offset) == one_by_one.who_has(
+            paths[0], offset
+        )
+
+    def test_add_files_adds_nothing_on_error(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(64 * 1024))
+        estimator = Estimator()
+        with pytest.raises(FileNotFoundError):
+            estimator.add_files([tmp_path / "a", tmp_path / "missing"])
+        assert len(estimator) == 0
+        assert estimator.stats()["total_len"] == 0
+
+    def test_buffers_and_segments(self, tmp_path):
+        data = os.urandom(512 * 1024)
+        estimator = Estimator(target_chunk_size=16 * 1024)
+        estimator.add_bytes(data, label="first")
+        estimator.add_bytes(np.frombuffer(data, dtype=np.uint32))
+        assert estimator.labels() == ["first", "<buffer 1>"]
+        assert estimator.stats()["chunk_bytes"] == len(data)
+        segments = estimator.segments()
+        assert len(segments) == 2 * len(chunks([data], target_chunk_size=16 * 1024))
+        assert set(segments) == {0}
+
+    def test_save_and_load_index(self, tmp_path):
+        for name in ["a", "b"]:
+            (tmp_path / name).write_bytes(os.urandom(512 * 1024))
+        (tmp_path / "c").write_bytes((tmp_path / "a").read_bytes())
+        paths = [tmp_path / name for name in ["a", "b", "c"]]
+        estimator = Estimator(target_chunk_size=16 * 1024)
+        estimator.add_file(paths[0])
+        estimator.add_file(paths[1])
+        estimator.save(tmp_path / "corpus.idx")
+
+        loaded = Estimator(index=tmp_path / "corpus.idx")
+        assert loaded.labels() == list(map(str, paths[:2]))
+        assert loaded.stats() == estimator.stats()
+        # the chunks are written in hash order whatever the order in memory
+        again = Estimator(target_chunk_size=16 * 1024)
+        again.add_files(paths[:2])
+        again.save(tmp_path / "copy.idx")
+        copy = (tmp_path / "copy.idx").read_bytes()
+        assert copy == (tmp_path / "corpus.idx").read_bytes()
+        assert loaded.add_file(paths[2]) == 2
+        assert loaded.segments() == [0] * len(
+            chunks([str(paths[2])], target_chunk_size=16 * 1024)
+        )
+        expected = estimate(paths, target_chunk_size=16 * 1024)
+        assert loaded.stats()["chunk_bytes"] == expected["chunk_bytes"]
+        assert loaded.stats()["total_len"] == expected["total_len"]
+
+    def test_concurrent_saves_merge(self, tmp_path):
+        paths = []
+        for name in "abcd":
+            (tmp_path / name).write_bytes(os.urandom(256 * 1024))
+            paths.append(tmp_path / name)
+        index = tmp_path / "corpus.idx"
+        first = Estimator(target_chunk_size=16 * 1024)
+        first.add_file(paths[0])
+        assert first.save(index) == {
+            "numfiles": 1,
+            "conflict": False,
+            "merged_inputs": 0,
+        }
+
+        # two workers load the index and save the inputs they added
+        workers = [Estimator(index=index), Estimator(index=index)]
+        workers[0].add_file(paths[1])
+        workers[1].add_file(paths[2])
+        workers[1].add_file(paths[0])
+        assert workers[0].save(index)["merged_inputs"] == 0
+        report = workers[1].save(index)
+        assert report == {"numfiles": 4, "conflict": True, "merged_inputs": 1}
+        assert workers[1].labels() == list(map(str, [*paths[:3], paths[0]]))
+        counts = [
+            len(chunks([str(path)], target_chunk_size=16 * 1024))
+            for path in [paths[2], paths[0]]
+        ]
+        # only the inputs added since loading, attributed in the merged index
+        assert workers[1].segments() == [2] * counts[0] + [0] * counts[1]
+
+        loaded = Estimator(index=index)
+        assert loaded.labels() == workers[1].labels()
+        stats = workers[1].stats()
+        assert stats["conflicts"] == 1
+        assert stats["merged_inputs"] == 1
+        assert loaded.stats() == {**stats, "conflicts": 0, "merged_inputs": 0}
+        expected = estimate([*paths[:3], paths[0]], target_chunk_size=16 * 1024)
+        assert loaded.stats()["total_len"] == expected["total_len"]
+        assert loaded.stats()["chunk_bytes"] == expected["chunk_bytes"]
+        # the first worker is behind and merges its next input
+        workers[0].add_file(paths[3])
+        assert workers[0].save(index)["merged_inputs"] == 2
+        assert workers[0].stats()["conflicts"] == 1
+        assert workers[0].stats()["merged_inputs"] == 2
+        assert len(Estimator(index=index)) == 5
+        assert (tmp_path / "corpus.idx.lock").exists()
+
+    def test_save_rejects_unrelated_index(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(64 * 1024))
+        (tmp_path / "b").write_bytes(os.urandom(64 * 1024))
+        one, other = Estimator(), Estimator()
+        one.add_file(tmp_path / "a")
+        one.save(tmp_path / "one.idx")
+        other.add_file(tmp_path / "b")
+        other.save(tmp_path / "other.idx")
+        loaded = Estimator(index=tmp_path / "one.idx")
+        with pytest.raises(ValueError, match="doesn't extend"):
+            loaded.save(tmp_path / "other.idx")
+        with pytest.raises(ValueError, match="other chunker parameters"):
+            Estimator(chunker="fastcdc").save(tmp_path / "one.idx")
+
+    def test_who_has(self, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(os.urandom(100 * 1024) + data)
+        estimator = Estimator()
+        estimator.add_file(tmp_path / "a")
+        estimator.add_file(tmp_path / "b")
+        found = estimator.who_has(tmp_path / "a", 600_000)
+        assert found["offset"] <= 600_000 < found["offset"] + found["size"]
+        a, b = found["occurrences"]
+        assert (a["index"], a["label"]) == (0, str(tmp_path / "a"))
+        assert (b["index"], b["label"]) == (1, str(tmp_path / "b"))
+        assert a["offset"] == found["offset"]
+        assert b["offset"] == found["offset"] + 100 * 1024
+        assert estimator.who_has(tmp_path / "a", len(data)) is None
+        with pytest.raises(ValueError, match="No input labeled"):
+            estimator.who_has(tmp_path / "c", 0)
+        estimator.save(tmp_path / "corpus.idx")
+        loaded = Estimator(index=tmp_path / "corpus.idx")
+        with pytest.raises(ValueError, match="isn't known"):
+            loaded.who_has(tmp_path / "a", 0)
+
+    def test_export_manifest(self, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(os.urandom(100 * 1024) + data)
+        estimator = Estimator()
+        estimator.add_file(tmp_path / "a")
+        estimator.add_bytes(data, label="c")
+        rows = estimator.export_manifest(tmp_path / "manifest.parquet")
+        table = pq.read_table(tmp_path / "manifest.parquet")
+        assert table.num_rows == rows
+        assert table.column_names == [
+            "hash",
+            "file",
+            "file_index",
+            "offset",
+            "size",
+            "compressed",
+            "first_seen_in",
+        ]
+        file_index = table["file_index"].to_numpy()
+        a, c = (table.filter(pa.array(file_index == i)) for i in [0, 1])
+        assert a["hash"].to_pylist() == c["hash"].to_pylist()
+        assert a["offset"].to_pylist() == c["offset"].to_pylist()
+        assert set(c["file"].to_pylist()) == {"c"}
+        assert set(c["first_seen_in"].to_pylist()) == {0}
+        assert sum(a["size"].to_pylist()) == len(data)
+
+        estimator.export_manifest(tmp_path / "manifest", format="jsonl")
+        lines = (tmp_path / "manifest").read_text().splitlines()
+        first = json.loads(lines[0])
+        assert first["hash"] == f"{table['hash'][0].as_py():016x}"
+        assert first["file"] == str(tmp_path / "a")
+        assert first["offset"] == 0
+        with pytest.raises(ValueError, match="Unknown manifest format"):
+            estimator.export_manifest(tmp_path / "manifest", format="csv")
+
+        paths = [tmp_path / "a", tmp_path / "b"]
+        export_manifest(paths, tmp_path / "files.parquet")
+        files = pq.read_table(tmp_path / "files.parquet")
+        assert files["file"].unique().to_pylist() == list(map(str, paths))
+
+    def test_index_rejects_other_params(self, tmp_path):
+        Estimator(target_chunk_size=16 * 1024).save(tmp_path / "corpus.idx")
+        Estimator(index=tmp_path / "corpus.idx", target_chunk_size=16 * 1024)
+        with pytest.raises(ValueError, match="other chunker parameters"):
+            Estimator(index=tmp_path / "corpus.idx", chunker="fastcdc")
+        (tmp_path / "other.idx").write_bytes(b"not an index")
+        with pytest.raises(ValueError, match="not a chunk index"):
+            Estimator(index=tmp_path / "other.idx")
+        # a corrupt string length fails at the end of the file
+        header = b"DEINDEX\0" + (2).to_bytes(4, "little")
+        (tmp_path / "other.idx").write_bytes(header + (2**32 - 1).to_bytes(4, "little"))
+        with pytest.raises(OSError):
+            Estimator(index=tmp_path / "other.idx")
+
+    def test_streams(self):
+        data = os.urandom(512 * 1024)
+
+        class Trickle:
+            # returns short reads like a network stream
+            def __init__(self, data):
+                self.stream = io.BytesIO(data)
+
+            def read(self, size):
+                return self.stream.read(min(size, 1000))
+
+        estimator = Estimator(target_chunk_size=16 * 1024)
+        estimator.add_bytes(io.BytesIO(data))
+        estimator.add_bytes(Trickle(data), label="trickle")
+        assert estimator.labels() == ["<stream 0>", "trickle"]
+        assert estimator.stats()["total_len"] == 2 * len(data)
+        assert estimator.stats()["chunk_bytes"] == len(data)
+        expected = chunks([data], target_chunk_size=16 * 1024)
+        streamed = chunks([Trickle(data)], target_chunk_size=16 * 1024)
+        assert [h for h, _ in streamed] == [h for h, _ in expected]
+        with pytest.raises(OSError, match="binary mode"):
+            estimator.add_bytes(io.StringIO("text"))
+
+    def test_add_bytes_rejects_paths(self, tmp_path):
+        with pytest.raises(ValueError, match="add_file"):
+            Estimator().add_bytes(str(tmp_path))
+
+
+class TestRetention:
+    DAY = 86400
+    T0 = 1_700_000_000
+
+    @pytest.fixture
+    def files(self, tmp_path):
+        for name in ["a", "b"]:
+            (tmp_path / name).write_bytes(os.urandom(512 * 1024))
+        return {name: str(tmp_path / name) for name in ["a", "b"]}
+
+    def test_unstamped_by_default(self, tmp_path, files):
+        export_store([files["a"]], str(tmp_path / "store.json"))
+        doc = json.loads((tmp_path / "store.json").read_text())
+        assert "retained" not in doc
+        assert all("first_seen" not in c for c in doc["chunks"])
+
+    def test_unreferenced_bytes(self, tmp_path, files):
+        old, new = str(tmp_path / "old.json"), str(tmp_path / "new.cbor")
+        export_store([files["a"], files["b"]], old, timestamp=self.T0)
+        now = self.T0 + 100 * self.DAY
+        export_store([files["a"]], new, previous=old, timestamp=now)
+
+        ages = retention(new, days=90, now=now)
+        assert ages["live_bytes"] == ages["aged_bytes"] == 512 * 1024
+        assert ages["retained_bytes"] == ages["stale_bytes"] == 512 * 1024
+        ages = retention(new, days=120, now=now)
+        assert ages["aged_bytes"] == ages["stale_bytes"] == 0
+        assert ages["retained_bytes"] == 512 * 1024
+
+    def test_first_seen_survives_unreferenced_period(self, tmp_path, files):
+        paths = [str(tmp_path / f"{i}.json") for i in range(3)]
+        export_store([files["b"]], paths[0], timestamp=self.T0)
+        export_store([files["a"]], paths[1], previous=paths[0], timestamp=self.T0 + 1)
+        export_store(
+            [files["a"], files["b"]], paths[2], previous=paths[1], timestamp=self.T0 + 2
+        )
+        doc = json.loads(Path(paths[2]).read_text())
+        assert "retained" not in doc
+        first_seen = {c["hash"]: c["first_seen"] for c in doc["chunks"]}
+        assert sorted(set(first_seen.values())) == [self.T0, self.T0 + 1]
+        assert all(c["last_seen"] == self.T0 + 2 for c in doc["chunks"])
+
+    def test_rejects_other_documents(self, tmp_path, files):
+        (tmp_path / "other.json").write_text('{"format": "other"}')
+        with pytest.raises(ValueError):
+            retention(tmp_path / "other.json")
+        with pytest.raises(ValueError):
+            export_store(
+                [files["a"]],
+                str(tmp_path / "store.json"),
+                previous=str(tmp_path / "other.json"),
+            )
+
+
+class TestBloomExchange:
+    def test_shared_bytes_are_matched(self, tmp_path):
+        shared = os.urandom(512 * 1024)
+        (tmp_path / "ours").write_bytes(shared + os.urandom(512 * 1024))
+        (tmp_path / "theirs").write_bytes(shared)
+        export_bloom([tmp_path / "theirs"], tmp_path / "theirs.bloom", fpp=0.001)
+        result = estimate_against_bloom([tmp_path / "ours"], tmp_path / "theirs.bloom")
+        assert 0.3 < result["shared_ratio"] < 0.7
+
+    def test_invalid_fpp(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"abc")
+        with pytest.raises(ValueError):
+            export_bloom([tmp_path / "a"], tmp_path / "a.bloom", fpp=1.5)
+
+
+class TestLeaderboard:
+    def test_ranks_files_by_duplicate_bytes(self, tmp_path):
+        data = os.urandom(256 * 1024)
+        block = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        (tmp_path / "c").write_bytes(block + block)
+        (tmp_path / "d").write_bytes(os.urandom(256 * 1024))
+        paths = [tmp_path / name for name in "abcd"]
+        result = leaderboard(paths, top=2)
+        expected = estimate(paths)
+        assert result["numfiles"] == 4
+        assert result["total_bytes"] == expected["total_len"]
+        assert result["duplicate_bytes"] == (
+            expected["total_len"] - expected["chunk_bytes"]
+        )
+        first, second = result["files"]
+        assert (first["path"], second["path"]) == (str(paths[2]), str(paths[1]))
+        assert first["intra_file_bytes"] == first["duplicate_bytes"]
+        assert second["inter_file_bytes"] == second["duplicate_bytes"] == len(data)
+        assert [first["rank"], second["rank"]] == [1, 2]
+        assert first["share"] > second["share"] > 0
+        assert second["cumulative_share"] == pytest.approx(1.0)
+
+    def test_no_duplicates(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(64 * 1024))
+        result = leaderboard([tmp_path / "a"])
+        assert result["duplicate_bytes"] == 0
+        assert result["files"][0]["share"] == 0.0
+
+
+class TestRefcounts:
+    def test_distribution_and_top_chunks(self, tmp_path):
+        block = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(block * 4)
+        (tmp_path / "b").write_bytes(block)
+        (tmp_path / "c").write_bytes(os.urandom(256 * 1024))
+        paths = [tmp_path / name for name in "abc"]
+        result = refcounts(paths, top=3)
+        expected = estimate(paths)
+        assert result["numfiles"] == 3
+        assert result["total_bytes"] == expected["total_len"]
+        assert result["saved_bytes"] == expected["total_len"] - expected["chunk_bytes"]
+        assert sum(row["unique_bytes"] for row in result["distribution"]) == (
+            expected["chunk_bytes"]
+        )
+        assert [row["min_occurrences"] for row in result["buckets"]][:2] == [1, 2]
+        assert sum(row["savings_share"] for row in result["buckets"]) == (
+            pytest.approx(1.0)
+        )
+        assert len(result["top"]) == 3
+        occurrences = [chunk["occurrences"] for chunk in result["top"]]
+        assert occurrences == sorted(occurrences, reverse=True)
+        first = result["top"][0]
+        assert first["occurrences"] >= 5
+        assert first["files"] == [str(paths[0]), str(paths[1])]
+        assert first["saved_bytes"] == first["size"] * (first["occurrences"] - 1)
+
+    def test_no_duplicates(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(64 * 1024))
+        result = refcounts([tmp_path / "a"])
+        assert result["saved_bytes"] == 0
+        assert [row["occurrences"] for row in result["distribution"]] == [1]
+        assert result["buckets"][0]["savings_share"] == 0.0
+
+
+class TestDuplication:
+    def test_repeated_file_has_factor_two(self, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        result = duplication([tmp_path / "a", tmp_path / "b"])
+        assert all(b["duplication_factor"] == 2 for b in result["buckets"])
+        assert sum(b["total_bytes"] for b in result["buckets"]) == 2 * len(data)
+        assert [f["duplication_factor"] for f in result["files"]] == [1.0, 1.0]
+
+    def test_intra_and_inter_file_bytes(self, tmp_path):
+        block = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(block + block)
+        (tmp_path / "b").write_bytes(block)
+        (tmp_path / "c").write_bytes(os.urandom(256 * 1024))
+        result = duplication([tmp_path / name for name in "abc"])
+        files = result["files"]
+        assert files[0]["intra_file_bytes"] > 0
+        assert files[0]["inter_file_bytes"] == 0
+        assert files[1]["intra_file_bytes"] == 0
+        assert files[1]["inter_file_bytes"] == files[1]["unique_bytes"]
+        assert files[2]["intra_file_bytes"] == files[2]["inter_file_bytes"] == 0
+        total = sum(f["total_bytes"] for f in files)
+        unique = sum(b["unique_bytes"] for b in result["buckets"])
+        saved = result["intra_file_bytes"] + result["inter_file_bytes"]
+        assert saved == total - unique
+
+    def test_occurrences_exposed_in_chunks(self, tmp_path):
+        block = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(block + block)
+        assert max(c["occurrences"] for _, c in chunks([str(tmp_path / "a")])) == 2
+
+
+class TestBoundaries:
+    def test_matches_chunks(self, tmp_path):
+        path = tmp_path / "a"
+        path.write_bytes(os.urandom(1024 * 1024))
+        offsets, hashes = boundaries(path)
+        # boundaries() leaves out the empty last chunk
+        expected = [(h, c) for h, c in chunks([str(path)]) if c["size"]]
+        assert offsets[-1] == 1024 * 1024
+        assert list(hashes) == [hash for hash, _ in expected]
+        assert list(offsets) == list(
+            itertools.accumulate(chunk["size"] for _, chunk in expected)
+        )
+
+
+class TestChunkBuffers:
+    @pytest.fixture
+    def data(self, tmp_path):
+        data = os.urandom(1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        return data
+
+    def test_buffers_match_files(self, tmp_path, data):
+        expected = [hash for hash, _ in chunks([str(tmp_path / "a")])]
+        array = np.frombuffer(data, dtype=np.float32)
+        with open(tmp_path / "a", "rb") as f, mmap.mmap(
+            f.fileno(), 0, access=mmap.ACCESS_READ
+        ) as mm:
+            for buffer in [data, bytearray(data), array, pa.py_buffer(data), mm]:
+                assert [hash for hash, _ in chunks([buffer])] == expected
+
+    @pytest.mark.parametrize("chunker", ["gearhash", "fastcdc", "rabin", "fixed"])
+    def test_large_buffers_cut_in_parallel(self, tmp_path, chunker):
+        # several ranges cut concurrently, with zeros forcing maximum size
+        # cuts across the range boundaries
+        data = os.urandom(5 * 1024 * 1024) + bytes(3 * 1024 * 1024)
+        data += os.urandom(3 * 1024 * 1024 + 123)
+        (tmp_path / "large").write_bytes(data)
+        options = dict(chunker=chunker, target_chunk_size=16 * 1024)
+        expected = chunks([str(tmp_path / "large")], **options)
+        assert chunks([data], **options) == expected
+
+    def test_mixed_with_paths(self, tmp_path, data):
+        merged = chunks([str(tmp_path / "a"), memoryview(data)])
+        assert all(chunk["seen_in"] == [0, 1] for _, chunk in merged)
+
+    def test_non_contiguous(self, data):
+        array = np.frombuffer(data, dtype=np.uint8)[::2]
+        with pytest.raises(TypeError):
+            chunks([array])
+
+
+class TestChunkParams:
+    @pytest.fixture
+    def path(self, tmp_path):
+        path = tmp_path / "a"
+        path.write_bytes(os.urandom(4 * 1024 * 1024))
+        return str(path)
+
+    def test_target_chunk_size(self, path):
+        default = [chunk["size"] for _, chunk in chunks([path])]
+        large = chunks([path], target_chunk_size=1 << 20)
+        large = [chunk["size"] for _, chunk in large]
+        assert sum(large) == sum(default)
+        assert len(large) < len(default)
+        assert min(large[:-1]) >= 1 << 17
+
+    def test_mask_matches_target(self, path):
+        mask = chunks([path], chunk_mask=0xFFFF000000000000)
+        assert mask == chunks([path]) == chunks([path], target_chunk_size=1 << 16)
+
+    def test_min_chunk_size(self, path):
+        sizes = [chunk["size"] for _, chunk in chunks([path], min_chunk_size=1 << 16)]
+        assert min(sizes[:-1]) >= 1 << 16
+
+    def test_buffers_match_files(self, path):
+        data = Path(path).read_bytes()
+        params = dict(target_chunk_size=1 << 14, min_chunk_size=1024)
+        assert chunks([data], **params) == chunks([path], **params)
+
+    @pytest.mark.parametrize(
+        "params",
+        [
+            dict(target_chunk_size=1000),
+            dict(target_chunk_size=1 << 16, chunk_mask=0xFFFF000000000000),
+            dict(chunk_mask=0),
+            dict(min_chunk_size=1 << 20, max_chunk_size=1 << 16),
+        ],
+    )
+    def test_invalid(self, path, params):
+        with pytest.raises(ValueError):
+            chunks([path], **params)
+
+    def test_estimate(self, tmp_path, path):
+        cache = tmp_path / "cache.json"
+        default = estimate([path], cache_path=cache)
+        large = estimate([path], cache_path=cache, target_chunk_size=1 << 20)
+        assert large == estimate([path], target_chunk_size=1 << 20)
+        assert large["total_len"] == default["total_len"]
+
+
+class TestMaxChunkSize:
+    @pytest.mark.parametrize("byte", [b"\x00", b"\xff"])
+    def test_constant_bytes_are_split(self, tmp_path, byte):
+        path = tmp_path / "constant"
+        path.write_bytes(byte * (10 * MAX_CHUNK_SIZE + 123))
+        [(_, chunk), *rest] = chunks([str(path)])
+        assert chunk["size"] == MAX_CHUNK_SIZE
+        assert chunk["occurrences"] == 10
+        assert [c["size"] for _, c in rest] == [MAX_CHUNK_SIZE] * 9 + [123]
+
+    def test_constant_bytes_estimate(self, tmp_path):
+        path = tmp_path / "constant"
+        path.write_bytes(b"\x00" * (16 * MAX_CHUNK_SIZE))
+        result = estimate([path])
+        assert result["chunk_bytes"] == MAX_CHUNK_SIZE
+        assert result["compressed_chunk_bytes"] < MAX_CHUNK_SIZE
+
+    def test_buffers_match_files(self, tmp_path):
+        data = b"\x00" * (3 * MAX_CHUNK_SIZE) + os.urandom(1024 * 1024)
+        path = tmp_path / "a"
+        path.write_bytes(data + b"\x00" * (3 * MAX_CHUNK_SIZE))
+        for params in [{}, dict(max_chunk_size=10_000)]:
+            expected = chunks([str(path)], **params)
+            assert chunks([path.read_bytes()], **params) == expected
+            assert max(c["size"] for _, c in expected) <= params.get(
+                "max_chunk_size", MAX_CHUNK_SIZE
+            )
+
+
+class TestChunkers:
+    @pytest.fixture
+    def data(self):
+        return os.urandom(2 * 1024 * 1024)
+
+    @pytest.mark.parametrize("chunker", ["gearhash", "fastcdc", "rabin", "fixed"])
+    def test_chunks_cover_data(self, tmp_path, data, chunker):
+        (tmp_path / "a").write_bytes(data)
+        merged = chunks([str(tmp_path / "a")], store_data=True, chunker=chunker)
+        assert b"".join(chunk["data"] for _, chunk in merged) == data
+        sizes = [chunk["size"] for _, chunk in merged][:-1]
+        assert max(sizes) <= MAX_CHUNK_SIZE
+        if chunker != "fixed":
+            assert min(sizes) >= MIN_CHUNK_SIZE
+        assert chunks([data], chunker=chunker) == chunks(
+            [str(tmp_path / "a")], chunker=chunker
+        )
+
+    def test_fixed_blocks(self, data):
+        sizes = [c["size"] for _, c in chunks([data], chunker="fixed")]
+        assert sizes == [64 * 1024] * 32 + [0]
+
+    def test_algorithms_differ(self, data):
+        boundaries = {
+            chunker: [hash for hash, _ in chunks([data], chunker=chunker)]
+            for chunker in ["gearhash", "fastcdc", "rabin"]
+        }
+        assert boundaries["gearhash"] != boundaries["fastcdc"]
+        assert boundaries["gearhash"] != boundaries["rabin"]
+
+    def test_unknown_chunker(self, data):
+        with pytest.raises(ValueError, match="Unknown chunker"):
+            chunks([data], chunker="buzhash")
+
+    def test_sensitivity(self, tmp_path, data):
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(os.urandom(100) + data)
+        results = chunker_sensitivity([tmp_path / "a", tmp_path / "b"])
+        ratios = {r["chunker"]: r["dedup_ratio"] for r in results}
+        assert list(ratios) == ["gearhash", "fastcdc", "rabin", "fixed"]
+        assert all(ratios[c] < 0.6 for c in ["gearhash", "fastcdc", "rabin"])
+        assert ratios["fixed"] > 0.99
+
+
+class TestRecommendChunkSize:
+    def test_long_runs_favor_large_chunks(self, tmp_path):
+        data = os.urandom(8 * 1024 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        paths = [tmp_path / "a", tmp_path / "b"]
+        result = recommend_chunk_size(paths, chunk_overhead=1024)
+        assert result["target_chunk_size"] == 1 << 17
+        assert result["chunk_mask"] == 0xFFFF800000000000
+        assert result["runs"] == 1
+        assert result["adjacent_fraction"] > 0.9
+        assert result["dedup_ratio"] == 0.5
+        assert 0.5 < result["predicted_dedup_ratio"] < 0.51
+
+    def test_scattered_matches_favor_small_chunks(self, tmp_path):
+        blocks = [os.urandom(256 * 1024) for _ in range(32)]
+        (tmp_path / "a").write_bytes(b"".join(blocks))
+        (tmp_path / "b").write_bytes(
+            b"".join(
+                block if i % 4 == 0 else os.urandom(len(block))
+                for i, block in enumerate(blocks)
+            )
+        )
+        result = recommend_chunk_size([tmp_path / "a", tmp_path / "b"])
+        assert result["target_chunk_size"] < result["current_target_chunk_size"]
+        assert result["predicted_dedup_ratio"] < result["dedup_ratio"]
+
+    def test_unique_data_prefers_largest_chunks(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(1024 * 1024))
+        result = recommend_chunk_size([tmp_path / "a"])
+        assert result["runs"] == 0
+        assert result["target_chunk_size"] == 4 * 1024 * 1024
+        assert result["predicted_dedup_ratio"] == result["dedup_ratio"] == 1.0
+
+
+class TestChunkTable:
+    def test_matches_chunks(self, tmp_path):
+        block = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(block + block)
+        (tmp_path / "b").write_bytes(block)
+        paths = [str(tmp_path / "a"), str(tmp_path / "b")]
+        table = chunk_table(paths)
+        expected = chunks(paths)
+        assert table.column("hash").to_pylist() == [h for h, _ in expected]
+        assert table.column("seen_in").to_pylist() == [
+            c["seen_in"] for _, c in expected
+        ]
+        assert table.column("occurrences").to_pylist() == [
+            c["occurrences"] for _, c in expected
+        ]
+        assert table.column("data").null_count == len(table)
+
+    def test_store_data(self, tmp_path):
+        data = os.urandom(256 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        table = chunk_table([tmp_path / "a"], store_data=True)
+        assert table.schema.field("data").type == pa.large_binary()
+        assert b"".join(table.column("data").to_pylist()) == data
+
+    def test_in_memory_buffers(self):
+        data = os.urandom(256 * 1024)
+        buffers = [data, bytearray(data), np.frombuffer(data, dtype=np.uint16)]
+        table = chunk_table([*buffers, pa.py_buffer(data)], store_data=True)
+        assert table.column("hash").to_pylist() == [h for h, _ in chunks([data])]
+        assert set(table.column("occurrences").to_pylist()) == {4}
+        assert b"".join(table.column("data").to_pylist()) == data
+
+    def test_store_data_of_shared_chunks(self, tmp_path):
+        data = os.urandom(256 * 1024)
+        paths = []
+        for name in "abc":
+            (tmp_path / name).write_bytes(data)
+            paths.append(tmp_path / name)
+        table = chunk_table(paths, store_data=True)
+        assert table.column("data").null_count == 0
+        assert b"".join(table.column("data").to_pylist()) == data
+        assert set(table.column("occurrences").to_pylist()) == {3}
+
+
+class TestChunkSizeStats:
+    def test_fixed_chunks(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(1024 * 1024 + 1000))
+        result = chunk_size_stats(
+            [tmp_path / "a"], bins=4, chunker="fixed", target_chunk_size=16 * 1024
+        )
+        assert result["num_chunks"] == 65
+        assert (result["min"], result["max"]) == (1000, 16 * 1024)
+        assert result["median"] == result["p95"] == 16 * 1024
+        assert result["histogram"]["counts"] == [1, 0, 0, 64]
+        assert len(result["histogram"]["edges"]) == 5
+
+    def test_distribution_follows_target(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(4 * 1024 * 1024))
+        small = chunk_size_stats([tmp_path / "a"], target_chunk_size=8 * 1024)
+        large = chunk_size_stats([tmp_path / "a"], target_chunk_size=64 * 1024)
+        assert small["num_chunks"] > large["num_chunks"]
+        assert small["mean"] < large["mean"]
+        assert large["max"] <= MAX_CHUNK_SIZE
+        assert sum(large["histogram"]["counts"]) == large["num_chunks"]
+
+    def test_no_chunks(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"")
+        with pytest.raises(ValueError, match="No chunks"):
+            chunk_size_stats([tmp_path / "a"])
+
+
+class TestAttribution:
+    @pytest.fixture
+    def files(self, tmp_path):
+        shared = os.urandom(256 * 1024)
+        (tmp_path / "small").write_bytes(shared)
+        (tmp_path / "large").write_bytes(shared + os.urandom(256 * 1024))
+        return [str(tmp_path / "small"), str(tmp_path / "large")]
+
+    def first_seen(self, files, **kwargs):
+        return {
+            c["first_seen_in"]
+            for _, c in chunks(files, **kwargs)
+            if len(c["seen_in"]) == 2
+        }
+
+    def test_order(self, files):
+        assert self.first_seen(files) == {0}
+
+    def test_largest(self, files):
+        assert self.first_seen(files, attribution="largest") == {1}
+
+    def test_priority_list(self, files):
+        assert self.first_seen(files, attribution=[1, 0]) == {1}
+
+    def test_unknown_strategy(self, files):
+        with pytest.raises(ValueError):
+            chunks(files, attribution="newest")
+
+
+class TestFormatPairs:
+    def test_pairs_sum_to_cross_file_duplicates(self, tmp_path):
+        data = os.urandom(256 * 1024)
+        (tmp_path / "a.parquet").write_bytes(b"PAR1" + data)
+        (tmp_path / "b.parquet").write_bytes(b"PAR1" + data)
+        (tmp_path / "c.jsonl").write_bytes(data)
+        (tmp_path / "d.bin").write_bytes(os.urandom(256 * 1024))
+        paths = [tmp_path / name for name in ["a.parquet", "b.parquet", "c.jsonl"]]
+        records = format_pairs([*paths, tmp_path / "d.bin"])
+        pairs = {r["formats"]: r["duplicate_bytes"] for r in records}
+        assert set(pairs) == {("parquet", "parquet"), ("json", "parquet")}
+        assert pairs[("parquet", "parquet")] > 200 * 1024
+        assert pairs[("json", "parquet")] > 200 * 1024
+        assert sum(r["fraction"] for r in records) == pytest.approx(1.0)
+
+
+class TestDeletionSavings:
+    def test_returns_expected_keys(self):
+        with patch("de.estimate._deletion_savings", return_value=(10, 5)) as mock:
+            result = deletion_savings([Path("a"), Path("b")], [Path("a")])
+        mock.assert_called_once_with(["a", "b"], ["a"])
+        assert result["reclaimable_bytes"] == 10
+        assert result["shared_bytes"] == 5
+        assert result["numdeleted"] == 1
+
+    def test_identical_files_are_fully_shared(self, duplicate_files):
+        result = deletion_savings(duplicate_files, duplicate_files[:1])
+        assert result["reclaimable_bytes"] == 0
+        assert result["shared_bytes"] == os.path.getsize(duplicate_files[0])
+
+
+class TestOverlap:
+    def test_all_regions_present(self):
+        with patch("de.estimate._overlaps", return_value=[([0], 7, 1), ([0, 1], 3, 2)]):
+            result = overlap([Path("a"), Path("b")])
+        assert result == {("a",): 7, ("b",): 0, ("a", "b"): 3}
+
+    def test_regions_sum_to_unique_bytes(self, tmp_path):
+        shared = os.urandom(128 * 1024)
+        (tmp_path / "a").write_bytes(shared + os.urandom(128 * 1024))
+        (tmp_path / "b").write_bytes(shared + os.urandom(128 * 1024))
+        paths = [tmp_path / "a", tmp_path / "b"]
+        result = overlap(paths)
+        assert len(result) == 3
+        assert sum(result.values()) == estimate(paths)["chunk_bytes"]
+
+    def test_too_many_files(self):
+        with pytest.raises(ValueError):
+            overlap([Path(str(i)) for i in range(6)])
+
+
+class TestSimilarityMatrix:
+    def test_shared_fractions(self):
+        overlaps = [([0], 6, 1), ([0, 1], 2, 1), ([1, 2], 4, 1), ([2], 4, 1)]
+        with patch("de.estimate._overlaps", return_value=overlaps):
+            matrix = similarity_matrix(["a", "b", "c"])
+        assert matrix.tolist() == [
+            [1.0, 0.25, 0.0],
+            [1 / 3, 1.0, 2 / 3],
+            [0.0, 0.5, 1.0],
+        ]
+
+    def test_contained_file(self, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data + os.urandom(512 * 1024))
+        (tmp_path / "c").write_bytes(b"")
+        matrix = similarity_matrix([tmp_path / "a", tmp_path / "b", tmp_path / "c"])
+        assert matrix[0, 1] > 0.7
+        assert 0.3 < matrix[1, 0] < 0.7
+        assert matrix[2].tolist() == [0.0, 0.0, 0.0]
+
+
+class TestShardRenames:
+    @pytest.fixture
+    def revisions(self, tmp_path):
+        shards = [os.urandom(1024 * 1024) for _ in range(3)]
+        old, new = tmp_path / "old", tmp_path / "new"
+        for directory in [old, new]:
+            (directory / "data").mkdir(parents=True)
+        for i, shard in enumerate(shards):
+            (old / "data" / f"train-{i:05d}-of-00003.parquet").write_bytes(shard)
+        # two shards renamed, the third one edited, plus a new shard
+        for i, shard in enumerate(shards[:2]):
+            (new / "data" / f"train-{i:05d}-of-00004.parquet").write_bytes(shard)
+        edited = shards[2][: 512 * 1024] + os.urandom(1024) + shards[2][512 * 1024 :]
+        (new / "data" / "train-00002-of-00004.parquet").write_bytes(edited)
+        (new / "data" / "train-00003-of-00004.parquet").write_bytes(os.urandom(1024))
+        (new / "README.md").write_bytes(b"readme")
+        return old, new
+
+    def test_rename_map(self, revisions):
+        old, new = revisions
+        old_files = sorted(p for p in old.rglob("*") if p.is_file())
+        new_files = sorted(p for p in new.rglob("*") if p.is_file())
+        result = shard_renames(old_files, new_files, old, new, min_similarity=0.5)
+        assert result["unchanged"] == 0
+        assert result["renamed"] == [
+            {
+                "old": "data/train-00000-of-00003.parquet",
+                "new": "data/train-00000-of-00004.parquet",
+            },
+            {
+                "old": "data/train-00001-of-00003.parquet",
+                "new": "data/train-00001-of-00004.parquet",
+            },
+        ]
+        (pair,) = result["near_duplicates"]
+        assert pair["old"] == "data/train-00002-of-00003.parquet"
+        assert pair["new"] == "data/train-00002-of-00004.parquet"
+        assert 0.5 < pair["similarity"] < 1
+        assert result["resharded"] == {"train": [3, 4]}
+        assert sorted(result["added"]) == [
+            "README.md",
+            "data/train-00003-of-00004.parquet",
+        ]
+        assert result["removed"] == []
+
+    def test_names_without_roots(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"same")
+        (tmp_path / "b").write_bytes(b"same")
+        result = shard_renames([tmp_path / "a"], [tmp_path / "b"])
+        assert result["renamed"] == [{"old": "a", "new": "b"}]
+        assert result["resharded"] == {}
+
+
+class TestMarginalContributions:
+    def test_leave_one_out_and_shapley(self):
+        overlaps = [([0], 8, 1), ([1, 2], 6, 1), ([0, 2], 4, 1)]
+        with patch("de.estimate._overlaps", return_value=overlaps) as mock:
+            result = marginal_contributions(
+                {"x": [Path("a")], "y": [Path("b"), Path("c")]}
+            )
+        mock.assert_called_once_with(["a", "b", "c"])
+        assert result == [
+            {
+                "dataset": "y",
+                "unique_bytes": 10,
+                "marginal_bytes": 6,
+                "shapley_bytes": 8.0,
+            },
+            {
+                "dataset": "x",
+                "unique_bytes": 12,
+                "marginal_bytes": 8,
+                "shapley_bytes": 10.0,
+            },
+        ]
+
+    def test_shapley_sums_to_unique_bytes(self, tmp_path):
+        shared = os.urandom(256 * 1024)
+        (tmp_path / "a").write_bytes(shared)
+        (tmp_path / "b").write_bytes(shared + os.urandom(256 * 1024))
+        datasets = {"a": [tmp_path / "a"], "b": [tmp_path / "b"]}
+        result = marginal_contributions(datasets)
+        assert [r["dataset"] for r in result] == ["a", "b"]
+        assert result[0]["marginal_bytes"] == 0
+        total = estimate([tmp_path / "a", tmp_path / "b"])["chunk_bytes"]
+        assert sum(r["shapley_bytes"] for r in result) == pytest.approx(total)
+
+
+class TestSimulateCache:
+    @pytest.fixture
+    def files(self, tmp_path):
+        data = os.urandom(512 * 1024)
+        for name in ["a", "b"]:
+            (tmp_path / name).write_bytes(data)
+        return [tmp_path / "a", tmp_path / "b"]
+
+    def test_large_cache_hits_repeated_file(self, files):
+        [record] = simulate_cache(files, 2**30)
+        first, second = record["files"]
+        assert first["hits"] == 0
+        assert second["hits"] == second["requests"]
+        assert record["byte_hit_rate"] == 0.5
+
+    def test_small_cache_misses(self, files):
+        small, large = simulate_cache(files, [1, 2**30])
+        assert small["hits"] == 0
+        assert large["hits"] > 0
+
+
+class TestPacking:
+    def test_packs_unique_chunks(self, tmp_path):
+        data = os.urandom(512 * 1024)
+        (tmp_path / "a").write_bytes(data)
+        (tmp_path / "b").write_bytes(data)
+        paths = [tmp_path / "a", tmp_path / "b"]
+        [record] = packing(paths)
+        assert record["chunk_bytes"] == estimate(paths)["chunk_bytes"]
+        assert record["blocks"] == len(data) // (64 * 1024)
+        assert record["compressed_bytes"] == len(data)
+        assert record["storage_ratio"] > 1
+
+    def test_compresses_across_chunks(self, tmp_path):
+        (tmp_path / "a").write_bytes(b" ".join(b"%d" % i for i in range(200_000)))
+        small, large = packing([tmp_path / "a"], block_sizes=[4096, 1024 * 1024])
+        assert small["blocks"] > large["blocks"]
+        assert large["compressed_bytes"] < small["compressed_bytes"]
+
+
+class TestIntraChunkRedundancy:
+    def test_repeats_within_chunk(self, tmp_path):
+        block = os.urandom(32 * 1024)
+        (tmp_path / "a").write_bytes(block * 2)
+        [record] = intra_chunk_redundancy([tmp_path / "a"], [1024])
+        assert record["chunk_bytes"] == 64 * 1024
+        assert record["redundant_fraction"] > 0.3
+
+    def test_random_data_has_no_redundancy(self, tmp_path):
+        (tmp_path / "a").write_bytes(os.urandom(512 * 1024))
+        records = intra_chunk_redundancy([tmp_path / "a"])
+        assert [r["sub_chunk_size"] for r in records] == [16384, 4096, 1024]
+        assert all(r["redundant_bytes"] == 0 for r in records)
+
+    def test_invalid_size(self, tmp_path):
+        (tmp_path / "a").write_bytes(b"abc")
+        with pytest.raises(ValueError, match="power of two"):
+            intra_chunk_redundancy([tmp_path / "a"], [1000])
+
+
+class TestUpset:
+    def test_membership_bitsets(self):
+        rows = [([0, 2], 9, 3), ([1], 5, 1), ([0], 2, 1)]
+        with patch("de.estimate._overlaps", return_value=rows):
+            result = upset(["a", "b", "c"], top_k=2)
+        assert [r["membership"] for r in result] == ["101", "010"]
+        assert result[0]["files"] == ["a", "c"]
+        assert result[0]["degree"] == 2
+        assert result[0]["bytes"] == 9
+        assert result[0]["chunks"] == 3
 
 
 class TestCompareFormatsTables:
@@ -175,3 +2180,41 @@ class TestCompareFormats:
         assert len(results) == 2
         assert all(r.total_len > 0 for r in results)
         assert all(r.chunk_bytes > 0 for r in results)
+
+
+class TestExperiment:
+    def test_one_row_per_combination(self, tmp_path, table, edit):
+        pq.write_table(table, tmp_path / "v0.parquet")
+        pq.write_table(edit, tmp_path / "v1.parquet")
+        result = experiment(
+            [tmp_path / "v0.parquet", tmp_path / "v1.parquet"],
+            {"compression": ["none", "zstd"], "use_cdc": [False, True]},
+        )
+        assert result.num_rows == 4
+        assert set(result.column_names) >= {"compression", "use_cdc", "dedup_ratio"}
+        assert all(n == 2 for n in result.column("numfiles").to_pylist())
+
+    def test_writes_to_given_directory(self, tmp_path, table):
+        pq.write_table(table, tmp_path / "v0.parquet")
+        out = tmp_path / "out"
+        result = experiment(
+            tmp_path / "v0.parquet", {"row_group_size": [1024, 2048]}, out
+        )
+        assert result.column("row_group_size").to_pylist() == [1024, 2048]
+        assert len(list((out / "experiment" / "parquet-cpp").glob("*.parquet"))) == 2
+
+
+class TestRowOrderChurn:
+    def test_sorting_recovers_shuffled_rows(self, tmp_path):
+        ids = list(range(50_000))
+        table = pa.table({"id": ids, "v": [str(i) for i in ids]})
+        random.Random(42).shuffle(ids)
+        original = tmp_path / "original.parquet"
+        updated = tmp_path / "updated.parquet"
+        pq.write_table(table, original)
+        pq.write_table(table.take(pa.array(ids)), updated)
+        results = row_order_churn(original, updated, "id", tmp_path / "out")
+        assert [r.group for r in results] == ["unsorted", "sorted", "reordered"]
+        unsorted, sorted_, _ = results
+        assert sorted_.dedup_ratio < unsorted.dedup_ratio
+        assert sorted_.dedup_ratio == pytest.approx(0.5, abs=0.05)
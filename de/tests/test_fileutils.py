@@ -0,0 +1,93 @@
+import os
+
+import pytest
+
+from de.fileutils import (
+    IgnoreRules,
+    detect_format,
+    filesystem_savings,
+    filesystem_sharing,
+    filter_paths,
+)
+
+
+class TestIgnoreRules:
+    @pytest.mark.parametrize(
+        "patterns,path,ignored",
+        [
+            (["*.log"], "run/train.log", True),
+            (["*.log"], "run/train.txt", False),
+            (["tmp/"], "a/tmp/x.bin", True),
+            (["tmp/"], "a/tmp", False),
+            (["/ckpt"], "ckpt/step1.pt", True),
+            (["/ckpt"], "data/ckpt/step1.pt", False),
+            (["data/*.pt"], "data/model.pt", True),
+            (["data/*.pt"], "data/sub/model.pt", False),
+            (["data/**/*.pt"], "data/sub/model.pt", True),
+            (["data/**/*.pt"], "data/model.pt", True),
+            (["*.log", "!keep.log"], "keep.log", False),
+            (["# comment", "", "*.tmp"], "x.tmp", True),
+        ],
+    )
+    def test_patterns(self, patterns, path, ignored):
+        assert IgnoreRules(patterns).ignored(path) is ignored
+
+    def test_relative_to_root(self, tmp_path):
+        rules = IgnoreRules(["/logs"])
+        assert rules.ignored(tmp_path / "logs" / "a", root=tmp_path)
+        assert not rules.ignored(tmp_path / "data" / "logs", root=tmp_path)
+
+    def test_from_file(self, tmp_path):
+        (tmp_path / ".deignore").write_text("*.log\n!keep.log\n")
+        rules = IgnoreRules.from_file(tmp_path / ".deignore")
+        assert rules.ignored("a.log")
+        assert not rules.ignored("keep.log")
+
+
+class TestFilterPaths:
+    def test_patterns_and_sizes(self, tmp_path):
+        for name, size in [("a.bin", 10), ("b.bin", 100), ("c.bin", 1000)]:
+            (tmp_path / name).write_bytes(bytes(size))
+        (tmp_path / "d.log").write_bytes(bytes(100))
+        paths = sorted(tmp_path.iterdir())
+        selected = filter_paths(
+            paths, IgnoreRules(["*.log"]), min_size=50, max_size=500
+        )
+        assert [p.name for p in selected] == ["b.bin"]
+
+
+class TestDetectFormat:
+    @pytest.mark.parametrize(
+        "name,content,expected",
+        [
+            ("a.bin", b"PAR1\x15\x04", "parquet"),
+            ("a.json", b"PAR1\x15\x04", "parquet"),
+            ("a.arrow", b"\x00\x00", "arrow"),
+            ("a.jsonl", b'{"a": 1}\n', "json"),
+            ("a", b'  [{"a": 1}]', "json"),
+            ("a.gz", b"\x1f\x8b\x08", "gzip"),
+            ("a.csv", b"a,b\n1,2\n", "csv"),
+            ("a", b"\x00\x01\x02", "binary"),
+        ],
+    )
+    def test_magic_then_extension(self, tmp_path, name, content, expected):
+        path = tmp_path / name
+        path.write_bytes(content)
+        assert detect_format(path) == expected
+
+
+class TestFilesystemSavings:
+    def test_sparse_holes(self, tmp_path):
+        path = tmp_path / "sparse.bin"
+        with open(path, "wb") as f:
+            f.seek(16 * 1024 * 1024)
+            f.write(b"x")
+        sparse, cloned = filesystem_sharing(path)
+        assert sparse > 15 * 1024 * 1024
+        assert cloned == 0
+
+    def test_dense_files(self, tmp_path):
+        path = tmp_path / "dense.bin"
+        path.write_bytes(os.urandom(64 * 1024))
+        savings = filesystem_savings([path])
+        assert savings == {"sparse_bytes": 0, "cloned_bytes": 0, "files": 0}
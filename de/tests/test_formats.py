@@ -6,7 +6,7 @@ import pyarrow as pa
 import pyarrow.parquet as pq
 import pytest
 
-from de.formats import JsonLines, ParquetCpp, Sqlite
+from de.formats import EncryptionKeys, JsonLines, ParquetCpp, ParquetRs, Sqlite
 
 
 @pytest.fixture
@@ -30,6 +30,10 @@ class TestParquetCpp:
     def test_paramstem_with_compression_and_cdc(self):
         assert ParquetCpp(use_cdc=True, compression="zstd").paramstem == "zstd cdc"
 
+    def test_paramstem_with_compression_level(self):
+        fmt = ParquetCpp(use_cdc=False, compression="zstd", compression_level=9)
+        assert fmt.paramstem == "zstd l=9"
+
     def test_derive_path_plain(self, tmp_path):
         assert (
             ParquetCpp(use_cdc=False).derive_path("data", tmp_path)
@@ -99,6 +103,27 @@ class TestParquetCpp:
         assert pq.read_metadata(dest).num_row_groups == 5
 
 
+class TestParquetRs:
+    def test_paramstem_with_encryption(self):
+        keys = EncryptionKeys(footer_key=b"0123456789012345")
+        assert ParquetRs(use_cdc=True, encryption=keys).paramstem == "cdc encrypted"
+
+    def test_write_encrypted_footer(self, tmp_path, table):
+        keys = EncryptionKeys(
+            footer_key=b"0123456789012345",
+            column_keys=(("a", b"1234567890123450"),),
+        )
+        path = ParquetRs(use_cdc=False, encryption=keys).write("out", table, tmp_path)
+        assert path.read_bytes()[-4:] == b"PARE"
+
+    def test_write_from_str_and_url(self, tmp_path, table):
+        src = tmp_path / "src.parquet"
+        pq.write_table(table, src)
+        for source in [str(src), f"file://{src}"]:
+            path = ParquetRs(use_cdc=False).write("out", source, tmp_path)
+            assert pq.read_table(path).equals(table)
+
+
 class TestJsonLines:
     def test_suffix(self):
         assert JsonLines().suffix == "jsonlines"
@@ -0,0 +1,196 @@
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+import json
+import os
+import shutil
+import threading
+
+import pytest
+
+import de.manifest
+from de.manifest import (
+    ManifestEntry,
+    SamplingRules,
+    estimate_hub_repo,
+    estimate_manifest,
+    read_hub_tree,
+    read_s3_inventory,
+)
+
+
+@pytest.fixture
+def objects(tmp_path):
+    data = os.urandom(256 * 1024)
+    objects = {}
+    for name in ["a.bin", "b.bin", "logs/c.txt"]:
+        path = tmp_path / "bucket" / name
+        path.parent.mkdir(parents=True, exist_ok=True)
+        path.write_bytes(data)
+        objects[f"s3://bucket/{name}"] = path
+    return objects
+
+
+@pytest.fixture
+def fetcher(objects):
+    fetched = []
+
+    def fetch(url, dest):
+        fetched.append(url)
+        shutil.copyfile(objects[url], dest)
+
+    fetch.fetched = fetched
+    return fetch
+
+
+class TestManifests:
+    def test_s3_inventory(self, tmp_path):
+        path = tmp_path / "inventory.csv"
+        path.write_text(
+            '"bucket","data/a%20b.parquet","100","2024-01-01T00:00:00.000Z"\n'
+            '"bucket","data/c.parquet","200","2024-01-01T00:00:00.000Z"\n'
+        )
+        entries = read_s3_inventory(path, fields=["bucket", "key", "size", "mtime"])
+        assert entries == [
+            ManifestEntry("data/a b.parquet", "s3://bucket/data/a b.parquet", 100),
+            ManifestEntry("data/c.parquet", "s3://bucket/data/c.parquet", 200),
+        ]
+
+    def test_hub_tree(self, tmp_path):
+        path = tmp_path / "tree.json"
+        listing = [
+            {"type": "directory", "path": "data"},
+            {"type": "file", "path": "README.md", "size": 10},
+            {
+                "type": "file",
+                "path": "data/train.parquet",
+                "size": 134,
+                "lfs": {"size": 5000},
+            },
+        ]
+        path.write_text(json.dumps(listing))
+        entries = read_hub_tree(path, "org/name")
+        assert [e.size for e in entries] == [10, 5000]
+        assert entries[1].url == (
+            "https://huggingface.co/datasets/org/name/resolve/main/data/train.parquet"
+        )
+
+
+class TestSamplingRules:
+    @pytest.fixture
+    def entries(self):
+        return [ManifestEntry(f"{i}.bin", f"s3://b/{i}.bin", 10) for i in range(1000)]
+
+    def test_fraction_is_deterministic(self, entries):
+        sampled = SamplingRules(fraction=0.1).sample(entries)
+        assert 50 < len(sampled) < 150
+        assert sampled == SamplingRules(fraction=0.1).sample(entries)
+        assert sampled != SamplingRules(fraction=0.1, seed=1).sample(entries)
+
+    def test_caps(self, entries):
+        assert len(SamplingRules(max_files=5).sample(entries)) == 5
+        assert len(SamplingRules(max_bytes=55).sample(entries)) == 5
+
+    def test_patterns(self, entries):
+        rules = SamplingRules(include=("1*.bin",), exclude=("10*",))
+        keys = {e.key for e in rules.sample(entries)}
+        assert "1.bin" in keys and "11.bin" in keys
+        assert "10.bin" not in keys and "2.bin" not in keys
+
+
+class TestEstimateManifest:
+    def test_only_sampled_objects_are_fetched(self, objects, fetcher):
+        entries = [
+            ManifestEntry(url.removeprefix("s3://bucket/"), url, 256 * 1024)
+            for url in objects
+        ]
+        result = estimate_manifest(entries, SamplingRules(exclude=("logs/",)), fetcher)
+        assert fetcher.fetched == ["s3://bucket/a.bin", "s3://bucket/b.bin"]
+        assert result["sampled_objects"] == 2
+        assert result["corpus_bytes"] == 3 * 256 * 1024
+        assert result["dedup_ratio"] == pytest.approx(0.5, abs=0.01)
+        assert result["corpus_chunk_bytes"] == pytest.approx(384 * 1024, rel=0.05)
+
+    def test_empty_sample(self, fetcher):
+        with pytest.raises(ValueError, match="didn't select"):
+            estimate_manifest([], fetcher=fetcher)
+
+
+class HubHandler(BaseHTTPRequestHandler):
+    """Serves the routes of a fake Hub, a str route redirecting there."""
+
+    routes = {}
+    requests = []
+
+    def do_GET(self):
+        path = self.path.partition("?")[0]
+        self.requests.append((path, self.headers.get("Authorization")))
+        body = self.routes.get(path)
+        if body is None:
+            self.send_response(404)
+            self.end_headers()
+        elif isinstance(body, str):
+            self.send_response(302)
+            self.send_header("Location", body)
+            self.end_headers()
+        else:
+            self.send_response(200)
+            self.send_header("Content-Length", str(len(body)))
+            self.end_headers()
+            self.wfile.write(body)
+
+    def log_message(self, format, *args):
+        pass
+
+
+class TestEstimateHubRepo:
+    @pytest.fixture
+    def hub(self, monkeypatch):
+        httpd = ThreadingHTTPServer(("127.0.0.1", 0), HubHandler)
+        port = httpd.server_address[1]
+        data = os.urandom(256 * 1024)
+        listing = [
+            {"type": "directory", "path": "data"},
+            {"type": "file", "path": "README.md", "size": 6},
+            {"type": "file", "path": "data/a.parquet", "size": len(data)},
+            {"type": "file", "path": "data/b.parquet", "size": len(data)},
+        ]
+        resolve = "/datasets/org/name/resolve/main"
+        HubHandler.requests = []
+        HubHandler.routes = {
+            "/api/datasets/org/name/tree/main": json.dumps(listing).encode(),
+            f"{resolve}/README.md": b"readme",
+            f"{resolve}/data/a.parquet": data,
+            # another host, like the storage the Hub redirects to
+            f"{resolve}/data/b.parquet": f"http://localhost:{port}/storage/b",
+            "/storage/b": data,
+        }
+        monkeypatch.setattr(de.manifest, "HUB_ENDPOINT", f"http://127.0.0.1:{port}")
+        thread = threading.Thread(target=httpd.serve_forever, daemon=True)
+        thread.start()
+        yield HubHandler
+        httpd.shutdown()
+        httpd.server_close()
+
+    def test_streams_the_matching_files(self, hub):
+        result = estimate_hub_repo("org/name", allow_patterns="*.parquet", token="t")
+        assert result["repo_id"] == "org/name"
+        assert result["revision"] == "main"
+        assert result["numfiles"] == 2
+        assert result["dedup_ratio"] == 0.5
+        labels = [row["label"] for row in result["inputs"]]
+        assert labels == ["data/a.parquet", "data/b.parquet"]
+        authorizations = dict(hub.requests)
+        assert authorizations["/api/datasets/org/name/tree/main"] == "Bearer t"
+        assert authorizations["/datasets/org/name/resolve/main/data/a.parquet"] == (
+            "Bearer t"
+        )
+        # the token isn't forwarded to the storage
+        assert authorizations["/storage/b"] is None
+
+    def test_all_files_without_patterns(self, hub):
+        result = estimate_hub_repo("org/name", revision="main")
+        assert result["numfiles"] == 3
+        assert result["total_len"] == 2 * 256 * 1024 + len(b"readme")
+
+    def test_no_matching_file(self, hub):
+        with pytest.raises(ValueError, match="No files of org/name"):
+            estimate_hub_repo("org/name", allow_patterns=["*.csv"])
@@ -0,0 +1,132 @@
+from functools import partial
+from http.server import SimpleHTTPRequestHandler, ThreadingHTTPServer
+import os
+import threading
+
+import pytest
+
+from de.estimate import Estimator, estimate, estimate_labeled
+from de.remote import URLStream, is_remote, is_url, open_input
+
+
+class QuietHandler(SimpleHTTPRequestHandler):
+    requests = []
+
+    def do_GET(self):
+        self.requests.append(self.path)
+        super().do_GET()
+
+    def log_message(self, format, *args):
+        pass
+
+
+@pytest.fixture
+def server(tmp_path):
+    QuietHandler.requests = []
+    handler = partial(QuietHandler, directory=str(tmp_path))
+    httpd = ThreadingHTTPServer(("127.0.0.1", 0), handler)
+    thread = threading.Thread(target=httpd.serve_forever, daemon=True)
+    thread.start()
+    yield f"http://127.0.0.1:{httpd.server_address[1]}"
+    httpd.shutdown()
+    httpd.server_close()
+
+
+def test_is_url():
+    assert is_url("https://huggingface.co/datasets/x/resolve/main/a.parquet")
+    assert is_url("http://localhost/a")
+    assert not is_url("data/a.parquet")
+    assert not is_url(b"https://")
+    assert open_input("data/a.parquet") == "data/a.parquet"
+
+
+def test_is_remote():
+    assert is_remote("https://huggingface.co/a")
+    assert is_remote("s3://bucket/prefix/file.parquet")
+    assert is_remote("gs://bucket/file.parquet")
+    assert is_remote("az://container/file.parquet")
+    assert is_remote("file:///data/file.parquet")
+    assert not is_url("s3://bucket/file.parquet")
+    assert not is_remote("bucket/file.parquet")
+    # the Rust side reads the objects of object stores
+    assert open_input("s3://bucket/a") == "s3://bucket/a"
+
+
+def test_stream_is_requested_on_first_read(tmp_path, server):
+    data = os.urandom(100_000)
+    (tmp_path / "a").write_bytes(data)
+    stream = URLStream(f"{server}/a")
+    assert QuietHandler.requests == []
+    chunks = []
+    while block := stream.read(8192):
+        chunks.append(block)
+    assert b"".join(chunks) == data
+    assert QuietHandler.requests == ["/a"]
+    assert stream.read(8192) == b""
+
+
+def test_estimate_urls(tmp_path, server):
+    data = os.urandom(512 * 1024)
+    (tmp_path / "a").write_bytes(data)
+    (tmp_path / "b").write_bytes(data)
+    paths = [f"{server}/a", str(tmp_path / "b")]
+    result = estimate(paths)
+    expected = estimate([tmp_path / "a", tmp_path / "b"])
+    assert result["total_len"] == expected["total_len"]
+    assert result["chunk_bytes"] == expected["chunk_bytes"]
+    assert result["dedup_ratio"] == 0.5
+    assert result["xet_bytes"] is None
+    assert [f["path"] for f in result["files"]] == paths
+    assert [f["owned_bytes"] for f in result["files"]] == [len(data), 0]
+    for streamed, exact in zip(result["files"], expected["files"]):
+        assert streamed.keys() == exact.keys() - {"chunks", "chunk_digest"}
+        for key in ["unique_bytes", "shared_bytes", "owned_bytes", "compressed_bytes"]:
+            assert streamed[key] == exact[key]
+    assert estimate(paths, compress=False)["compressed_chunk_bytes"] is None
+
+
+def test_estimate_urls_rejects_unsupported_options(tmp_path, server):
+    (tmp_path / "a").write_bytes(b"abc")
+    with pytest.raises(ValueError, match="inputs don't support size_maps"):
+        estimate([f"{server}/a"], size_maps=True)
+    with pytest.raises(ValueError, match="don't support mmap"):
+        estimate([f"{server}/a"], mmap=True)
+
+
+def test_object_reader(tmp_path):
+    # past the doubling blocks of the reader, up to 8 MiB, and their prefetch
+    data = os.urandom(12 * 1024 * 1024)
+    (tmp_path / "a").write_bytes(data)
+    (tmp_path / "b").write_bytes(data[: 5 * 1024 * 1024] + os.urandom(1024))
+    urls = [f"file://{tmp_path / 'a'}", f"file://{tmp_path / 'b'}"]
+    result = estimate(urls)
+    expected = estimate([tmp_path / "a", tmp_path / "b"])
+    assert result["total_len"] == expected["total_len"]
+    assert result["chunk_bytes"] == expected["chunk_bytes"]
+    # b is older, so it owns the shared chunks
+    os.utime(tmp_path / "b", (0, 0))
+    result = estimate(urls, attribution="mtime")
+    assert [f["owned_bytes"] for f in result["files"]][1] == 5 * 1024 * 1024 + 1024
+
+
+def test_mtime_of_urls(tmp_path, server):
+    (tmp_path / "a").write_bytes(b"abc")
+    with pytest.raises(ValueError, match="mtime attribution"):
+        estimate([f"{server}/a"], attribution="mtime")
+
+
+def test_missing_url(server):
+    with pytest.raises(OSError):
+        estimate([f"{server}/missing"])
+
+
+def test_labeled_and_estimator_urls(tmp_path, server):
+    data = os.urandom(256 * 1024)
+    (tmp_path / "a").write_bytes(data)
+    result = estimate_labeled([("remote", f"{server}/a"), ("local", tmp_path / "a")])
+    assert result["dedup_ratio"] == 0.5
+    estimator = Estimator()
+    assert estimator.add_file(f"{server}/a") == 0
+    assert estimator.add_file(tmp_path / "a") == 1
+    assert estimator.labels() == [f"{server}/a", str(tmp_path / "a")]
+    assert estimator.stats()["chunk_bytes"] == len(data)
@@ -0,0 +1,171 @@
+import json
+import os
+
+import pyarrow.parquet as pq
+import pytest
+
+from de.estimate import EstimationResult, estimate
+from de.formats import ParquetCpp
+from de.report import REPORT_VERSION, load_report, verify_report, write_report
+
+
+@pytest.fixture
+def inputs(tmp_path):
+    path = tmp_path / "input.bin"
+    path.write_bytes(b"abc")
+    return [path]
+
+
+@pytest.fixture
+def result():
+    return EstimationResult(
+        format=ParquetCpp(use_cdc=True, compression="zstd"),
+        numfiles=2,
+        total_len=100,
+        chunk_bytes=60,
+        compressed_chunk_bytes=50,
+        dedup_ratio=0.6,
+        xet_bytes=70,
+        xet_dedup_ratio=0.7,
+        group="g",
+    )
+
+
+class TestReport:
+    @pytest.mark.parametrize("suffix", [".json", ".parquet"])
+    def test_roundtrip(self, tmp_path, inputs, result, suffix):
+        path = tmp_path / f"report{suffix}"
+        write_report([result], path, inputs=inputs)
+        metadata, rows = load_report(path)
+        assert metadata["version"] == REPORT_VERSION
+        assert metadata["chunker"]["algorithm"] == "gearhash"
+        [entry] = metadata["inputs"]
+        assert entry["path"] == str(inputs[0])
+        assert entry["size"] == 3
+        assert entry["sha256"] == (
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        )
+        assert entry["chunks"] == 1
+        assert len(entry["chunk_digest"]) == 64
+        assert rows[0]["format"] == "parquet-cpp zstd cdc"
+        assert rows[0]["chunk_bytes"] == 60
+
+    def test_approximate_results_mark_report(self, tmp_path, result):
+        exact, approximate = tmp_path / "exact.json", tmp_path / "approximate.json"
+        write_report([result], exact)
+        write_report([{"chunk_bytes": 10, "approximate": True}], approximate)
+        assert load_report(exact)[0]["approximate"] is False
+        assert load_report(approximate)[0]["approximate"] is True
+
+    @pytest.mark.parametrize("suffix", [".json", ".parquet"])
+    def test_tags(self, tmp_path, result, suffix):
+        path = tmp_path / f"report{suffix}"
+        write_report([result], path, tags={"experiment": "e1", "revision": "abc"})
+        metadata, _ = load_report(path)
+        assert metadata["tags"] == {"experiment": "e1", "revision": "abc"}
+
+    def test_parquet_metadata_embedded(self, tmp_path, result):
+        path = tmp_path / "report.parquet"
+        write_report([result], path)
+        assert b"de.report" in pq.read_schema(path).metadata
+
+    def test_rejects_unknown_version(self, tmp_path, result):
+        path = tmp_path / "report.json"
+        write_report([result], path)
+        doc = json.loads(path.read_text())
+        path.write_text(json.dumps({**doc, "version": REPORT_VERSION + 1}))
+        with pytest.raises(ValueError, match="report version"):
+            load_report(path)
+
+    def test_rejects_other_documents(self, tmp_path):
+        path = tmp_path / "other.json"
+        path.write_text(json.dumps({"format": "de-chunk-store", "version": 1}))
+        with pytest.raises(ValueError, match="not a de-report"):
+            load_report(path)
+
+
+@pytest.fixture
+def archive(tmp_path, result):
+    paths = []
+    for i in range(4):
+        path = tmp_path / f"part-{i}.bin"
+        path.write_bytes(os.urandom(200_000))
+        paths.append(path)
+    report = tmp_path / "report.json"
+    write_report([result], report, inputs=paths)
+    return report, paths
+
+
+class TestVerifyReport:
+    def test_unchanged(self, archive):
+        report, _ = archive
+        verification = verify_report(report, fraction=1.0)
+        assert verification["checked"] == verification["total"] == 4
+        assert verification["mismatches"] == []
+        assert verification["chunker_changed"] is False
+
+    def test_samples_at_least_one_input(self, archive):
+        report, _ = archive
+        assert verify_report(report, fraction=0.01, seed=0)["checked"] == 1
+
+    def test_detects_corruption(self, archive):
+        report, paths = archive
+        data = bytearray(paths[1].read_bytes())
+        data[100_000] ^= 0xFF
+        paths[1].write_bytes(data)
+        paths[2].unlink()
+        verification = verify_report(report, fraction=1.0)
+        assert sorted(m["path"] for m in verification["mismatches"]) == [
+            str(paths[1]),
+            str(paths[2]),
+        ]
+        reasons = {m["path"]: m["reason"] for m in verification["mismatches"]}
+        assert reasons[str(paths[1])] == "sha256 mismatch"
+        assert reasons[str(paths[2])] == "missing"
+
+    def test_rejects_invalid_fraction(self, archive):
+        report, _ = archive
+        with pytest.raises(ValueError, match="fraction"):
+            verify_report(report, fraction=0)
+
+
+class TestReportOfEstimate:
+    def test_records_the_chunker_and_reuses_the_digests(self, tmp_path, monkeypatch):
+        path = tmp_path / "a.bin"
+        path.write_bytes(os.urandom(300_000))
+        result = estimate([path], chunker="fastcdc", target_chunk_size=16 * 1024)
+        report = tmp_path / "report.json"
+
+        def rechunk(*args, **kwargs):
+            raise AssertionError("the input was chunked again")
+
+        with monkeypatch.context() as m:
+            m.setattr("de.report.boundaries", rechunk)
+            write_report([result], report, inputs=[path])
+        metadata, rows = load_report(report)
+        assert metadata["chunker"]["algorithm"] == "fastcdc"
+        assert metadata["chunker"]["min_size"] == 2 * 1024
+        assert "chunker" not in rows[0]
+        [entry] = metadata["inputs"]
+        assert entry["chunks"] == result["files"][0]["chunks"] > 1
+        verification = verify_report(report, fraction=1.0)
+        assert verification["mismatches"] == []
+        assert verification["chunker_changed"] is False
+
+    def test_rejects_results_of_different_chunkers(self, tmp_path):
+        path = tmp_path / "a.bin"
+        path.write_bytes(os.urandom(1000))
+        results = [estimate([path]), estimate([path], chunker="fixed")]
+        with pytest.raises(ValueError, match="different chunkers"):
+            write_report(results, tmp_path / "report.json")
+
+    def test_records_the_chunker_of_estimation_results(self, tmp_path):
+        path = tmp_path / "a.bin"
+        path.write_bytes(os.urandom(1000))
+        result = estimate([path], chunker="fastcdc")
+        record = EstimationResult.from_estimate(result, format=ParquetCpp())
+        assert record.chunker == result["chunker"]
+        write_report([record], tmp_path / "report.json")
+        metadata, rows = load_report(tmp_path / "report.json")
+        assert metadata["chunker"]["algorithm"] == "fastcdc"
+        assert "chunker" not in rows[0]
@@ -0,0 +1,95 @@
+import io
+import os
+import threading
+import time
+
+import pytest
+
+from de.session import QuotaExceededError, Session
+
+
+def test_namespaces_are_isolated_and_persisted(tmp_path):
+    shared = os.urandom(512 * 1024)
+    (tmp_path / "a").write_bytes(shared + os.urandom(512 * 1024))
+    (tmp_path / "b").write_bytes(shared)
+    session = Session(tmp_path / "session", target_chunk_size=16 * 1024)
+    session.create_namespace("vision")
+    session.create_namespace("audio")
+    assert session.add_file("vision", tmp_path / "a") == 0
+    assert session.add_file("audio", tmp_path / "b") == 0
+    assert session.stats("vision")["total_len"] == 1024 * 1024
+    assert session.stats("audio")["chunk_bytes"] == 512 * 1024
+
+    overlap = session.overlap("vision", "audio")
+    assert overlap["shared_bytes"] == 512 * 1024
+    assert overlap["first_share"] == 0.5
+    assert overlap["second_share"] == 1.0
+    session.save()
+
+    reopened = Session(tmp_path / "session")
+    assert reopened.namespaces() == ["audio", "vision"]
+    assert reopened.stats("vision") == session.stats("vision")
+    assert reopened.overlap("vision", "audio") == overlap
+    with pytest.raises(ValueError, match="other chunker options"):
+        Session(tmp_path / "session", target_chunk_size=32 * 1024)
+
+
+def test_quotas(tmp_path):
+    session = Session(tmp_path)
+    session.create_namespace("small", max_bytes=1024 * 1024, max_inputs=2)
+    session.add_bytes("small", os.urandom(512 * 1024))
+    with pytest.raises(QuotaExceededError, match="1048576 bytes"):
+        session.add_bytes("small", os.urandom(768 * 1024))
+    with pytest.raises(QuotaExceededError, match="unknown size"):
+        session.add_bytes("small", io.BytesIO(b"x"))
+    session.add_bytes("small", os.urandom(256 * 1024))
+    with pytest.raises(QuotaExceededError, match="2 inputs"):
+        session.add_bytes("small", b"x")
+    assert session.stats("small")["total_len"] == 768 * 1024
+
+
+def test_namespace_names(tmp_path):
+    session = Session(tmp_path)
+    for name in ["../escape", ".hidden", "a/b", ""]:
+        with pytest.raises(ValueError, match="Invalid namespace"):
+            session.create_namespace(name)
+    session.create_namespace("team-1")
+    with pytest.raises(ValueError, match="already exists"):
+        session.create_namespace("team-1")
+    with pytest.raises(KeyError):
+        session.add_bytes("team-2", b"x")
+    session.add_bytes("team-1", b"x")
+    session.save()
+    session.drop_namespace("team-1")
+    assert session.namespaces() == []
+    assert not (tmp_path / "team-1.index").exists()
+
+
+def test_drop_namespace_while_waiting(tmp_path):
+    session = Session(tmp_path)
+    session.create_namespace("team")
+    session.add_bytes("team", b"x")
+    outcomes = []
+
+    def add():
+        try:
+            outcomes.append(session.add_bytes("team", b"y"))
+        except KeyError:
+            outcomes.append(None)
+
+    # the input and the drop wait for the namespace in either order
+    with session._namespace("team"):
+        threads = [
+            threading.Thread(target=add),
+            threading.Thread(target=session.drop_namespace, args=["team"]),
+        ]
+        for thread in threads:
+            thread.start()
+        time.sleep(0.1)
+    for thread in threads:
+        thread.join()
+    assert outcomes in ([1], [None])
+    assert session.namespaces() == []
+    assert session._estimators == {}
+    assert session._locks == {}
+    assert session.save() == {}
@@ -0,0 +1,116 @@
+import os
+import struct
+
+import pytest
+
+from de.core import xet_chunks, xet_hmac
+from de.xet import estimate_upload, is_global_dedup_eligible, parse_shard, xet_hex
+
+FOOTER_SIZE = 200
+
+
+def make_shard(hashes, key=bytes(32)):
+    cas = struct.pack("<32sIIII", os.urandom(32), 0, len(hashes), 0, 0)
+    cas += b"".join(struct.pack("<32sIIII", h, 0, 0, 0, 0) for h in hashes)
+    cas += struct.pack("<32sIIII", b"\xff" * 32, 0, 0, 0, 0)
+    footer = bytearray(FOOTER_SIZE)
+    struct.pack_into("<Q", footer, 16, 48)
+    footer[72:104] = key
+    return struct.pack("<32sQQ", bytes(32), 2, FOOTER_SIZE) + cas + bytes(footer)
+
+
+@pytest.fixture
+def files(tmp_path):
+    stored = os.urandom(1024 * 1024)
+    (tmp_path / "a").write_bytes(stored)
+    (tmp_path / "b").write_bytes(stored + os.urandom(512 * 1024))
+    return tmp_path / "a", tmp_path / "b"
+
+
+def credentials(repo_id, token, repo_type, revision):
+    assert (repo_id, token) == ("org/name", "hf_x")
+    return "https://cas", "access"
+
+
+class TestShards:
+    def test_hex_and_sampling(self):
+        raw = bytes(range(24)) + (1024).to_bytes(8, "little")
+        assert xet_hex(raw)[:16] == "0706050403020100"
+        assert is_global_dedup_eligible(raw)
+        assert not is_global_dedup_eligible(bytes(24) + (1).to_bytes(8, "little"))
+
+    def test_parse(self):
+        hashes = [os.urandom(32) for _ in range(3)]
+        key, parsed = parse_shard(make_shard(hashes, key=b"k" * 32))
+        assert key == b"k" * 32
+        assert parsed == set(hashes)
+
+    def test_rejects_truncated_shards(self):
+        shard = make_shard([os.urandom(32) for _ in range(3)])
+        for data in [shard[:16], shard[:48] + shard[-FOOTER_SIZE:], shard[:-1]]:
+            with pytest.raises(ValueError, match="shard"):
+                parse_shard(data)
+
+
+class TestEstimateUpload:
+    @pytest.mark.parametrize("key", [bytes(32), b"k" * 32])
+    def test_stored_chunks_are_not_transferred(self, files, key):
+        a, _ = files
+        stored = [raw for raw, _ in xet_chunks([str(a)])[0]]
+        listed = xet_hmac(stored, key) if any(key) else stored
+        shard = make_shard(listed, key)
+        queried = []
+
+        def query(cas_url, access_token, raw):
+            queried.append(raw)
+            return shard if raw in stored else None
+
+        result = estimate_upload(
+            files, "org/name", "hf_x", credentials=credentials, query=query
+        )
+        assert queried[0] == stored[0]
+        assert result["stored_bytes"] == 1024 * 1024
+        assert result["total_len"] == 2 * 1024 * 1024 + 512 * 1024
+        assert result["transfer_bytes"] == result["xet_bytes"] - 1024 * 1024
+        assert result["shards"] == 1
+
+    def test_keys_the_chunks_once_per_key(self, tmp_path, monkeypatch):
+        paths = []
+        for name in ["c", "d"]:
+            (tmp_path / name).write_bytes(os.urandom(256 * 1024))
+            paths.append(tmp_path / name)
+        stored = [raw for raw, _ in xet_chunks([str(paths[0])])[0]]
+        shard = make_shard(xet_hmac(stored, b"k" * 32), b"k" * 32)
+        calls = []
+
+        def hmac(hashes, key):
+            calls.append(key)
+            return xet_hmac(hashes, key)
+
+        monkeypatch.setattr("de.xet._xet_hmac", hmac)
+        # the first chunks of both files are queried and hit the same shard
+        result = estimate_upload(
+            paths,
+            "org/name",
+            "hf_x",
+            credentials=credentials,
+            query=lambda cas_url, access_token, raw: shard,
+        )
+        assert result["shards"] == 2
+        assert calls == [b"k" * 32]
+        assert result["stored_bytes"] == 256 * 1024
+
+    def test_names_the_chunk_of_an_invalid_shard(self, files):
+        with pytest.raises(ValueError, match="Invalid shard of chunk [0-9a-f]{64}"):
+            estimate_upload(
+                files,
+                "org/name",
+                "hf_x",
+                credentials=credentials,
+                query=lambda cas_url, access_token, raw: b"truncated",
+            )
+
+    def test_requires_token(self, files, monkeypatch):
+        monkeypatch.delenv("HF_TOKEN", raising=False)
+        with pytest.raises(ValueError, match="token is required"):
+            estimate_upload(files, "org/name", credentials=credentials)
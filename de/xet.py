@@ -0,0 +1,173 @@
+"""Pre-upload planning against the chunks already stored in the Hub's Xet
+storage.
+
+The files are cut with the Xet chunker and the chunks the Xet client would
+use for global deduplication (the first chunk of every file and the chunks
+sampled by their hash) are looked up in the content addressed storage of the
+Hub. Every hit returns a shard listing the chunks of the xorbs containing
+that chunk, and the local chunks found in any of the shards wouldn't be
+uploaded. Like the Xet client, chunks of xorbs never hit by a sampled chunk
+aren't found, so the transfer estimate is what a push would actually send.
+"""
+
+import json
+import os
+import struct
+from urllib.error import HTTPError
+from urllib.request import Request, urlopen
+
+from .core import xet_chunks as _xet_chunks, xet_hmac as _xet_hmac
+
+HUB_URL = "https://huggingface.co"
+# One in this many chunks, by hash, is queried for global deduplication.
+GLOBAL_DEDUP_SAMPLING = 1024
+
+# The shard layout of xet-core: a header with the footer size, the CAS info
+# section made of per-xorb headers followed by their chunk entries and closed
+# by a bookend, and a footer with the section offsets and the HMAC key.
+_SHARD_HEADER = struct.Struct("<32sQQ")
+_CAS_HEADER = struct.Struct("<32sIIII")
+_CAS_ENTRY = struct.Struct("<32sIIII")
+_FOOTER_CAS_INFO_OFFSET = 16
+_FOOTER_HMAC_KEY = slice(72, 104)
+_BOOKEND = b"\xff" * 32
+
+
+def xet_hex(raw: bytes) -> str:
+    """Format a raw chunk hash like the Xet API, as four little-endian u64
+    words each printed as 16 hex digits."""
+    return "".join(
+        f"{int.from_bytes(raw[i : i + 8], 'little'):016x}" for i in range(0, 32, 8)
+    )
+
+
+def is_global_dedup_eligible(raw: bytes) -> bool:
+    return int.from_bytes(raw[24:32], "little") % GLOBAL_DEDUP_SAMPLING == 0
+
+
+def xet_write_token(
+    repo_id, token, repo_type="datasets", revision="main"
+) -> tuple[str, str]:
+    """Exchange a Hub token for the URL of the Xet storage and an access
+    token to it, which requires write access to the repository."""
+    request = Request(
+        f"{HUB_URL}/api/{repo_type}/{repo_id}/xet-write-token/{revision}",
+        headers={"Authorization": f"Bearer {token}"},
+    )
+    with urlopen(request) as response:
+        credentials = json.load(response)
+    return credentials["casUrl"], credentials["accessToken"]
+
+
+def query_shard(cas_url, access_token, raw_hash) -> bytes | None:
+    """Return the shard of the xorbs containing a chunk, or None if the
+    storage doesn't know the chunk."""
+    request = Request(
+        f"{cas_url}/v1/chunks/default-merkledb/{xet_hex(raw_hash)}",
+        headers={"Authorization": f"Bearer {access_token}"},
+    )
+    try:
+        with urlopen(request) as response:
+            return response.read()
+    except HTTPError as e:
+        if e.code == 404:
+            return None
+        raise
+
+
+def parse_shard(data: bytes) -> tuple[bytes, set[bytes]]:
+    """Return the HMAC key and the chunk hashes listed by a shard; the hashes
+    are keyed with the HMAC key unless it is all zeros. Raises ValueError if
+    the shard is truncated or its offsets point outside of it."""
+    if len(data) < _SHARD_HEADER.size:
+        raise ValueError(f"Truncated shard of {len(data)} bytes")
+    _, _, footer_size = _SHARD_HEADER.unpack_from(data)
+    # the CAS info section lies between the header and the footer
+    end = len(data) - footer_size
+    if footer_size < _FOOTER_HMAC_KEY.stop or end < _SHARD_HEADER.size:
+        raise ValueError(f"Invalid footer size {footer_size} of a shard")
+    footer = data[end:]
+    key = footer[_FOOTER_HMAC_KEY]
+    (pos,) = struct.unpack_from("<Q", footer, _FOOTER_CAS_INFO_OFFSET)
+    hashes = set()
+    while True:
+        if pos + _CAS_HEADER.size > end:
+            raise ValueError(f"Truncated CAS info section of a shard at {pos}")
+        xorb_hash, _, num_entries, _, _ = _CAS_HEADER.unpack_from(data, pos)
+        pos += _CAS_HEADER.size
+        if xorb_hash == _BOOKEND:
+            return key, hashes
+        if pos + num_entries * _CAS_ENTRY.size > end:
+            raise ValueError(f"Truncated CAS info section of a shard at {pos}")
+        for _ in range(num_entries):
+            hashes.add(_CAS_ENTRY.unpack_from(data, pos)[0])
+            pos += _CAS_ENTRY.size
+
+
+def estimate_upload(
+    paths,
+    repo_id,
+    token=None,
+    repo_type="datasets",
+    revision="main",
+    credentials=xet_write_token,
+    query=query_shard,
+) -> dict:
+    """Estimate the bytes a push of the files to a Hub repository would
+    transfer, given the chunks already stored by the Xet storage.
+
+    The token defaults to the HF_TOKEN environment variable. credentials(repo_id,
+    token, repo_type, revision) returns the storage URL and access token, and
+    query(cas_url, access_token, raw_hash) the shard of a chunk or None. The
+    xet_bytes are the unique bytes of the files, of which the stored_bytes are
+    already in the storage and the transfer_bytes would be uploaded.
+    """
+    string_paths = list(map(str, paths))
+    token = token if token is not None else os.environ.get("HF_TOKEN")
+    if not token:
+        raise ValueError("A Hub token is required, pass it or set HF_TOKEN")
+
+    unique, sampled = {}, []
+    total_bytes = 0
+    for chunks in _xet_chunks(string_paths):
+        for i, (raw, size) in enumerate(chunks):
+            total_bytes += size
+            if raw in unique:
+                continue
+            unique[raw] = size
+            if i == 0 or is_global_dedup_eligible(raw):
+                sampled.append(raw)
+
+    cas_url, access_token = credentials(repo_id, token, repo_type, revision)
+    stored, shards = set(), 0
+    # the unique chunks by their keyed hash for every HMAC key, which the
+    # shards of a storage usually share, so they are keyed once
+    keyed = {}
+    for raw in sampled:
+        if raw in stored:
+            continue
+        shard = query(cas_url, access_token, raw)
+        if shard is None:
+            continue
+        shards += 1
+        try:
+            key, hashes = parse_shard(shard)
+        except ValueError as e:
+            raise ValueError(f"Invalid shard of chunk {xet_hex(raw)}: {e}") from e
+        if key not in keyed:
+            raws = list(unique)
+            keyed[key] = dict(zip(_xet_hmac(raws, key) if any(key) else raws, raws))
+        chunks = keyed[key]
+        stored.update(chunks[h] for h in hashes if h in chunks)
+
+    xet_bytes = sum(unique.values())
+    stored_bytes = sum(unique[h] for h in stored)
+    return {
+        "numfiles": len(string_paths),
+        "total_len": total_bytes,
+        "xet_bytes": xet_bytes,
+        "stored_bytes": stored_bytes,
+        "transfer_bytes": xet_bytes - stored_bytes,
+        "queried_chunks": len(sampled),
+        "shards": shards,
+    }
@@ -0,0 +1,119 @@
+use std::f64::consts::LN_2;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"DEBLOOM1";
+
+/// Bloom filter over 64-bit chunk hashes, exchanged between parties to
+/// estimate their mutual deduplication without revealing the exact hashes.
+#[derive(Debug)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    /// Size the filter for the expected number of items and false positive
+    /// probability.
+    pub fn new(num_items: usize, fpp: f64) -> io::Result<Self> {
+        if fpp.is_nan() || fpp <= 0.0 || fpp >= 1.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("False positive probability must be in (0, 1), got {fpp}"),
+            ));
+        }
+        let n = num_items.max(1) as f64;
+        let num_bits = (-n * fpp.ln() / (LN_2 * LN_2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / n) * LN_2).round().max(1.0) as u32;
+        Ok(BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+        })
+    }
+
+    /// A filter of the given number of bits and hash functions, e.g. to fit
+    /// a memory budget rather than an expected number of items.
+    pub fn with_bits(num_bits: u64, num_hashes: u32) -> Self {
+        let num_bits = num_bits.max(64);
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes: num_hashes.max(1),
+        }
+    }
+
+    /// The probability that an item never inserted is reported as
+    /// contained, given the fraction of the bits set so far.
+    pub fn false_positive_rate(&self) -> f64 {
+        let set: u64 = self.bits.iter().map(|word| word.count_ones() as u64).sum();
+        (set as f64 / self.num_bits as f64).powi(self.num_hashes as i32)
+    }
+
+    fn positions(&self, hash: u64) -> impl Iterator<Item = u64> + '_ {
+        // double hashing, the second hash is derived from the first one
+        let h1 = hash;
+        let h2 = hash.rotate_left(32).wrapping_mul(0x9e3779b97f4a7c15) | 1;
+        (0..self.num_hashes as u64)
+            .map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+    }
+
+    pub fn insert(&mut self, hash: u64) {
+        let positions: Vec<u64> = self.positions(hash).collect();
+        for pos in positions {
+            self.bits[(pos / 64) as usize] |= 1 << (pos % 64);
+        }
+    }
+
+    pub fn contains(&self, hash: u64) -> bool {
+        self.positions(hash)
+            .all(|pos| self.bits[(pos / 64) as usize] & (1 << (pos % 64)) != 0)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&self.num_bits.to_le_bytes())?;
+        w.write_all(&self.num_hashes.to_le_bytes())?;
+        for word in &self.bits {
+            w.write_all(&word.to_le_bytes())?;
+        }
+        w.flush()
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a bloom filter file",
+            ));
+        }
+        let mut u64_buf = [0u8; 8];
+        let mut u32_buf = [0u8; 4];
+        r.read_exact(&mut u64_buf)?;
+        let num_bits = u64::from_le_bytes(u64_buf);
+        r.read_exact(&mut u32_buf)?;
+        let num_hashes = u32::from_le_bytes(u32_buf);
+        if num_bits == 0 || num_hashes == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Bloom filter has no bits or hash functions",
+            ));
+        }
+        let mut bits = Vec::with_capacity(num_bits.div_ceil(64) as usize);
+        for _ in 0..num_bits.div_ceil(64) {
+            r.read_exact(&mut u64_buf)?;
+            bits.push(u64::from_le_bytes(u64_buf));
+        }
+        Ok(BloomFilter {
+            bits,
+            num_bits,
+            num_hashes,
+        })
+    }
+}
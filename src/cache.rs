@@ -0,0 +1,176 @@
+//! Cache of per-file chunk recipes, used to avoid re-chunking unchanged files
+//! and to only re-chunk the new tail of appended files on repeated runs.
+
+use crate::decompress;
+use crate::progress::Progress;
+use crate::sniff::{sniff, sniff_reader};
+use crate::store::{is_hash_list, ChunkParams, ChunkStore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use xxhash_rust::xxh3::xxh3_64;
+
+const QUICK_HASH_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct FileRecipe {
+    size: u64,
+    mtime: SystemTime,
+    /// Hash of the first QUICK_HASH_SIZE bytes.
+    head_hash: u64,
+    /// Hash of the last QUICK_HASH_SIZE bytes.
+    tail_hash: u64,
+    /// The (hash, size, compressed size) of the chunks in order.
+    chunks: Vec<(u64, usize, usize)>,
+    /// Why the file looked compressed or encrypted, see the `sniff` module.
+    #[serde(default)]
+    sniffed: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct RecipeCache {
+    /// The chunker parameters the recipes were cut with, caches written
+    /// before they were recorded used the defaults.
+    #[serde(default)]
+    params: ChunkParams,
+    files: HashMap<String, FileRecipe>,
+}
+
+fn quick_hash(file: &mut File, end: u64) -> io::Result<(u64, u64)> {
+    let mut buf = Vec::with_capacity(QUICK_HASH_SIZE as usize);
+    file.seek(SeekFrom::Start(0))?;
+    file.by_ref()
+        .take(QUICK_HASH_SIZE.min(end))
+        .read_to_end(&mut buf)?;
+    let head = xxh3_64(&buf);
+
+    buf.clear();
+    file.seek(SeekFrom::Start(end.saturating_sub(QUICK_HASH_SIZE)))?;
+    file.by_ref()
+        .take(QUICK_HASH_SIZE.min(end))
+        .read_to_end(&mut buf)?;
+    let tail = xxh3_64(&buf);
+    Ok((head, tail))
+}
+
+impl RecipeCache {
+    /// Load the cache of recipes cut with the given chunker parameters, a
+    /// missing file or one cut with other parameters results in an empty
+    /// cache. Recipes with chunks over the maximum length were cut before it
+    /// was enforced and are dropped.
+    pub fn load<P: AsRef<Path>>(path: P, params: ChunkParams) -> io::Result<Self> {
+        let empty = RecipeCache {
+            params,
+            files: HashMap::new(),
+        };
+        match File::open(path) {
+            Ok(file) => {
+                let mut cache: Self =
+                    serde_json::from_reader(BufReader::new(file)).map_err(io::Error::other)?;
+                if cache.params != params {
+                    return Ok(empty);
+                }
+                cache.files.retain(|_, recipe| {
+                    recipe
+                        .chunks
+                        .iter()
+                        .all(|&(_, size, _)| size <= params.max_len)
+                });
+                Ok(cache)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(empty),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut w, self)?;
+        w.flush()
+    }
+
+    pub fn insert(&mut self, path: String, recipe: FileRecipe) {
+        self.files.insert(path, recipe);
+    }
+
+    /// Chunk a file reusing its cached recipe where possible. Unchanged files
+    /// (same size, mtime and quick hashes) are not read at all, files that only
+    /// grew are re-chunked from the start of their last cached chunk, every
+    /// other file is chunked from scratch. Hash lists are not cached, and
+    /// decompressed files which grew are chunked from scratch too since
+    /// appending to them doesn't append to their decompressed stream. The
+    /// blocks read are reported to `progress`, the cached chunks at once.
+    pub fn chunk_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        progress: &Progress,
+    ) -> io::Result<(ChunkStore, Option<FileRecipe>)> {
+        let key = path.as_ref().to_string_lossy().into_owned();
+        let mut file = File::open(&path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+        let mtime = metadata.modified()?;
+
+        if is_hash_list(&path) {
+            let store = ChunkStore::from_file_with_params(&path, false, self.params, progress)?;
+            return Ok((store, None));
+        }
+
+        let decompressed = self.params.decompress && decompress::detect(&path)?.is_some();
+        let mut store = ChunkStore::new(false);
+        let mut resume_from = 0;
+        if let Some(cached) = self.files.get(&key) {
+            if cached.size == size && cached.mtime == mtime {
+                if quick_hash(&mut file, size)? == (cached.head_hash, cached.tail_hash) {
+                    store.set_sniffed(cached.sniffed.clone());
+                    for &(hash, size, compressed) in &cached.chunks {
+                        store.add_cached(hash, size, compressed);
+                    }
+                    progress.add_bytes(store.total())?;
+                    return Ok((store, Some(cached.clone())));
+                }
+            } else if cached.size < size
+                && !decompressed
+                && quick_hash(&mut file, cached.size)? == (cached.head_hash, cached.tail_hash)
+            {
+                // the last chunk ended at EOF instead of a content boundary
+                if let Some((_, rest)) = cached.chunks.split_last() {
+                    for &(hash, size, compressed) in rest {
+                        store.add_cached(hash, size, compressed);
+                    }
+                    resume_from = store.total() as u64;
+                    progress.add_bytes(store.total())?;
+                }
+            }
+        }
+
+        store.set_params(self.params);
+        let sniffed = if decompressed {
+            let (sniffed, mut reader) = sniff_reader(decompress::open(&path, true)?)?;
+            store.set_sniffed(sniffed.clone());
+            store.chunk_stream(&mut reader, progress)?;
+            sniffed
+        } else {
+            file.seek(SeekFrom::Start(0))?;
+            let sniffed = sniff(&mut file)?;
+            store.set_sniffed(sniffed.clone());
+            file.seek(SeekFrom::Start(resume_from))?;
+            store.chunk_stream(&mut BufReader::new(&mut file), progress)?;
+            sniffed
+        };
+
+        let (head_hash, tail_hash) = quick_hash(&mut file, size)?;
+        let recipe = FileRecipe {
+            size,
+            mtime,
+            head_hash,
+            tail_hash,
+            chunks: store.recipe(),
+            sniffed,
+        };
+        Ok((store, Some(recipe)))
+    }
+}
@@ -0,0 +1,69 @@
+//! Writer of casync/desync blob index files (`.caibx`), listing the chunks of
+//! a file so the estimates can be validated with those tools on the same
+//! data. All integers are little-endian, the layout is:
+//!
+//! ```text
+//! header:  size=48, type=CA_FORMAT_INDEX, feature flags, min/avg/max size
+//! table:   size=u64::MAX, type=CA_FORMAT_TABLE
+//! items:   end offset of the chunk, SHA-512/256 chunk id (32 bytes)
+//! tail:    0, 0, index offset=48, table size, CA_FORMAT_TABLE_TAIL_MARKER
+//! ```
+
+use crate::store::{ChunkStore, MAX_LEN, MIN_LEN};
+use sha2::{Digest, Sha512_256};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const CA_FORMAT_INDEX: u64 = 0x96824d9c7b129ff9;
+const CA_FORMAT_TABLE: u64 = 0xe75b9e112f17417d;
+const CA_FORMAT_TABLE_TAIL_MARKER: u64 = 0x4b4f050e5549ecd1;
+const CA_FORMAT_EXCLUDE_NODUMP: u64 = 0x8000000000000000;
+const CA_FORMAT_SHA512_256: u64 = 0x2000000000000000;
+const INDEX_HEADER_SIZE: u64 = 48;
+const TABLE_HEADER_SIZE: u64 = 16;
+const TABLE_ITEM_SIZE: u64 = 40;
+const TABLE_TAIL_SIZE: u64 = 40;
+/// Expected chunk size of the gearhash mask, which has 16 bits set.
+const AVG_LEN: usize = 65536;
+
+/// Re-read the file along the chunk boundaries of its store and write its
+/// blob index, returning the number of chunks. Empty chunks, e.g. the final
+/// one of files ending at a boundary, are left out since casync has none.
+pub(crate) fn write_index<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: P,
+    store: &ChunkStore,
+    output: Q,
+) -> io::Result<usize> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut w = BufWriter::new(File::create(output)?);
+    w.write_all(&INDEX_HEADER_SIZE.to_le_bytes())?;
+    w.write_all(&CA_FORMAT_INDEX.to_le_bytes())?;
+    w.write_all(&(CA_FORMAT_EXCLUDE_NODUMP | CA_FORMAT_SHA512_256).to_le_bytes())?;
+    for size in [MIN_LEN, AVG_LEN, MAX_LEN] {
+        w.write_all(&(size as u64).to_le_bytes())?;
+    }
+    w.write_all(&u64::MAX.to_le_bytes())?;
+    w.write_all(&CA_FORMAT_TABLE.to_le_bytes())?;
+
+    let mut offset = 0u64;
+    let mut count = 0;
+    let mut data = Vec::with_capacity(MAX_LEN);
+    for size in store.sizes().into_iter().filter(|&size| size > 0) {
+        data.resize(size, 0);
+        reader.read_exact(&mut data)?;
+        offset += size as u64;
+        count += 1;
+        w.write_all(&offset.to_le_bytes())?;
+        w.write_all(&Sha512_256::digest(&data))?;
+    }
+
+    let table_size = TABLE_HEADER_SIZE + count as u64 * TABLE_ITEM_SIZE + TABLE_TAIL_SIZE;
+    w.write_all(&0u64.to_le_bytes())?;
+    w.write_all(&0u64.to_le_bytes())?;
+    w.write_all(&INDEX_HEADER_SIZE.to_le_bytes())?;
+    w.write_all(&table_size.to_le_bytes())?;
+    w.write_all(&CA_FORMAT_TABLE_TAIL_MARKER.to_le_bytes())?;
+    w.flush()?;
+    Ok(count)
+}
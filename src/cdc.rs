@@ -0,0 +1,310 @@
+//! Cut point detection of the supported chunking algorithms.
+//!
+//! - `gearhash`: a boundary wherever the gear rolling hash matches the mask,
+//!   the default of this tool.
+//! - `fastcdc`: the same gear hash with normalized chunking, a mask with two
+//!   more bits below the target size and two less above it, which narrows the
+//!   chunk size distribution around the target.
+//! - `rabin`: a Rabin fingerprint over a 64-byte window with the irreducible
+//!   polynomial of restic's tests, matching the low bits of the digest like
+//!   LBFS and restic.
+//! - `fixed`: blocks of the target size, i.e. no content defined boundaries.
+//!
+//! All of them honor the minimum and maximum chunk lengths of `ChunkParams`,
+//! only the fixed-size chunker ignores the minimum.
+//!
+//! The rolling hashes only depend on the last `HASH_HISTORY` bytes, so
+//! `parallel_cuts` can split a buffer into ranges scanned concurrently and
+//! stitch their cuts back into the ones of a single sequential scan.
+
+use crate::store::ChunkParams;
+use gearhash::Hasher;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::OnceLock;
+
+const RABIN_POLYNOMIAL: u64 = 0x3DA3358B4DC173;
+const RABIN_DEGREE: u32 = 53;
+const RABIN_WINDOW: usize = 64;
+/// Bytes the rolling hash states depend on: the gear hash shifts every byte
+/// out of its 64 bits after 64 more bytes and the Rabin window is as long.
+const HASH_HISTORY: usize = 64;
+/// Size of the ranges of a buffer scanned concurrently by `parallel_cuts`.
+const RANGE_SIZE: usize = 4 << 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Algorithm {
+    #[default]
+    Gearhash,
+    FastCdc,
+    Rabin,
+    Fixed,
+}
+
+impl Algorithm {
+    pub fn new(name: &str) -> io::Result<Self> {
+        match name.to_lowercase().as_str() {
+            "gearhash" => Ok(Algorithm::Gearhash),
+            "fastcdc" => Ok(Algorithm::FastCdc),
+            "rabin" => Ok(Algorithm::Rabin),
+            "fixed" => Ok(Algorithm::Fixed),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown chunker: {other}"),
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Gearhash => "gearhash",
+            Algorithm::FastCdc => "fastcdc",
+            Algorithm::Rabin => "rabin",
+            Algorithm::Fixed => "fixed",
+        }
+    }
+}
+
+/// Degree of a polynomial over GF(2), -1 for the zero polynomial.
+fn degree(x: u64) -> i32 {
+    63 - x.leading_zeros() as i32
+}
+
+fn modulo(mut x: u64, d: u64) -> u64 {
+    while degree(x) >= degree(d) {
+        x ^= d << (degree(x) - degree(d));
+    }
+    x
+}
+
+struct RabinTables {
+    /// Contribution of a byte leaving the window.
+    out: [u64; 256],
+    /// Reduction of the byte shifted out at the top of the digest.
+    reduce: [u64; 256],
+}
+
+fn rabin_tables() -> &'static RabinTables {
+    static TABLES: OnceLock<RabinTables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = RabinTables {
+            out: [0; 256],
+            reduce: [0; 256],
+        };
+        for b in 0..256u64 {
+            let mut h = modulo(b, RABIN_POLYNOMIAL);
+            for _ in 0..RABIN_WINDOW - 1 {
+                h = modulo(h << 8, RABIN_POLYNOMIAL);
+            }
+            tables.out[b as usize] = h;
+            let top = b << RABIN_DEGREE;
+            tables.reduce[b as usize] = modulo(top, RABIN_POLYNOMIAL) | top;
+        }
+        tables
+    })
+}
+
+/// Rabin fingerprint of the last `RABIN_WINDOW` bytes.
+struct Rabin {
+    window: [u8; RABIN_WINDOW],
+    pos: usize,
+    digest: u64,
+}
+
+impl Rabin {
+    fn new() -> Self {
+        let mut rabin = Rabin {
+            window: [0; RABIN_WINDOW],
+            pos: 0,
+            digest: 0,
+        };
+        // like restic, start from a non-zero digest
+        rabin.roll(1);
+        rabin
+    }
+
+    fn roll(&mut self, b: u8) {
+        let tables = rabin_tables();
+        let out = std::mem::replace(&mut self.window[self.pos], b);
+        self.pos = (self.pos + 1) % RABIN_WINDOW;
+        self.digest ^= tables.out[out as usize];
+        let top = (self.digest >> (RABIN_DEGREE - 8)) as usize;
+        self.digest = ((self.digest << 8) | b as u64) ^ tables.reduce[top];
+    }
+
+    fn next_match(&mut self, data: &[u8], mask: u64) -> Option<usize> {
+        for (i, &b) in data.iter().enumerate() {
+            self.roll(b);
+            if self.digest & mask == 0 {
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+}
+
+enum Roller {
+    Gear(Hasher<'static>),
+    Rabin(Rabin),
+    None,
+}
+
+/// Finds the cut points of consecutive data with the configured algorithm.
+/// The rolling hash carries over between calls, so a stream can be scanned
+/// in blocks of any size.
+pub(crate) struct Cutter {
+    params: ChunkParams,
+    roller: Roller,
+    /// Mask used from the target size on, and below it unless normalized.
+    mask: u64,
+    /// Stricter mask used below the target size by FastCDC.
+    small_mask: u64,
+}
+
+impl Cutter {
+    pub fn new(params: ChunkParams) -> Self {
+        let bits = params.mask.count_ones();
+        let high_bits = |bits: u32| !0u64 << (64 - bits.clamp(1, 63));
+        let (roller, mask, small_mask) = match params.algorithm {
+            Algorithm::Gearhash => (Roller::Gear(Hasher::default()), params.mask, params.mask),
+            Algorithm::FastCdc => (
+                Roller::Gear(Hasher::default()),
+                high_bits(bits.saturating_sub(2)),
+                high_bits(bits + 2),
+            ),
+            Algorithm::Rabin => {
+                let mask = (1u64 << bits) - 1;
+                (Roller::Rabin(Rabin::new()), mask, mask)
+            }
+            Algorithm::Fixed => (Roller::None, 0, 0),
+        };
+        Cutter {
+            params,
+            roller,
+            mask,
+            small_mask,
+        }
+    }
+
+    /// Roll the hash over the bytes preceding the scanned data without
+    /// looking for cuts, so that it matches the state of a cutter which
+    /// scanned them.
+    fn prime(&mut self, data: &[u8]) {
+        match &mut self.roller {
+            Roller::Gear(hasher) => hasher.update(data),
+            Roller::Rabin(rabin) => data.iter().for_each(|&b| rabin.roll(b)),
+            Roller::None => {}
+        }
+    }
+
+    fn next_match(&mut self, data: &[u8], mask: u64) -> Option<usize> {
+        match &mut self.roller {
+            Roller::Gear(hasher) => hasher.next_match(data, mask),
+            Roller::Rabin(rabin) => rabin.next_match(data, mask),
+            Roller::None => None,
+        }
+    }
+
+    /// Scan `data`, the continuation of a chunk already `len` bytes long, for
+    /// the end of the chunk. Returns the number of bytes of `data` up to the
+    /// cut, or None if the chunk continues past the end of `data`.
+    pub fn next_cut(&mut self, data: &[u8], mut len: usize) -> Option<usize> {
+        let (min_len, max_len) = (self.params.min_len, self.params.max_len);
+        let target = self.params.target();
+        if self.params.algorithm == Algorithm::Fixed {
+            let rest = target.min(max_len) - len;
+            return (data.len() >= rest).then_some(rest);
+        }
+        let mut pos = 0;
+        while pos < data.len() {
+            // never scan past the bytes which fit in the current chunk, nor
+            // past the target size while the normalized mask applies
+            let mut limit = max_len - len;
+            let mask = if len < target && self.small_mask != self.mask {
+                limit = limit.min(target - len);
+                self.small_mask
+            } else {
+                self.mask
+            };
+            let end = data.len().min(pos + limit);
+            match self.next_match(&data[pos..end], mask) {
+                Some(size) => {
+                    pos += size;
+                    len += size;
+                    if len >= min_len {
+                        return Some(pos);
+                    }
+                }
+                None => {
+                    len += end - pos;
+                    pos = end;
+                }
+            }
+            if len == max_len {
+                return Some(pos);
+            }
+        }
+        None
+    }
+}
+
+/// A cutter scanning `data` from `start` on, a chunk boundary, in the state
+/// of one which scanned everything before it.
+fn cutter_at(data: &[u8], start: usize, params: ChunkParams) -> Cutter {
+    let mut cutter = Cutter::new(params);
+    cutter.prime(&data[start.saturating_sub(HASH_HISTORY)..start]);
+    cutter
+}
+
+/// The end offsets of the chunks of `data` but the last one, the same as a
+/// sequential scan, found on all the cores.
+///
+/// Every range is scanned as if a chunk started at its beginning. Its cuts
+/// are those of the sequential scan from the first one the two share on,
+/// since both then continue from the same position with the same hash state,
+/// so the sequential scan only needs to be replayed from the last shared cut
+/// of a range until it meets a cut of the next one, usually within a couple
+/// of chunks.
+pub(crate) fn parallel_cuts(data: &[u8], params: ChunkParams) -> Vec<usize> {
+    let range_size = RANGE_SIZE.max(4 * params.max_len);
+    let ranges: Vec<usize> = (0..data.len()).step_by(range_size).collect();
+    let range_cuts: Vec<Vec<usize>> = ranges
+        .par_iter()
+        .map(|&start| {
+            let end = data.len().min(start + range_size);
+            let mut cutter = cutter_at(data, start, params);
+            let mut cuts = Vec::new();
+            let mut pos = start;
+            while let Some(size) = cutter.next_cut(&data[pos..end], 0) {
+                pos += size;
+                cuts.push(pos);
+            }
+            cuts
+        })
+        .collect();
+
+    let mut cuts = Vec::new();
+    let mut pos = 0;
+    loop {
+        let range = pos / range_size;
+        if let Some(range_cuts) = range_cuts.get(range) {
+            let synced = pos == ranges[range] || range_cuts.binary_search(&pos).is_ok();
+            let rest = &range_cuts[range_cuts.partition_point(|&cut| cut <= pos)..];
+            if let Some(&last) = rest.last().filter(|_| synced) {
+                cuts.extend_from_slice(rest);
+                pos = last;
+                continue;
+            }
+        }
+        // replay the sequential scan for the chunk starting at pos
+        match cutter_at(data, pos, params).next_cut(&data[pos..], 0) {
+            Some(size) => {
+                pos += size;
+                cuts.push(pos);
+            }
+            None => break,
+        }
+    }
+    cuts
+}
@@ -0,0 +1,96 @@
+//! Codecs estimating the stored size of the unique chunks, so the compressed
+//! stats match the compression of the storage backend.
+//!
+//! - `lz4`: lz4_flex block compression, the default of this tool.
+//! - `zstd`: levels 1 to 22, 3 by default.
+//! - `gzip`: levels 0 to 9, 6 by default.
+//! - `brotli`: qualities 0 to 11, 11 by default.
+//! - `none`: the chunks are stored as is.
+//!
+//! Every chunk is compressed on its own, like backends storing the chunks as
+//! separate objects or frames.
+
+use brotli::enc::BrotliEncoderParams;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lz4_flex::block;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Codec {
+    #[default]
+    Lz4,
+    Zstd(i32),
+    Gzip(u32),
+    Brotli(u32),
+    None,
+}
+
+impl Codec {
+    /// The codec called `name` at `level`, or at its default level if None.
+    /// The level is ignored by `lz4` and `none`.
+    pub fn new(name: &str, level: Option<i32>) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        let name = name.to_lowercase();
+        let level = |default: i32, min: i32, max: i32| match level.unwrap_or(default) {
+            level if (min..=max).contains(&level) => Ok(level),
+            level => Err(invalid(format!(
+                "The {name} level must be between {min} and {max}, got {level}"
+            ))),
+        };
+        match name.as_str() {
+            "lz4" => Ok(Codec::Lz4),
+            "zstd" => Ok(Codec::Zstd(level(3, 1, 22)?)),
+            "gzip" => Ok(Codec::Gzip(level(6, 0, 9)? as u32)),
+            "brotli" => Ok(Codec::Brotli(level(11, 0, 11)? as u32)),
+            "none" => Ok(Codec::None),
+            other => Err(invalid(format!("Unknown codec: {other}"))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Lz4 => "lz4",
+            Codec::Zstd(_) => "zstd",
+            Codec::Gzip(_) => "gzip",
+            Codec::Brotli(_) => "brotli",
+            Codec::None => "none",
+        }
+    }
+
+    /// The level of the codec, 0 for the codecs without levels.
+    pub fn level(&self) -> i32 {
+        match self {
+            Codec::Zstd(level) => *level,
+            Codec::Gzip(level) | Codec::Brotli(level) => *level as i32,
+            Codec::Lz4 | Codec::None => 0,
+        }
+    }
+
+    /// The size of `data` compressed by this codec. Compressing into memory
+    /// can't fail, the size of the data is returned if it does anyway.
+    pub fn compressed_len(&self, data: &[u8]) -> usize {
+        let compressed = match self {
+            Codec::Lz4 => Ok(block::compress(data).len()),
+            Codec::Zstd(level) => zstd::bulk::compress(data, *level).map(|c| c.len()),
+            Codec::Gzip(level) => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::new(*level));
+                encoder
+                    .write_all(data)
+                    .and_then(|_| encoder.finish())
+                    .map(|c| c.len())
+            }
+            Codec::Brotli(level) => {
+                let params = BrotliEncoderParams {
+                    quality: *level as i32,
+                    ..Default::default()
+                };
+                let mut out = Vec::new();
+                brotli::BrotliCompress(&mut &data[..], &mut out, &params).map(|_| out.len())
+            }
+            Codec::None => Ok(data.len()),
+        };
+        compressed.unwrap_or(data.len())
+    }
+}
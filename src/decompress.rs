@@ -0,0 +1,87 @@
+//! Transparent decompression of gzip, zstd and xz compressed inputs, e.g.
+//! `jsonl.gz` or `.tar.zst` dumps. Their raw bytes barely deduplicate since
+//! any change reshuffles the whole compressed stream after it, so with
+//! `ChunkParams::decompress` the decompressed stream is chunked instead.
+//!
+//! The format is detected from the magic bytes rather than the extension,
+//! concatenated members or frames are decompressed as one stream.
+
+use crate::remote::{self, RemoteFile};
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use xz2::read::XzDecoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+const MAGIC_BYTES: [(&[u8], Format); 3] = [
+    (&[0x1f, 0x8b], Format::Gzip),
+    (&[0x28, 0xb5, 0x2f, 0xfd], Format::Zstd),
+    (&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00], Format::Xz),
+];
+
+/// Length of the longest magic bytes.
+const MAGIC_LEN: usize = 6;
+
+/// Open a local file or, for the URL of an object store, a reader of the
+/// object, see the `remote` module.
+fn open_raw<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Read + Send>> {
+    if remote::is_remote(&path) {
+        let url = path.as_ref().to_string_lossy();
+        return Ok(Box::new(RemoteFile::open(&url)?.reader(0)));
+    }
+    Ok(Box::new(File::open(path)?))
+}
+
+/// Read the first bytes of a stream and the compression format they start,
+/// if it is one of the decompressed ones.
+fn read_magic<R: Read>(reader: &mut R) -> io::Result<(Vec<u8>, Option<Format>)> {
+    let mut header = Vec::with_capacity(MAGIC_LEN);
+    reader
+        .by_ref()
+        .take(MAGIC_LEN as u64)
+        .read_to_end(&mut header)?;
+    let format = MAGIC_BYTES
+        .iter()
+        .find(|(magic, _)| header.starts_with(magic))
+        .map(|&(_, format)| format);
+    Ok((header, format))
+}
+
+/// The compression format of a file from its magic bytes, if it is one of
+/// the decompressed ones.
+pub(crate) fn detect<P: AsRef<Path>>(path: P) -> io::Result<Option<Format>> {
+    Ok(read_magic(&mut open_raw(path)?)?.1)
+}
+
+/// Open a file as a reader of its decompressed stream if `decompress` is set
+/// and its format is detected, of its raw bytes otherwise. The file is opened
+/// once, the magic bytes peeked at being chained back in front of the rest.
+pub(crate) fn open<P: AsRef<Path>>(path: P, decompress: bool) -> io::Result<Box<dyn Read + Send>> {
+    let file = open_raw(path)?;
+    if !decompress {
+        return Ok(file);
+    }
+    decompressed(file)
+}
+
+/// A reader of the decompressed stream of `reader` if its format is detected,
+/// of its bytes otherwise, like `open` for a stream which is not a file.
+pub(crate) fn decompressed<'a, R: Read + Send + 'a>(
+    mut reader: R,
+) -> io::Result<Box<dyn Read + Send + 'a>> {
+    let (header, format) = read_magic(&mut reader)?;
+    let reader = io::Cursor::new(header).chain(reader);
+    Ok(match format {
+        None => Box::new(reader),
+        Some(Format::Gzip) => Box::new(MultiGzDecoder::new(BufReader::new(reader))),
+        Some(Format::Zstd) => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        Some(Format::Xz) => Box::new(XzDecoder::new_multi_decoder(BufReader::new(reader))),
+    })
+}
@@ -0,0 +1,126 @@
+//! Delta compression of the unique chunks against similar earlier chunks,
+//! estimating the savings left on the table by chunks which differ from an
+//! earlier one by a few bytes, e.g. a rewritten parquet page, and so never
+//! deduplicate exactly.
+//!
+//! Similar chunks are found by their super-features like in "WAN Optimized
+//! Replication of Backup Datasets Using Stream-Informed Delta Compression"
+//! (Shilane et al.): every feature is the maximum of a linear transform of
+//! the gear rolling hash over the chunk, and a super-feature hashes a group
+//! of them. Chunks sharing a super-feature very likely share most of their
+//! content, while the few changed bytes only affect the features whose
+//! maximum they hold.
+
+use crate::store::ChunkStore;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+use xxhash_rust::xxh3::xxh3_64;
+use zstd::bulk::Compressor;
+
+const FEATURES: usize = 12;
+const FEATURES_PER_SUPER_FEATURE: usize = 4;
+const SUPER_FEATURES: usize = FEATURES / FEATURES_PER_SUPER_FEATURE;
+
+/// Unique chunks with a similar earlier chunk and their delta-compressed
+/// size.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct DeltaStats {
+    pub total: usize,
+    pub chunk_bytes: usize,
+    pub similar_chunks: usize,
+    pub similar_bytes: usize,
+    /// The similar chunks compressed on their own.
+    pub compressed_bytes: usize,
+    /// The similar chunks compressed against their similar chunk, at most
+    /// their `compressed_bytes` since a store would keep the smaller one.
+    pub delta_bytes: usize,
+}
+
+struct Tables {
+    gear: [u64; 256],
+    /// The (odd multiplier, addend) of the transform of every feature.
+    transforms: [(u64, u64); FEATURES],
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| Tables {
+        gear: std::array::from_fn(|b| xxh3_64(&[b as u8])),
+        transforms: std::array::from_fn(|i| {
+            let seed = (i as u64).to_le_bytes();
+            (xxh3_64(&seed) | 1, xxh3_64(&xxh3_64(&seed).to_le_bytes()))
+        }),
+    })
+}
+
+fn super_features(data: &[u8]) -> [u64; SUPER_FEATURES] {
+    let tables = tables();
+    let mut features = [0u64; FEATURES];
+    let mut hash = 0u64;
+    for &b in data {
+        hash = (hash << 1).wrapping_add(tables.gear[b as usize]);
+        for (feature, (mul, add)) in features.iter_mut().zip(&tables.transforms) {
+            *feature = (*feature).max(hash.wrapping_mul(*mul).wrapping_add(*add));
+        }
+    }
+    let mut super_features = [0; SUPER_FEATURES];
+    for (sf, group) in super_features
+        .iter_mut()
+        .zip(features.chunks_exact(FEATURES_PER_SUPER_FEATURE))
+    {
+        let bytes: Vec<u8> = group.iter().flat_map(|f| f.to_le_bytes()).collect();
+        *sf = xxh3_64(&bytes);
+    }
+    super_features
+}
+
+/// Look up every new unique chunk of the files by its super-features among
+/// the chunks before it and compress the ones found similar at zstd `level`,
+/// both on their own and with the similar chunk as the dictionary. The data
+/// of all the unique chunks is held in memory to serve as the base of later
+/// ones, the files are chunked one at a time.
+pub(crate) fn delta_matches<P: AsRef<Path>>(paths: &[P], level: i32) -> io::Result<DeltaStats> {
+    let mut stats = DeltaStats::default();
+    let mut seen = HashSet::new();
+    let mut bases: Vec<Vec<u8>> = Vec::new();
+    // the first chunk with each super-feature, per super-feature slot
+    let mut index: HashMap<(usize, u64), usize> = HashMap::new();
+    for path in paths {
+        let store = ChunkStore::from_file(path, true)?;
+        stats.total += store.total();
+        for (hash, chunk) in store.iter_ordered() {
+            if !seen.insert(hash) {
+                continue;
+            }
+            let Some(data) = &chunk.data else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "hash lists don't carry the chunk data",
+                ));
+            };
+            stats.chunk_bytes += data.len();
+            let features = super_features(data);
+            let base = features
+                .iter()
+                .enumerate()
+                .find_map(|(slot, &sf)| index.get(&(slot, sf)).copied());
+            if let Some(base) = base {
+                let compressed = zstd::bulk::compress(data, level)?.len();
+                let delta = Compressor::with_dictionary(level, &bases[base])?
+                    .compress(data)?
+                    .len();
+                stats.similar_chunks += 1;
+                stats.similar_bytes += data.len();
+                stats.compressed_bytes += compressed;
+                stats.delta_bytes += delta.min(compressed);
+            }
+            for (slot, &sf) in features.iter().enumerate() {
+                index.entry((slot, sf)).or_insert(bases.len());
+            }
+            bases.push(data.clone());
+        }
+    }
+    Ok(stats)
+}
@@ -0,0 +1,100 @@
+//! Estimate of compressing the unique chunks with a shared zstd dictionary,
+//! which helps small chunks the most since they carry too little data to
+//! build up their own compression context.
+//!
+//! A first pass keeps the unique chunks with the smallest hashes up to a
+//! sample budget, a uniform and deterministic sample of the corpus, and
+//! trains the dictionary on them. A second pass compresses every unique
+//! chunk on its own with the dictionary. Both passes observe the chunks
+//! through the `metrics` hook.
+
+use crate::metrics::{chunk_files, ChunkMetric};
+use crate::store::{ChunkParams, ChunkStore};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use zstd::bulk::Compressor;
+use zstd::dict::EncoderDictionary;
+
+/// The default dictionary size of the zstd CLI.
+pub(crate) const DICT_SIZE: usize = 110 * 1024;
+/// Sample bytes per dictionary byte, zstd recommends about a hundred.
+const SAMPLE_RATIO: usize = 100;
+
+/// Bottom-k sample of the unique chunks by hash within a byte budget.
+struct Sampler {
+    budget: usize,
+    sample: Mutex<(BTreeMap<u64, Vec<u8>>, usize)>,
+}
+
+impl ChunkMetric for Sampler {
+    fn observe(&self, hash: u64, data: &[u8]) -> io::Result<()> {
+        let mut guard = self.sample.lock().unwrap();
+        let (chunks, size) = &mut *guard;
+        if *size >= self.budget && chunks.last_key_value().is_some_and(|(&h, _)| h < hash) {
+            return Ok(());
+        }
+        chunks.insert(hash, data.to_vec());
+        *size += data.len();
+        while *size > self.budget && chunks.len() > 1 {
+            let (_, evicted) = chunks.pop_last().unwrap();
+            *size -= evicted.len();
+        }
+        Ok(())
+    }
+}
+
+/// Sums the sizes of the unique chunks compressed with the dictionary.
+struct DictCompressor {
+    dictionary: EncoderDictionary<'static>,
+    compressed: AtomicUsize,
+}
+
+impl ChunkMetric for DictCompressor {
+    fn observe(&self, _hash: u64, data: &[u8]) -> io::Result<()> {
+        let compressed = Compressor::with_prepared_dictionary(&self.dictionary)
+            .and_then(|mut compressor| compressor.compress(data))?;
+        self.compressed
+            .fetch_add(compressed.len(), Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Chunk the files twice, training a dictionary of at most `dict_size` bytes
+/// on a sample of the unique chunks and then compressing them with it at
+/// zstd `level`. Returns the (total, unique, compressed) stats like
+/// `ChunkStore::stats`, the dictionary-compressed bytes of the unique chunks
+/// and the size of the dictionary.
+pub(crate) fn estimate<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    params: ChunkParams,
+    dict_size: usize,
+    level: i32,
+) -> io::Result<((usize, usize, usize), usize, usize)> {
+    let sampler = Sampler {
+        budget: dict_size * SAMPLE_RATIO,
+        sample: Mutex::new((BTreeMap::new(), 0)),
+    };
+    chunk_files(paths, params, &sampler)?;
+    let (sample, _) = sampler.sample.into_inner().unwrap();
+    let samples: Vec<Vec<u8>> = sample.into_values().collect();
+    let dictionary = zstd::dict::from_samples(&samples, dict_size).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Can't train a dictionary from {} chunks: {e}",
+                samples.len()
+            ),
+        )
+    })?;
+
+    let compressor = DictCompressor {
+        dictionary: EncoderDictionary::copy(&dictionary, level),
+        compressed: AtomicUsize::new(0),
+    };
+    let stores = chunk_files(paths, params, &compressor)?;
+    let stats = ChunkStore::merge_owned(stores, false).stats();
+    Ok((stats, compressor.compressed.into_inner(), dictionary.len()))
+}
@@ -0,0 +1,184 @@
+//! Comparison of two persisted chunk store documents, e.g. nightly snapshots
+//! of a growing corpus, attributing the storage growth to the input files,
+//! and of two revisions of a dataset chunked from their files.
+
+use crate::progress::Progress;
+use crate::serialize::{ChunkEntry, StoreDocument};
+use crate::store::{ChunkParams, ChunkStore};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Added and removed unique bytes attributed to an input file, added chunks
+/// belong to the file first containing them in the new snapshot and removed
+/// chunks to the one first containing them in the old snapshot.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct FileDiff {
+    pub path: String,
+    pub added_chunks: usize,
+    pub added_bytes: usize,
+    pub removed_chunks: usize,
+    pub removed_bytes: usize,
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct StoreDiff {
+    pub added_chunks: usize,
+    pub added_bytes: usize,
+    pub removed_chunks: usize,
+    pub removed_bytes: usize,
+    /// Chunks present in both snapshots whose occurrences went up or down.
+    pub increased_chunks: usize,
+    pub decreased_chunks: usize,
+    /// Change of the logical bytes referencing the chunks in both snapshots.
+    pub referenced_bytes_delta: i64,
+    pub files: Vec<FileDiff>,
+}
+
+/// The index in `files` of the file first containing a chunk of a document
+/// mapped by `files`, failing for a chunk of a malformed document.
+fn first_file(files: &[usize], chunk: &ChunkEntry) -> io::Result<usize> {
+    usize::try_from(chunk.first_seen_in)
+        .ok()
+        .and_then(|i| files.get(i).copied())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "chunk {} was first seen in input {} of a document of {} files",
+                    chunk.hash,
+                    chunk.first_seen_in,
+                    files.len()
+                ),
+            )
+        })
+}
+
+/// Diff the chunks of two documents, the files are listed in the order of
+/// the new snapshot followed by the files only present in the old one.
+pub(crate) fn diff_stores(old: &StoreDocument, new: &StoreDocument) -> io::Result<StoreDiff> {
+    let mut diff = StoreDiff::default();
+    let mut files: Vec<FileDiff> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut file_of = |path: &str, files: &mut Vec<FileDiff>| -> usize {
+        *index.entry(path).or_insert_with(|| {
+            files.push(FileDiff {
+                path: path.to_string(),
+                ..FileDiff::default()
+            });
+            files.len() - 1
+        })
+    };
+    let new_files: Vec<usize> = new
+        .files
+        .iter()
+        .map(|f| file_of(&f.path, &mut files))
+        .collect();
+    let old_files: Vec<usize> = old
+        .files
+        .iter()
+        .map(|f| file_of(&f.path, &mut files))
+        .collect();
+
+    let old_chunks: HashMap<&str, &ChunkEntry> =
+        old.chunks.iter().map(|c| (c.hash.as_str(), c)).collect();
+    let new_chunks: HashMap<&str, &ChunkEntry> =
+        new.chunks.iter().map(|c| (c.hash.as_str(), c)).collect();
+
+    for chunk in &new.chunks {
+        match old_chunks.get(chunk.hash.as_str()) {
+            None => {
+                diff.added_chunks += 1;
+                diff.added_bytes += chunk.size;
+                let file = &mut files[first_file(&new_files, chunk)?];
+                file.added_chunks += 1;
+                file.added_bytes += chunk.size;
+            }
+            Some(previous) => {
+                let delta = chunk.occurrences as i64 - previous.occurrences as i64;
+                if delta > 0 {
+                    diff.increased_chunks += 1;
+                } else if delta < 0 {
+                    diff.decreased_chunks += 1;
+                }
+                diff.referenced_bytes_delta += delta * chunk.size as i64;
+            }
+        }
+    }
+    for chunk in &old.chunks {
+        if !new_chunks.contains_key(chunk.hash.as_str()) {
+            diff.removed_chunks += 1;
+            diff.removed_bytes += chunk.size;
+            let file = &mut files[first_file(&old_files, chunk)?];
+            file.removed_chunks += 1;
+            file.removed_bytes += chunk.size;
+        }
+    }
+    diff.files = files;
+    Ok(diff)
+}
+
+/// Read and diff two documents written by `StoreDocument::write`.
+pub(crate) fn diff_files<P: AsRef<Path>, Q: AsRef<Path>>(old: P, new: Q) -> io::Result<StoreDiff> {
+    let old = StoreDocument::read(old)?;
+    let new = StoreDocument::read(new)?;
+    diff_stores(&old, &new)
+}
+
+/// Unique chunks of two revisions of a dataset, the added ones being what a
+/// content defined chunking store would upload for the update.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct RevisionDiff {
+    pub old_total: usize,
+    pub new_total: usize,
+    pub added_chunks: usize,
+    pub added_bytes: usize,
+    pub added_compressed_bytes: usize,
+    pub removed_chunks: usize,
+    pub removed_bytes: usize,
+    pub shared_chunks: usize,
+    pub shared_bytes: usize,
+}
+
+fn merged_revision<P: AsRef<Path> + Send + Sync>(
+    paths: &[P],
+    params: ChunkParams,
+) -> io::Result<ChunkStore> {
+    let progress = Progress::default();
+    let stores =
+        ChunkStore::from_files_with(paths, false, params, false, &progress, |_, _| Ok(()))?;
+    Ok(ChunkStore::merge_owned(stores, false))
+}
+
+/// Chunk the files of both revisions and diff their unique chunks.
+pub(crate) fn diff_revisions<P, Q>(
+    old: &[P],
+    new: &[Q],
+    params: ChunkParams,
+) -> io::Result<RevisionDiff>
+where
+    P: AsRef<Path> + Send + Sync,
+    Q: AsRef<Path> + Send + Sync,
+{
+    let old = merged_revision(old, params)?;
+    let new = merged_revision(new, params)?;
+    let old_chunks: HashMap<u64, usize> = old.iter_chunks().map(|(h, c)| (*h, c.size)).collect();
+    let mut diff = RevisionDiff {
+        old_total: old.total(),
+        new_total: new.total(),
+        ..RevisionDiff::default()
+    };
+    for (hash, chunk) in new.iter_chunks() {
+        if old_chunks.contains_key(hash) {
+            diff.shared_chunks += 1;
+            diff.shared_bytes += chunk.size;
+        } else {
+            diff.added_chunks += 1;
+            diff.added_bytes += chunk.size;
+            diff.added_compressed_bytes += chunk.compressed;
+        }
+    }
+    diff.removed_chunks = old_chunks.len() - diff.shared_chunks;
+    diff.removed_bytes = old_chunks.values().sum::<usize>() - diff.shared_bytes;
+    Ok(diff)
+}
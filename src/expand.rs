@@ -0,0 +1,208 @@
+//! Expansion of the directories and glob patterns passed as inputs into the
+//! files they contain, so that large repositories don't have to be listed
+//! file by file.
+//!
+//! The patterns support `*` and `?` within a path component, `[...]` classes
+//! with ranges and `!` or `^` negation, and `**` for any number of nested
+//! directories, e.g. `data/**/*.parquet`. Like a shell, wildcards don't match
+//! the names starting with a dot unless the pattern component does, and
+//! directories are walked without their hidden entries. A matched directory
+//! stands for all the files below it. Symlinks to files are kept but symlinks
+//! to directories are not followed, so a walk always terminates.
+
+use std::fs::{self, FileType};
+use std::io;
+use std::path::{Path, PathBuf};
+
+fn has_magic(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn is_hidden(name: &[u8]) -> bool {
+    name.first() == Some(&b'.')
+}
+
+/// Whether the `[...]` class at the start of `pattern` matches `c`, and the
+/// length of the class, None if it is never closed.
+fn class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some(b'!' | b'^'));
+    if negate {
+        i += 1;
+    }
+    let start = i;
+    let mut matched = false;
+    while let Some(&lo) = pattern.get(i) {
+        // a bracket right after the opening one is a member
+        if lo == b']' && i > start {
+            return Some((matched != negate, i + 1));
+        }
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some(b'-'), Some(&hi)) if hi != b']' => {
+                matched |= (lo..=hi).contains(&c);
+                i += 3;
+            }
+            _ => {
+                matched |= lo == c;
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+/// The length of the single byte token at the start of `pattern` if it
+/// matches `c`, an unclosed bracket matching itself.
+fn token(pattern: &[u8], c: u8) -> Option<usize> {
+    match pattern[0] {
+        b'?' => Some(1),
+        b'[' => match class(pattern, c) {
+            Some((matched, len)) => matched.then_some(len),
+            None => (c == b'[').then_some(1),
+        },
+        p => (p == c).then_some(1),
+    }
+}
+
+/// Match a file name against a pattern component.
+fn wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    if is_hidden(name) && !is_hidden(pattern) {
+        return false;
+    }
+    let (mut p, mut n) = (0, 0);
+    // the pattern position after the last star and the name position it
+    // resumes from when the rest fails to match
+    let mut backtrack = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            backtrack = Some((p, n));
+            continue;
+        }
+        let matched = if p < pattern.len() {
+            token(&pattern[p..], name[n])
+        } else {
+            None
+        };
+        if let Some(len) = matched {
+            p += len;
+            n += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star, from)) => {
+                backtrack = Some((star, from + 1));
+                p = star;
+                n = from + 1;
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// The entries of a directory, the empty path standing for the current one
+/// so that the expanded paths stay relative like the pattern.
+fn entries(dir: &Path) -> io::Result<Vec<(PathBuf, FileType)>> {
+    let listed = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    fs::read_dir(listed)?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((dir.join(entry.file_name()), entry.file_type()?))
+        })
+        .collect()
+}
+
+fn file_name(path: &Path) -> &[u8] {
+    path.file_name().map_or(&[], |name| name.as_encoded_bytes())
+}
+
+/// Collect the files below `dir`, leaving out the hidden entries.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for (path, file_type) in entries(dir)? {
+        if is_hidden(file_name(&path)) {
+            continue;
+        }
+        if file_type.is_dir() {
+            walk(&path, files)?;
+        } else if fs::metadata(&path).is_ok_and(|m| m.is_file()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Collect the files matching the pattern `components` below `dir`.
+fn glob(dir: &Path, components: &[&str], files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let Some((&first, rest)) = components.split_first() else {
+        if dir.is_dir() {
+            walk(dir, files)?;
+        } else if dir.is_file() {
+            files.push(dir.to_path_buf());
+        }
+        return Ok(());
+    };
+    if !has_magic(first) {
+        return glob(&dir.join(first), rest, files);
+    }
+    if !(dir.as_os_str().is_empty() || dir.is_dir()) {
+        return Ok(());
+    }
+    if first == "**" {
+        glob(dir, rest, files)?;
+        for (path, file_type) in entries(dir)? {
+            if file_type.is_dir() && !is_hidden(file_name(&path)) {
+                glob(&path, components, files)?;
+            }
+        }
+        return Ok(());
+    }
+    for (path, _) in entries(dir)? {
+        if wildcard(first.as_bytes(), file_name(&path)) {
+            glob(&path, rest, files)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace the directories and glob patterns among `inputs` by the files
+/// they contain, sorted, and keep the other inputs, e.g. files or URLs, as
+/// they are. An existing path is never taken for a pattern, even if its name
+/// contains wildcards, and neither is a URL, whose query may contain `?`.
+/// Fails with `NotFound` if a directory or a pattern yields no file.
+pub(crate) fn expand(inputs: &[String]) -> io::Result<Vec<String>> {
+    let mut paths = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let path = Path::new(input);
+        let mut files = Vec::new();
+        if path.is_dir() {
+            walk(path, &mut files)?;
+        } else if path.exists() || !has_magic(input) || input.contains("://") {
+            paths.push(input.clone());
+            continue;
+        } else {
+            let components: Vec<&str> = input.split('/').collect();
+            let literal = components.iter().take_while(|c| !has_magic(c)).count();
+            let base = match components[..literal].join("/") {
+                base if base.is_empty() && input.starts_with('/') => "/".to_string(),
+                base => base,
+            };
+            glob(Path::new(&base), &components[literal..], &mut files)?;
+        }
+        if files.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No files match {input}"),
+            ));
+        }
+        // `**` may reach a file through several paths
+        files.sort();
+        files.dedup();
+        paths.extend(files.iter().map(|f| f.to_string_lossy().into_owned()));
+    }
+    Ok(paths)
+}
@@ -1,10 +1,18 @@
+use crate::remote::{self, RemoteFile};
 use arrow_array::RecordBatchReader;
-use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter};
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ArrowWriter;
 use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use parquet::column::page::{Page, PageReader};
+use parquet::encryption::encrypt::FileEncryptionProperties;
 use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{ChunkReader, FileReader, SerializedFileReader};
 use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
 
 fn parse_compression(s: &str) -> PyResult<Compression> {
     match s.to_lowercase().as_str() {
@@ -20,7 +28,28 @@ fn parse_compression(s: &str) -> PyResult<Compression> {
     }
 }
 
-fn build_writer_properties(cdc: bool, compression: Option<String>) -> PyResult<WriterProperties> {
+/// Parquet modular encryption keys, the footer key is mandatory while the
+/// column keys are optional (columns without a key use the footer key).
+pub(crate) struct EncryptionKeys {
+    pub footer_key: Vec<u8>,
+    pub column_keys: HashMap<String, Vec<u8>>,
+}
+
+fn build_encryption_properties(keys: EncryptionKeys) -> PyResult<FileEncryptionProperties> {
+    let mut builder = FileEncryptionProperties::builder(keys.footer_key);
+    for (column, key) in keys.column_keys {
+        builder = builder.with_column_key(&column, key);
+    }
+    builder
+        .build()
+        .map_err(|e| PyValueError::new_err(format!("Invalid encryption keys: {e}")))
+}
+
+fn build_writer_properties(
+    cdc: bool,
+    compression: Option<String>,
+    encryption: Option<EncryptionKeys>,
+) -> PyResult<WriterProperties> {
     let mut builder = WriterProperties::builder();
     if cdc {
         builder = builder.set_content_defined_chunking(true);
@@ -28,28 +57,45 @@ fn build_writer_properties(cdc: bool, compression: Option<String>) -> PyResult<W
     if let Some(c) = compression {
         builder = builder.set_compression(parse_compression(&c)?);
     }
+    if let Some(keys) = encryption {
+        builder = builder.with_file_encryption_properties(build_encryption_properties(keys)?);
+    }
     Ok(builder.build())
 }
 
+fn batch_reader<T: ChunkReader + 'static>(
+    input: T,
+    batch_size: usize,
+) -> PyResult<ParquetRecordBatchReader> {
+    ParquetRecordBatchReaderBuilder::try_new(input)
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to create reader: {e}")))?
+        .with_batch_size(batch_size)
+        .build()
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to build reader: {e}")))
+}
+
+/// Rewrite a parquet file with the given writer properties. The source can
+/// be the URL of an object store object, whose footer and column chunks are
+/// then fetched with ranged reads, see the `remote` module.
 pub(crate) fn rewrite_to_parquet_rs(
     src_path: String,
     dest_path: String,
     batch_size: usize,
     cdc: bool,
     compression: Option<String>,
+    encryption: Option<EncryptionKeys>,
 ) -> PyResult<()> {
-    let input = File::open(&src_path)
-        .map_err(|e| PyRuntimeError::new_err(format!("Failed to open {src_path}: {e}")))?;
-    let reader = ParquetRecordBatchReaderBuilder::try_new(input)
-        .map_err(|e| PyRuntimeError::new_err(format!("Failed to create reader: {e}")))?
-        .with_batch_size(batch_size)
-        .build()
-        .map_err(|e| PyRuntimeError::new_err(format!("Failed to build reader: {e}")))?;
+    let open_error = |e| PyRuntimeError::new_err(format!("Failed to open {src_path}: {e}"));
+    let reader = if remote::is_remote(&src_path) {
+        batch_reader(RemoteFile::open(&src_path).map_err(open_error)?, batch_size)?
+    } else {
+        batch_reader(File::open(&src_path).map_err(open_error)?, batch_size)?
+    };
     let schema = reader.schema().clone();
 
     let output = File::create(&dest_path)
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to create {dest_path}: {e}")))?;
-    let props = build_writer_properties(cdc, compression)?;
+    let props = build_writer_properties(cdc, compression, encryption)?;
     let mut writer = ArrowWriter::try_new(output, schema, Some(props))
         .map_err(|e| PyRuntimeError::new_err(format!("Failed to create writer: {e}")))?;
 
@@ -67,3 +113,132 @@ pub(crate) fn rewrite_to_parquet_rs(
 
     Ok(())
 }
+
+/// The (uncompressed size, number of values) of the data pages of every column
+/// chunk, in row group then column order; dictionary pages are skipped.
+pub(crate) fn data_page_layout<P: AsRef<Path>>(path: P) -> io::Result<Vec<Vec<(usize, usize)>>> {
+    let reader = SerializedFileReader::new(File::open(path)?).map_err(io::Error::other)?;
+    let metadata = reader.metadata();
+    let mut columns = Vec::new();
+    for i in 0..metadata.num_row_groups() {
+        let row_group = reader.get_row_group(i).map_err(io::Error::other)?;
+        for j in 0..metadata.row_group(i).num_columns() {
+            let page_reader = row_group
+                .get_column_page_reader(j)
+                .map_err(io::Error::other)?;
+            let mut pages = Vec::new();
+            for page in page_reader {
+                let page = page.map_err(io::Error::other)?;
+                if let Page::DictionaryPage { .. } = page {
+                    continue;
+                }
+                pages.push((page.buffer().len(), page.num_values() as usize));
+            }
+            columns.push(pages);
+        }
+    }
+    Ok(columns)
+}
+
+/// The dotted paths of the leaf columns of a parquet file in column order.
+pub(crate) fn parquet_columns<P: AsRef<Path>>(path: P) -> io::Result<Vec<String>> {
+    let reader = SerializedFileReader::new(File::open(path)?).map_err(io::Error::other)?;
+    let schema = reader.metadata().file_metadata().schema_descr();
+    Ok(schema
+        .columns()
+        .iter()
+        .map(|column| column.path().string())
+        .collect())
+}
+
+/// Reader over the decompressed payloads of all pages of a parquet file, in
+/// row group then column order, used to estimate the deduplication that would
+/// be recoverable if pages were stored uncompressed.
+pub(crate) struct PageStream {
+    reader: SerializedFileReader<File>,
+    row_group: usize,
+    column: usize,
+    only_column: Option<usize>,
+    pages: Option<Box<dyn PageReader>>,
+    page: Option<Page>,
+    pos: usize,
+}
+
+impl PageStream {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let reader = SerializedFileReader::new(file).map_err(io::Error::other)?;
+        Ok(PageStream {
+            reader,
+            row_group: 0,
+            column: 0,
+            only_column: None,
+            pages: None,
+            page: None,
+            pos: 0,
+        })
+    }
+
+    /// Read only the pages of the given leaf column across all row groups.
+    pub fn open_column<P: AsRef<Path>>(path: P, column: usize) -> io::Result<Self> {
+        let mut stream = Self::open(path)?;
+        stream.column = column;
+        stream.only_column = Some(column);
+        Ok(stream)
+    }
+
+    fn next_page(&mut self) -> io::Result<Option<Page>> {
+        loop {
+            if let Some(pages) = &mut self.pages {
+                if let Some(page) = pages.next() {
+                    return page.map(Some).map_err(io::Error::other);
+                }
+                self.pages = None;
+                self.column += 1;
+            }
+
+            let metadata = self.reader.metadata();
+            if self.row_group >= metadata.num_row_groups() {
+                return Ok(None);
+            }
+            let num_columns = metadata.row_group(self.row_group).num_columns();
+            let past_column = self.only_column.is_some_and(|c| self.column > c);
+            if self.column >= num_columns || past_column {
+                self.row_group += 1;
+                self.column = self.only_column.unwrap_or(0);
+                continue;
+            }
+            let row_group = self
+                .reader
+                .get_row_group(self.row_group)
+                .map_err(io::Error::other)?;
+            let pages = row_group
+                .get_column_page_reader(self.column)
+                .map_err(io::Error::other)?;
+            self.pages = Some(pages);
+        }
+    }
+}
+
+impl Read for PageStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(page) = &self.page {
+                let data = page.buffer();
+                if self.pos < data.len() {
+                    let n = buf.len().min(data.len() - self.pos);
+                    buf[..n].copy_from_slice(&data[self.pos..self.pos + n]);
+                    self.pos += n;
+                    return Ok(n);
+                }
+            }
+            match self.next_page()? {
+                Some(page) => {
+                    self.page = Some(page);
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Writer of hash lists, the `<hash> <size>` inputs of
+//! `ChunkStore::from_hash_list`. The hashes can be keyed so hash lists of
+//! private data can be shared without exposing them to dictionary attacks,
+//! i.e. hashing candidate contents and looking them up in the list, while
+//! parties sharing the key can still measure the overlap of their data.
+
+use crate::store::chunk_file;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Context of the derivation of the hashing keys from the user keys.
+const KEY_CONTEXT: &str = "dataset-dedupe-estimator 2025 hash list chunk key";
+
+/// Derive the 256-bit BLAKE3 key from a user key of any length, e.g. a
+/// passphrase agreed upon by the parties.
+pub(crate) fn derive_key(key: &[u8]) -> [u8; 32] {
+    blake3::derive_key(KEY_CONTEXT, key)
+}
+
+/// Chunk the file and write a hash list of its chunks, returning the number
+/// of chunks. Without a key the chunks are listed by their xxh3 hash, with
+/// a key by their keyed BLAKE3 hash. The empty last chunk is left out.
+pub(crate) fn write_hash_list<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: P,
+    output: Q,
+    key: Option<&[u8; 32]>,
+) -> io::Result<usize> {
+    let mut w = BufWriter::new(File::create(output)?);
+    let mut count = 0;
+    let mut result = Ok(());
+    chunk_file(path, |chunk| {
+        if chunk.is_empty() || result.is_err() {
+            return;
+        }
+        count += 1;
+        result = match key {
+            Some(key) => writeln!(
+                w,
+                "{} {}",
+                blake3::keyed_hash(key, chunk).to_hex(),
+                chunk.len()
+            ),
+            None => writeln!(w, "{:016x} {}", xxh3_64(chunk), chunk.len()),
+        };
+    })?;
+    result?;
+    w.flush()?;
+    Ok(count)
+}
@@ -0,0 +1,75 @@
+//! Unique bytes counting with nothing but the set of chunk hashes, for the
+//! headline deduplication ratio as fast as possible.
+//!
+//! Unlike `ChunkStore` no chunk order, per chunk metadata or compressed size
+//! is kept: every file is memory mapped, cut and hashed on all the cores like
+//! `ChunkStore::from_slice`, and its hashes are folded into a shared set,
+//! adding the size of the chunks seen for the first time.
+
+use crate::cdc::parallel_cuts;
+use crate::decompress;
+use crate::progress::Progress;
+use crate::store::{is_hash_list, ChunkParams, ChunkStore};
+use memmap2::Mmap;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// The (hash, size) of the chunks of a file, in order.
+fn file_chunks<P: AsRef<Path>>(path: P, params: ChunkParams) -> io::Result<Vec<(u64, usize)>> {
+    // decompressed streams can't be mapped
+    if is_hash_list(&path) || (params.decompress && decompress::detect(&path)?.is_some()) {
+        let store = ChunkStore::from_file_with_params(path, false, params, &Progress::default())?;
+        return Ok(store
+            .recipe()
+            .into_iter()
+            .map(|(hash, size, _)| (hash, size))
+            .collect());
+    }
+    let file = File::open(path)?;
+    // SAFETY: see `ChunkStore::from_file_mmap`
+    let map = unsafe { Mmap::map(&file)? };
+    let cuts = parallel_cuts(&map, params);
+    let bounds: Vec<(usize, usize)> = std::iter::once(0)
+        .chain(cuts.iter().copied())
+        .zip(cuts.iter().copied().chain(std::iter::once(map.len())))
+        .filter(|(start, end)| start < end)
+        .collect();
+    Ok(bounds
+        .par_iter()
+        .map(|&(start, end)| (xxh3_64(&map[start..end]), end - start))
+        .collect())
+}
+
+/// Chunk the files in parallel folding the hashes of each one into a shared
+/// set as soon as it is chunked. Returns the total and unique bytes, the
+/// same as the first two stats of `ChunkStore::stats`.
+pub(crate) fn count_files<P: AsRef<Path> + Send + Sync>(
+    paths: &[P],
+    params: ChunkParams,
+) -> io::Result<(usize, usize)> {
+    let seen = Mutex::new((HashSet::new(), 0));
+    let total = paths
+        .par_iter()
+        .map(|path| {
+            let chunks = file_chunks(path, params)?;
+            let mut seen = seen.lock().unwrap();
+            let (hashes, unique) = &mut *seen;
+            let mut total = 0;
+            for (hash, size) in chunks {
+                total += size;
+                if hashes.insert(hash) {
+                    *unique += size;
+                }
+            }
+            Ok(total)
+        })
+        .collect::<io::Result<Vec<usize>>>()?
+        .into_iter()
+        .sum();
+    Ok((total, seen.into_inner().unwrap().1))
+}
@@ -0,0 +1,218 @@
+//! Compact binary persistence of a merged chunk store, so that later runs
+//! only chunk the new files and merge them against the saved index instead
+//! of re-scanning the whole corpus.
+//!
+//! All integers are little-endian and strings are a u32 byte length followed
+//! by UTF-8:
+//!
+//! ```text
+//! "DEINDEX\0" version:u32
+//! algorithm:string mask:u64 min_len:u64 max_len:u64 codec:string level:i32
+//! files:u32 label:string...
+//! total:u64
+//! chunks:u64 then per chunk
+//!   hash:u64 size:u64 compressed:u64 occurrences:u64 first_seen_in:u32
+//!   seen_in:u32 file index:u32...
+//! ```
+//!
+//! Version 1 indexes have no codec and were compressed with lz4, they are
+//! still read.
+//!
+//! The chunk order of the inputs is not saved: it takes as much space as the
+//! chunk table itself and merging against the index doesn't need it.
+//!
+//! An index is written to a temporary file renamed over it, so readers never
+//! see a partial one, and writers updating the same index hold the exclusive
+//! lock of `lock`, see `Estimator.save`.
+
+use crate::cdc::Algorithm;
+use crate::codec::Codec;
+use crate::store::{Chunk, ChunkParams, ChunkStore};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"DEINDEX\0";
+const VERSION: u32 = 2;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u32<W: Write>(w: &mut W, value: u32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(w: &mut W, value: u64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_str<W: Write>(w: &mut W, value: &str) -> io::Result<()> {
+    write_u32(w, value.len() as u32)?;
+    w.write_all(value.as_bytes())
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_str<R: Read>(r: &mut R) -> io::Result<String> {
+    // the length of a corrupt file is only trusted as far as the file goes
+    let len = read_u32(r)? as u64;
+    let mut buf = Vec::new();
+    if r.take(len).read_to_end(&mut buf)? as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(buf).map_err(|e| invalid(e.to_string()))
+}
+
+/// `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Take the exclusive lock of the index at `path`, released when the
+/// returned file is dropped. The lock is held on `{path}.lock` rather than on
+/// the index itself since writing an index replaces its file.
+pub(crate) fn lock<P: AsRef<Path>>(path: P) -> io::Result<File> {
+    let file = File::create(with_suffix(path.as_ref(), ".lock"))?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Write the chunks of a merged store and the labels of its inputs.
+pub(crate) fn write<P: AsRef<Path>>(
+    store: &ChunkStore,
+    labels: &[String],
+    path: P,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp = with_suffix(path, &format!(".{}.tmp", std::process::id()));
+    write_to(store, labels, &tmp)
+        .and_then(|()| fs::rename(&tmp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+}
+
+fn write_to(store: &ChunkStore, labels: &[String], path: &Path) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(MAGIC)?;
+    write_u32(&mut w, VERSION)?;
+
+    let params = store.params();
+    write_str(&mut w, params.algorithm.name())?;
+    write_u64(&mut w, params.mask)?;
+    write_u64(&mut w, params.min_len as u64)?;
+    write_u64(&mut w, params.max_len as u64)?;
+    write_str(&mut w, params.codec.name())?;
+    write_u32(&mut w, params.codec.level() as u32)?;
+
+    write_u32(&mut w, labels.len() as u32)?;
+    for label in labels {
+        write_str(&mut w, label)?;
+    }
+
+    write_u64(&mut w, store.total() as u64)?;
+    write_u64(&mut w, store.unique_chunks() as u64)?;
+    // in hash order, so that the same store always writes the same index
+    let mut chunks: Vec<_> = store.iter_chunks().collect();
+    chunks.sort_unstable_by_key(|(hash, _)| **hash);
+    for (hash, chunk) in chunks {
+        write_u64(&mut w, *hash)?;
+        write_u64(&mut w, chunk.size as u64)?;
+        write_u64(&mut w, chunk.compressed as u64)?;
+        write_u64(&mut w, chunk.occurrences as u64)?;
+        write_u32(&mut w, chunk.first_seen_in as u32)?;
+        write_u32(&mut w, chunk.seen_in.len() as u32)?;
+        for &index in &chunk.seen_in {
+            write_u32(&mut w, index as u32)?;
+        }
+    }
+    w.into_inner()?.sync_all()
+}
+
+/// Read the chunker parameters and the labels of the inputs of an index
+/// without its chunks.
+pub(crate) fn read_labels<P: AsRef<Path>>(path: P) -> io::Result<(ChunkParams, Vec<String>)> {
+    let path = path.as_ref();
+    read_header(BufReader::new(File::open(path)?), path)
+}
+
+fn read_header<R: Read>(mut r: R, path: &Path) -> io::Result<(ChunkParams, Vec<String>)> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    let version = read_u32(&mut r)?;
+    if &magic != MAGIC || !(1..=VERSION).contains(&version) {
+        return Err(invalid(format!(
+            "{} is not a chunk index of version 1 to {VERSION}",
+            path.display()
+        )));
+    }
+
+    let algorithm = Algorithm::new(&read_str(&mut r)?).map_err(|e| invalid(e.to_string()))?;
+    let mut params = ChunkParams {
+        mask: read_u64(&mut r)?,
+        min_len: read_u64(&mut r)? as usize,
+        max_len: read_u64(&mut r)? as usize,
+        algorithm,
+        codec: Codec::default(),
+        decompress: false,
+    };
+    if version >= 2 {
+        let name = read_str(&mut r)?;
+        let level = read_u32(&mut r)? as i32;
+        params.codec = Codec::new(&name, Some(level)).map_err(|e| invalid(e.to_string()))?;
+    }
+
+    let labels = (0..read_u32(&mut r)?)
+        .map(|_| read_str(&mut r))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok((params, labels))
+}
+
+/// Read an index written by `write`, returning the store and the labels of
+/// its inputs.
+pub(crate) fn read<P: AsRef<Path>>(path: P) -> io::Result<(ChunkStore, Vec<String>)> {
+    let path = path.as_ref();
+    let mut r = BufReader::new(File::open(path)?);
+    let (params, labels) = read_header(&mut r, path)?;
+
+    let total = read_u64(&mut r)? as usize;
+    let count = read_u64(&mut r)? as usize;
+    // the count of a corrupt file shouldn't reserve unbounded memory
+    let mut chunks = HashMap::with_capacity(count.min(1 << 20));
+    for _ in 0..count {
+        let hash = read_u64(&mut r)?;
+        let size = read_u64(&mut r)? as usize;
+        let compressed = read_u64(&mut r)? as usize;
+        let occurrences = read_u64(&mut r)? as usize;
+        let first_seen_in = read_u32(&mut r)? as i64;
+        let seen_in = (0..read_u32(&mut r)?)
+            .map(|_| Ok(read_u32(&mut r)? as i64))
+            .collect::<io::Result<Vec<_>>>()?;
+        let chunk = Chunk {
+            size,
+            compressed,
+            seen_in,
+            first_seen_in,
+            occurrences,
+            data: None,
+        };
+        chunks.insert(hash, chunk);
+    }
+    let store = ChunkStore::from_index(total, chunks, params, labels.len());
+    Ok((store, labels))
+}
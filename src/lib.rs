@@ -1,53 +1,2276 @@
-use pyo3::exceptions::PyRuntimeError;
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
-use std::path::Path;
+use pyo3::types::{PyBytes, PyMemoryView};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::Instant;
 
+mod bloom;
+mod cache;
+mod casync;
+mod cdc;
+mod codec;
+mod decompress;
+mod delta;
+mod dictionary;
+mod diff;
+mod expand;
 mod fileutils;
+mod font;
+mod hashlist;
+mod hashonly;
+mod index;
+mod metrics;
+mod pipeline;
+mod progress;
+mod remote;
+mod retention;
+mod sample;
+mod serialize;
+mod shift;
 mod show;
+mod sidecar;
+mod simulate;
+mod sketch;
+mod sniff;
+mod spill;
 mod store;
+mod subchunk;
+mod sweep;
+mod verify;
 mod xet;
 
-use fileutils::rewrite_to_parquet_rs as _rewrite_to_parquet_rs;
-use show::write_png;
-use store::{Chunk, ChunkStore};
+use bloom::BloomFilter;
+use cache::RecipeCache;
+use cdc::Algorithm;
+use codec::Codec;
+use delta::delta_matches;
+use fileutils::{
+    data_page_layout, parquet_columns, rewrite_to_parquet_rs as _rewrite_to_parquet_rs,
+    EncryptionKeys,
+};
+use metrics::PyMetric;
+use pipeline::QUEUE_DEPTH;
+use progress::Progress;
+use serialize::{chunks_to_arrow_ipc, ManifestFormat};
+use shift::shifted_matches;
+use show::{
+    write_column_image, write_dot_plot, write_image, write_poster, write_size_image, ImageFormat,
+    ImageOptions, IMAGE_DIM,
+};
+use simulate::{simulate_lru, simulate_packing};
+use store::{
+    chunk_boundaries, is_hash_list, Attribution, ChunkParams, ChunkStore, PayloadPool, MASK,
+    MAX_LEN, MIN_LEN,
+};
+use subchunk::intra_chunk_redundancy;
+use sweep::sweep_files;
+
+fn merge_stores(
+    file_paths: &[String],
+    stores: &mut [ChunkStore],
+    store_data: bool,
+    attribution: Option<Attribution>,
+) -> PyResult<ChunkStore> {
+    let priority = match attribution {
+        Some(attribution) => attribution
+            .priority(file_paths, stores)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?,
+        None => (0..stores.len()).collect(),
+    };
+    Ok(ChunkStore::merge_with_priority(
+        stores, store_data, &priority,
+    ))
+}
+
+/// Chunker parameters from the keyword arguments of the Python API, see
+/// `ChunkParams::new` and the `cdc` and `codec` modules for the chunker and
+/// codec names.
+fn chunk_params(
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+    codec: &str,
+    codec_level: Option<i32>,
+) -> PyResult<ChunkParams> {
+    Algorithm::new(chunker)
+        .and_then(|algorithm| {
+            ChunkParams::new(
+                target_chunk_size,
+                min_chunk_size,
+                max_chunk_size,
+                chunk_mask,
+                algorithm,
+            )
+        })
+        .and_then(|params| Codec::new(codec, codec_level).map(|c| params.with_codec(c)))
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Progress of a scan of `total_files` inputs reported to the Python
+/// `callback`, if any, as (bytes, files done, total files) positional
+/// arguments. An exception raised by the callback stops the scan and is
+/// raised by it.
+fn py_progress(total_files: usize, callback: Option<PyObject>) -> Progress {
+    let callback = callback.map(|callback| -> progress::Callback {
+        Box::new(move |bytes, files, total_files| {
+            Python::with_gil(|py| callback.call1(py, (bytes, files, total_files)))?;
+            Ok(())
+        })
+    });
+    Progress::new(total_files, callback)
+}
+
+/// Run the scan `f` without the GIL on another thread while this one checks
+/// for signals every `progress::INTERVAL`, so that Ctrl-C cancels the scan
+/// through `progress` and raises KeyboardInterrupt once its workers stopped
+/// rather than when it is done. Python only runs the signal handlers on the
+/// main thread, which would otherwise be blocked in Rust for the whole scan.
+fn interruptible<T, F>(py: Python<'_>, progress: &Progress, f: F) -> PyResult<T>
+where
+    T: Send,
+    F: FnOnce() -> PyResult<T> + Send,
+{
+    py.allow_threads(|| {
+        std::thread::scope(|s| {
+            let (tx, rx) = channel();
+            s.spawn(move || tx.send(f()));
+            loop {
+                match rx.recv_timeout(progress::INTERVAL) {
+                    Ok(result) => return result,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if let Err(e) = Python::with_gil(|py| py.check_signals()) {
+                            progress.cancel();
+                            // the workers stop at their next update
+                            let _ = rx.recv();
+                            return Err(e);
+                        }
+                    }
+                    // the scan panicked, which the scope propagates
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(PyRuntimeError::new_err("the scan panicked"))
+                    }
+                }
+            }
+        })
+    })
+}
+
+fn caption(
+    title: &str,
+    (total, unique, _): (usize, usize, usize),
+    params: &ChunkParams,
+) -> Vec<String> {
+    let dedup = if total > 0 {
+        100.0 * unique as f64 / total as f64
+    } else {
+        100.0
+    };
+    vec![
+        title.to_string(),
+        format!("dedup {dedup:.1}% ({unique} / {total} bytes)"),
+        format!(
+            "{} mask={:#x} min={} max={}",
+            params.algorithm.name(),
+            params.mask,
+            params.min_len,
+            params.max_len
+        ),
+    ]
+}
+
+/// The per-file images written by `estimate`.
+struct FileImages<'a> {
+    options: &'a ImageOptions,
+    params: ChunkParams,
+    captions: bool,
+    size_maps: bool,
+    self_maps: bool,
+    sidecars: bool,
+    file_paths: &'a [String],
+}
+
+impl FileImages<'_> {
+    fn caption(&self, store: &ChunkStore, file_path: &str) -> Vec<String> {
+        if self.captions {
+            let name = Path::new(file_path).file_name().unwrap().to_string_lossy();
+            caption(&name, store.stats(), &self.params)
+        } else {
+            vec![]
+        }
+    }
+
+    /// Write the chunk ownership image of the file at `index` and its
+    /// sidecar, which require the attribution of the merged store.
+    fn write_ownership(&self, store: &ChunkStore, index: usize) -> std::io::Result<()> {
+        let file_path = &self.file_paths[index];
+        if is_hash_list(file_path) {
+            return Ok(());
+        }
+        let lines = self.caption(store, file_path);
+        write_image(&store.segments(), file_path, self.options, &lines)?;
+        if self.sidecars {
+            let stores = std::slice::from_ref(store);
+            sidecar::write(stores, index, self.file_paths, file_path, self.options)?;
+        }
+        Ok(())
+    }
+
+    /// Write the size and self maps, which only depend on the file itself and
+    /// can be written as soon as it is chunked. Hash lists have no bytes to
+    /// draw, so none of the per-file images are written for them.
+    fn write_local(&self, store: &ChunkStore, file_path: &str) -> std::io::Result<()> {
+        if is_hash_list(file_path) {
+            return Ok(());
+        }
+        let lines = self.caption(store, file_path);
+        if self.size_maps {
+            let output_base = format!("{}.sizes", file_path);
+            let max_len = self.params.max_len;
+            write_size_image(&store.sizes(), max_len, &output_base, self.options, &lines)?;
+        }
+        if self.self_maps {
+            let output_base = format!("{}.self", file_path);
+            write_image(&store.self_segments(), &output_base, self.options, &lines)?;
+        }
+        Ok(())
+    }
+}
 
 /// Formats the sum of two numbers as string.
 #[pyfunction]
-fn estimate(py: Python<'_>, file_paths: Vec<String>) -> PyResult<(usize, usize, usize)> {
+#[pyo3(signature = (
+    file_paths,
+    size_maps = false,
+    image_format = "png",
+    quality = 90,
+    image_size = IMAGE_DIM,
+    thumbnail_size = None,
+    captions = false,
+    self_maps = false,
+    attribution = None,
+    cache_path = None,
+    stream_images = false,
+    tags = None,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+    codec = "lz4",
+    codec_level = None,
+    mmap = false,
+    workers = None,
+    sidecars = false,
+    poster = false,
+    decompress = false,
+    progress = None,
+))]
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn estimate(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    size_maps: bool,
+    image_format: &str,
+    quality: u8,
+    image_size: usize,
+    thumbnail_size: Option<usize>,
+    captions: bool,
+    self_maps: bool,
+    attribution: Option<Attribution>,
+    cache_path: Option<String>,
+    stream_images: bool,
+    tags: Option<BTreeMap<String, String>>,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+    codec: &str,
+    codec_level: Option<i32>,
+    mmap: bool,
+    workers: Option<usize>,
+    sidecars: bool,
+    poster: bool,
+    decompress: bool,
+    progress: Option<PyObject>,
+) -> PyResult<(
+    (usize, usize, usize),
+    (usize, usize),
+    Vec<(
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        Option<(usize, String)>,
+        Option<String>,
+    )>,
+)> {
+    let options = ImageFormat::new(image_format, quality)
+        .and_then(|format| ImageOptions::new(format, image_size, thumbnail_size))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?
+        .with_tags(tags.unwrap_or_default().into_iter().collect());
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        codec,
+        codec_level,
+    )?
+    .with_decompress(decompress);
+    let images = FileImages {
+        options: &options,
+        params,
+        captions,
+        size_maps,
+        self_maps,
+        sidecars,
+        file_paths: &file_paths,
+    };
+    // cached recipes are loaded almost instantly, so streaming only applies
+    // to files which are actually chunked
+    let streamed = stream_images && cache_path.is_none();
+    let progress = py_progress(file_paths.len(), progress);
+    interruptible(py, &progress, || {
+        let mut stores = match &cache_path {
+            Some(cache_path) => {
+                let mut cache = RecipeCache::load(cache_path, params)?;
+                let stores = ChunkStore::from_files_cached(&file_paths, &mut cache, &progress)?;
+                cache.save(cache_path)?;
+                stores
+            }
+            // the pipeline reads the files in blocks, so mapped files are
+            // chunked one per thread instead
+            None if streamed || mmap => ChunkStore::from_files_with(
+                &file_paths,
+                false,
+                params,
+                mmap,
+                &progress,
+                |path, store| {
+                    if streamed {
+                        images.write_local(store, path)?;
+                    }
+                    progress.file_done(0)
+                },
+            )?,
+            None => {
+                let workers = workers.unwrap_or_else(rayon::current_num_threads);
+                pipeline::chunk_files(&file_paths, workers, QUEUE_DEPTH, params, &progress)?.0
+            }
+        };
+        progress.finish()?;
+        let merged = merge_stores(&file_paths, &mut stores, false, attribution)?;
+        let files = merged.breakdown(&stores);
+
+        stores
+            .par_iter()
+            .zip(file_paths.par_iter())
+            .enumerate()
+            .try_for_each(|(index, (store, file_path))| {
+                // the images of a cancelled run are left unwritten
+                progress.check()?;
+                // the poster replaces the per-file ownership images
+                if !poster {
+                    images.write_ownership(store, index)?;
+                }
+                if !streamed {
+                    images.write_local(store, file_path)?;
+                }
+                Ok::<_, std::io::Error>(())
+            })?;
+
+        let file_dir = Path::new(file_paths.last().unwrap()).parent().unwrap();
+        let output_base = file_dir.join("merged");
+        let lines = if captions {
+            caption(
+                &format!("merged {} files", file_paths.len()),
+                merged.stats(),
+                &params,
+            )
+        } else {
+            vec![]
+        };
+        write_image(
+            &merged.segments(),
+            output_base.to_str().unwrap(),
+            &options,
+            &lines,
+        )?;
+        if sidecars {
+            let base = output_base.to_str().unwrap();
+            sidecar::write(&stores, 0, &file_paths, base, &options)?;
+        }
+        if poster {
+            // the files deduplicating best against the rest come first
+            let ratio = |&(total, exclusive, _, _): &(usize, usize, usize, usize)| {
+                exclusive as f64 / total.max(1) as f64
+            };
+            let mut order: Vec<usize> = (0..stores.len()).collect();
+            order.sort_by(|&a, &b| ratio(&files[a]).total_cmp(&ratio(&files[b])));
+            let strips: Vec<Vec<usize>> = order.iter().map(|&i| stores[i].segments()).collect();
+            let lines = if captions {
+                let title = format!("{} files by dedup ratio", file_paths.len());
+                caption(&title, merged.stats(), &params)
+            } else {
+                vec![]
+            };
+            let base = file_dir.join("poster");
+            write_poster(&strips, base.to_str().unwrap(), &options, &lines)?;
+        }
+
+        let forced = match params.algorithm {
+            // fixed-size blocks are all cut at the same length by design
+            Algorithm::Fixed => 0,
+            _ => merged.chunks_of_size(params.max_len),
+        };
+        // the bytes attributed to every file like in `estimate_labeled`, the
+        // chunk digests of the reports, which hash lists don't have, and the
+        // `sniff` verdicts of the warnings
+        let files = files
+            .into_iter()
+            .zip(stores.iter().zip(&file_paths))
+            .enumerate()
+            .map(
+                |(index, ((total, exclusive, shared, compressed), (store, path)))| {
+                    let owned = store.stats().1 - store.cross_file_bytes(index as i64);
+                    let digest = (!is_hash_list(path)).then(|| store.chunk_digest());
+                    let sniffed = store.sniffed().map(str::to_owned);
+                    (total, exclusive, shared, owned, compressed, digest, sniffed)
+                },
+            )
+            .collect();
+        Ok((merged.stats(), (merged.unique_chunks(), forced), files))
+    })
+}
+
+/// The chunker parameters of the scans: the default ones, with `compress`
+/// unset with no codec, skipping the compression of every chunk which can
+/// take most of the scan time when only the deduplication ratio matters, the
+/// compressed bytes being the chunk bytes then, and with `decompress` the
+/// compressed files chunked decompressed like in `estimate`.
+fn scan_params(compress: bool, decompress: bool) -> ChunkParams {
+    let codec = if compress {
+        Codec::default()
+    } else {
+        Codec::None
+    };
+    ChunkParams::default()
+        .with_codec(codec)
+        .with_decompress(decompress)
+}
+
+/// Estimate the deduplication tracking at most `max_chunks` unique chunks,
+/// switching to hash-sampled approximate counts once the limit is exceeded.
+/// Returns the (total, unique, compressed) stats and the sampling shift, the
+/// stats are exact if it is zero and sample one in 2^shift chunks otherwise,
+/// and the `sniff` verdict of every file, whose chunks are counted
+/// uncompressed if it looks compressed or encrypted. Without `compress` the
+/// chunks aren't compressed and with `decompress` the compressed files are
+/// chunked decompressed, see `scan_params`. The scan is reported to the
+/// `progress` callable like in `estimate`.
+#[pyfunction]
+#[pyo3(signature = (file_paths, max_chunks, compress = true, decompress = false, progress = None))]
+fn estimate_bounded(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    max_chunks: usize,
+    compress: bool,
+    decompress: bool,
+    progress: Option<PyObject>,
+) -> PyResult<((usize, usize, usize), u32, Vec<Option<String>>)> {
+    if max_chunks == 0 {
+        return Err(PyValueError::new_err("max_chunks must be positive"));
+    }
+    let progress = py_progress(file_paths.len(), progress);
+    interruptible(py, &progress, || {
+        let params = scan_params(compress, decompress);
+        let (counter, sniffed) = sample::count_files(&file_paths, max_chunks, params, &progress)?;
+        progress.finish()?;
+        Ok((counter.stats(), counter.shift(), sniffed))
+    })
+}
+
+/// Estimate the deduplication approximately within `memory_budget` bytes
+/// with a Bloom filter and a HyperLogLog sketch instead of the chunk index.
+/// Returns the (total, unique, compressed) stats, the relative error bound
+/// of the unique and compressed bytes and the `sniff` verdict of every file
+/// like `estimate_bounded`. The `compress` and `decompress` flags are the
+/// ones of `estimate_bounded`. The scan is reported to the `progress`
+/// callable like in `estimate`.
+#[pyfunction]
+#[pyo3(signature = (file_paths, memory_budget, compress = true, decompress = false, progress = None))]
+fn estimate_sketched(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    memory_budget: usize,
+    compress: bool,
+    decompress: bool,
+    progress: Option<PyObject>,
+) -> PyResult<((usize, usize, usize), f64, Vec<Option<String>>)> {
+    if memory_budget < sketch::MIN_BUDGET {
+        return Err(PyValueError::new_err(format!(
+            "memory_budget must be at least {} bytes",
+            sketch::MIN_BUDGET
+        )));
+    }
+    let progress = py_progress(file_paths.len(), progress);
+    interruptible(py, &progress, || {
+        let params = scan_params(compress, decompress);
+        let (counter, sniffed) =
+            sketch::count_files(&file_paths, memory_budget, params, &progress)?;
+        progress.finish()?;
+        let (stats, error) = counter.stats();
+        Ok((stats, error, sniffed))
+    })
+}
+
+/// Estimate the deduplication exactly with a chunk index spilled to
+/// `partitions` files in a temporary directory under `index_dir`, holding
+/// a single partition in memory at a time. Returns the same (total, unique,
+/// compressed) stats as `estimate` and the `sniff` verdict of every file like
+/// `estimate_bounded`. The `compress` and `decompress` flags are the ones of
+/// `estimate_bounded`. The chunking is reported to the `progress` callable
+/// like in `estimate`.
+#[pyfunction]
+#[pyo3(signature = (
+    file_paths,
+    index_dir,
+    partitions = 256,
+    compress = true,
+    decompress = false,
+    progress = None,
+))]
+fn estimate_on_disk(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    index_dir: PathBuf,
+    partitions: usize,
+    compress: bool,
+    decompress: bool,
+    progress: Option<PyObject>,
+) -> PyResult<((usize, usize, usize), Vec<Option<String>>)> {
+    if partitions == 0 {
+        return Err(PyValueError::new_err("partitions must be positive"));
+    }
+    let progress = py_progress(file_paths.len(), progress);
+    interruptible(py, &progress, || {
+        let params = scan_params(compress, decompress);
+        let counted = spill::count_files(&file_paths, &index_dir, partitions, params, &progress)?;
+        progress.finish()?;
+        Ok(counted)
+    })
+}
+
+/// Estimate the deduplication keeping nothing but the set of chunk hashes,
+/// see the `hashonly` module. Returns the (total, unique) bytes, the first
+/// two stats of `estimate`, without compressing any chunk.
+#[pyfunction]
+#[pyo3(signature = (
+    file_paths,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+))]
+fn estimate_hash_only(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+) -> PyResult<(usize, usize)> {
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        "none",
+        None,
+    )?;
+    py.allow_threads(|| Ok(hashonly::count_files(&file_paths, params)?))
+}
+
+/// Replace the directories and glob patterns among the paths by the files
+/// they contain, see the `expand` module.
+#[pyfunction]
+fn expand_paths(py: Python<'_>, paths: Vec<String>) -> PyResult<Vec<String>> {
+    py.allow_threads(|| Ok(expand::expand(&paths)?))
+}
+
+/// Rebuild file `index` from the deduplicated chunks of the files into
+/// `output_path` and verify it against the file, see the `verify` module.
+/// Returns the number of bytes written and the offset of the first byte
+/// differing from the file, None if they are identical.
+#[pyfunction]
+#[pyo3(signature = (
+    file_paths,
+    index,
+    output_path,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn reconstruct(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    index: usize,
+    output_path: PathBuf,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+) -> PyResult<(usize, Option<usize>)> {
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        "none",
+        None,
+    )?;
+    if let Some(path) = file_paths.iter().find(|path| is_hash_list(path)) {
+        return Err(PyValueError::new_err(format!(
+            "{path} is a hash list, reconstruction requires the chunk data"
+        )));
+    }
+    py.allow_threads(
+        || match verify::reconstruct(&file_paths, index, params, &output_path) {
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+                Err(PyValueError::new_err(e.to_string()))
+            }
+            result => Ok(result?),
+        },
+    )
+}
+
+/// Chunk the files with the default gearhash chunker at every target chunk
+/// size from a single read, see the `sweep` module. Returns the total bytes
+/// and per target the (chunks, unique chunks, unique bytes).
+#[pyfunction]
+fn chunk_size_sweep(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    target_chunk_sizes: Vec<usize>,
+) -> PyResult<(usize, Vec<(usize, usize, usize)>)> {
+    if target_chunk_sizes.is_empty() {
+        return Err(PyValueError::new_err("Pass at least one target chunk size"));
+    }
+    let params = target_chunk_sizes
+        .iter()
+        .map(|&target| ChunkParams::new(Some(target), None, None, None, Algorithm::Gearhash))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    if let Some(path) = file_paths.iter().find(|path| is_hash_list(path)) {
+        return Err(PyValueError::new_err(format!(
+            "{path} is a hash list, the sweep requires the file data"
+        )));
+    }
+    py.allow_threads(|| {
+        let (total, stats) = sweep_files(&file_paths, &params)?;
+        let stats = stats
+            .into_iter()
+            .map(|s| (s.chunks, s.unique_chunks, s.chunk_bytes))
+            .collect();
+        Ok((total, stats))
+    })
+}
+
+/// Chunk the files through the staged pipeline of the estimate path without
+/// rendering images, its stages splitting `workers` workers, the size of the
+/// rayon pool by default. Returns the (total, unique, compressed) stats, the per
+/// stage (name, workers, items, busy seconds, blocked seconds) and the wall
+/// time in seconds.
+#[pyfunction]
+#[pyo3(signature = (file_paths, queue_depth = QUEUE_DEPTH, workers = None))]
+#[allow(clippy::type_complexity)]
+fn stage_timings(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    queue_depth: usize,
+    workers: Option<usize>,
+) -> PyResult<(
+    (usize, usize, usize),
+    Vec<(&'static str, usize, usize, f64, f64)>,
+    f64,
+)> {
+    let workers = workers.unwrap_or_else(rayon::current_num_threads);
+    py.allow_threads(|| {
+        let start = Instant::now();
+        let params = ChunkParams::default();
+        let progress = Progress::default();
+        let (stores, timings) =
+            pipeline::chunk_files(&file_paths, workers, queue_depth, params, &progress)?;
+        let merged = ChunkStore::merge_owned(stores, false);
+        let timings = timings
+            .into_iter()
+            .map(|t| {
+                let (busy, blocked) = (t.busy.as_secs_f64(), t.blocked.as_secs_f64());
+                (t.name, t.workers, t.items, busy, blocked)
+            })
+            .collect();
+        Ok((merged.stats(), timings, start.elapsed().as_secs_f64()))
+    })
+}
+
+/// Chunk the files in parallel like `estimate` without rendering images,
+/// reading them through a buffer or with `mmap` memory mapped. Returns the
+/// bytes chunked and the wall time in seconds.
+#[pyfunction]
+#[pyo3(signature = (file_paths, mmap = false))]
+fn ingest_timing(py: Python<'_>, file_paths: Vec<String>, mmap: bool) -> PyResult<(usize, f64)> {
+    py.allow_threads(|| {
+        let start = Instant::now();
+        let params = ChunkParams::default();
+        let progress = Progress::default();
+        let stores = ChunkStore::from_files_with(
+            &file_paths,
+            false,
+            params,
+            mmap,
+            &progress,
+            |_, _| Ok(()),
+        )?;
+        let total = stores.iter().map(ChunkStore::total).sum();
+        Ok((total, start.elapsed().as_secs_f64()))
+    })
+}
+
+/// Return the end offsets and the hashes of the chunks of a file as the
+/// bytes of little endian u64 arrays, skipping the chunk store entirely.
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+    decompress = false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn boundaries(
+    py: Python<'_>,
+    path: String,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+    decompress: bool,
+) -> PyResult<(Py<PyBytes>, Py<PyBytes>)> {
+    if is_hash_list(&path) {
+        return Err(PyValueError::new_err(format!(
+            "{path} is a hash list, it has no chunk boundaries"
+        )));
+    }
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        "none",
+        None,
+    )?
+    .with_decompress(decompress);
+    let (offsets, hashes) = py.allow_threads(|| chunk_boundaries(&path, params))?;
+    let to_bytes =
+        |values: Vec<u64>| -> Vec<u8> { values.into_iter().flat_map(u64::to_le_bytes).collect() };
+    Ok((
+        PyBytes::new(py, &to_bytes(offsets)).unbind(),
+        PyBytes::new(py, &to_bytes(hashes)).unbind(),
+    ))
+}
+
+/// Resolve the chunker options like `estimate` into the (algorithm, mask,
+/// min, max) chunker parameters, e.g. to record them in reports.
+#[pyfunction]
+#[pyo3(signature = (
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+))]
+fn chunker_params(
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+) -> PyResult<(&'static str, u64, usize, usize)> {
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        "none",
+        None,
+    )?;
+    Ok((
+        params.algorithm.name(),
+        params.mask,
+        params.min_len,
+        params.max_len,
+    ))
+}
+
+/// Estimate the deduplication of the decompressed parquet page payloads,
+/// returning the same (total, unique, compressed) stats as `estimate`.
+#[pyfunction]
+fn estimate_pages(py: Python<'_>, file_paths: Vec<String>) -> PyResult<(usize, usize, usize)> {
+    py.allow_threads(|| {
+        let stores = ChunkStore::from_parquet_files(&file_paths, false)?;
+        let merged = ChunkStore::merge_owned(stores, false);
+        Ok(merged.stats())
+    })
+}
+
+/// Render the chunk ownership of every leaf column of the parquet files to
+/// `{file}.columns.{ext}`, one strip per column. Columns are matched across
+/// files by their path, so a strip shows which file first stored each chunk
+/// of that column. Returns the column paths of each file in strip order.
+#[pyfunction]
+#[pyo3(signature = (
+    file_paths,
+    image_format = "png",
+    quality = 90,
+    image_size = IMAGE_DIM,
+    captions = false,
+))]
+fn column_maps(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    image_format: &str,
+    quality: u8,
+    image_size: usize,
+    captions: bool,
+) -> PyResult<Vec<Vec<String>>> {
+    let options = ImageFormat::new(image_format, quality)
+        .and_then(|format| ImageOptions::new(format, image_size, None))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    py.allow_threads(|| {
+        let columns = file_paths
+            .par_iter()
+            .map(parquet_columns)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let mut stores = file_paths
+            .par_iter()
+            .zip(columns.par_iter())
+            .map(|(path, names)| {
+                (0..names.len())
+                    .map(|i| ChunkStore::from_parquet_column(path, i, false))
+                    .collect::<std::io::Result<Vec<_>>>()
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        // the (file, column) indices of every column path in file order
+        let mut groups: Vec<(&str, Vec<(usize, usize)>)> = Vec::new();
+        for (i, names) in columns.iter().enumerate() {
+            for (j, name) in names.iter().enumerate() {
+                match groups.iter_mut().find(|(n, _)| *n == name.as_str()) {
+                    Some((_, members)) => members.push((i, j)),
+                    None => groups.push((name.as_str(), vec![(i, j)])),
+                }
+            }
+        }
+
+        let mut segments: Vec<Vec<Vec<usize>>> = columns
+            .iter()
+            .map(|names| vec![Vec::new(); names.len()])
+            .collect();
+        for (_, members) in &groups {
+            let mut group: Vec<ChunkStore> = members
+                .iter()
+                .map(|&(i, j)| std::mem::take(&mut stores[i][j]))
+                .collect();
+            ChunkStore::merge(&mut group, false);
+            // map the owners from indices within the group back to files
+            for (store, &(i, j)) in group.iter().zip(members) {
+                segments[i][j] = store
+                    .segments()
+                    .into_iter()
+                    .map(|owner| members[owner].0)
+                    .collect();
+            }
+        }
+
+        file_paths
+            .par_iter()
+            .zip(columns.par_iter())
+            .zip(segments.par_iter())
+            .try_for_each(|((file_path, names), segments)| {
+                let lines: Vec<String> = if captions {
+                    let name = Path::new(file_path).file_name().unwrap().to_string_lossy();
+                    std::iter::once(name.into_owned())
+                        .chain(names.iter().enumerate().map(|(i, n)| format!("{i}: {n}")))
+                        .collect()
+                } else {
+                    vec![]
+                };
+                let output_base = format!("{}.columns", file_path);
+                write_column_image(segments, &output_base, &options, &lines)
+            })?;
+
+        Ok(columns)
+    })
+}
+
+/// Estimate the deduplication of the members of a single concatenated file,
+/// e.g. a tar archive, starting at the given byte offsets and ending at the
+/// next one or at their `ends` offsets, chunked with the chunker and codec
+/// options of `estimate`. Returns the per member (total bytes, unique bytes)
+/// and the merged (total, unique, compressed) stats.
+#[pyfunction]
+#[pyo3(signature = (
+    file_path,
+    boundaries,
+    ends = None,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+    codec = "lz4",
+    codec_level = None,
+    decompress = false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn estimate_concatenated(
+    py: Python<'_>,
+    file_path: String,
+    boundaries: Vec<u64>,
+    ends: Option<Vec<u64>>,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+    codec: &str,
+    codec_level: Option<i32>,
+    decompress: bool,
+) -> PyResult<(Vec<(usize, usize)>, (usize, usize, usize))> {
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        codec,
+        codec_level,
+    )?
+    .with_decompress(decompress);
+    py.allow_threads(|| {
+        let mut reader = BufReader::new(File::open(&file_path)?);
+        let stores =
+            ChunkStore::from_concatenated(&mut reader, &boundaries, ends.as_deref(), false, params);
+        let stores = match stores {
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+                return Err(PyValueError::new_err(e.to_string()))
+            }
+            stores => stores?,
+        };
+        let members = stores
+            .iter()
+            .map(|store| {
+                let (total, unique, _) = store.stats();
+                (total, unique)
+            })
+            .collect();
+        let merged = ChunkStore::merge_owned(stores, false);
+        Ok((members, merged.stats()))
+    })
+}
+
+/// Return the (uncompressed size, number of values) of the data pages of
+/// every column chunk of a parquet file.
+#[pyfunction]
+fn page_layout(py: Python<'_>, file_path: String) -> PyResult<Vec<Vec<(usize, usize)>>> {
+    py.allow_threads(|| Ok(data_page_layout(&file_path)?))
+}
+
+/// Return a warning for each input that looks already compressed or
+/// encrypted, such inputs are expected to show almost no deduplication.
+#[pyfunction]
+fn detect_incompressible(py: Python<'_>, file_paths: Vec<String>) -> PyResult<Vec<Option<String>>> {
+    py.allow_threads(|| {
+        file_paths
+            .iter()
+            .map(|path| Ok(sniff::sniff_file(path)?))
+            .collect()
+    })
+}
+
+/// Write the merged chunk store of the given files as a versioned JSON or
+/// CBOR document tagged with the given metadata, see the `serialize` module
+/// for the schema. If a `previous` snapshot or a `timestamp` is given, the
+/// chunks are stamped with their first and last seen unix times, the
+/// timestamp defaulting to now, see the `retention` module.
+#[pyfunction]
+#[pyo3(signature = (file_paths, output_path, tags = None, previous = None, timestamp = None))]
+fn export_store(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    output_path: String,
+    tags: Option<BTreeMap<String, String>>,
+    previous: Option<String>,
+    timestamp: Option<u64>,
+) -> PyResult<()> {
     py.allow_threads(|| {
         let mut stores = ChunkStore::from_files(&file_paths, false)?;
         let merged = ChunkStore::merge(&mut stores, false);
-
-        for (store, file_path) in stores.iter().zip(file_paths.iter()) {
-            let segments = store.segments();
-            let output_file_path = format!("{}.png", file_path);
-            write_png(&segments, &output_file_path)?;
+        let mut doc = serialize::StoreDocument::new(&file_paths, &stores, &merged);
+        doc.tags = tags.unwrap_or_default();
+        if previous.is_some() || timestamp.is_some() {
+            let now = timestamp.unwrap_or_else(retention::unix_now);
+            match retention::stamp(&mut doc, previous.as_ref(), now) {
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    return Err(PyValueError::new_err(e.to_string()))
+                }
+                r => r?,
+            }
         }
+        doc.write(&output_path)?;
+        Ok(())
+    })
+}
 
-        let file_dir = Path::new(file_paths.last().unwrap()).parent().unwrap();
-        let output_file_path = file_dir.join("merged.png");
-        write_png(&merged.segments(), output_file_path.to_str().unwrap())?;
+/// Write a casync/desync blob index of every file to `{file}.caibx`, returning
+/// the number of chunks of each.
+#[pyfunction]
+fn export_caibx(py: Python<'_>, file_paths: Vec<String>) -> PyResult<Vec<usize>> {
+    if let Some(path) = file_paths.iter().find(|path| is_hash_list(path)) {
+        return Err(PyValueError::new_err(format!(
+            "{path} is a hash list, indexes require the chunk data"
+        )));
+    }
+    py.allow_threads(|| {
+        file_paths
+            .par_iter()
+            .map(|path| {
+                let store = ChunkStore::from_file(path, false)?;
+                Ok(casync::write_index(path, &store, format!("{path}.caibx"))?)
+            })
+            .collect()
+    })
+}
 
-        Ok(merged.stats())
+/// Write the hash list of every file to `{file}.hashes`, keyed with the
+/// BLAKE3 key derived from `key` if given, returning the number of chunks of
+/// each.
+#[pyfunction]
+#[pyo3(signature = (file_paths, key = None))]
+fn export_hash_lists(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    key: Option<Vec<u8>>,
+) -> PyResult<Vec<usize>> {
+    if let Some(path) = file_paths.iter().find(|path| is_hash_list(path)) {
+        return Err(PyValueError::new_err(format!(
+            "{path} is already a hash list"
+        )));
+    }
+    let key = key.map(|key| hashlist::derive_key(&key));
+    py.allow_threads(|| {
+        file_paths
+            .par_iter()
+            .map(|path| {
+                let output = format!("{path}.hashes");
+                Ok(hashlist::write_hash_list(path, output, key.as_ref())?)
+            })
+            .collect()
     })
 }
 
+/// Diff two documents written by `export_store`. Returns the (added chunks,
+/// added bytes, removed chunks, removed bytes, chunks with more occurrences,
+/// chunks with fewer occurrences, change of the referenced bytes) and the per
+/// file (path, added chunks, added bytes, removed chunks, removed bytes).
 #[pyfunction]
-#[pyo3(signature = (file_paths, store_data = false))]
-fn chunks(
+#[allow(clippy::type_complexity)]
+fn diff_stores(
+    py: Python<'_>,
+    old_path: String,
+    new_path: String,
+) -> PyResult<(
+    (usize, usize, usize, usize, usize, usize, i64),
+    Vec<(String, usize, usize, usize, usize)>,
+)> {
+    py.allow_threads(|| {
+        let d = match diff::diff_files(&old_path, &new_path) {
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return Err(PyValueError::new_err(e.to_string()))
+            }
+            d => d?,
+        };
+        let files = d
+            .files
+            .into_iter()
+            .map(|f| {
+                let (added, removed) = (f.added_bytes, f.removed_bytes);
+                (f.path, f.added_chunks, added, f.removed_chunks, removed)
+            })
+            .collect();
+        Ok((
+            (
+                d.added_chunks,
+                d.added_bytes,
+                d.removed_chunks,
+                d.removed_bytes,
+                d.increased_chunks,
+                d.decreased_chunks,
+                d.referenced_bytes_delta,
+            ),
+            files,
+        ))
+    })
+}
+
+/// Diff two revisions of a dataset from their files, see `diff::RevisionDiff`.
+/// Returns the total bytes of the old and new revisions, the (chunks, bytes,
+/// compressed bytes) added, the (chunks, bytes) removed and the (chunks,
+/// bytes) shared.
+#[pyfunction]
+#[pyo3(signature = (
+    old_paths,
+    new_paths,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+    codec = "lz4",
+    codec_level = None,
+))]
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn diff_revisions(
+    py: Python<'_>,
+    old_paths: Vec<String>,
+    new_paths: Vec<String>,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+    codec: &str,
+    codec_level: Option<i32>,
+) -> PyResult<(
+    (usize, usize),
+    (usize, usize, usize),
+    (usize, usize),
+    (usize, usize),
+)> {
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        codec,
+        codec_level,
+    )?;
+    py.allow_threads(|| {
+        let d = diff::diff_revisions(&old_paths, &new_paths, params)?;
+        Ok((
+            (d.old_total, d.new_total),
+            (d.added_chunks, d.added_bytes, d.added_compressed_bytes),
+            (d.removed_chunks, d.removed_bytes),
+            (d.shared_chunks, d.shared_bytes),
+        ))
+    })
+}
+
+/// Summarize the chunk ages of a document written by `export_store` relative
+/// to the `cutoff` unix time. Returns the (chunks, bytes) referenced by the
+/// snapshot, referenced and first seen before the cutoff, retained from
+/// earlier snapshots and retained but last seen before the cutoff.
+#[pyfunction]
+fn retention(py: Python<'_>, path: String, cutoff: u64) -> PyResult<[(usize, usize); 4]> {
+    py.allow_threads(|| {
+        let doc = match serialize::StoreDocument::read(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return Err(PyValueError::new_err(e.to_string()))
+            }
+            doc => doc?,
+        };
+        let r = retention::retention(&doc, cutoff);
+        Ok([
+            (r.live_chunks, r.live_bytes),
+            (r.aged_chunks, r.aged_bytes),
+            (r.retained_chunks, r.retained_bytes),
+            (r.stale_chunks, r.stale_bytes),
+        ])
+    })
+}
+
+/// Write a bloom filter over the unique chunk hashes of the given files.
+#[pyfunction]
+#[pyo3(signature = (file_paths, output_path, fpp = 0.01))]
+fn export_bloom(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    output_path: String,
+    fpp: f64,
+) -> PyResult<()> {
+    py.allow_threads(|| {
+        let stores = ChunkStore::from_files(&file_paths, false)?;
+        let merged = ChunkStore::merge_owned(stores, false);
+        let bloom = merged
+            .bloom_filter(fpp)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        bloom.write(&output_path)?;
+        Ok(())
+    })
+}
+
+/// Return the unique bytes of the given files and the part of them matching
+/// the chunk hashes in a bloom filter exported by `export_bloom`.
+#[pyfunction]
+fn estimate_against_bloom(
     py: Python<'_>,
     file_paths: Vec<String>,
+    bloom_path: String,
+) -> PyResult<(usize, usize)> {
+    py.allow_threads(|| {
+        let bloom = BloomFilter::read(&bloom_path)?;
+        let stores = ChunkStore::from_files(&file_paths, false)?;
+        let merged = ChunkStore::merge_owned(stores, false);
+        Ok(merged.matches_bloom(&bloom))
+    })
+}
+
+/// Render a dot plot of the chunks shared between two files to
+/// `{output_base}.{ext}`.
+#[pyfunction]
+#[pyo3(signature = (
+    file_a,
+    file_b,
+    output_base,
+    image_format = "png",
+    quality = 90,
+    image_size = IMAGE_DIM,
+))]
+fn dot_plot(
+    py: Python<'_>,
+    file_a: String,
+    file_b: String,
+    output_base: String,
+    image_format: &str,
+    quality: u8,
+    image_size: usize,
+) -> PyResult<()> {
+    let options = ImageFormat::new(image_format, quality)
+        .and_then(|format| ImageOptions::new(format, image_size, None))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    py.allow_threads(|| {
+        let a = ChunkStore::from_file(&file_a, false)?;
+        let b = ChunkStore::from_file(&file_b, false)?;
+        let matches = a.matches(&b);
+        write_dot_plot(&matches, a.total(), b.total(), &output_base, &options)?;
+        Ok(())
+    })
+}
+
+/// A Python file-like object read to the end through its `read` method. The
+/// GIL is only taken for every read, so the chunking runs without it and
+/// other Python threads, e.g. the one downloading the data, run meanwhile.
+struct PyReader(Py<PyAny>);
+
+impl std::io::Read for &PyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Python::with_gil(|py| {
+            let data = self.0.call_method1(py, "read", (buf.len(),))?;
+            let data: std::borrow::Cow<[u8]> = data.extract(py).map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "read() must return bytes, open the file in binary mode",
+                )
+            })?;
+            if data.len() > buf.len() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("read({}) returned {} bytes", buf.len(), data.len()),
+                ));
+            }
+            buf[..data.len()].copy_from_slice(&data);
+            Ok(data.len())
+        })
+    }
+}
+
+/// An input of `chunks`, either a file path, an object exposing the buffer
+/// protocol, e.g. bytes, mmap, numpy arrays or Arrow buffers, or a file-like
+/// object with a `read` method, e.g. a network stream.
+enum Input {
+    Path(String),
+    Buffer(PyBuffer<u8>),
+    Stream(PyReader),
+}
+
+impl<'py> FromPyObject<'py> for Input {
+    fn extract_bound(obj: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(path) = obj.extract::<String>() {
+            return Ok(Input::Path(path));
+        }
+        // view the buffer as bytes so arrays of any item type are accepted,
+        // the cast fails for non-contiguous buffers
+        let buffer = PyMemoryView::from(obj)
+            .and_then(|view| view.call_method1("cast", ("B",)))
+            .and_then(|view| PyBuffer::get(&view));
+        match buffer {
+            Ok(buffer) => Ok(Input::Buffer(buffer)),
+            // buffers like mmap are chunked in place rather than read
+            Err(_) if obj.hasattr("read")? => Ok(Input::Stream(PyReader(obj.clone().unbind()))),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Input {
+    fn label(&self, index: usize) -> String {
+        match self {
+            Input::Path(path) => path.clone(),
+            Input::Buffer(_) => format!("<buffer {index}>"),
+            Input::Stream(_) => format!("<stream {index}>"),
+        }
+    }
+
+    /// Chunk the input, reporting the bytes chunked to `progress`.
+    fn chunk(
+        &self,
+        store_data: bool,
+        params: ChunkParams,
+        progress: &Progress,
+    ) -> std::io::Result<ChunkStore> {
+        match self {
+            Input::Path(path) => {
+                ChunkStore::from_file_with_params(path, store_data, params, progress)
+            }
+            Input::Buffer(buffer) => {
+                // SAFETY: the buffer is C-contiguous bytes after the cast and
+                // the PyBuffer keeps it alive and its memory in place until
+                // dropped; like any buffer consumer, concurrent writes from
+                // other threads would race with the chunking.
+                let data = unsafe {
+                    std::slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes())
+                };
+                ChunkStore::from_slice(data, store_data, params, progress)
+            }
+            Input::Stream(reader) => {
+                let mut reader = reader;
+                ChunkStore::from_stream_with_params(&mut reader, store_data, params, progress)
+            }
+        }
+    }
+}
+
+/// Return the merged chunks in order, either as a list of (hash, chunk)
+/// tuples or, with `arrow`, as the bytes of an Arrow IPC stream which avoids
+/// creating a Python object per chunk. Inputs exposing the buffer protocol
+/// are chunked in place without copying them, file-like ones are read to the
+/// end. The chunker parameters are
+/// derived by `chunk_params` and default to the built-in ones.
+#[pyfunction]
+#[pyo3(signature = (
+    file_paths,
+    store_data = false,
+    attribution = None,
+    arrow = false,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+    codec = "lz4",
+    codec_level = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn chunks(
+    py: Python<'_>,
+    file_paths: Vec<Input>,
     store_data: bool,
-) -> PyResult<Vec<(u64, Chunk)>> {
+    attribution: Option<Attribution>,
+    arrow: bool,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+    codec: &str,
+    codec_level: Option<i32>,
+) -> PyResult<PyObject> {
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        codec,
+        codec_level,
+    )?;
+    let merged = py.allow_threads(|| {
+        let labels: Vec<String> = file_paths
+            .iter()
+            .enumerate()
+            .map(|(i, input)| input.label(i))
+            .collect();
+        let pool = PayloadPool::default();
+        let mut stores = file_paths
+            .par_iter()
+            .map(|input| {
+                let mut store = input.chunk(store_data, params, &Progress::default())?;
+                if store_data {
+                    store.share_payloads(&pool);
+                }
+                Ok(store)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        merge_stores(&labels, &mut stores, store_data, attribution)
+    })?;
+    if arrow {
+        let ipc = py.allow_threads(|| chunks_to_arrow_ipc(&merged))?;
+        Ok(PyBytes::new(py, &ipc).into_any().unbind())
+    } else {
+        Ok(merged.chunks().into_pyobject(py)?.into_any().unbind())
+    }
+}
+
+/// Return the sizes of the unique chunks of the merged store in ascending
+/// order as the bytes of a little endian u64 array, to check the chunk size
+/// distribution produced by the chunker parameters.
+#[pyfunction]
+#[pyo3(signature = (
+    file_paths,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+))]
+fn chunk_sizes(
+    py: Python<'_>,
+    file_paths: Vec<Input>,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+) -> PyResult<Py<PyBytes>> {
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        "none",
+        None,
+    )?;
+    let sizes = py.allow_threads(|| {
+        let stores = file_paths
+            .par_iter()
+            .map(|input| input.chunk(false, params, &Progress::default()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let merged = ChunkStore::merge_owned(stores, false);
+        // the empty last chunk of files ending on a boundary is not a chunk
+        let mut sizes: Vec<u64> = merged
+            .iter_chunks()
+            .filter(|(_, chunk)| chunk.size > 0)
+            .map(|(_, chunk)| chunk.size as u64)
+            .collect();
+        sizes.sort_unstable();
+        Ok::<_, std::io::Error>(
+            sizes
+                .into_iter()
+                .flat_map(u64::to_le_bytes)
+                .collect::<Vec<u8>>(),
+        )
+    })?;
+    Ok(PyBytes::new(py, &sizes).unbind())
+}
+
+/// Estimate the deduplication passing the (hash, bytes) of every unique chunk
+/// to the Python `callback` in the same pass, e.g. to compute custom metrics
+/// without re-reading the corpus, see the `metrics` module. Returns the same
+/// (total, unique, compressed) stats as `estimate`, an exception raised by
+/// the callback aborts the run.
+#[pyfunction]
+#[pyo3(signature = (
+    file_paths,
+    callback,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+    codec = "lz4",
+    codec_level = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn estimate_with_metric(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    callback: PyObject,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+    codec: &str,
+    codec_level: Option<i32>,
+) -> PyResult<(usize, usize, usize)> {
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        codec,
+        codec_level,
+    )?;
+    let metric = PyMetric::new(callback);
+    let stats = py.allow_threads(|| {
+        let stores = metrics::chunk_files(&file_paths, params, &metric)?;
+        Ok::<_, std::io::Error>(ChunkStore::merge_owned(stores, false).stats())
+    });
+    if let Some(e) = metric.take_error() {
+        return Err(e);
+    }
+    Ok(stats?)
+}
+
+/// Estimate the benefit of compressing the unique chunks with a shared zstd
+/// dictionary of at most `dict_size` bytes trained on a sample of them, see
+/// the `dictionary` module. Returns the (total, unique, compressed) stats of
+/// `estimate`, the compressed ones with `codec`, the dictionary-compressed
+/// bytes of the unique chunks at zstd `level` and the size of the dictionary.
+#[pyfunction]
+#[pyo3(signature = (
+    file_paths,
+    dict_size = dictionary::DICT_SIZE,
+    level = 3,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+    codec = "lz4",
+    codec_level = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn estimate_dictionary(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    dict_size: usize,
+    level: i32,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+    codec: &str,
+    codec_level: Option<i32>,
+) -> PyResult<((usize, usize, usize), usize, usize)> {
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        codec,
+        codec_level,
+    )?;
+    if dict_size == 0 {
+        return Err(PyValueError::new_err(
+            "The dictionary size must be positive",
+        ));
+    }
+    // validates the level against the zstd range
+    Codec::new("zstd", Some(level)).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let result = py.allow_threads(|| dictionary::estimate(&file_paths, params, dict_size, level));
+    result.map_err(|e| match e.kind() {
+        std::io::ErrorKind::InvalidData => PyValueError::new_err(e.to_string()),
+        _ => e.into(),
+    })
+}
+
+/// Estimate the deduplication of labeled inputs, file paths or objects
+/// exposing the buffer protocol, e.g. data which only exists in memory.
+/// Returns the merged (total, unique, compressed) stats and per input its
+/// (total, unique, owned) bytes, the owned ones being the unique bytes
+/// attributed to it, its (exclusive, shared, compressed) bytes of
+/// `ChunkStore::breakdown` and its `sniff` verdict. With `image_base` the
+/// merged chunk ownership image is written to `{image_base}.{ext}` along with
+/// a sidecar mapping its blocks to the labels, see the `sidecar` module.
+/// Every input done is reported to the `progress` callable, see
+/// `py_progress`.
+#[pyfunction]
+#[pyo3(signature = (
+    inputs,
+    image_base = None,
+    image_format = "png",
+    quality = 90,
+    image_size = IMAGE_DIM,
+    captions = false,
+    attribution = None,
+    chunker = "gearhash",
+    target_chunk_size = None,
+    min_chunk_size = None,
+    max_chunk_size = None,
+    chunk_mask = None,
+    codec = "lz4",
+    codec_level = None,
+    progress = None,
+))]
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn estimate_labeled(
+    py: Python<'_>,
+    inputs: Vec<(String, Input)>,
+    image_base: Option<String>,
+    image_format: &str,
+    quality: u8,
+    image_size: usize,
+    captions: bool,
+    attribution: Option<Attribution>,
+    chunker: &str,
+    target_chunk_size: Option<usize>,
+    min_chunk_size: Option<usize>,
+    max_chunk_size: Option<usize>,
+    chunk_mask: Option<u64>,
+    codec: &str,
+    codec_level: Option<i32>,
+    progress: Option<PyObject>,
+) -> PyResult<(
+    (usize, usize, usize),
+    Vec<(usize, usize, usize, (usize, usize, usize), Option<String>)>,
+)> {
+    let options = ImageFormat::new(image_format, quality)
+        .and_then(|format| ImageOptions::new(format, image_size, None))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let params = chunk_params(
+        chunker,
+        target_chunk_size,
+        min_chunk_size,
+        max_chunk_size,
+        chunk_mask,
+        codec,
+        codec_level,
+    )?;
+    let (labels, inputs): (Vec<String>, Vec<Input>) = inputs.into_iter().unzip();
+    let progress = py_progress(inputs.len(), progress);
+    interruptible(py, &progress, || {
+        let mut stores = inputs
+            .par_iter()
+            .map(|input| {
+                let store = input.chunk(false, params, &progress)?;
+                progress.file_done(0)?;
+                Ok(store)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        progress.finish()?;
+        let merged = merge_stores(&labels, &mut stores, false, attribution)?;
+        let per_input = stores
+            .iter()
+            .zip(merged.breakdown(&stores))
+            .enumerate()
+            .map(|(index, (store, (_, exclusive, shared, compressed)))| {
+                let (total, unique, _) = store.stats();
+                let owned = unique - store.cross_file_bytes(index as i64);
+                let breakdown = (exclusive, shared, compressed);
+                (
+                    total,
+                    unique,
+                    owned,
+                    breakdown,
+                    store.sniffed().map(str::to_owned),
+                )
+            })
+            .collect();
+
+        if let Some(base) = &image_base {
+            let lines = if captions {
+                let title = format!("merged {} inputs", labels.len());
+                caption(&title, merged.stats(), &params)
+            } else {
+                vec![]
+            };
+            write_image(&merged.segments(), base, &options, &lines)?;
+            sidecar::write(&stores, 0, &labels, base, &options)?;
+        }
+        Ok((merged.stats(), per_input))
+    })
+}
+
+/// Deduplication state built incrementally from files and buffers added one
+/// at a time, e.g. as they arrive from a pipeline, which can be queried at
+/// any point. Shared chunks are attributed to the first input containing
+/// them, like the default attribution of `estimate`. The state can be saved
+/// as an index and loaded back to only chunk the inputs added since, see the
+/// `index` module.
+#[pyclass(subclass, module = "de.core")]
+struct Estimator {
+    params: ChunkParams,
+    merged: ChunkStore,
+    labels: Vec<String>,
+    /// The number of inputs of the index loaded or last saved.
+    saved: usize,
+    /// The inputs added since, merged on their own to save them into an
+    /// index updated meanwhile by another process.
+    added: ChunkStore,
+    /// The saves which found the index updated meanwhile, and the inputs of
+    /// other processes they merged in.
+    conflicts: usize,
+    merged_inputs: usize,
+}
+
+#[pymethods]
+impl Estimator {
+    /// Start empty or from the index saved at `index`, whose chunker
+    /// parameters are used unless others are given, which must match them.
+    #[new]
+    #[pyo3(signature = (
+        chunker = None,
+        target_chunk_size = None,
+        min_chunk_size = None,
+        max_chunk_size = None,
+        chunk_mask = None,
+        codec = None,
+        codec_level = None,
+        index = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        chunker: Option<&str>,
+        target_chunk_size: Option<usize>,
+        min_chunk_size: Option<usize>,
+        max_chunk_size: Option<usize>,
+        chunk_mask: Option<u64>,
+        codec: Option<&str>,
+        codec_level: Option<i32>,
+        index: Option<PathBuf>,
+    ) -> PyResult<Self> {
+        let explicit = chunker.is_some()
+            || target_chunk_size.is_some()
+            || min_chunk_size.is_some()
+            || max_chunk_size.is_some()
+            || chunk_mask.is_some()
+            || codec.is_some()
+            || codec_level.is_some();
+        let params = chunk_params(
+            chunker.unwrap_or("gearhash"),
+            target_chunk_size,
+            min_chunk_size,
+            max_chunk_size,
+            chunk_mask,
+            codec.unwrap_or("lz4"),
+            codec_level,
+        )?;
+        let Some(index) = index else {
+            return Ok(Estimator {
+                params,
+                merged: Estimator::empty(params),
+                labels: Vec::new(),
+                saved: 0,
+                added: Estimator::empty(params),
+                conflicts: 0,
+                merged_inputs: 0,
+            });
+        };
+        let (merged, labels) = match py.allow_threads(|| ChunkStore::load(&index)) {
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return Err(PyValueError::new_err(e.to_string()))
+            }
+            loaded => loaded?,
+        };
+        if explicit && merged.params() != params {
+            return Err(PyValueError::new_err(format!(
+                "{} was chunked with other chunker parameters",
+                index.display()
+            )));
+        }
+        Ok(Estimator {
+            params: merged.params(),
+            saved: labels.len(),
+            added: Estimator::empty(merged.params()),
+            merged,
+            labels,
+            conflicts: 0,
+            merged_inputs: 0,
+        })
+    }
+
+    /// Save the state as an index to load back with `Estimator(index=path)`.
+    /// The chunk order isn't saved, so `segments` only covers the inputs
+    /// added after loading it.
+    ///
+    /// Several processes can update the same index: the save holds the lock
+    /// of the index, and if another process saved inputs to it since this
+    /// state was loaded or last saved, the inputs added since are merged into
+    /// the index on disk instead of overwriting it, and the state becomes the
+    /// merged one. Returns the number of inputs of other processes merged in
+    /// this way, zero without conflict.
+    fn save(&mut self, py: Python<'_>, path: PathBuf) -> PyResult<usize> {
+        py.allow_threads(|| {
+            let _lock = index::lock(&path)?;
+            let labels = match index::read_labels(&path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    return Err(PyValueError::new_err(e.to_string()))
+                }
+                read => {
+                    let (params, labels) = read?;
+                    if params != self.params {
+                        return Err(PyValueError::new_err(format!(
+                            "{} was chunked with other chunker parameters",
+                            path.display()
+                        )));
+                    }
+                    labels
+                }
+            };
+            let loaded = &self.labels[..self.saved];
+            let merged_in = if labels == loaded {
+                self.merged.save(&self.labels, &path)?;
+                0
+            } else if labels.starts_with(loaded) {
+                let (mut merged, mut labels) = ChunkStore::load(&path)?;
+                let merged_in = labels.len() - self.saved;
+                merged.append_merged(&mut self.added, labels.len());
+                labels.extend_from_slice(&self.labels[self.saved..]);
+                merged.save(&labels, &path)?;
+                self.merged = merged;
+                self.labels = labels;
+                merged_in
+            } else {
+                return Err(PyValueError::new_err(format!(
+                    "{} doesn't extend the index this state was loaded from",
+                    path.display()
+                )));
+            };
+            self.saved = self.labels.len();
+            self.added = Estimator::empty(self.params);
+            if merged_in > 0 {
+                self.conflicts += 1;
+                self.merged_inputs += merged_in;
+            }
+            Ok(merged_in)
+        })
+    }
+
+    /// Chunk a file or hash list and add it, returning its input index.
+    fn add_file(&mut self, py: Python<'_>, path: String) -> PyResult<usize> {
+        self.add(py, Input::Path(path), None)
+    }
+
+    /// Chunk files or hash lists in parallel and add them, returning their
+    /// input indices. Every file is merged as soon as it is chunked, the
+    /// result being the same as adding them one at a time in order. Nothing
+    /// is added if one of them fails.
+    fn add_files(&mut self, py: Python<'_>, paths: Vec<String>) -> PyResult<Vec<usize>> {
+        let params = self.params;
+        let batch = Mutex::new(Estimator::empty(params));
+        py.allow_threads(|| {
+            paths.par_iter().enumerate().try_for_each(|(i, path)| {
+                let store =
+                    ChunkStore::from_file_with_params(path, false, params, &Progress::default())?;
+                batch.lock().unwrap().absorb(store, i);
+                Ok::<_, std::io::Error>(())
+            })
+        })?;
+        self.merge_batch(batch.into_inner().unwrap());
+        let first = self.labels.len();
+        self.labels.extend(paths);
+        Ok((first..self.labels.len()).collect())
+    }
+
+    /// Chunk an object exposing the buffer protocol in place, or a file-like
+    /// object read to the end, and add it, returning its input index. The
+    /// label defaults to `<buffer {index}>` or `<stream {index}>`.
+    #[pyo3(signature = (data, label = None))]
+    fn add_bytes(&mut self, py: Python<'_>, data: Input, label: Option<String>) -> PyResult<usize> {
+        if matches!(data, Input::Path(_)) {
+            return Err(PyValueError::new_err(
+                "add_bytes expects a buffer or a file-like object, use add_file for paths",
+            ));
+        }
+        self.add(py, data, label)
+    }
+
+    /// The (total, unique, compressed) bytes of the inputs added so far.
+    fn stats(&self) -> (usize, usize, usize) {
+        self.merged.stats()
+    }
+
+    /// The (saves, inputs merged in) of the saves of this state which found
+    /// the index updated meanwhile by another process.
+    fn conflicts(&self) -> (usize, usize) {
+        (self.conflicts, self.merged_inputs)
+    }
+
+    /// The (chunks, bytes, compressed bytes) of the unique chunks of this
+    /// state also found in `other`, which must use the same chunker
+    /// parameters for the chunks to be comparable.
+    fn shared(
+        &self,
+        py: Python<'_>,
+        other: PyRef<'_, Estimator>,
+    ) -> PyResult<(usize, usize, usize)> {
+        if other.params != self.params {
+            return Err(PyValueError::new_err(
+                "The estimators were chunked with other chunker parameters",
+            ));
+        }
+        let other = &other.merged;
+        Ok(py.allow_threads(|| self.merged.shared(other)))
+    }
+
+    /// The index of the input first containing each chunk, in input order.
+    fn segments(&self, py: Python<'_>) -> Vec<usize> {
+        py.allow_threads(|| self.merged.segments())
+    }
+
+    /// The chunk of the first input labeled `label` containing the byte at
+    /// `offset`, as its hash and the (input index, offset, size) of every
+    /// occurrence of it, see `ChunkStore::who_has`. None past the end of the
+    /// input.
+    #[allow(clippy::type_complexity)]
+    fn who_has(
+        &self,
+        py: Python<'_>,
+        label: String,
+        offset: usize,
+    ) -> PyResult<Option<(u64, Vec<(usize, usize, usize)>)>> {
+        let Some(index) = self.labels.iter().position(|l| *l == label) else {
+            return Err(PyValueError::new_err(format!("No input labeled {label}")));
+        };
+        py.allow_threads(|| self.merged.who_has(index, offset))
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Write one row per chunk occurrence of the inputs added so far to
+    /// `path` as Parquet or JSON lines, see `serialize::write_manifest`. The
+    /// format defaults to Parquet for a `.parquet` path and to JSON lines
+    /// otherwise. Returns the number of rows.
+    #[pyo3(signature = (path, format = None))]
+    fn export_manifest(
+        &self,
+        py: Python<'_>,
+        path: PathBuf,
+        format: Option<&str>,
+    ) -> PyResult<usize> {
+        let format = match format {
+            Some(name) => {
+                ManifestFormat::new(name).map_err(|e| PyValueError::new_err(e.to_string()))?
+            }
+            None if path.extension() == Some("parquet".as_ref()) => ManifestFormat::Parquet,
+            None => ManifestFormat::JsonLines,
+        };
+        py.allow_threads(|| {
+            Ok(serialize::write_manifest(
+                &self.merged,
+                &self.labels,
+                &path,
+                format,
+            )?)
+        })
+    }
+
+    /// The labels of the inputs added so far, the paths of the files.
+    fn labels(&self) -> Vec<String> {
+        self.labels.clone()
+    }
+
+    fn __len__(&self) -> usize {
+        self.labels.len()
+    }
+}
+
+impl Estimator {
+    fn empty(params: ChunkParams) -> ChunkStore {
+        let mut store = ChunkStore::new(false);
+        store.set_params(params);
+        store
+    }
+
+    fn add(&mut self, py: Python<'_>, input: Input, label: Option<String>) -> PyResult<usize> {
+        let index = self.labels.len();
+        let params = self.params;
+        let mut batch = Estimator::empty(params);
+        py.allow_threads(|| {
+            batch.absorb(input.chunk(false, params, &Progress::default())?, 0);
+            Ok::<_, std::io::Error>(())
+        })?;
+        self.merge_batch(batch);
+        self.labels
+            .push(label.unwrap_or_else(|| input.label(index)));
+        Ok(index)
+    }
+
+    /// Merge the inputs of a store merged on their own after the ones added
+    /// so far, before their labels are pushed.
+    fn merge_batch(&mut self, mut batch: ChunkStore) {
+        let index = self.labels.len();
+        self.merged.append_merged(&mut batch, index);
+        self.added.append_merged(&mut batch, index - self.saved);
+    }
+}
+
+#[pyfunction]
+fn overlaps(py: Python<'_>, file_paths: Vec<String>) -> PyResult<Vec<(Vec<i64>, usize, usize)>> {
+    py.allow_threads(|| {
+        let stores = ChunkStore::from_files(&file_paths, false)?;
+        let merged = ChunkStore::merge_owned(stores, false);
+        Ok(merged.overlaps())
+    })
+}
+
+/// Replay the chunks of the files in order through an LRU chunk cache of each
+/// of the given sizes in bytes. Returns per cache size the per file
+/// (requests, hits, requested bytes, hit bytes).
+#[pyfunction]
+fn simulate_cache(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    cache_sizes: Vec<usize>,
+) -> PyResult<Vec<Vec<(usize, usize, usize, usize)>>> {
+    py.allow_threads(|| {
+        let stores = ChunkStore::from_files(&file_paths, false)?;
+        Ok(cache_sizes
+            .par_iter()
+            .map(|&capacity| {
+                simulate_lru(&stores, capacity)
+                    .into_iter()
+                    .map(|s| (s.requests, s.hits, s.requested_bytes, s.hit_bytes))
+                    .collect()
+            })
+            .collect())
+    })
+}
+
+/// Pack the unique chunks of the files into lz4 frames of `block_size`
+/// uncompressed bytes, returning the (chunks, blocks, raw bytes, compressed
+/// bytes, framing and index overhead bytes) of the packed storage.
+#[pyfunction]
+fn pack_chunks(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    block_size: usize,
+) -> PyResult<(usize, usize, usize, usize, usize)> {
+    if block_size == 0 {
+        return Err(PyValueError::new_err("block_size must be positive"));
+    }
+    py.allow_threads(|| {
+        let s = simulate_packing(&file_paths, block_size)?;
+        Ok((
+            s.chunks,
+            s.blocks,
+            s.raw_bytes,
+            s.compressed_bytes,
+            s.overhead_bytes,
+        ))
+    })
+}
+
+/// Cut the unique chunks of the files into sub-chunks of each average size
+/// and return per size the (sub-chunks, unique sub-chunks, unique chunk
+/// bytes, redundant sub-chunk bytes).
+#[pyfunction]
+fn sub_chunk_redundancy(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    sizes: Vec<usize>,
+) -> PyResult<Vec<(usize, usize, usize, usize)>> {
+    if let Some(size) = sizes.iter().find(|&&s| !s.is_power_of_two() || s < 64) {
+        return Err(PyValueError::new_err(format!(
+            "Sub-chunk size must be a power of two of at least 64, got {size}"
+        )));
+    }
+    if let Some(path) = file_paths.iter().find(|path| is_hash_list(path)) {
+        return Err(PyValueError::new_err(format!(
+            "{path} is a hash list, sub-chunking requires the chunk data"
+        )));
+    }
     py.allow_threads(|| {
-        let mut stores = ChunkStore::from_files(&file_paths, store_data)?;
-        let merged = ChunkStore::merge(&mut stores, store_data);
-        Ok(merged.chunks())
+        let stats = intra_chunk_redundancy(&file_paths, &sizes)?;
+        Ok(stats
+            .into_iter()
+            .map(|s| {
+                (
+                    s.sub_chunks,
+                    s.unique_sub_chunks,
+                    s.chunk_bytes,
+                    s.redundant_bytes,
+                )
+            })
+            .collect())
     })
 }
 
+/// Match the new chunks of the files against the blocks of the earlier
+/// chunks at every offset and return the (total, unique chunk bytes, bytes
+/// found at a shifted offset).
 #[pyfunction]
-#[pyo3(signature = (src_path, dest_path, batch_size = 1024 * 1024, cdc = false, compression = None))]
+#[pyo3(signature = (file_paths, window = 512))]
+fn shifted_chunks(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    window: usize,
+) -> PyResult<(usize, usize, usize)> {
+    if !(16..=MIN_LEN).contains(&window) {
+        return Err(PyValueError::new_err(format!(
+            "Window must be between 16 and {MIN_LEN} bytes, got {window}"
+        )));
+    }
+    if let Some(path) = file_paths.iter().find(|path| is_hash_list(path)) {
+        return Err(PyValueError::new_err(format!(
+            "{path} is a hash list, shift detection requires the chunk data"
+        )));
+    }
+    py.allow_threads(|| {
+        let stats = shifted_matches(&file_paths, window)?;
+        Ok((stats.total, stats.chunk_bytes, stats.shifted_bytes))
+    })
+}
+
+/// Delta-compress the new chunks of the files against a similar earlier
+/// chunk at zstd `level` and return the (total, unique chunk bytes, similar
+/// chunks, similar chunk bytes, similar chunks compressed on their own,
+/// similar chunks delta-compressed).
+#[pyfunction]
+#[pyo3(signature = (file_paths, level = 3))]
+fn delta_chunks(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    level: i32,
+) -> PyResult<(usize, usize, usize, usize, usize, usize)> {
+    if !(1..=22).contains(&level) {
+        return Err(PyValueError::new_err(format!(
+            "Level must be between 1 and 22, got {level}"
+        )));
+    }
+    if let Some(path) = file_paths.iter().find(|path| is_hash_list(path)) {
+        return Err(PyValueError::new_err(format!(
+            "{path} is a hash list, delta compression requires the chunk data"
+        )));
+    }
+    py.allow_threads(|| {
+        let s = delta_matches(&file_paths, level)?;
+        Ok((
+            s.total,
+            s.chunk_bytes,
+            s.similar_chunks,
+            s.similar_bytes,
+            s.compressed_bytes,
+            s.delta_bytes,
+        ))
+    })
+}
+
+/// Return the per chunk-size bucket (bucket, total bytes, unique bytes,
+/// unique chunks) and the per file (total bytes, unique bytes, unique bytes
+/// attributed to an earlier file) statistics.
+#[pyfunction]
+#[allow(clippy::type_complexity)]
+fn duplication(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+) -> PyResult<(
+    Vec<(usize, usize, usize, usize)>,
+    Vec<(usize, usize, usize)>,
+)> {
+    py.allow_threads(|| {
+        let mut stores = ChunkStore::from_files(&file_paths, false)?;
+        let merged = ChunkStore::merge(&mut stores, false);
+        let files = stores
+            .iter()
+            .enumerate()
+            .map(|(i, store)| {
+                let (total, unique, _) = store.stats();
+                (total, unique, store.cross_file_bytes(i as i64))
+            })
+            .collect();
+        Ok((merged.duplication(), files))
+    })
+}
+
+/// Return the (occurrences, unique chunks, unique bytes) per reference count
+/// and the (hash, size, occurrences, file indices) of the `top` most
+/// duplicated chunks.
+#[pyfunction]
+#[allow(clippy::type_complexity)]
+fn refcounts(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    top: usize,
+) -> PyResult<(
+    Vec<(usize, usize, usize)>,
+    Vec<(u64, usize, usize, Vec<i64>)>,
+)> {
+    py.allow_threads(|| {
+        let stores = ChunkStore::from_files(&file_paths, false)?;
+        let merged = ChunkStore::merge_owned(stores, false);
+        Ok(merged.refcounts(top))
+    })
+}
+
+#[pyfunction]
+fn deletion_savings(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    deleted: Vec<String>,
+) -> PyResult<(usize, usize)> {
+    let indices = deleted
+        .iter()
+        .map(|path| {
+            file_paths
+                .iter()
+                .position(|p| p == path)
+                .map(|i| i as i64)
+                .ok_or_else(|| PyValueError::new_err(format!("{path} is not in file_paths")))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    py.allow_threads(|| {
+        let stores = ChunkStore::from_files(&file_paths, false)?;
+        let merged = ChunkStore::merge_owned(stores, false);
+        Ok(merged.deletion_savings(&indices))
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    src_path,
+    dest_path,
+    batch_size = 1024 * 1024,
+    cdc = false,
+    compression = None,
+    footer_key = None,
+    column_keys = None,
+))]
+#[allow(clippy::too_many_arguments)]
 fn rewrite_to_parquet_rs(
     py: Python<'_>,
     src_path: String,
@@ -55,23 +2278,144 @@ fn rewrite_to_parquet_rs(
     batch_size: usize,
     cdc: bool,
     compression: Option<String>,
+    footer_key: Option<Vec<u8>>,
+    column_keys: Option<HashMap<String, Vec<u8>>>,
 ) -> PyResult<()> {
-    py.allow_threads(|| _rewrite_to_parquet_rs(src_path, dest_path, batch_size, cdc, compression))
+    let encryption = match (footer_key, column_keys) {
+        (Some(footer_key), column_keys) => Some(EncryptionKeys {
+            footer_key,
+            column_keys: column_keys.unwrap_or_default(),
+        }),
+        (None, Some(_)) => {
+            return Err(PyValueError::new_err(
+                "column_keys require a footer_key to be set",
+            ))
+        }
+        (None, None) => None,
+    };
+    py.allow_threads(|| {
+        _rewrite_to_parquet_rs(
+            src_path,
+            dest_path,
+            batch_size,
+            cdc,
+            compression,
+            encryption,
+        )
+    })
 }
 
+/// The unique bytes of the files cut with the Xet chunker, reporting the
+/// bytes read and the files done to the `progress` callable like `estimate`.
 #[pyfunction]
-fn estimate_xet(py: Python<'_>, file_paths: Vec<String>) -> PyResult<u64> {
-    py.allow_threads(|| {
-        xet::dedup_estimate(file_paths).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+#[pyo3(signature = (file_paths, decompress = false, progress = None))]
+fn estimate_xet(
+    py: Python<'_>,
+    file_paths: Vec<String>,
+    decompress: bool,
+    progress: Option<PyObject>,
+) -> PyResult<u64> {
+    let progress = py_progress(file_paths.len(), progress);
+    interruptible(py, &progress, || {
+        let unique = xet::dedup_estimate(file_paths, decompress, &progress).map_err(|e| {
+            // keeps the exception raised by the progress callable
+            match e.downcast::<std::io::Error>() {
+                Ok(e) => PyErr::from(e),
+                Err(e) => PyRuntimeError::new_err(e.to_string()),
+            }
+        })?;
+        progress.finish()?;
+        Ok(unique)
     })
 }
 
+/// Return the Xet chunks of every file as (raw 32-byte hash, size) pairs.
+#[pyfunction]
+#[allow(clippy::type_complexity)]
+fn xet_chunks(py: Python<'_>, file_paths: Vec<String>) -> PyResult<Vec<Vec<(Py<PyBytes>, usize)>>> {
+    let files = py.allow_threads(|| {
+        file_paths
+            .par_iter()
+            .map(|path| xet::chunk_hashes(path))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    })?;
+    Ok(files
+        .into_iter()
+        .map(|chunks| {
+            chunks
+                .into_iter()
+                .map(|(hash, size)| (PyBytes::new(py, &hash).unbind(), size))
+                .collect()
+        })
+        .collect())
+}
+
+/// Key the raw Xet chunk hashes with the HMAC key of a shard footer.
+#[pyfunction]
+fn xet_hmac(py: Python<'_>, hashes: Vec<Vec<u8>>, key: Vec<u8>) -> PyResult<Vec<Py<PyBytes>>> {
+    let key: [u8; 32] = key
+        .try_into()
+        .map_err(|_| PyValueError::new_err("The HMAC key must be 32 bytes"))?;
+    let keyed: Vec<[u8; 32]> =
+        py.allow_threads(|| hashes.iter().map(|hash| xet::hmac(hash, &key)).collect());
+    Ok(keyed
+        .iter()
+        .map(|hash| PyBytes::new(py, hash).unbind())
+        .collect())
+}
+
 /// A Python module implemented in Rust.
 #[pymodule]
 fn core(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add("CHUNK_MASK", MASK)?;
+    m.add("MIN_CHUNK_SIZE", MIN_LEN)?;
+    m.add("MAX_CHUNK_SIZE", MAX_LEN)?;
     m.add_function(wrap_pyfunction!(estimate, m)?)?;
+    m.add_class::<Estimator>()?;
     m.add_function(wrap_pyfunction!(chunks, m)?)?;
+    m.add_function(wrap_pyfunction!(chunk_sizes, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_labeled, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_with_metric, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_dictionary, m)?)?;
+    m.add_function(wrap_pyfunction!(dot_plot, m)?)?;
+    m.add_function(wrap_pyfunction!(deletion_savings, m)?)?;
+    m.add_function(wrap_pyfunction!(duplication, m)?)?;
+    m.add_function(wrap_pyfunction!(refcounts, m)?)?;
+    m.add_function(wrap_pyfunction!(overlaps, m)?)?;
+    m.add_function(wrap_pyfunction!(simulate_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(pack_chunks, m)?)?;
+    m.add_function(wrap_pyfunction!(sub_chunk_redundancy, m)?)?;
+    m.add_function(wrap_pyfunction!(shifted_chunks, m)?)?;
+    m.add_function(wrap_pyfunction!(delta_chunks, m)?)?;
+    m.add_function(wrap_pyfunction!(boundaries, m)?)?;
     m.add_function(wrap_pyfunction!(rewrite_to_parquet_rs, m)?)?;
     m.add_function(wrap_pyfunction!(estimate_xet, m)?)?;
+    m.add_function(wrap_pyfunction!(xet_chunks, m)?)?;
+    m.add_function(wrap_pyfunction!(xet_hmac, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_pages, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_bounded, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_on_disk, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_sketched, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_hash_only, m)?)?;
+    m.add_function(wrap_pyfunction!(chunk_size_sweep, m)?)?;
+    m.add_function(wrap_pyfunction!(expand_paths, m)?)?;
+    m.add_function(wrap_pyfunction!(chunker_params, m)?)?;
+    m.add_function(wrap_pyfunction!(reconstruct, m)?)?;
+    m.add_function(wrap_pyfunction!(stage_timings, m)?)?;
+    m.add_function(wrap_pyfunction!(ingest_timing, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_concatenated, m)?)?;
+    m.add_function(wrap_pyfunction!(page_layout, m)?)?;
+    m.add_function(wrap_pyfunction!(column_maps, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_incompressible, m)?)?;
+    m.add_function(wrap_pyfunction!(export_bloom, m)?)?;
+    m.add_function(wrap_pyfunction!(export_store, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_stores, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_revisions, m)?)?;
+    m.add_function(wrap_pyfunction!(retention, m)?)?;
+    m.add_function(wrap_pyfunction!(export_caibx, m)?)?;
+    m.add_function(wrap_pyfunction!(export_hash_lists, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_against_bloom, m)?)?;
     Ok(())
 }
@@ -0,0 +1,126 @@
+//! Custom per-chunk metrics computed during the chunking pass, e.g. domain
+//! specific signatures, PII detectors or token counts, instead of re-reading
+//! the corpus afterwards.
+//!
+//! A `ChunkMetric` sees the bytes of every unique chunk of the corpus exactly
+//! once, from whichever chunking thread cuts it first. `PyMetric` forwards
+//! them to a Python callable, which serializes the threads on the GIL and is
+//! the slow path.
+
+use crate::decompress;
+use crate::sniff::sniff_reader;
+use crate::store::{is_hash_list, ChunkParams, ChunkStore, Chunker, READ_BUFFER_SIZE};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub(crate) trait ChunkMetric: Sync {
+    /// Observe a unique chunk by its xxh3-64 hash and its bytes. An error
+    /// stops the chunking.
+    fn observe(&self, hash: u64, data: &[u8]) -> io::Result<()>;
+}
+
+/// Calls a Python callable with the (hash, bytes) of every unique chunk. The
+/// first exception it raises is kept and stops the chunking, no more chunks
+/// are passed after it.
+pub(crate) struct PyMetric {
+    callback: PyObject,
+    error: Mutex<Option<PyErr>>,
+}
+
+impl PyMetric {
+    pub fn new(callback: PyObject) -> Self {
+        PyMetric {
+            callback,
+            error: Mutex::new(None),
+        }
+    }
+
+    /// The exception raised by the callback, if any.
+    pub fn take_error(&self) -> Option<PyErr> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+impl ChunkMetric for PyMetric {
+    fn observe(&self, hash: u64, data: &[u8]) -> io::Result<()> {
+        let mut error = self.error.lock().unwrap();
+        if error.is_none() {
+            Python::with_gil(|py| {
+                let data = PyBytes::new(py, data);
+                if let Err(e) = self.callback.call1(py, (hash, data)) {
+                    *error = Some(e);
+                }
+            });
+        }
+        match *error {
+            None => Ok(()),
+            // the exception itself is returned by take_error
+            Some(_) => Err(io::Error::other("the metric callback raised")),
+        }
+    }
+}
+
+/// Add a chunk to the store of its file, passing it to `metric` if no file
+/// contained it before. Only the chunks new to the file can be new to the
+/// corpus, so the others don't take the lock of the hashes `seen`.
+fn visit(
+    store: &mut ChunkStore,
+    seen: &Mutex<HashSet<u64>>,
+    metric: &dyn ChunkMetric,
+    chunk: &[u8],
+) -> io::Result<()> {
+    let (hash, new) = store.insert(chunk);
+    if new && !chunk.is_empty() && seen.lock().unwrap().insert(hash) {
+        metric.observe(hash, chunk)?;
+    }
+    Ok(())
+}
+
+/// Chunk the files in parallel like `ChunkStore::from_files_with`, passing
+/// every chunk not seen in any file before to `metric`. Hash lists have no
+/// chunk bytes to observe and are only counted. An error of `metric` stops
+/// the worker which got it at the end of the block in hand, and the others
+/// once it is returned.
+pub(crate) fn chunk_files<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    params: ChunkParams,
+    metric: &dyn ChunkMetric,
+) -> io::Result<Vec<ChunkStore>> {
+    let seen = Mutex::new(HashSet::new());
+    paths
+        .par_iter()
+        .map(|path| {
+            if is_hash_list(path) {
+                return ChunkStore::from_file(path, false);
+            }
+            let mut store = ChunkStore::new(false);
+            let (sniffed, mut file) = sniff_reader(decompress::open(path, params.decompress)?)?;
+            store.set_sniffed(sniffed);
+            store.set_params(params);
+            let mut observed = Ok(());
+            let mut chunker = Chunker::new(params);
+            let mut buffer = vec![0; READ_BUFFER_SIZE];
+            loop {
+                let bytes_read = file.read(&mut buffer)?;
+                if bytes_read == 0 {
+                    break;
+                }
+                chunker.feed(&buffer[..bytes_read], |chunk| {
+                    if observed.is_ok() {
+                        observed = visit(&mut store, &seen, metric, chunk);
+                    }
+                });
+                observed?;
+                observed = Ok(());
+            }
+            chunker.finish(|chunk| observed = visit(&mut store, &seen, metric, chunk));
+            observed?;
+            Ok(store)
+        })
+        .collect()
+}
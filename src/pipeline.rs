@@ -0,0 +1,345 @@
+//! The chunking of the estimate path split into explicit stages connected by
+//! bounded channels: reader → chunker → hasher → compressor → aggregator.
+//!
+//! A full channel blocks the upstream stage, so the memory in flight is
+//! bounded by the queue depth and the slowest stage sets the pace of the
+//! whole pipeline. The reader, chunker, hasher and compressor stages split
+//! a budget of workers, the size of the rayon pool by default, so IO-bound
+//! corpora keep many reads in flight while CPU-bound ones keep all cores
+//! chunking, hashing and compressing without running more threads than
+//! cores. The per-stage timings tell which stage
+//! is the bottleneck: it is the one busy for most of the wall time while the
+//! stages upstream of it are blocked on sending.
+
+use crate::decompress;
+use crate::progress::Progress;
+use crate::sniff::sniff_reader;
+use crate::store::{is_hash_list, ChunkParams, ChunkStore, Chunker, READ_BUFFER_SIZE};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Default capacity of the channels between the stages, in messages.
+pub(crate) const QUEUE_DEPTH: usize = 64;
+
+/// Work done by a stage summed over its workers. Busy is the time spent
+/// processing items and blocked the time spent waiting for the next stage to
+/// accept them, the rest of the wall time the workers waited for input.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StageTiming {
+    pub name: &'static str,
+    pub workers: usize,
+    pub items: usize,
+    pub busy: Duration,
+    pub blocked: Duration,
+}
+
+impl StageTiming {
+    fn new(name: &'static str, workers: usize) -> Self {
+        StageTiming {
+            name,
+            workers,
+            items: 0,
+            busy: Duration::ZERO,
+            blocked: Duration::ZERO,
+        }
+    }
+}
+
+/// Per-worker accumulator, added to the stage timing when the worker exits.
+#[derive(Default)]
+struct Timer {
+    items: usize,
+    busy: Duration,
+    blocked: Duration,
+}
+
+impl Timer {
+    fn run<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let result = f();
+        self.busy += start.elapsed();
+        self.items += 1;
+        result
+    }
+
+    fn send<T>(&mut self, tx: &SyncSender<T>, msg: T) {
+        let start = Instant::now();
+        // the receiver only hangs up if its stage panicked, which the scope
+        // propagates anyway
+        let _ = tx.send(msg);
+        self.blocked += start.elapsed();
+    }
+
+    fn record(self, stage: &Mutex<StageTiming>) {
+        let mut stage = stage.lock().unwrap();
+        stage.items += self.items;
+        stage.busy += self.busy;
+        stage.blocked += self.blocked;
+    }
+}
+
+enum Block {
+    Start { file: usize, compress: bool },
+    Data { file: usize, data: Vec<u8> },
+    End { file: usize },
+    Loaded { file: usize, store: ChunkStore },
+}
+
+struct PendingChunk {
+    file: usize,
+    seq: usize,
+    data: Vec<u8>,
+    size: usize,
+    compress: bool,
+    hash: u64,
+    compressed: usize,
+}
+
+enum Piece {
+    Chunk(PendingChunk),
+    Loaded { file: usize, store: ChunkStore },
+}
+
+fn recv<T>(rx: &Mutex<Receiver<T>>) -> Option<T> {
+    rx.lock().unwrap().recv().ok()
+}
+
+/// Send the blocks of a file to its chunker, returning its `sniff` verdict.
+fn read_file<P: AsRef<Path>>(
+    path: P,
+    file: usize,
+    decompress: bool,
+    tx: &SyncSender<Block>,
+    timer: &mut Timer,
+    progress: &Progress,
+) -> io::Result<Option<String>> {
+    // hash lists carry no data to chunk, so they skip straight to the end
+    if is_hash_list(&path) {
+        let reader = BufReader::new(File::open(path)?);
+        let store = timer.run(|| ChunkStore::from_hash_list(reader))?;
+        let total = store.total();
+        timer.send(tx, Block::Loaded { file, store });
+        progress.file_done(total)?;
+        return Ok(None);
+    }
+    let (sniffed, mut f) = sniff_reader(decompress::open(path, decompress)?)?;
+    let compress = sniffed.is_none();
+    timer.send(tx, Block::Start { file, compress });
+    loop {
+        let mut data = vec![0; READ_BUFFER_SIZE];
+        let bytes_read = timer.run(|| f.read(&mut data))?;
+        if bytes_read == 0 {
+            break;
+        }
+        data.truncate(bytes_read);
+        timer.send(tx, Block::Data { file, data });
+        progress.add_bytes(bytes_read)?;
+    }
+    timer.send(tx, Block::End { file });
+    progress.file_done(0)?;
+    Ok(sniffed)
+}
+
+fn send_chunk(
+    tx: &SyncSender<Piece>,
+    timer: &mut Timer,
+    file: usize,
+    seq: &mut usize,
+    data: Vec<u8>,
+    compress: bool,
+) {
+    let chunk = PendingChunk {
+        file,
+        seq: *seq,
+        size: data.len(),
+        data,
+        compress,
+        hash: 0,
+        compressed: 0,
+    };
+    *seq += 1;
+    timer.send(tx, Piece::Chunk(chunk));
+}
+
+/// Number of stages splitting the workers evenly, at least one worker each.
+const PARALLEL_STAGES: usize = 4;
+
+/// Chunk the files through the pipeline with `workers` workers split between
+/// the reader, chunker, hasher and compressor stages, plus the aggregator,
+/// returning the same stores as `ChunkStore::from_files` without the chunk
+/// data, and the timings of the reader, chunker, hasher, compressor and
+/// aggregator stages. The files and bytes read are reported to `progress`,
+/// whose errors stop the readers like read errors.
+pub(crate) fn chunk_files<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    workers: usize,
+    queue_depth: usize,
+    params: ChunkParams,
+    progress: &Progress,
+) -> io::Result<(Vec<ChunkStore>, Vec<StageTiming>)> {
+    let workers = (workers / PARALLEL_STAGES).max(1);
+    let stages = [
+        Mutex::new(StageTiming::new("reader", workers)),
+        Mutex::new(StageTiming::new("chunker", workers)),
+        Mutex::new(StageTiming::new("hasher", workers)),
+        Mutex::new(StageTiming::new("compressor", workers)),
+        Mutex::new(StageTiming::new("aggregator", 1)),
+    ];
+    let [reading, chunking, hashing, compressing, aggregating] = &stages;
+    let next_file = AtomicUsize::new(0);
+    let error: Mutex<Option<io::Error>> = Mutex::new(None);
+    let sniffed: Mutex<Vec<Option<String>>> = Mutex::new(vec![None; paths.len()]);
+
+    let (block_txs, block_rxs): (Vec<SyncSender<Block>>, Vec<Receiver<Block>>) =
+        (0..workers).map(|_| sync_channel(queue_depth)).unzip();
+    let (hash_tx, hash_rx) = sync_channel(queue_depth);
+    let (compress_tx, compress_rx) = sync_channel(queue_depth);
+    let (aggregate_tx, aggregate_rx) = sync_channel(queue_depth);
+    let hash_rx = Mutex::new(hash_rx);
+    let compress_rx = Mutex::new(compress_rx);
+
+    let mut recipes: Vec<Vec<(usize, u64, usize, usize)>> = vec![Vec::new(); paths.len()];
+    let mut loaded: Vec<Option<ChunkStore>> = paths.iter().map(|_| None).collect();
+
+    thread::scope(|s| {
+        // readers take the next file and route its blocks to a fixed chunker,
+        // so the blocks of a file reach its chunker in order
+        for _ in 0..workers {
+            let txs = block_txs.clone();
+            let (next_file, error, sniffed) = (&next_file, &error, &sniffed);
+            s.spawn(move || {
+                let mut timer = Timer::default();
+                loop {
+                    let file = next_file.fetch_add(1, Ordering::Relaxed);
+                    if file >= paths.len() || error.lock().unwrap().is_some() {
+                        break;
+                    }
+                    let tx = &txs[file % txs.len()];
+                    let path = &paths[file];
+                    match read_file(path, file, params.decompress, tx, &mut timer, progress) {
+                        Ok(reason) => sniffed.lock().unwrap()[file] = reason,
+                        Err(e) => {
+                            error.lock().unwrap().get_or_insert(e);
+                            break;
+                        }
+                    }
+                }
+                timer.record(reading);
+            });
+        }
+        drop(block_txs);
+
+        for rx in block_rxs {
+            let tx = hash_tx.clone();
+            s.spawn(move || {
+                let mut timer = Timer::default();
+                let mut states: HashMap<usize, (Chunker, usize, bool)> = HashMap::new();
+                for block in rx {
+                    match block {
+                        Block::Start { file, compress } => {
+                            states.insert(file, (Chunker::new(params), 0, compress));
+                        }
+                        Block::Data { file, data } => {
+                            let (chunker, seq, compress) = states.get_mut(&file).unwrap();
+                            let mut chunks = Vec::new();
+                            timer.run(|| chunker.feed(&data, |chunk| chunks.push(chunk.to_vec())));
+                            for chunk in chunks {
+                                send_chunk(&tx, &mut timer, file, seq, chunk, *compress);
+                            }
+                        }
+                        Block::End { file } => {
+                            let (chunker, mut seq, compress) = states.remove(&file).unwrap();
+                            let mut last = Vec::new();
+                            chunker.finish(|chunk| last = chunk.to_vec());
+                            send_chunk(&tx, &mut timer, file, &mut seq, last, compress);
+                        }
+                        Block::Loaded { file, store } => {
+                            timer.send(&tx, Piece::Loaded { file, store });
+                        }
+                    }
+                }
+                timer.record(chunking);
+            });
+        }
+        drop(hash_tx);
+
+        for _ in 0..workers {
+            let (rx, tx) = (&hash_rx, compress_tx.clone());
+            s.spawn(move || {
+                let mut timer = Timer::default();
+                while let Some(mut piece) = recv(rx) {
+                    if let Piece::Chunk(chunk) = &mut piece {
+                        chunk.hash = timer.run(|| xxh3_64(&chunk.data));
+                    }
+                    timer.send(&tx, piece);
+                }
+                timer.record(hashing);
+            });
+        }
+        drop(compress_tx);
+
+        for _ in 0..workers {
+            let (rx, tx) = (&compress_rx, aggregate_tx.clone());
+            s.spawn(move || {
+                let mut timer = Timer::default();
+                while let Some(mut piece) = recv(rx) {
+                    if let Piece::Chunk(chunk) = &mut piece {
+                        chunk.compressed = timer.run(|| {
+                            if chunk.compress {
+                                params.codec.compressed_len(&chunk.data)
+                            } else {
+                                chunk.size
+                            }
+                        });
+                        // only the sizes are aggregated, free the data early
+                        chunk.data = Vec::new();
+                    }
+                    timer.send(&tx, piece);
+                }
+                timer.record(compressing);
+            });
+        }
+        drop(aggregate_tx);
+
+        // the chunks arrive out of order from the worker pools, the sequence
+        // numbers restore the order within each file
+        let mut timer = Timer::default();
+        for piece in aggregate_rx {
+            timer.run(|| match piece {
+                Piece::Chunk(c) => recipes[c.file].push((c.seq, c.hash, c.size, c.compressed)),
+                Piece::Loaded { file, store } => loaded[file] = Some(store),
+            });
+        }
+        timer.record(aggregating);
+    });
+
+    if let Some(e) = error.into_inner().unwrap() {
+        return Err(e);
+    }
+    let stores = recipes
+        .into_iter()
+        .zip(loaded)
+        .zip(sniffed.into_inner().unwrap())
+        .map(|((mut recipe, loaded), sniffed)| {
+            loaded.unwrap_or_else(|| {
+                recipe.sort_unstable_by_key(|&(seq, ..)| seq);
+                let mut store = ChunkStore::new(false);
+                store.set_sniffed(sniffed);
+                for (_, hash, size, compressed) in recipe {
+                    store.add_cached(hash, size, compressed);
+                }
+                store
+            })
+        })
+        .collect();
+    let timings = stages.map(|stage| stage.into_inner().unwrap()).to_vec();
+    Ok((stores, timings))
+}
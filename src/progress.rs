@@ -0,0 +1,115 @@
+//! Progress of the long-running scans reported to a callback, e.g. a Python
+//! callable driving tqdm or a logger where terminal progress bars don't show,
+//! like in notebooks or with a captured stdout.
+//!
+//! The workers add the bytes and files they process to shared counters, and
+//! whichever worker finds the last report older than `INTERVAL` calls the
+//! callback with the (bytes, files done, total files) so far. The calls are
+//! serialized and their rate bounded however small the reads are, and a
+//! last call reports the final counts once the scan is done. An error of the
+//! callback stops the scan and is returned by it.
+//!
+//! The updates are also where the workers notice a cancellation, e.g. on
+//! Ctrl-C, and stop with an `Interrupted` error, so a scan is cancelled
+//! within a block read of each worker. The workers with nothing to report,
+//! e.g. rendering images, `check` for it instead.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two calls of the callback.
+pub(crate) const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Called with the bytes processed, the files done and the total files.
+pub(crate) type Callback = Box<dyn Fn(u64, usize, usize) -> io::Result<()> + Send + Sync>;
+
+pub(crate) struct Progress {
+    callback: Option<Callback>,
+    total_files: usize,
+    bytes: AtomicU64,
+    files: AtomicUsize,
+    cancelled: AtomicBool,
+    /// The time of the last call, locked while the callback runs.
+    last: Mutex<Instant>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress::new(0, None)
+    }
+}
+
+impl Progress {
+    pub fn new(total_files: usize, callback: Option<Callback>) -> Self {
+        Progress {
+            callback,
+            total_files,
+            bytes: AtomicU64::new(0),
+            files: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+            last: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Make the workers stop at their next update.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Fail with `Interrupted` once cancelled, for the workers with no bytes
+    /// to report, e.g. while rendering images.
+    pub fn check(&self) -> io::Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+        }
+        Ok(())
+    }
+
+    pub fn add_bytes(&self, bytes: usize) -> io::Result<()> {
+        self.check()?;
+        if self.callback.is_none() {
+            return Ok(());
+        }
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.report(false)
+    }
+
+    /// Count a file as done, along with its bytes not added yet, e.g. all of
+    /// them for the files chunked at once.
+    pub fn file_done(&self, bytes: usize) -> io::Result<()> {
+        self.check()?;
+        if self.callback.is_none() {
+            return Ok(());
+        }
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.files.fetch_add(1, Ordering::Relaxed);
+        self.report(false)
+    }
+
+    /// Report the final counts regardless of the time of the last call.
+    pub fn finish(&self) -> io::Result<()> {
+        self.report(true)
+    }
+
+    fn report(&self, force: bool) -> io::Result<()> {
+        let Some(callback) = &self.callback else {
+            return Ok(());
+        };
+        let mut last = if force {
+            self.last.lock().unwrap()
+        } else {
+            // another worker is already reporting
+            match self.last.try_lock() {
+                Ok(last) if last.elapsed() >= INTERVAL => last,
+                _ => return Ok(()),
+            }
+        };
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let files = self.files.load(Ordering::Relaxed);
+        callback(bytes, files, self.total_files)?;
+        *last = Instant::now();
+        Ok(())
+    }
+}
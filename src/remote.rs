@@ -0,0 +1,216 @@
+//! Inputs in cloud object stores, `s3://`, `gs://` and `az://` URLs among
+//! others, read with ranged GET requests as the chunker consumes them
+//! instead of being downloaded first. The credentials, region and endpoint
+//! are picked up from the environment like the cloud SDKs do, e.g.
+//! `AWS_ACCESS_KEY_ID`, `GOOGLE_APPLICATION_CREDENTIALS` or
+//! `AZURE_STORAGE_ACCOUNT_NAME`.
+//!
+//! The requests run on a shared tokio runtime and the readers block on them,
+//! so the rayon workers chunk remote inputs like local ones. While a block is
+//! chunked the next one is already requested, and the blocks double in size
+//! up to `MAX_BLOCK_LEN`, so sniffing the first bytes of an object stays
+//! cheap while long objects are streamed with few requests.
+//!
+//! `file://` URLs are read from the local filesystem through the same
+//! readers, which exercises them without any cloud account.
+
+use bytes::Bytes;
+use object_store::aws::AmazonS3Builder;
+use object_store::azure::MicrosoftAzureBuilder;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, ObjectStoreScheme};
+use parquet::errors::ParquetError;
+use parquet::file::reader::{ChunkReader, Length};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+use url::Url;
+
+const SCHEMES: [&str; 8] = [
+    "s3://", "s3a://", "gs://", "az://", "azure://", "abfs://", "abfss://", "file://",
+];
+const FIRST_BLOCK_LEN: u64 = 64 * 1024;
+const MAX_BLOCK_LEN: u64 = 8 * 1024 * 1024;
+
+/// Whether a path is the URL of an object store object.
+pub(crate) fn is_remote<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref().to_str().unwrap_or_default();
+    SCHEMES.iter().any(|scheme| path.starts_with(scheme))
+}
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the object store runtime")
+    })
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// The store of the bucket or container of a URL and the location of the
+/// object in it. The stores are kept per bucket, so the credentials are only
+/// resolved once.
+fn object_store(url: &str) -> io::Result<(Arc<dyn ObjectStore>, ObjectPath)> {
+    static STORES: OnceLock<Mutex<HashMap<String, Arc<dyn ObjectStore>>>> = OnceLock::new();
+    let parsed = Url::parse(url).map_err(|e| invalid(format!("{url}: {e}")))?;
+    let (scheme, location) =
+        ObjectStoreScheme::parse(&parsed).map_err(|e| invalid(format!("{url}: {e}")))?;
+    let bucket = format!(
+        "{}://{}",
+        parsed.scheme(),
+        parsed.host_str().unwrap_or_default()
+    );
+    let mut stores = STORES.get_or_init(Default::default).lock().unwrap();
+    if let Some(store) = stores.get(&bucket) {
+        return Ok((store.clone(), location));
+    }
+    let store: Arc<dyn ObjectStore> = match scheme {
+        ObjectStoreScheme::AmazonS3 => Arc::new(AmazonS3Builder::from_env().with_url(url).build()?),
+        ObjectStoreScheme::GoogleCloudStorage => Arc::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_url(url)
+                .build()?,
+        ),
+        ObjectStoreScheme::MicrosoftAzure => {
+            Arc::new(MicrosoftAzureBuilder::from_env().with_url(url).build()?)
+        }
+        ObjectStoreScheme::Local => Arc::new(LocalFileSystem::new()),
+        _ => {
+            return Err(invalid(format!(
+                "{url} is not an S3, GCS, Azure or file URL"
+            )))
+        }
+    };
+    stores.insert(bucket, store.clone());
+    Ok((store, location))
+}
+
+/// An object of an object store, read in ranges.
+pub(crate) struct RemoteFile {
+    store: Arc<dyn ObjectStore>,
+    location: ObjectPath,
+    len: u64,
+}
+
+impl RemoteFile {
+    pub fn open(url: &str) -> io::Result<Self> {
+        let (store, location) = object_store(url)?;
+        let meta = runtime().block_on(store.head(&location))?;
+        Ok(RemoteFile {
+            store,
+            location,
+            len: meta.size,
+        })
+    }
+
+    /// The last modification time of an object.
+    pub fn last_modified(url: &str) -> io::Result<SystemTime> {
+        let (store, location) = object_store(url)?;
+        let meta = runtime().block_on(store.head(&location))?;
+        Ok(meta.last_modified.into())
+    }
+
+    fn get_range(&self, range: Range<u64>) -> io::Result<Bytes> {
+        Ok(runtime().block_on(self.store.get_range(&self.location, range))?)
+    }
+
+    /// A reader of the object from `offset` to its end.
+    pub fn reader(&self, offset: u64) -> RemoteReader {
+        RemoteReader {
+            store: self.store.clone(),
+            location: self.location.clone(),
+            len: self.len,
+            requested: offset.min(self.len),
+            block_len: FIRST_BLOCK_LEN,
+            block: Bytes::new(),
+            pending: None,
+        }
+    }
+}
+
+impl Length for RemoteFile {
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl ChunkReader for RemoteFile {
+    type T = RemoteReader;
+
+    fn get_read(&self, start: u64) -> parquet::errors::Result<RemoteReader> {
+        Ok(self.reader(start))
+    }
+
+    fn get_bytes(&self, start: u64, length: usize) -> parquet::errors::Result<Bytes> {
+        self.get_range(start..start + length as u64)
+            .map_err(|e| ParquetError::External(Box::new(e)))
+    }
+}
+
+/// Sequential reader of an object, requesting the next block while the
+/// current one is consumed.
+pub(crate) struct RemoteReader {
+    store: Arc<dyn ObjectStore>,
+    location: ObjectPath,
+    len: u64,
+    /// The end of the ranges requested so far.
+    requested: u64,
+    block_len: u64,
+    /// The unread bytes of the current block.
+    block: Bytes,
+    pending: Option<JoinHandle<object_store::Result<Bytes>>>,
+}
+
+impl RemoteReader {
+    fn request(&mut self) {
+        if self.requested == self.len {
+            return;
+        }
+        let end = (self.requested + self.block_len).min(self.len);
+        let (store, location) = (self.store.clone(), self.location.clone());
+        let range = self.requested..end;
+        self.pending =
+            Some(runtime().spawn(async move { store.get_range(&location, range).await }));
+        self.requested = end;
+        self.block_len = (self.block_len * 2).min(MAX_BLOCK_LEN);
+    }
+}
+
+impl Read for RemoteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.block.is_empty() {
+            if self.pending.is_none() {
+                self.request();
+            }
+            let Some(pending) = self.pending.take() else {
+                return Ok(0);
+            };
+            self.block = runtime().block_on(pending).map_err(io::Error::other)??;
+            self.request();
+        }
+        let n = buf.len().min(self.block.len());
+        buf[..n].copy_from_slice(&self.block[..n]);
+        self.block = self.block.slice(n..);
+        Ok(n)
+    }
+}
+
+impl Drop for RemoteReader {
+    fn drop(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            pending.abort();
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Chunk age analytics over persisted chunk store snapshots.
+//!
+//! Exporting a snapshot on top of the previous one carries the first seen
+//! times of the chunks over and keeps the chunks which dropped out of every
+//! file as retained, so the latest snapshot of an archive answers questions
+//! like "how many bytes haven't been referenced in 90 days" when planning a
+//! retention policy for the deduplicated storage.
+
+use crate::serialize::{RetainedChunk, StoreDocument};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+pub(crate) fn unix_now() -> u64 {
+    unix_time(SystemTime::now())
+}
+
+/// Stamp the chunks of `doc` as seen at `now`, carrying over the first seen
+/// times of the `previous` snapshot and retaining its chunks which are not
+/// referenced anymore. The chunks of a previous snapshot written without
+/// stamps count as seen when its file was last modified.
+pub(crate) fn stamp<P: AsRef<Path>>(
+    doc: &mut StoreDocument,
+    previous: Option<P>,
+    now: u64,
+) -> io::Result<()> {
+    let previous = match previous {
+        Some(path) => {
+            let modified = unix_time(std::fs::metadata(&path)?.modified()?);
+            Some((StoreDocument::read(&path)?, modified))
+        }
+        None => None,
+    };
+    // first seen, last seen and size of every chunk known to the previous
+    // snapshot, referenced or not
+    let mut known: HashMap<&str, (u64, u64, usize)> = HashMap::new();
+    if let Some((previous, modified)) = &previous {
+        for chunk in &previous.retained {
+            let times = (chunk.first_seen, chunk.last_seen, chunk.size);
+            known.insert(&chunk.hash, times);
+        }
+        for chunk in &previous.chunks {
+            let first_seen = chunk.first_seen.unwrap_or(*modified);
+            let last_seen = chunk.last_seen.unwrap_or(*modified);
+            known.insert(&chunk.hash, (first_seen, last_seen, chunk.size));
+        }
+    }
+    for chunk in &mut doc.chunks {
+        let first_seen = known.remove(chunk.hash.as_str()).map_or(now, |(t, _, _)| t);
+        chunk.first_seen = Some(first_seen.min(now));
+        chunk.last_seen = Some(now);
+    }
+    doc.retained = known
+        .into_iter()
+        .map(|(hash, (first_seen, last_seen, size))| RetainedChunk {
+            hash: hash.to_string(),
+            size,
+            first_seen,
+            last_seen,
+        })
+        .collect();
+    doc.retained.sort_by(|a, b| a.hash.cmp(&b.hash));
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Retention {
+    /// Chunks referenced by the snapshot.
+    pub live_chunks: usize,
+    pub live_bytes: usize,
+    /// Referenced chunks first seen before the cutoff.
+    pub aged_chunks: usize,
+    pub aged_bytes: usize,
+    /// Chunks of earlier snapshots no longer referenced.
+    pub retained_chunks: usize,
+    pub retained_bytes: usize,
+    /// Retained chunks last referenced before the cutoff, the candidates for
+    /// deletion under a retention policy of that age.
+    pub stale_chunks: usize,
+    pub stale_bytes: usize,
+}
+
+/// Summarize the chunk ages of a snapshot relative to the `cutoff` unix
+/// time. Referenced chunks without stamps are never counted as aged.
+pub(crate) fn retention(doc: &StoreDocument, cutoff: u64) -> Retention {
+    let mut r = Retention::default();
+    for chunk in &doc.chunks {
+        r.live_chunks += 1;
+        r.live_bytes += chunk.size;
+        if chunk.first_seen.is_some_and(|t| t < cutoff) {
+            r.aged_chunks += 1;
+            r.aged_bytes += chunk.size;
+        }
+    }
+    for chunk in &doc.retained {
+        r.retained_chunks += 1;
+        r.retained_bytes += chunk.size;
+        if chunk.last_seen < cutoff {
+            r.stale_chunks += 1;
+            r.stale_bytes += chunk.size;
+        }
+    }
+    r
+}
@@ -0,0 +1,98 @@
+//! Bounded-memory counting of the unique chunks for corpora whose chunk
+//! index doesn't fit in memory.
+
+use crate::progress::Progress;
+use crate::store::{self, ChunkParams};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Unique chunk counter tracking at most `max_chunks` chunks. It counts
+/// exactly until the limit is exceeded, then keeps only the chunks whose hash
+/// has its `shift` lowest bits unset, incrementing the shift whenever the
+/// limit is exceeded again, and scales the sampled unique bytes back up.
+/// Sampling by hash keeps or drops all occurrences of a chunk together, so
+/// the duplicates of the sampled chunks are still recognized.
+pub(crate) struct SampledCounter {
+    max_chunks: usize,
+    shift: u32,
+    total: usize,
+    chunks: HashMap<u64, (usize, usize)>,
+}
+
+impl SampledCounter {
+    pub fn new(max_chunks: usize) -> Self {
+        SampledCounter {
+            max_chunks: max_chunks.max(1),
+            shift: 0,
+            total: 0,
+            chunks: HashMap::new(),
+        }
+    }
+
+    fn sampled(&self, hash: u64) -> bool {
+        hash.trailing_zeros() >= self.shift
+    }
+
+    pub fn add(&mut self, hash: u64, size: usize, compressed: usize) {
+        self.total += size;
+        if !self.sampled(hash) {
+            return;
+        }
+        self.chunks.entry(hash).or_insert((size, compressed));
+        while self.chunks.len() > self.max_chunks && self.shift < u64::BITS {
+            self.shift += 1;
+            let shift = self.shift;
+            self.chunks.retain(|hash, _| hash.trailing_zeros() >= shift);
+        }
+    }
+
+    /// The number of low hash bits a chunk needs unset to be tracked, zero
+    /// while the counts are exact.
+    pub fn shift(&self) -> u32 {
+        self.shift
+    }
+
+    /// The (total, unique, compressed) bytes like `ChunkStore::stats`, the
+    /// unique and compressed bytes are estimates once sampling kicked in.
+    pub fn stats(&self) -> (usize, usize, usize) {
+        let scale = 2f64.powi(self.shift as i32);
+        let (unique, compressed) = self
+            .chunks
+            .values()
+            .fold((0, 0), |(u, c), &(size, compressed)| {
+                (u + size, c + compressed)
+            });
+        let estimate = |bytes: usize| ((bytes as f64 * scale) as usize).min(self.total);
+        (self.total, estimate(unique), estimate(compressed))
+    }
+}
+
+/// Chunk the files in parallel folding the chunks of every block read into a
+/// shared counter, so only a block per worker and the bounded counter are
+/// held in memory however large the files. The files are chunked with
+/// `params`. Every file done is reported to `progress`. Returns the counter and the `sniff` verdict of every file.
+pub(crate) fn count_files<P: AsRef<Path> + Send + Sync>(
+    paths: &[P],
+    max_chunks: usize,
+    params: ChunkParams,
+    progress: &Progress,
+) -> io::Result<(SampledCounter, Vec<Option<String>>)> {
+    let counter = Mutex::new(SampledCounter::new(max_chunks));
+    let sniffed = paths
+        .par_iter()
+        .map(|path| {
+            let (_, sniffed) = store::stream_recipe(path, params, progress, |chunks| {
+                let mut counter = counter.lock().unwrap();
+                for &(hash, size, compressed) in chunks {
+                    counter.add(hash, size, compressed);
+                }
+            })?;
+            progress.file_done(0)?;
+            Ok(sniffed)
+        })
+        .collect::<io::Result<_>>()?;
+    Ok((counter.into_inner().unwrap(), sniffed))
+}
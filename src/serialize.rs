@@ -0,0 +1,322 @@
+//! Versioned, language-agnostic serialization of a merged chunk store.
+//!
+//! The document is written either as JSON or as CBOR with the same layout:
+//!
+//! ```text
+//! {
+//!   "format": "de-chunk-store",
+//!   "version": 1,
+//!   "total_bytes": <sum of the input file sizes>,
+//!   "tags": {<key>: <value>, ...},
+//!   "files": [
+//!     {"path": <input path>, "size": <bytes>, "order": [<hash>, ...]},
+//!     ...
+//!   ],
+//!   "chunks": [
+//!     {
+//!       "hash": <hash>,
+//!       "size": <bytes>,
+//!       "compressed": <lz4 compressed bytes>,
+//!       "seen_in": [<file index>, ...],
+//!       "first_seen_in": <file index>,
+//!       "occurrences": <number of times the chunk appears in all files>,
+//!       "first_seen": <unix time>,
+//!       "last_seen": <unix time>
+//!     },
+//!     ...
+//!   ],
+//!   "retained": [
+//!     {"hash": <hash>, "size": <bytes>, "first_seen": <unix time>, "last_seen": <unix time>},
+//!     ...
+//!   ]
+//! }
+//! ```
+//!
+//! Hashes are the xxh3-64 digests of the chunk contents formatted as 16
+//! lowercase hex digits, since 64-bit integers are not representable in every
+//! consumer language. Chunks are sorted by hash and file indices refer to the
+//! position in `files`, so the same inputs always produce the same document.
+//! Readers must reject documents with an unknown major `version`. The `tags`
+//! are free-form metadata of the run, e.g. an experiment id or the revision
+//! of the dataset, and are omitted when empty.
+//!
+//! Snapshots exported on top of a previous one are stamped by the
+//! `retention` module: every chunk carries the time of the first and the
+//! last snapshot referencing it, and chunks no longer referenced by any file
+//! are kept in `retained` with the time they were last seen. Unstamped
+//! documents omit these fields.
+//!
+//! The chunk table of a merged store can also be encoded as an Arrow IPC
+//! stream with [`chunks_to_arrow_ipc`], which keeps the hashes as `uint64`
+//! and is meant for in-process consumption by pyarrow rather than archival.
+//!
+//! [`write_manifest`] writes one row per chunk occurrence instead, for
+//! analysis in DuckDB or pandas, as Parquet with the hashes as `uint64` or
+//! as JSON lines with the hashes formatted like the document above:
+//!
+//! ```text
+//! {"hash": <hash>, "file": <input label>, "file_index": <input index>,
+//!  "offset": <byte offset in the input>, "size": <bytes>,
+//!  "compressed": <compressed bytes>, "first_seen_in": <input index>}
+//! ```
+
+use crate::store::{Chunk, ChunkStore};
+use arrow_array::builder::{Int64Builder, ListBuilder};
+use arrow_array::{ArrayRef, Int64Array, LargeBinaryArray, RecordBatch, StringArray, UInt64Array};
+use arrow_ipc::writer::StreamWriter;
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+pub(crate) const FORMAT_NAME: &str = "de-chunk-store";
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct FileEntry {
+    pub path: String,
+    pub size: usize,
+    pub order: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ChunkEntry {
+    pub hash: String,
+    pub size: usize,
+    pub compressed: usize,
+    pub seen_in: Vec<i64>,
+    pub first_seen_in: i64,
+    pub occurrences: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<u64>,
+}
+
+/// A chunk of an earlier snapshot no longer referenced by any file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RetainedChunk {
+    pub hash: String,
+    pub size: usize,
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct StoreDocument {
+    pub format: String,
+    pub version: u32,
+    pub total_bytes: usize,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    pub files: Vec<FileEntry>,
+    pub chunks: Vec<ChunkEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retained: Vec<RetainedChunk>,
+}
+
+#[inline(always)]
+fn format_hash(hash: u64) -> String {
+    format!("{hash:016x}")
+}
+
+impl StoreDocument {
+    /// Build the document from the per-file stores and their merged store.
+    pub fn new<P: AsRef<Path>>(paths: &[P], stores: &[ChunkStore], merged: &ChunkStore) -> Self {
+        let files = paths
+            .iter()
+            .zip(stores)
+            .map(|(path, store)| FileEntry {
+                path: path.as_ref().to_string_lossy().into_owned(),
+                size: store.total(),
+                order: store.order().iter().map(|h| format_hash(*h)).collect(),
+            })
+            .collect();
+
+        let mut chunks: Vec<ChunkEntry> = merged
+            .iter_chunks()
+            .map(|(hash, chunk)| ChunkEntry {
+                hash: format_hash(*hash),
+                size: chunk.size,
+                compressed: chunk.compressed,
+                seen_in: chunk.seen_in.clone(),
+                first_seen_in: chunk.first_seen_in,
+                occurrences: chunk.occurrences,
+                first_seen: None,
+                last_seen: None,
+            })
+            .collect();
+        chunks.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+        StoreDocument {
+            format: FORMAT_NAME.to_string(),
+            version: FORMAT_VERSION,
+            total_bytes: merged.total(),
+            tags: BTreeMap::new(),
+            files,
+            chunks,
+            retained: Vec::new(),
+        }
+    }
+
+    /// Read a document written by `write`, rejecting other documents and
+    /// unknown versions.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let r = BufReader::new(File::open(path)?);
+        let doc: Self = if path.extension() == Some("cbor".as_ref()) {
+            ciborium::from_reader(r).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        } else {
+            serde_json::from_reader(r)?
+        };
+        if doc.format != FORMAT_NAME || doc.version != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is not a {FORMAT_NAME} version {FORMAT_VERSION} document",
+                    path.display()
+                ),
+            ));
+        }
+        Ok(doc)
+    }
+
+    /// Write the document as CBOR if the path has a `.cbor` extension,
+    /// otherwise as JSON.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let is_cbor = path.as_ref().extension() == Some("cbor".as_ref());
+        let mut w = BufWriter::new(File::create(path)?);
+        if is_cbor {
+            ciborium::into_writer(self, &mut w).map_err(io::Error::other)?;
+        } else {
+            serde_json::to_writer(&mut w, self)?;
+        }
+        w.flush()
+    }
+}
+
+/// Encode the chunks of the store in order as a single record batch of an
+/// Arrow IPC stream with the columns hash, size, compressed, seen_in,
+/// first_seen_in, occurrences and data (null unless the data was stored),
+/// the data being large binary since the chunks of a store can add up to
+/// more than the 2 GiB of the 32-bit offsets of binary arrays.
+pub(crate) fn chunks_to_arrow_ipc(store: &ChunkStore) -> io::Result<Vec<u8>> {
+    let mut seen_in = ListBuilder::new(Int64Builder::new());
+    let (mut hashes, mut sizes, mut compressed, mut first_seen_in, mut occurrences, mut data) =
+        (vec![], vec![], vec![], vec![], vec![], vec![]);
+    for (hash, chunk) in store.iter_ordered() {
+        hashes.push(hash);
+        sizes.push(chunk.size as u64);
+        compressed.push(chunk.compressed as u64);
+        seen_in.values().append_slice(&chunk.seen_in);
+        seen_in.append(true);
+        first_seen_in.push(chunk.first_seen_in);
+        occurrences.push(chunk.occurrences as u64);
+        data.push(chunk.data.as_deref());
+    }
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        ("hash", Arc::new(UInt64Array::from(hashes))),
+        ("size", Arc::new(UInt64Array::from(sizes))),
+        ("compressed", Arc::new(UInt64Array::from(compressed))),
+        ("seen_in", Arc::new(seen_in.finish())),
+        ("first_seen_in", Arc::new(Int64Array::from(first_seen_in))),
+        ("occurrences", Arc::new(UInt64Array::from(occurrences))),
+        ("data", Arc::new(LargeBinaryArray::from(data))),
+    ];
+    let batch = RecordBatch::try_from_iter(columns).map_err(io::Error::other)?;
+
+    let mut buffer = Vec::new();
+    let mut writer =
+        StreamWriter::try_new(&mut buffer, &batch.schema()).map_err(io::Error::other)?;
+    writer.write(&batch).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)?;
+    drop(writer);
+    Ok(buffer)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ManifestFormat {
+    Parquet,
+    JsonLines,
+}
+
+impl ManifestFormat {
+    pub fn new(name: &str) -> io::Result<Self> {
+        match name.to_lowercase().as_str() {
+            "parquet" => Ok(ManifestFormat::Parquet),
+            "jsonl" => Ok(ManifestFormat::JsonLines),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown manifest format: {other}"),
+            )),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ManifestRow<'a> {
+    hash: String,
+    file: &'a str,
+    file_index: usize,
+    offset: usize,
+    size: usize,
+    compressed: usize,
+    first_seen_in: i64,
+}
+
+/// Write one row per chunk occurrence of the inputs of a merged store, see
+/// `ChunkStore::locations`, labeled with the `labels` of its inputs.
+/// Returns the number of rows.
+pub(crate) fn write_manifest<P: AsRef<Path>>(
+    store: &ChunkStore,
+    labels: &[String],
+    path: P,
+    format: ManifestFormat,
+) -> io::Result<usize> {
+    let locations = store.locations();
+    let file = File::create(path)?;
+    if format == ManifestFormat::JsonLines {
+        let mut w = BufWriter::new(file);
+        for &(input, offset, hash, chunk) in &locations {
+            let row = ManifestRow {
+                hash: format_hash(hash),
+                file: &labels[input],
+                file_index: input,
+                offset,
+                size: chunk.size,
+                compressed: chunk.compressed,
+                first_seen_in: chunk.first_seen_in,
+            };
+            serde_json::to_writer(&mut w, &row)?;
+            w.write_all(b"\n")?;
+        }
+        w.flush()?;
+        return Ok(locations.len());
+    }
+
+    let column = |f: fn(&(usize, usize, u64, &Chunk)) -> u64| -> ArrayRef {
+        Arc::new(UInt64Array::from_iter_values(locations.iter().map(f)))
+    };
+    let files = StringArray::from_iter_values(locations.iter().map(|l| &labels[l.0]));
+    let first_seen_in = locations.iter().map(|l| l.3.first_seen_in);
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        ("hash", column(|l| l.2)),
+        ("file", Arc::new(files)),
+        ("file_index", column(|l| l.0 as u64)),
+        ("offset", column(|l| l.1 as u64)),
+        ("size", column(|l| l.3.size as u64)),
+        ("compressed", column(|l| l.3.compressed as u64)),
+        (
+            "first_seen_in",
+            Arc::new(Int64Array::from_iter_values(first_seen_in)),
+        ),
+    ];
+    let batch = RecordBatch::try_from_iter(columns).map_err(io::Error::other)?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None).map_err(io::Error::other)?;
+    writer.write(&batch).map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+    Ok(locations.len())
+}
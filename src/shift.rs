@@ -0,0 +1,148 @@
+//! Alignment-tolerant matching of the chunks that don't deduplicate exactly,
+//! quantifying the dedup lost to chunk boundaries cutting the same content
+//! at different offsets rather than to the content changing.
+
+use crate::store::ChunkStore;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Bytes of the new chunks also found at a shifted offset in earlier chunks.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ShiftStats {
+    pub total: usize,
+    pub chunk_bytes: usize,
+    pub shifted_bytes: usize,
+}
+
+/// The weak rolling checksum of rsync over a fixed size window, cheap to
+/// slide by one byte and only used to filter the candidates of the strong
+/// hash.
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+    window: u32,
+}
+
+impl RollingChecksum {
+    fn new(data: &[u8]) -> Self {
+        let window = data.len() as u32;
+        let (a, b) = data
+            .iter()
+            .enumerate()
+            .fold((0u32, 0u32), |(a, b), (i, &x)| {
+                let x = x as u32;
+                (
+                    a.wrapping_add(x),
+                    b.wrapping_add((window - i as u32).wrapping_mul(x)),
+                )
+            });
+        RollingChecksum { a, b, window }
+    }
+
+    fn roll(&mut self, out: u8, next: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(next as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.window.wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+
+    fn digest(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+/// Index of the window-aligned blocks of the chunks seen so far.
+struct BlockIndex {
+    window: usize,
+    weak: HashSet<u32>,
+    /// Offset of each block within the first chunk containing it.
+    strong: HashMap<u64, usize>,
+}
+
+impl BlockIndex {
+    fn new(window: usize) -> Self {
+        BlockIndex {
+            window,
+            weak: HashSet::new(),
+            strong: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, data: &[u8]) {
+        for (i, block) in data.chunks_exact(self.window).enumerate() {
+            self.weak.insert(RollingChecksum::new(block).digest());
+            self.strong.entry(xxh3_64(block)).or_insert(i * self.window);
+        }
+    }
+
+    /// Slide a window over the chunk and count the bytes of the blocks found
+    /// in the index at a different offset than in the chunk. Blocks found at
+    /// the same offset would also be found by cutting the chunks smaller, so
+    /// they are lost to a content change and not to the misalignment.
+    fn shifted_bytes(&self, data: &[u8]) -> usize {
+        let window = self.window;
+        if data.len() < window {
+            return 0;
+        }
+        let mut shifted = 0;
+        let mut pos = 0;
+        let mut checksum = RollingChecksum::new(&data[..window]);
+        loop {
+            if self.weak.contains(&checksum.digest()) {
+                let offset = self.strong.get(&xxh3_64(&data[pos..pos + window]));
+                if offset.is_some_and(|&offset| offset != pos) {
+                    shifted += window;
+                    pos += window;
+                    if pos + window > data.len() {
+                        break;
+                    }
+                    checksum = RollingChecksum::new(&data[pos..pos + window]);
+                    continue;
+                }
+            }
+            if pos + window >= data.len() {
+                break;
+            }
+            checksum.roll(data[pos], data[pos + window]);
+            pos += 1;
+        }
+        shifted
+    }
+}
+
+/// Match every new chunk of the files against the `window` sized blocks of
+/// the chunks before it at every byte offset, like rsync does, and count the
+/// bytes found shifted. The files are chunked one at a time so only the data
+/// of a single file and the block index are held in memory.
+pub(crate) fn shifted_matches<P: AsRef<Path>>(
+    paths: &[P],
+    window: usize,
+) -> io::Result<ShiftStats> {
+    let mut stats = ShiftStats::default();
+    let mut seen = HashSet::new();
+    let mut index = BlockIndex::new(window);
+    for path in paths {
+        let store = ChunkStore::from_file(path, true)?;
+        stats.total += store.total();
+        for (hash, chunk) in store.iter_ordered() {
+            if !seen.insert(hash) {
+                continue;
+            }
+            let Some(data) = &chunk.data else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "hash lists don't carry the chunk data",
+                ));
+            };
+            stats.chunk_bytes += data.len();
+            // only earlier chunks are matched, so shifted content is counted
+            // once, in the later of the chunks containing it
+            stats.shifted_bytes += index.shifted_bytes(data);
+            index.insert(data);
+        }
+    }
+    Ok(stats)
+}
@@ -1,7 +1,11 @@
+use crate::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::ExtendedColorType;
 use png::Encoder;
 use std::cmp::min;
 use std::fs::File;
-use std::io;
+use std::io::{self, Write};
 
 #[derive(Copy, Clone)]
 struct Rgb {
@@ -17,9 +21,16 @@ struct Frgb {
     b: f32,
 }
 
-const IMAGE_DIM: usize = 256;
-const BLOCK_DIM: usize = 8;
-const SEQUENCE_LENGTH: usize = (IMAGE_DIM / BLOCK_DIM) * IMAGE_DIM;
+pub(crate) const IMAGE_DIM: usize = 256;
+pub(crate) const BLOCK_DIM: usize = 8;
+const CAPTION_PADDING: usize = 2;
+/// Height of the strip of every file in the corpus poster.
+const POSTER_STRIP_HEIGHT: usize = 4;
+
+#[inline(always)]
+fn sequence_length(dim: usize) -> usize {
+    (dim / BLOCK_DIM) * dim
+}
 
 const COLORS: [Frgb; 32] = [
     Frgb {
@@ -184,12 +195,36 @@ const COLORS: [Frgb; 32] = [
     }, // Misty Rose
 ];
 
+const GRADIENT: [Frgb; 3] = [
+    Frgb {
+        r: 0.0,
+        g: 0.0,
+        b: 255.0,
+    }, // Blue
+    Frgb {
+        r: 0.0,
+        g: 255.0,
+        b: 0.0,
+    }, // Green
+    Frgb {
+        r: 255.0,
+        g: 0.0,
+        b: 0.0,
+    }, // Red
+];
+
 #[inline(always)]
 fn getcolor(i: usize) -> Frgb {
     COLORS[i % COLORS.len()]
 }
 
-fn interpolate_sample(s: &[usize], pos: f32) -> Frgb {
+/// Map a value in the [0, 1] range to the blue-green-red gradient.
+fn gradient(t: f32) -> Frgb {
+    let pos = t.clamp(0.0, 1.0) * (GRADIENT.len() - 1) as f32;
+    interpolate_sample(&GRADIENT, pos)
+}
+
+fn interpolate_sample(s: &[Frgb], pos: f32) -> Frgb {
     if pos == pos.floor() {
         let mut ipos = pos as isize;
         if ipos < 0 {
@@ -198,7 +233,7 @@ fn interpolate_sample(s: &[usize], pos: f32) -> Frgb {
         if ipos >= s.len() as isize {
             ipos = s.len() as isize - 1;
         }
-        getcolor(s[ipos as usize])
+        s[ipos as usize]
     } else {
         let mut ipos = pos as isize;
         if ipos < 0 {
@@ -209,8 +244,8 @@ fn interpolate_sample(s: &[usize], pos: f32) -> Frgb {
         }
         let left_weight = 1.0 - (pos - ipos as f32);
         let right_weight = 1.0 - left_weight;
-        let color_left = getcolor(s[ipos as usize]);
-        let color_right = getcolor(s[min(ipos as usize + 1, s.len() - 1)]);
+        let color_left = s[ipos as usize];
+        let color_right = s[min(ipos as usize + 1, s.len() - 1)];
         Frgb {
             r: left_weight * color_left.r + right_weight * color_right.r,
             g: left_weight * color_left.g + right_weight * color_right.g,
@@ -219,11 +254,11 @@ fn interpolate_sample(s: &[usize], pos: f32) -> Frgb {
     }
 }
 
-fn generate_color_sequence(s: &[usize]) -> Vec<Rgb> {
+fn generate_color_sequence(s: &[Frgb], length: usize) -> Vec<Rgb> {
     let mut ret = Vec::new();
-    for i in 0..SEQUENCE_LENGTH {
-        let mut fpos = (i * s.len()) as f32 / SEQUENCE_LENGTH as f32;
-        let fnextpos = ((i + 1) * s.len()) as f32 / SEQUENCE_LENGTH as f32;
+    for i in 0..length {
+        let mut fpos = (i * s.len()) as f32 / length as f32;
+        let fnextpos = ((i + 1) * s.len()) as f32 / length as f32;
         if fpos > (s.len() - 1) as f32 {
             fpos = (s.len() - 1) as f32;
         }
@@ -256,28 +291,358 @@ fn generate_color_sequence(s: &[usize]) -> Vec<Rgb> {
     ret
 }
 
-pub(crate) fn write_png(segments: &[usize], filename: &str) -> io::Result<()> {
-    let colors = generate_color_sequence(segments);
-    let file = File::create(filename)?;
-    let w = &mut io::BufWriter::new(file);
+/// The first and last of `count` samples averaged into every block of an
+/// image of width `dim` by `generate_color_sequence`, with fewer samples than
+/// blocks consecutive blocks repeat the same sample.
+pub(crate) fn block_samples(count: usize, dim: usize) -> Vec<(usize, usize)> {
+    let length = sequence_length(dim);
+    if count == 0 {
+        return vec![];
+    }
+    (0..length)
+        .map(|i| {
+            let first = (i * count / length).min(count - 1);
+            let last = ((i + 1) * count).div_ceil(length).saturating_sub(1);
+            (first, last.clamp(first, count - 1))
+        })
+        .collect()
+}
+
+/// Raster format of the generated images, lossy formats carry a 0-100 quality.
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum ImageFormat {
+    Png,
+    Bmp,
+    Jpeg(u8),
+    WebP(u8),
+}
+
+impl ImageFormat {
+    pub fn new(name: &str, quality: u8) -> io::Result<Self> {
+        if quality > 100 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Image quality must be between 0 and 100, got {quality}"),
+            ));
+        }
+        match name.to_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "bmp" => Ok(ImageFormat::Bmp),
+            "jpeg" | "jpg" => Ok(ImageFormat::Jpeg(quality)),
+            "webp" => Ok(ImageFormat::WebP(quality)),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown image format: {other}"),
+            )),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Bmp => "bmp",
+            ImageFormat::Jpeg(_) => "jpg",
+            ImageFormat::WebP(_) => "webp",
+        }
+    }
+}
 
-    let mut encoder = Encoder::new(w, IMAGE_DIM as u32, IMAGE_DIM as u32);
-    encoder.set_color(png::ColorType::Rgb);
-    encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
+/// Options shared by all generated images, the full-size image is written to
+/// `{base}.{ext}` and the optional thumbnail to `{base}.thumb.{ext}`. The
+/// tags are embedded as text chunks of PNG images, the other formats don't
+/// carry them.
+#[derive(Clone, Debug)]
+pub(crate) struct ImageOptions {
+    pub format: ImageFormat,
+    pub size: usize,
+    pub thumbnail_size: Option<usize>,
+    pub tags: Vec<(String, String)>,
+}
 
-    let mut data = Vec::with_capacity(IMAGE_DIM * IMAGE_DIM * 3);
-    for i in 0..IMAGE_DIM {
-        for j in 0..IMAGE_DIM {
+impl ImageOptions {
+    pub fn new(
+        format: ImageFormat,
+        size: usize,
+        thumbnail_size: Option<usize>,
+    ) -> io::Result<Self> {
+        for dim in std::iter::once(size).chain(thumbnail_size) {
+            if dim < BLOCK_DIM || dim % BLOCK_DIM != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Image size must be a positive multiple of {BLOCK_DIM}, got {dim}"),
+                ));
+            }
+        }
+        Ok(ImageOptions {
+            format,
+            size,
+            thumbnail_size,
+            tags: Vec::new(),
+        })
+    }
+
+    pub fn with_tags(mut self, tags: Vec<(String, String)>) -> Self {
+        self.tags = tags;
+        self
+    }
+}
+
+pub(crate) fn write_image(
+    segments: &[usize],
+    base: &str,
+    options: &ImageOptions,
+    caption: &[String],
+) -> io::Result<()> {
+    let samples: Vec<Frgb> = segments.iter().map(|&i| getcolor(i)).collect();
+    write_samples(&samples, base, options, caption)
+}
+
+/// Render the chunk sizes along the file, small chunks are blue while chunks
+/// reaching max_size are red.
+pub(crate) fn write_size_image(
+    sizes: &[usize],
+    max_size: usize,
+    base: &str,
+    options: &ImageOptions,
+    caption: &[String],
+) -> io::Result<()> {
+    let samples: Vec<Frgb> = sizes
+        .iter()
+        .map(|&size| gradient(size as f32 / max_size as f32))
+        .collect();
+    write_samples(&samples, base, options, caption)
+}
+
+/// Render one horizontal strip per column showing the owner of each chunk
+/// along the pages of that column, columns without any data are left black.
+pub(crate) fn write_column_image(
+    columns: &[Vec<usize>],
+    base: &str,
+    options: &ImageOptions,
+    caption: &[String],
+) -> io::Result<()> {
+    let dim = options.size;
+    let mut data = Vec::with_capacity(columns.len() * BLOCK_DIM * dim * 3);
+    for segments in columns {
+        let samples: Vec<Frgb> = segments.iter().map(|&i| getcolor(i)).collect();
+        let row: Vec<u8> = if samples.is_empty() {
+            vec![0; dim * 3]
+        } else {
+            generate_color_sequence(&samples, dim)
+                .iter()
+                .flat_map(|color| [color.r, color.g, color.b])
+                .collect()
+        };
+        for _ in 0..BLOCK_DIM {
+            data.extend_from_slice(&row);
+        }
+    }
+    let caption_height = render_caption(&mut data, dim, caption);
+    let filename = format!("{base}.{}", options.format.extension());
+    encode(
+        &data,
+        dim as u32,
+        (columns.len() * BLOCK_DIM + caption_height) as u32,
+        &filename,
+        options.format,
+        &options.tags,
+    )
+}
+
+/// Render the corpus poster: one strip per file of the chunk owners along
+/// it, downsampled to the image width and `POSTER_STRIP_HEIGHT` pixels high,
+/// tiled top to bottom then left to right in the given order. The number of
+/// columns keeps the poster roughly square, so thousands of files fit in a
+/// single image. Files without any data are left black.
+pub(crate) fn write_poster(
+    strips: &[Vec<usize>],
+    base: &str,
+    options: &ImageOptions,
+    caption: &[String],
+) -> io::Result<()> {
+    let dim = options.size;
+    let area = strips.len() * POSTER_STRIP_HEIGHT;
+    let columns = ((area as f64 / dim as f64).sqrt().round() as usize).max(1);
+    let rows = strips.len().div_ceil(columns).max(1);
+    let width = columns * dim;
+    let mut data = vec![0u8; rows * POSTER_STRIP_HEIGHT * width * 3];
+    for (i, segments) in strips.iter().enumerate() {
+        if segments.is_empty() {
+            continue;
+        }
+        let samples: Vec<Frgb> = segments.iter().map(|&i| getcolor(i)).collect();
+        let row: Vec<u8> = generate_color_sequence(&samples, dim)
+            .iter()
+            .flat_map(|color| [color.r, color.g, color.b])
+            .collect();
+        let (column, top) = (i / rows, (i % rows) * POSTER_STRIP_HEIGHT);
+        for y in top..top + POSTER_STRIP_HEIGHT {
+            let offset = (y * width + column * dim) * 3;
+            data[offset..offset + dim * 3].copy_from_slice(&row);
+        }
+    }
+    let caption_height = render_caption(&mut data, width, caption);
+    let filename = format!("{base}.{}", options.format.extension());
+    encode(
+        &data,
+        width as u32,
+        (rows * POSTER_STRIP_HEIGHT + caption_height) as u32,
+        &filename,
+        options.format,
+        &options.tags,
+    )
+}
+
+/// Write the full-size image with the caption lines rendered below it, the
+/// thumbnail is kept uncaptioned since the text wouldn't fit anyway.
+fn write_samples(
+    samples: &[Frgb],
+    base: &str,
+    options: &ImageOptions,
+    caption: &[String],
+) -> io::Result<()> {
+    let ext = options.format.extension();
+    let filename = format!("{base}.{ext}");
+    write_resolution(samples, &filename, options, options.size, caption)?;
+    if let Some(dim) = options.thumbnail_size {
+        let filename = format!("{base}.thumb.{ext}");
+        write_resolution(samples, &filename, options, dim, &[])?;
+    }
+    Ok(())
+}
+
+/// Append a black strip with one line of white text per caption entry, lines
+/// longer than the image width are truncated.
+fn render_caption(data: &mut Vec<u8>, dim: usize, caption: &[String]) -> usize {
+    if caption.is_empty() {
+        return 0;
+    }
+    let line_height = GLYPH_HEIGHT + CAPTION_PADDING;
+    let height = caption.len() * line_height + CAPTION_PADDING;
+    let max_chars = (dim - CAPTION_PADDING) / (GLYPH_WIDTH + 1);
+    let mut strip = vec![0u8; height * dim * 3];
+    for (row, line) in caption.iter().enumerate() {
+        let top = CAPTION_PADDING + row * line_height;
+        for (col, c) in line.chars().take(max_chars).enumerate() {
+            let left = CAPTION_PADDING + col * (GLYPH_WIDTH + 1);
+            for x in 0..GLYPH_WIDTH {
+                for y in 0..GLYPH_HEIGHT {
+                    if font::pixel(c, x, y) {
+                        let offset = ((top + y) * dim + left + x) * 3;
+                        strip[offset..offset + 3].fill(255);
+                    }
+                }
+            }
+        }
+    }
+    data.extend_from_slice(&strip);
+    height
+}
+
+fn write_resolution(
+    samples: &[Frgb],
+    filename: &str,
+    options: &ImageOptions,
+    dim: usize,
+    caption: &[String],
+) -> io::Result<()> {
+    let colors = generate_color_sequence(samples, sequence_length(dim));
+    let mut data = Vec::with_capacity(dim * dim * 3);
+    for i in 0..dim {
+        for j in 0..dim {
             let block_x = i / BLOCK_DIM;
             let block_y = j;
-            let block_idx = block_x * IMAGE_DIM + block_y;
+            let block_idx = block_x * dim + block_y;
             let color = colors[block_idx];
             data.push(color.r);
             data.push(color.g);
             data.push(color.b);
         }
     }
-    writer.write_image_data(&data).unwrap();
-    Ok(())
+    let caption_height = render_caption(&mut data, dim, caption);
+    encode(
+        &data,
+        dim as u32,
+        (dim + caption_height) as u32,
+        filename,
+        options.format,
+        &options.tags,
+    )
+}
+
+/// Render a dot plot of the chunks shared between two files, each match is
+/// drawn as a diagonal from (offset_a, offset_b) spanning the chunk size, with
+/// file a along the x axis and file b along the y axis.
+pub(crate) fn write_dot_plot(
+    matches: &[(usize, usize, usize)],
+    len_a: usize,
+    len_b: usize,
+    base: &str,
+    options: &ImageOptions,
+) -> io::Result<()> {
+    let dim = options.size;
+    let mut data = vec![255u8; dim * dim * 3];
+    let scale_a = dim as f64 / len_a.max(1) as f64;
+    let scale_b = dim as f64 / len_b.max(1) as f64;
+    for &(offset_a, offset_b, size) in matches {
+        let steps = ((size as f64 * scale_a.max(scale_b)).ceil() as usize).max(1);
+        for step in 0..=steps {
+            let delta = size as f64 * step as f64 / steps as f64;
+            let x = (((offset_a as f64 + delta) * scale_a) as usize).min(dim - 1);
+            let y = (((offset_b as f64 + delta) * scale_b) as usize).min(dim - 1);
+            let offset = (y * dim + x) * 3;
+            data[offset..offset + 3].fill(0);
+        }
+    }
+    let filename = format!("{base}.{}", options.format.extension());
+    encode(
+        &data,
+        dim as u32,
+        dim as u32,
+        &filename,
+        options.format,
+        &options.tags,
+    )
+}
+
+fn encode(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    filename: &str,
+    format: ImageFormat,
+    tags: &[(String, String)],
+) -> io::Result<()> {
+    let file = File::create(filename)?;
+    let mut w = io::BufWriter::new(file);
+
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = Encoder::new(&mut w, width, height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            for (key, value) in tags {
+                encoder
+                    .add_itxt_chunk(key.clone(), value.clone())
+                    .map_err(io::Error::other)?;
+            }
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(data).unwrap();
+        }
+        ImageFormat::Bmp => {
+            BmpEncoder::new(&mut w)
+                .encode(data, width, height, ExtendedColorType::Rgb8)
+                .map_err(io::Error::other)?;
+        }
+        ImageFormat::Jpeg(quality) => {
+            JpegEncoder::new_with_quality(&mut w, quality)
+                .encode(data, width, height, ExtendedColorType::Rgb8)
+                .map_err(io::Error::other)?;
+        }
+        ImageFormat::WebP(quality) => {
+            let encoded = webp::Encoder::from_rgb(data, width, height).encode(quality as f32);
+            w.write_all(&encoded)?;
+        }
+    }
+    w.flush()
 }
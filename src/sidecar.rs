@@ -0,0 +1,119 @@
+//! JSON sidecars of the chunk ownership images mapping every block of an
+//! image back to the bytes it shows, so external viewers can build tooltips
+//! on top of the rendered images:
+//!
+//! ```text
+//! {
+//!   "image": <image file name>,
+//!   "width": <pixels>,
+//!   "block_height": <pixels>,
+//!   "blocks": [
+//!     {
+//!       "file": <input path>,
+//!       "start": <offset>,
+//!       "end": <offset>,
+//!       "hash": <hash>,
+//!       "owner": <input path>
+//!     },
+//!     ...
+//!   ]
+//! }
+//! ```
+//!
+//! The pixel at (x, y) belongs to block `(y / block_height) * width + x`. A
+//! block covering several chunks spans their byte range within the file of
+//! the first one, whose hash and owner, the input the chunk is attributed
+//! to, it carries. Hashes are formatted like in the chunk store documents.
+//! Only the full-size images get a sidecar, not the thumbnails.
+
+use crate::show::{block_samples, ImageOptions, BLOCK_DIM};
+use crate::store::ChunkStore;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Serialize)]
+struct Block<'a> {
+    file: &'a str,
+    start: usize,
+    end: usize,
+    hash: String,
+    owner: &'a str,
+}
+
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    image: String,
+    width: usize,
+    block_height: usize,
+    blocks: Vec<Block<'a>>,
+}
+
+/// A chunk of an input at a byte range of it.
+struct Placed {
+    file: usize,
+    start: usize,
+    end: usize,
+    hash: u64,
+    owner: usize,
+}
+
+/// Write the sidecar of the ownership image `{base}.{ext}` showing the chunks
+/// of the `stores` in order to `{base}.blocks.json`. The stores are the
+/// inputs starting at index `first` of `labels`, and must carry the
+/// attribution of the merged store.
+pub(crate) fn write(
+    stores: &[ChunkStore],
+    first: usize,
+    labels: &[String],
+    base: &str,
+    options: &ImageOptions,
+) -> io::Result<()> {
+    let mut placed = Vec::new();
+    for (file, store) in (first..).zip(stores) {
+        let mut offset = 0;
+        for (hash, chunk) in store.iter_ordered() {
+            placed.push(Placed {
+                file,
+                start: offset,
+                end: offset + chunk.size,
+                hash,
+                owner: chunk.first_seen_in as usize,
+            });
+            offset += chunk.size;
+        }
+    }
+    let blocks = block_samples(placed.len(), options.size)
+        .into_iter()
+        .map(|(a, b)| {
+            let chunk = &placed[a];
+            // the range stops at the end of the file of the first chunk
+            let end = placed[a..=b]
+                .iter()
+                .take_while(|p| p.file == chunk.file)
+                .last()
+                .unwrap()
+                .end;
+            Block {
+                file: &labels[chunk.file],
+                start: chunk.start,
+                end,
+                hash: format!("{:016x}", chunk.hash),
+                owner: &labels[chunk.owner],
+            }
+        })
+        .collect();
+    let name = Path::new(base)
+        .file_name()
+        .map_or(base.into(), |name| name.to_string_lossy());
+    let sidecar = Sidecar {
+        image: format!("{name}.{}", options.format.extension()),
+        width: options.size,
+        block_height: BLOCK_DIM,
+        blocks,
+    };
+    let mut w = BufWriter::new(File::create(format!("{base}.blocks.json"))?);
+    serde_json::to_writer(&mut w, &sidecar)?;
+    w.flush()
+}
@@ -0,0 +1,180 @@
+//! Simulations of chunk-addressed storage infrastructure, used to size caches
+//! for serving the deduplicated chunks and to account for the container
+//! formats storing them.
+
+use crate::store::ChunkStore;
+use lz4_flex::block;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path::Path;
+
+/// Bytes of the lz4 frame magic, descriptor, block size and end mark around
+/// every packed block.
+const FRAME_OVERHEAD: usize = 15;
+/// Bytes of the index entry locating a chunk in its block: hash, offset and
+/// length.
+const CHUNK_INDEX_BYTES: usize = 16;
+
+/// Request and hit counts of a cache simulation.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct CacheStats {
+    pub requests: usize,
+    pub hits: usize,
+    pub requested_bytes: usize,
+    pub hit_bytes: usize,
+}
+
+/// Least recently used cache of chunks bounded by their total size in bytes.
+struct LruCache {
+    capacity: usize,
+    used: usize,
+    tick: u64,
+    entries: HashMap<u64, (usize, u64)>,
+    recency: BTreeMap<u64, u64>,
+}
+
+impl LruCache {
+    fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            used: 0,
+            tick: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    /// Access a chunk and return whether it was cached. Missed chunks are
+    /// cached, evicting the least recently used ones, unless they are larger
+    /// than the whole cache.
+    fn access(&mut self, hash: u64, size: usize) -> bool {
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.get_mut(&hash) {
+            self.recency.remove(last_used);
+            *last_used = self.tick;
+            self.recency.insert(self.tick, hash);
+            return true;
+        }
+        if size > self.capacity {
+            return false;
+        }
+        while self.used + size > self.capacity {
+            let (_, evicted) = self.recency.pop_first().unwrap();
+            let (evicted_size, _) = self.entries.remove(&evicted).unwrap();
+            self.used -= evicted_size;
+        }
+        self.entries.insert(hash, (size, self.tick));
+        self.recency.insert(self.tick, hash);
+        self.used += size;
+        false
+    }
+}
+
+/// Replay the chunks of the stores in order through an LRU cache of
+/// `capacity` bytes, returning the statistics of each store.
+pub(crate) fn simulate_lru(stores: &[ChunkStore], capacity: usize) -> Vec<CacheStats> {
+    let mut cache = LruCache::new(capacity);
+    stores
+        .iter()
+        .map(|store| {
+            let mut stats = CacheStats::default();
+            for (hash, size, _) in store.recipe() {
+                stats.requests += 1;
+                stats.requested_bytes += size;
+                if cache.access(hash, size) {
+                    stats.hits += 1;
+                    stats.hit_bytes += size;
+                }
+            }
+            stats
+        })
+        .collect()
+}
+
+/// Storage of the unique chunks packed into independently compressed blocks.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct PackingStats {
+    pub chunks: usize,
+    pub blocks: usize,
+    pub raw_bytes: usize,
+    pub compressed_bytes: usize,
+    pub overhead_bytes: usize,
+}
+
+/// Appends chunks to fixed size blocks, chunks may span block boundaries.
+struct Packer {
+    block_size: usize,
+    buffer: Vec<u8>,
+    stats: PackingStats,
+}
+
+impl Packer {
+    fn new(block_size: usize) -> Self {
+        Packer {
+            block_size,
+            buffer: Vec::with_capacity(block_size),
+            stats: PackingStats::default(),
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        self.stats.chunks += 1;
+        self.stats.raw_bytes += data.len();
+        self.stats.overhead_bytes += CHUNK_INDEX_BYTES;
+        let mut rest = data;
+        while !rest.is_empty() {
+            let n = (self.block_size - self.buffer.len()).min(rest.len());
+            self.buffer.extend_from_slice(&rest[..n]);
+            rest = &rest[n..];
+            if self.buffer.len() == self.block_size {
+                self.flush();
+            }
+        }
+    }
+
+    /// Account for a chunk known only by its size, e.g. from a hash list,
+    /// which is assumed to be incompressible.
+    fn push_opaque(&mut self, size: usize) {
+        self.stats.chunks += 1;
+        self.stats.raw_bytes += size;
+        self.stats.compressed_bytes += size;
+        self.stats.overhead_bytes += CHUNK_INDEX_BYTES;
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        // lz4 frames store the blocks which don't compress uncompressed
+        let compressed = block::compress(&self.buffer).len();
+        self.stats.blocks += 1;
+        self.stats.compressed_bytes += compressed.min(self.buffer.len());
+        self.stats.overhead_bytes += FRAME_OVERHEAD;
+        self.buffer.clear();
+    }
+}
+
+/// Pack the unique chunks of the files in first seen order into lz4 frames
+/// of `block_size` uncompressed bytes. The files are chunked one at a time so
+/// only the data of a single file is held in memory.
+pub(crate) fn simulate_packing<P: AsRef<Path>>(
+    paths: &[P],
+    block_size: usize,
+) -> io::Result<PackingStats> {
+    let mut seen = HashSet::new();
+    let mut packer = Packer::new(block_size);
+    for path in paths {
+        let store = ChunkStore::from_file(path, true)?;
+        for (hash, chunk) in store.iter_ordered() {
+            if !seen.insert(hash) {
+                continue;
+            }
+            match &chunk.data {
+                Some(data) => packer.push(data),
+                None => packer.push_opaque(chunk.size),
+            }
+        }
+    }
+    packer.flush();
+    Ok(packer.stats)
+}
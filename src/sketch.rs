@@ -0,0 +1,173 @@
+//! Approximate unique chunk counting in a fixed memory budget, for quick
+//! ballparks of corpora of any size.
+//!
+//! A Bloom filter recognizes the chunks already seen and sums the sizes of
+//! the new ones. Its false positives only ever hide new chunks, so these sums
+//! are lower bounds which stay exact while the filter is sparse. A
+//! HyperLogLog sketch estimates the number of distinct chunks independently
+//! of the filter saturation, and once the filter misses chunks the unique
+//! bytes are scaled up by the ratio of the distinct chunks to the ones the
+//! filter recognized as new, assuming the missed chunks have the same mean
+//! size.
+
+use crate::bloom::BloomFilter;
+use crate::progress::Progress;
+use crate::store::{self, ChunkParams};
+use rayon::prelude::*;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The smallest accepted memory budget in bytes.
+pub(crate) const MIN_BUDGET: usize = 1024;
+const MIN_PRECISION: u32 = 4;
+const MAX_PRECISION: u32 = 16;
+const BLOOM_HASHES: u32 = 3;
+/// False positive rate below which the Bloom filter counts are used as is.
+const EXACT_FALSE_POSITIVE_RATE: f64 = 1e-3;
+
+/// HyperLogLog cardinality sketch over 64-bit hashes with 2^precision
+/// one-byte registers.
+struct HyperLogLog {
+    precision: u32,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new(precision: u32) -> Self {
+        HyperLogLog {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    fn insert(&mut self, hash: u64) {
+        let index = (hash >> (64 - self.precision)) as usize;
+        let rest = hash << self.precision;
+        let rank = rest.leading_zeros().min(64 - self.precision) as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        // linear counting is more accurate for small cardinalities
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+
+    /// The relative standard error of the estimates.
+    fn standard_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+}
+
+pub(crate) struct SketchCounter {
+    bloom: BloomFilter,
+    hll: HyperLogLog,
+    total: usize,
+    new_chunks: usize,
+    new_bytes: usize,
+    new_compressed: usize,
+}
+
+impl SketchCounter {
+    /// Split the budget between the HyperLogLog registers, about a sixteenth
+    /// of it, and the bits of the Bloom filter.
+    pub fn new(budget: usize) -> io::Result<Self> {
+        if budget < MIN_BUDGET {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The memory budget must be at least {MIN_BUDGET} bytes, got {budget}"),
+            ));
+        }
+        let precision = (budget / 16).ilog2().clamp(MIN_PRECISION, MAX_PRECISION);
+        let bloom_bytes = budget - (1 << precision);
+        Ok(SketchCounter {
+            bloom: BloomFilter::with_bits(bloom_bytes as u64 * 8, BLOOM_HASHES),
+            hll: HyperLogLog::new(precision),
+            total: 0,
+            new_chunks: 0,
+            new_bytes: 0,
+            new_compressed: 0,
+        })
+    }
+
+    pub fn add(&mut self, hash: u64, size: usize, compressed: usize) {
+        self.total += size;
+        self.hll.insert(hash);
+        if !self.bloom.contains(hash) {
+            self.bloom.insert(hash);
+            self.new_chunks += 1;
+            self.new_bytes += size;
+            self.new_compressed += compressed;
+        }
+    }
+
+    /// The estimated (total, unique, compressed) bytes like
+    /// `ChunkStore::stats` and the relative error bound of the unique and
+    /// compressed bytes, two standard errors of the sketch once it is used.
+    /// While the filter is sparse, every distinct chunk is missed with at
+    /// most its false positive rate, so the missed share of the new chunks
+    /// is bounded by that rate plus two standard errors of a binomial
+    /// sample of their count, which is zero only without any chunk.
+    pub fn stats(&self) -> ((usize, usize, usize), f64) {
+        let false_positive_rate = self.bloom.false_positive_rate();
+        if self.new_chunks == 0 {
+            return ((self.total, 0, 0), 0.0);
+        }
+        if false_positive_rate < EXACT_FALSE_POSITIVE_RATE {
+            let stats = (self.total, self.new_bytes, self.new_compressed);
+            let p = false_positive_rate;
+            let bound = p + 2.0 * (p * (1.0 - p) / self.new_chunks as f64).sqrt();
+            return (stats, bound);
+        }
+        let scale = (self.hll.estimate() / self.new_chunks as f64).max(1.0);
+        let estimate = |bytes: usize| ((bytes as f64 * scale) as usize).min(self.total);
+        let stats = (
+            self.total,
+            estimate(self.new_bytes),
+            estimate(self.new_compressed),
+        );
+        (stats, 2.0 * self.hll.standard_error())
+    }
+}
+
+/// Chunk the files in parallel folding the chunks of every block read into
+/// a shared sketch, so the memory holds the sketch and a block per worker
+/// whatever the size of the files. The files are chunked with `params`.
+/// Every file done is reported to `progress`.
+/// Returns the sketch and the `sniff` verdict of every file.
+pub(crate) fn count_files<P: AsRef<Path> + Send + Sync>(
+    paths: &[P],
+    budget: usize,
+    params: ChunkParams,
+    progress: &Progress,
+) -> io::Result<(SketchCounter, Vec<Option<String>>)> {
+    let counter = Mutex::new(SketchCounter::new(budget)?);
+    let sniffed = paths
+        .par_iter()
+        .map(|path| {
+            let (_, sniffed) = store::stream_recipe(path, params, progress, |chunks| {
+                let mut counter = counter.lock().unwrap();
+                for &(hash, size, compressed) in chunks {
+                    counter.add(hash, size, compressed);
+                }
+            })?;
+            progress.file_done(0)?;
+            Ok(sniffed)
+        })
+        .collect::<io::Result<_>>()?;
+    Ok((counter.into_inner().unwrap(), sniffed))
+}
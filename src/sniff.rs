@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+const SNIFF_SIZE: usize = 1024 * 1024;
+const ENTROPY_THRESHOLD: f64 = 7.9;
+
+const MAGIC_BYTES: [(&[u8], &str); 8] = [
+    (&[0x28, 0xb5, 0x2f, 0xfd], "zstd"),
+    (&[0x1f, 0x8b], "gzip"),
+    (&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00], "xz"),
+    (b"BZh", "bzip2"),
+    (&[0x04, 0x22, 0x4d, 0x18], "lz4"),
+    (b"PK\x03\x04", "zip"),
+    (&[0x37, 0x7a, 0xbc, 0xaf, 0x27, 0x1c], "7z"),
+    (b"Salted__", "openssl encrypted"),
+];
+
+/// Shannon entropy of the byte distribution in bits per byte.
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Why the beginning of a stream is likely already compressed or encrypted.
+fn describe(data: &[u8]) -> Option<String> {
+    for (magic, name) in MAGIC_BYTES {
+        if data.starts_with(magic) {
+            return Some(format!("{name} magic bytes"));
+        }
+    }
+    // short inputs don't have enough samples for a meaningful estimate
+    if data.len() >= 4096 {
+        let bits = entropy(data);
+        if bits > ENTROPY_THRESHOLD {
+            return Some(format!("high entropy ({bits:.2} bits/byte)"));
+        }
+    }
+    None
+}
+
+fn read_head<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(SNIFF_SIZE);
+    reader
+        .by_ref()
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Inspect the beginning of a stream and describe why it is likely already
+/// compressed or encrypted, such inputs barely deduplicate or compress.
+pub(crate) fn sniff<R: Read>(reader: &mut R) -> io::Result<Option<String>> {
+    Ok(describe(&read_head(reader)?))
+}
+
+/// Sniff a stream like `sniff` without consuming it: the returned reader
+/// yields the bytes inspected followed by the rest of the stream, so an
+/// input is opened and read once to be sniffed and chunked.
+pub(crate) fn sniff_reader<R: Read>(
+    mut reader: R,
+) -> io::Result<(Option<String>, io::Chain<io::Cursor<Vec<u8>>, R>)> {
+    let data = read_head(&mut reader)?;
+    let reason = describe(&data);
+    Ok((reason, io::Cursor::new(data).chain(reader)))
+}
+
+pub(crate) fn sniff_file<P: AsRef<Path>>(path: P) -> io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    sniff(&mut file)
+}
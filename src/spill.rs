@@ -0,0 +1,147 @@
+//! Exact bounded-memory counting of the unique chunks with a hash
+//! partitioned index on disk, for corpora whose chunk index doesn't fit in
+//! memory and for which the sampled counts of the `sample` module are not
+//! good enough.
+//!
+//! The chunks of every file are appended as (hash, size, compressed) records
+//! to one of the partition files picked by the hash, so all the occurrences
+//! of a chunk land in the same partition. The partitions are then
+//! deduplicated one at a time, so the memory holds the chunks of a single
+//! partition, at the cost of writing and reading every record once.
+
+use crate::progress::Progress;
+use crate::store::{self, ChunkParams};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RECORD_SIZE: usize = 24;
+/// Records a worker buffers before writing them to the partitions, so every
+/// writer is locked once per batch rather than once per chunk.
+const BATCH_RECORDS: usize = 4096;
+
+/// Partition files in a fresh directory, removed when dropped.
+struct Partitions {
+    dir: PathBuf,
+    writers: Vec<Mutex<BufWriter<File>>>,
+}
+
+impl Partitions {
+    fn create(parent: &Path, count: usize) -> io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        let dir = parent.join(format!("de-index-{}-{nanos}", std::process::id()));
+        fs::create_dir(&dir)?;
+        let mut partitions = Partitions {
+            dir,
+            writers: Vec::with_capacity(count),
+        };
+        for i in 0..count {
+            let file = File::create(partitions.path(i))?;
+            partitions.writers.push(Mutex::new(BufWriter::new(file)));
+        }
+        Ok(partitions)
+    }
+
+    fn path(&self, i: usize) -> PathBuf {
+        self.dir.join(format!("{i:05}.bin"))
+    }
+
+    fn partition(&self, hash: u64) -> usize {
+        ((hash >> 32) % self.writers.len() as u64) as usize
+    }
+
+    /// Append the records grouped by partition to their files, leaving the
+    /// groups empty.
+    fn write(&self, records: &mut [Vec<u8>]) -> io::Result<()> {
+        for (writer, record) in self.writers.iter().zip(records) {
+            if !record.is_empty() {
+                writer.lock().unwrap().write_all(record)?;
+                record.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Partitions {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Chunk the files in parallel spilling their chunks to `partitions` files
+/// in a temporary directory under `dir` in batches as they are cut, so the
+/// memory holds a batch per worker however large the files, then count the
+/// unique chunks of one partition at a time. Returns the exact (total, unique,
+/// compressed) bytes like `ChunkStore::stats` and the `sniff` verdict of every
+/// file. `partitions` must be positive. The files are chunked with `params`.
+/// Every file done is reported to `progress`.
+pub(crate) fn count_files<P: AsRef<Path> + Send + Sync>(
+    paths: &[P],
+    dir: &Path,
+    partitions: usize,
+    params: ChunkParams,
+    progress: &Progress,
+) -> io::Result<((usize, usize, usize), Vec<Option<String>>)> {
+    let parts = Partitions::create(dir, partitions)?;
+    let files = paths
+        .par_iter()
+        .map(|path| {
+            // group the records by partition to lock every writer once per batch
+            let mut records: Vec<Vec<u8>> = vec![Vec::new(); partitions];
+            let mut buffered = 0;
+            let mut written = Ok(());
+            let file = store::stream_recipe(path, params, progress, |chunks| {
+                for &(hash, size, compressed) in chunks {
+                    let record = &mut records[parts.partition(hash)];
+                    record.extend_from_slice(&hash.to_le_bytes());
+                    record.extend_from_slice(&(size as u64).to_le_bytes());
+                    record.extend_from_slice(&(compressed as u64).to_le_bytes());
+                }
+                buffered += chunks.len();
+                if buffered >= BATCH_RECORDS && written.is_ok() {
+                    written = parts.write(&mut records);
+                    buffered = 0;
+                }
+            })?;
+            written?;
+            parts.write(&mut records)?;
+            progress.file_done(0)?;
+            Ok(file)
+        })
+        .collect::<io::Result<Vec<(usize, Option<String>)>>>()?;
+    let total = files.iter().map(|(total, _)| total).sum();
+    let sniffed = files.into_iter().map(|(_, sniffed)| sniffed).collect();
+    for writer in &parts.writers {
+        writer.lock().unwrap().flush()?;
+    }
+
+    let (mut unique, mut compressed) = (0, 0);
+    for i in 0..partitions {
+        let mut chunks: HashMap<u64, (usize, usize)> = HashMap::new();
+        let mut reader = BufReader::new(File::open(parts.path(i))?);
+        let mut record = [0u8; RECORD_SIZE];
+        loop {
+            match reader.read_exact(&mut record) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            let word = |i: usize| u64::from_le_bytes(record[i..i + 8].try_into().unwrap());
+            chunks
+                .entry(word(0))
+                .or_insert((word(8) as usize, word(16) as usize));
+        }
+        for (size, c) in chunks.into_values() {
+            unique += size;
+            compressed += c;
+        }
+    }
+    Ok(((total, unique, compressed), sniffed))
+}
@@ -1,33 +1,419 @@
-use gearhash::Hasher;
-use lz4_flex::block;
-use pyo3::IntoPyObject;
+use crate::bloom::BloomFilter;
+use crate::cache::RecipeCache;
+use crate::cdc::{parallel_cuts, Algorithm, Cutter};
+use crate::codec::Codec;
+use crate::decompress;
+use crate::fileutils::PageStream;
+use crate::index;
+use crate::progress::Progress;
+use crate::remote::{self, RemoteFile};
+use crate::sniff::{sniff, sniff_reader};
+use memmap2::Mmap;
+use pyo3::{FromPyObject, IntoPyObject};
 use rayon::prelude::*;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufRead, BufReader, Read};
+use std::ops::Range;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use xxhash_rust::xxh3::xxh3_64;
 
-const MASK: u64 = 0xffff000000000000;
-const MIN_LEN: usize = 65536 / 8;
-const MAX_LEN: usize = 65536 * 2;
-const READ_BUFFER_SIZE: usize = 1024 * 1024;
+pub(crate) const MASK: u64 = 0xffff000000000000;
+pub(crate) const MIN_LEN: usize = 65536 / 8;
+pub(crate) const MAX_LEN: usize = 65536 * 2;
+pub(crate) const READ_BUFFER_SIZE: usize = 1024 * 1024;
+const HASH_LIST_EXTENSION: &str = "hashes";
 
 #[derive(Debug, Clone, IntoPyObject)]
 pub(crate) struct Chunk {
-    size: usize,
-    compressed: usize,
-    seen_in: Vec<i64>,
-    first_seen_in: i64,
-    data: Option<Vec<u8>>,
+    pub(crate) size: usize,
+    pub(crate) compressed: usize,
+    pub(crate) seen_in: Vec<i64>,
+    pub(crate) first_seen_in: i64,
+    pub(crate) occurrences: usize,
+    pub(crate) data: Option<Vec<u8>>,
 }
 
+pub(crate) fn is_hash_list<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension() == Some(HASH_LIST_EXTENSION.as_ref())
+}
+
+/// Strategy deciding which file shared chunks are attributed to when merging:
+/// `"order"` (first file wins), `"mtime"` (oldest file wins), `"largest"`
+/// (largest file wins) or an explicit list of file indices in priority order.
+#[derive(Debug, Clone, FromPyObject)]
+pub(crate) enum Attribution {
+    Named(String),
+    Priority(Vec<usize>),
+}
+
+impl Attribution {
+    /// Resolve the strategy to the order in which the stores get merged.
+    pub fn priority<P: AsRef<Path>>(
+        &self,
+        paths: &[P],
+        stores: &[ChunkStore],
+    ) -> Result<Vec<usize>, std::io::Error> {
+        let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg);
+        let mut indices: Vec<usize> = (0..stores.len()).collect();
+        match self {
+            Attribution::Named(name) => match name.as_str() {
+                "order" => {}
+                "mtime" => {
+                    // objects of object stores have no metadata on disk
+                    let mtimes = paths
+                        .iter()
+                        .map(|p| {
+                            if remote::is_remote(p) {
+                                RemoteFile::last_modified(&p.as_ref().to_string_lossy())
+                            } else {
+                                p.as_ref().metadata()?.modified()
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    indices.sort_by_key(|&i| mtimes[i]);
+                }
+                "largest" => indices.sort_by_key(|&i| std::cmp::Reverse(stores[i].total)),
+                other => return Err(invalid(format!("Unknown attribution strategy: {other}"))),
+            },
+            Attribution::Priority(priority) => {
+                let mut seen = vec![false; stores.len()];
+                for &i in priority {
+                    if i >= stores.len() || seen[i] {
+                        return Err(invalid(format!("Invalid priority file index: {i}")));
+                    }
+                    seen[i] = true;
+                }
+                // files missing from the priority list come last in their order
+                indices = priority
+                    .iter()
+                    .copied()
+                    .chain(indices.into_iter().filter(|&i| !seen[i]))
+                    .collect();
+            }
+        }
+        Ok(indices)
+    }
+}
+
+/// Parameters of the content defined chunker: a boundary is cut where the
+/// rolling hash of the algorithm matches `mask`, once the chunk is at least
+/// `min_len` bytes long, and forced once it reaches `max_len` bytes. The
+/// defaults target 64 KiB chunks with gearhash, see the `cdc` module for the
+/// other algorithms. The `codec` estimates the compressed size of the unique
+/// chunks, see the `codec` module. With `decompress` compressed files are
+/// chunked decompressed, see the `decompress` module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ChunkParams {
+    pub mask: u64,
+    pub min_len: usize,
+    pub max_len: usize,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub codec: Codec,
+    #[serde(default)]
+    pub decompress: bool,
+}
+
+impl Default for ChunkParams {
+    fn default() -> Self {
+        ChunkParams {
+            mask: MASK,
+            min_len: MIN_LEN,
+            max_len: MAX_LEN,
+            algorithm: Algorithm::default(),
+            codec: Codec::default(),
+            decompress: false,
+        }
+    }
+}
+
+impl ChunkParams {
+    /// Derive the parameters from either a power of two target chunk size or
+    /// a mask, the target setting as many high bits of the mask as its log2.
+    /// The minimum and maximum default to 1/8 and twice the target like the
+    /// default parameters.
+    pub fn new(
+        target: Option<usize>,
+        min_len: Option<usize>,
+        max_len: Option<usize>,
+        mask: Option<u64>,
+        algorithm: Algorithm,
+    ) -> Result<Self, std::io::Error> {
+        let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg);
+        let mask = match (target, mask) {
+            (Some(_), Some(_)) => {
+                return Err(invalid(
+                    "Pass either a target chunk size or a chunk mask, not both".to_string(),
+                ))
+            }
+            (Some(target), None) => {
+                if !target.is_power_of_two() || !(64..=1 << 32).contains(&target) {
+                    return Err(invalid(format!(
+                        "Target chunk size must be a power of two between 64 bytes and 4 GiB, \
+                         got {target}"
+                    )));
+                }
+                !0u64 << (64 - target.ilog2())
+            }
+            (None, Some(mask)) if !(1..=32).contains(&mask.count_ones()) => {
+                return Err(invalid(format!(
+                    "Chunk mask must have between 1 and 32 bits set, got {mask:#x}"
+                )))
+            }
+            (None, Some(mask)) => mask,
+            (None, None) => MASK,
+        };
+        let target = 1usize << mask.count_ones();
+        let params = ChunkParams {
+            mask,
+            min_len: min_len.unwrap_or(target / 8),
+            max_len: max_len.unwrap_or(target * 2),
+            algorithm,
+            codec: Codec::default(),
+            decompress: false,
+        };
+        if params.max_len == 0 || params.min_len > params.max_len {
+            return Err(invalid(format!(
+                "Maximum chunk size must be positive and at least the minimum, got {} < {}",
+                params.max_len, params.min_len
+            )));
+        }
+        Ok(params)
+    }
+
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn with_decompress(mut self, decompress: bool) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// The average chunk size the mask aims at, also the size of the fixed
+    /// blocks.
+    pub fn target(&self) -> usize {
+        1 << self.mask.count_ones()
+    }
+}
+
+/// Incremental content defined chunker fed with consecutive blocks of a
+/// stream, calling `emit` with every complete chunk.
+pub(crate) struct Chunker {
+    cutter: Cutter,
+    chunk: Vec<u8>,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Chunker::new(ChunkParams::default())
+    }
+}
+
+impl Chunker {
+    pub fn new(params: ChunkParams) -> Self {
+        Chunker {
+            cutter: Cutter::new(params),
+            chunk: Vec::with_capacity(params.max_len),
+        }
+    }
+
+    pub fn feed<F: FnMut(&[u8])>(&mut self, data: &[u8], mut emit: F) {
+        let mut start = 0;
+        while let Some(size) = self.cutter.next_cut(&data[start..], self.chunk.len()) {
+            self.chunk.extend_from_slice(&data[start..start + size]);
+            start += size;
+            emit(&self.chunk);
+            self.chunk.clear();
+        }
+        self.chunk.extend_from_slice(&data[start..]);
+    }
+
+    /// Emit the remaining bytes as the last chunk, which may be empty.
+    pub fn finish<F: FnOnce(&[u8])>(self, emit: F) {
+        emit(&self.chunk);
+    }
+}
+
+/// Chunk a file without building a store, calling `emit` with every chunk
+/// including the possibly empty last one.
+pub(crate) fn chunk_file<P: AsRef<Path>, F: FnMut(&[u8])>(
+    path: P,
+    emit: F,
+) -> Result<(), std::io::Error> {
+    chunk_file_with_params(path, ChunkParams::default(), emit)
+}
+
+/// Like `chunk_file` with the given chunker parameters, with
+/// `params.decompress` compressed files are chunked decompressed.
+pub(crate) fn chunk_file_with_params<P: AsRef<Path>, F: FnMut(&[u8])>(
+    path: P,
+    params: ChunkParams,
+    mut emit: F,
+) -> Result<(), std::io::Error> {
+    let mut file = decompress::open(path, params.decompress)?;
+    let mut chunker = Chunker::new(params);
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        chunker.feed(&buffer[..bytes_read], &mut emit);
+    }
+    chunker.finish(emit);
+    Ok(())
+}
+
+/// Return the end offset and the hash of every chunk of a file cut with
+/// `params`, leaving out the empty last chunk.
+pub(crate) fn chunk_boundaries<P: AsRef<Path>>(
+    path: P,
+    params: ChunkParams,
+) -> Result<(Vec<u64>, Vec<u64>), std::io::Error> {
+    let (mut offsets, mut hashes) = (Vec::new(), Vec::new());
+    let mut offset = 0;
+    chunk_file_with_params(path, params, |chunk| {
+        if !chunk.is_empty() {
+            offset += chunk.len() as u64;
+            offsets.push(offset);
+            hashes.push(xxh3_64(chunk));
+        }
+    })?;
+    Ok((offsets, hashes))
+}
+
+/// Chunk a file like `ChunkStore::from_file_with_params` without building a
+/// store, calling `emit` with the (hash, size, compressed size) of the chunks
+/// cut out of every block read, so only a block is held in memory however
+/// large the file. Unlike a store, which compresses the first occurrence of
+/// every chunk, every occurrence is compressed. The blocks read are reported
+/// to `progress`. Returns the bytes of the file and its `sniff` verdict.
+pub(crate) fn stream_recipe<P, F>(
+    path: P,
+    params: ChunkParams,
+    progress: &Progress,
+    mut emit: F,
+) -> Result<(usize, Option<String>), std::io::Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&[(u64, usize, usize)]),
+{
+    if is_hash_list(&path) {
+        // hash lists are already chunked, their records are kept as is
+        let store = ChunkStore::from_hash_list(BufReader::new(File::open(path)?))?;
+        emit(&store.recipe());
+        return Ok((store.total(), None));
+    }
+    let (sniffed, mut reader) = sniff_reader(decompress::open(path, params.decompress)?)?;
+    let compress = sniffed.is_none();
+    let record = |chunk: &[u8]| {
+        let compressed = if compress {
+            params.codec.compressed_len(chunk)
+        } else {
+            chunk.len()
+        };
+        (xxh3_64(chunk), chunk.len(), compressed)
+    };
+    let mut chunker = Chunker::new(params);
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    let mut chunks = Vec::new();
+    let mut total = 0;
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        progress.add_bytes(bytes_read)?;
+        total += bytes_read;
+        chunker.feed(&buffer[..bytes_read], |chunk| chunks.push(record(chunk)));
+        emit(&chunks);
+        chunks.clear();
+    }
+    chunker.finish(|chunk| chunks.push(record(chunk)));
+    emit(&chunks);
+    Ok((total, sniffed))
+}
+
+/// Payloads of the unique chunks of the stores chunked for one merge, keyed
+/// by hash, so a chunk contained in many inputs is kept once instead of once
+/// per store.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PayloadPool(Arc<Mutex<HashMap<u64, Vec<u8>>>>);
+
 #[derive(Debug, Default)]
 pub(crate) struct ChunkStore {
     total: usize,
     order: Vec<u64>,
     chunks: HashMap<u64, Chunk>,
     store_data: bool,
+    compress: bool,
+    /// Why the input looks already compressed or encrypted, its chunks then
+    /// being counted uncompressed, see the `sniff` module.
+    sniffed: Option<String>,
+    params: ChunkParams,
+    payloads: Option<PayloadPool>,
+    /// The range of `order` holding the chunks of each input of a merged
+    /// store, None for the inputs read back from an index.
+    spans: Vec<Option<Range<usize>>>,
+}
+
+/// The chunks of input `index` of a merge, attributed to it.
+fn attributed(chunks: impl Iterator<Item = (u64, Chunk)>, index: usize) -> HashMap<u64, Chunk> {
+    chunks
+        .map(|(hash, mut chunk)| {
+            chunk.seen_in = vec![index as i64];
+            chunk.first_seen_in = index as i64;
+            (hash, chunk)
+        })
+        .collect()
+}
+
+/// Merge the chunks of the inputs in parallel, as a tree of pairwise merges
+/// moving the entries of the smaller map into the larger one. Whatever the
+/// order of the merges, a chunk is attributed to the first of its inputs in
+/// `priority` order, and its `seen_in` inputs end up sorted.
+fn merge_maps(maps: Vec<HashMap<u64, Chunk>>, priority: &[usize]) -> HashMap<u64, Chunk> {
+    let mut ranks = vec![0; priority.len()];
+    for (rank, &index) in priority.iter().enumerate() {
+        ranks[index] = rank;
+    }
+    let rank = |chunk: &Chunk| ranks[chunk.first_seen_in as usize];
+    let mut merged = maps.into_par_iter().reduce(HashMap::new, |mut a, mut b| {
+        if a.len() < b.len() {
+            std::mem::swap(&mut a, &mut b);
+        }
+        for (hash, chunk) in b {
+            match a.entry(hash) {
+                Entry::Vacant(entry) => {
+                    entry.insert(chunk);
+                }
+                Entry::Occupied(mut entry) => {
+                    let entry = entry.get_mut();
+                    if rank(&chunk) < rank(entry) {
+                        entry.first_seen_in = chunk.first_seen_in;
+                    }
+                    entry.occurrences += chunk.occurrences;
+                    entry.seen_in.extend(chunk.seen_in);
+                    if entry.data.is_none() {
+                        entry.data = chunk.data;
+                    }
+                }
+            }
+        }
+        a
+    });
+    merged
+        .par_iter_mut()
+        .for_each(|(_, chunk)| chunk.seen_in.sort_unstable());
+    merged
 }
 
 impl ChunkStore {
@@ -37,14 +423,99 @@ impl ChunkStore {
             order: Vec::new(),
             chunks: HashMap::new(),
             store_data,
+            compress: true,
+            sniffed: None,
+            params: ChunkParams::default(),
+            payloads: None,
+            spans: Vec::new(),
         }
     }
 
+    /// Record the `sniff` verdict of the input, skipping the compression of
+    /// its chunks if it looks already compressed or encrypted.
+    pub fn set_sniffed(&mut self, sniffed: Option<String>) {
+        self.compress = sniffed.is_none();
+        self.sniffed = sniffed;
+    }
+
+    pub fn sniffed(&self) -> Option<&str> {
+        self.sniffed.as_deref()
+    }
+
+    /// Set the chunker parameters used by the following `chunk_stream` calls.
+    pub fn set_params(&mut self, params: ChunkParams) {
+        self.params = params;
+    }
+
+    pub fn params(&self) -> ChunkParams {
+        self.params
+    }
+
+    /// Rebuild a merged store of `inputs` inputs read back from a saved
+    /// index, without their chunk order.
+    pub fn from_index(
+        total: usize,
+        chunks: HashMap<u64, Chunk>,
+        params: ChunkParams,
+        inputs: usize,
+    ) -> Self {
+        ChunkStore {
+            total,
+            order: Vec::new(),
+            chunks,
+            store_data: false,
+            compress: true,
+            sniffed: None,
+            params,
+            payloads: None,
+            spans: vec![None; inputs],
+        }
+    }
+
+    /// Move the chunk payloads of this store to the `pool` shared by the
+    /// stores of a merge, dropping the ones it already holds. `merge` moves
+    /// them on to the merged chunks.
+    pub fn share_payloads(&mut self, pool: &PayloadPool) {
+        let mut payloads = pool.0.lock().unwrap();
+        for (hash, chunk) in &mut self.chunks {
+            if let Some(data) = chunk.data.take() {
+                payloads.entry(*hash).or_insert(data);
+            }
+        }
+        self.payloads = Some(pool.clone());
+    }
+
+    /// Save the chunks of this merged store and the labels of its inputs,
+    /// see the `index` module for the format.
+    pub fn save<P: AsRef<Path>>(&self, labels: &[String], path: P) -> std::io::Result<()> {
+        index::write(self, labels, path)
+    }
+
+    /// Load a store saved with `save`, returning it with its input labels.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<(Self, Vec<String>)> {
+        index::read(path)
+    }
+
     pub fn add(&mut self, chunk: &[u8]) {
+        self.insert(chunk);
+    }
+
+    /// Like `add`, returning the hash of the chunk and whether it is new to
+    /// the store.
+    pub fn insert(&mut self, chunk: &[u8]) -> (u64, bool) {
         let hash = xxh3_64(chunk);
-        let comp = block::compress(chunk);
         self.total += chunk.len();
         self.order.push(hash);
+        if let Some(existing) = self.chunks.get_mut(&hash) {
+            existing.occurrences += 1;
+            return (hash, false);
+        }
+
+        let compressed = if self.compress {
+            self.params.codec.compressed_len(chunk)
+        } else {
+            chunk.len()
+        };
 
         let data = if self.store_data {
             Some(chunk.to_vec())
@@ -54,87 +525,839 @@ impl ChunkStore {
 
         let chunk = Chunk {
             size: chunk.len(),
-            compressed: comp.len(),
+            compressed,
             seen_in: vec![],
             first_seen_in: 0,
+            occurrences: 1,
             data,
         };
         self.chunks.insert(hash, chunk);
+        (hash, true)
+    }
+
+    /// Add a chunk known only by its hash and size, e.g. produced by another
+    /// tool; such chunks are assumed to be incompressible.
+    pub fn add_hashed(&mut self, hash: u64, size: usize) {
+        self.add_cached(hash, size, size);
+    }
+
+    /// Add a chunk whose hash and compressed size are already known, e.g.
+    /// from a cached recipe of an unchanged file.
+    pub fn add_cached(&mut self, hash: u64, size: usize, compressed: usize) {
+        self.total += size;
+        self.order.push(hash);
+        self.chunks
+            .entry(hash)
+            .and_modify(|existing| existing.occurrences += 1)
+            .or_insert(Chunk {
+                size,
+                compressed,
+                seen_in: vec![],
+                first_seen_in: 0,
+                occurrences: 1,
+                data: None,
+            });
+    }
+
+    /// Build a store from a list of pre-computed `<hash> <size>` records, one
+    /// per line and separated by whitespace or a comma. Hashes of any length
+    /// are accepted and mapped to 64 bits, blank lines and lines starting with
+    /// `#` are skipped.
+    pub fn from_hash_list<R: BufRead>(reader: R) -> Result<Self, std::io::Error> {
+        let mut store = ChunkStore::new(false);
+        for (lineno, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Invalid hash list record on line {}: {line}", lineno + 1),
+                )
+            };
+            let mut fields = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|f| !f.is_empty());
+            let (Some(hash), Some(size), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let size = size.parse::<usize>().map_err(|_| invalid())?;
+            store.add_hashed(xxh3_64(hash.to_lowercase().as_bytes()), size);
+        }
+        Ok(store)
     }
 
     pub fn from_stream<R: Read>(reader: &mut R, store_data: bool) -> Result<Self, std::io::Error> {
+        Self::from_stream_with(reader, store_data, true)
+    }
+
+    /// Chunk a stream, optionally skipping the per-chunk compression in which
+    /// case the compressed size equals the chunk size.
+    pub fn from_stream_with<R: Read>(
+        reader: &mut R,
+        store_data: bool,
+        compress: bool,
+    ) -> Result<Self, std::io::Error> {
+        let mut store = ChunkStore::new(store_data);
+        store.compress = compress;
+        store.chunk_stream(reader, &Progress::default())?;
+        Ok(store)
+    }
+
+    /// Chunk a stream with the given chunker parameters, sniffing its first
+    /// bytes like `from_file_with_params`. Every block read is reported to
+    /// `progress`.
+    pub fn from_stream_with_params<R: Read>(
+        reader: &mut R,
+        store_data: bool,
+        params: ChunkParams,
+        progress: &Progress,
+    ) -> Result<Self, std::io::Error> {
+        let (sniffed, mut reader) = sniff_reader(reader)?;
+        let mut store = ChunkStore::new(store_data);
+        store.set_sniffed(sniffed);
+        store.params = params;
+        store.chunk_stream(&mut reader, progress)?;
+        Ok(store)
+    }
+
+    /// Chunk an in-memory buffer, cutting the chunks straight out of it with
+    /// the same boundaries as `chunk_stream` instead of copying it first. The
+    /// buffer is cut, and its chunks hashed and compressed, on all the cores,
+    /// so a single huge input isn't bound to one of them. The chunks hashed
+    /// are reported to `progress`.
+    pub fn from_slice(
+        data: &[u8],
+        store_data: bool,
+        params: ChunkParams,
+        progress: &Progress,
+    ) -> Result<Self, std::io::Error> {
         let mut store = ChunkStore::new(store_data);
-        let mut hasher = Hasher::default();
+        store.set_sniffed(sniff(&mut &data[..])?);
+        store.params = params;
+        let cuts = parallel_cuts(data, params);
+        let bounds: Vec<(usize, usize)> = std::iter::once(0)
+            .chain(cuts.iter().copied())
+            .zip(cuts.iter().copied().chain(std::iter::once(data.len())))
+            .collect();
+        if store_data {
+            for &(start, end) in &bounds {
+                store.add(&data[start..end]);
+                progress.add_bytes(end - start)?;
+            }
+            return Ok(store);
+        }
+        let hashes: Vec<u64> = bounds
+            .par_iter()
+            .map(|&(start, end)| {
+                progress.add_bytes(end - start)?;
+                Ok(xxh3_64(&data[start..end]))
+            })
+            .collect::<Result<_, std::io::Error>>()?;
+        // only the first occurrence of every chunk gets compressed
+        let compress = store.compress;
+        let mut seen = HashSet::new();
+        let firsts: Vec<usize> = (0..bounds.len())
+            .filter(|&i| seen.insert(hashes[i]))
+            .collect();
+        let compressed: HashMap<u64, usize> = firsts
+            .par_iter()
+            .map(|&i| {
+                progress.check()?;
+                let chunk = &data[bounds[i].0..bounds[i].1];
+                let compressed = if compress {
+                    params.codec.compressed_len(chunk)
+                } else {
+                    chunk.len()
+                };
+                Ok((hashes[i], compressed))
+            })
+            .collect::<Result<_, std::io::Error>>()?;
+        for (&(start, end), hash) in bounds.iter().zip(hashes) {
+            store.add_cached(hash, end - start, compressed[&hash]);
+        }
+        Ok(store)
+    }
+
+    /// Chunk a stream appending the chunks to this store, the stream must
+    /// start at a chunk boundary. Every block read is reported to `progress`,
+    /// so a cancelled scan stops within a block.
+    pub fn chunk_stream<R: Read>(
+        &mut self,
+        reader: &mut R,
+        progress: &Progress,
+    ) -> Result<(), std::io::Error> {
+        let mut chunker = Chunker::new(self.params);
         let mut buffer = [0; READ_BUFFER_SIZE];
-        let mut chunk = Vec::<u8>::with_capacity(MAX_LEN);
 
         loop {
             let bytes_read = reader.read(&mut buffer)?;
             if bytes_read == 0 {
                 break;
             }
-
-            let mut start = 0;
-            while let Some(size) = hasher.next_match(&buffer[start..bytes_read], MASK) {
-                chunk.extend_from_slice(&buffer[start..start + size]);
-                start += size;
-
-                // TODO(kszucs): MAX_LEN is not implemented yet
-                if chunk.len() >= MIN_LEN {
-                    store.add(&chunk);
-                    chunk.clear();
-                }
-            }
-            chunk.extend_from_slice(&buffer[start..bytes_read]);
+            chunker.feed(&buffer[..bytes_read], |chunk| self.add(chunk));
+            progress.add_bytes(bytes_read)?;
         }
+        chunker.finish(|chunk| self.add(chunk));
 
-        // add remaining as last chunk
-        store.add(&chunk);
+        Ok(())
+    }
 
+    /// Chunk the file, or load it as a hash list if it has the `.hashes`
+    /// extension. Inputs which look already compressed or encrypted skip the
+    /// per-chunk compression since it wouldn't gain anything.
+    pub fn from_file<P: AsRef<Path>>(path: P, store_data: bool) -> Result<Self, std::io::Error> {
+        Self::from_file_with_params(
+            path,
+            store_data,
+            ChunkParams::default(),
+            &Progress::default(),
+        )
+    }
+
+    /// Like `from_file` with the given chunker parameters, which don't apply
+    /// to hash lists since they are already chunked. With `params.decompress`
+    /// gzip, zstd and xz compressed files are chunked decompressed. Every
+    /// block read is reported to `progress`, a hash list once loaded.
+    pub fn from_file_with_params<P: AsRef<Path>>(
+        path: P,
+        store_data: bool,
+        params: ChunkParams,
+        progress: &Progress,
+    ) -> Result<Self, std::io::Error> {
+        if is_hash_list(&path) {
+            let store = Self::from_hash_list(BufReader::new(File::open(path)?))?;
+            progress.add_bytes(store.total())?;
+            return Ok(store);
+        }
+        let (sniffed, mut reader) = sniff_reader(decompress::open(path, params.decompress)?)?;
+        let mut store = ChunkStore::new(store_data);
+        store.set_sniffed(sniffed);
+        store.params = params;
+        store.chunk_stream(&mut reader, progress)?;
         Ok(store)
     }
 
-    pub fn from_file<P: AsRef<Path>>(path: P, store_data: bool) -> Result<Self, std::io::Error> {
+    /// Like `from_file_with_params` over a memory map of the file, cutting the
+    /// chunks straight out of the page cache instead of copying the file
+    /// through a read buffer and the chunk buffer first, which is noticeably
+    /// faster for multi-GB files on fast storage, and cutting them on all the
+    /// cores, see `from_slice`. The chunks are the same. Decompressed files
+    /// and objects of object stores are read instead since their stream
+    /// can't be mapped.
+    pub fn from_file_mmap<P: AsRef<Path>>(
+        path: P,
+        store_data: bool,
+        params: ChunkParams,
+        progress: &Progress,
+    ) -> Result<Self, std::io::Error> {
+        if is_hash_list(&path)
+            || remote::is_remote(&path)
+            || (params.decompress && decompress::detect(&path)?.is_some())
+        {
+            return Self::from_file_with_params(path, store_data, params, progress);
+        }
         let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
+        // SAFETY: the map is read-only and dropped before returning; like with
+        // buffered reads, a file truncated by another process meanwhile is
+        // undefined, here a SIGBUS instead of a short read.
+        let map = unsafe { Mmap::map(&file)? };
+        Self::from_slice(&map, store_data, params, progress)
+    }
+
+    /// Chunk the decompressed page payloads of a parquet file instead of its
+    /// raw bytes.
+    pub fn from_parquet_pages<P: AsRef<Path>>(
+        path: P,
+        store_data: bool,
+    ) -> Result<Self, std::io::Error> {
+        let mut reader = PageStream::open(path)?;
         Self::from_stream(&mut reader, store_data)
     }
 
+    /// Chunk the decompressed page payloads of a single leaf column of a
+    /// parquet file across all of its row groups.
+    pub fn from_parquet_column<P: AsRef<Path>>(
+        path: P,
+        column: usize,
+        store_data: bool,
+    ) -> Result<Self, std::io::Error> {
+        let mut reader = PageStream::open_column(path, column)?;
+        Self::from_stream(&mut reader, store_data)
+    }
+
+    pub fn from_parquet_files<P: AsRef<Path> + Send + Sync>(
+        paths: &[P],
+        store_data: bool,
+    ) -> Result<Vec<Self>, std::io::Error> {
+        paths
+            .par_iter()
+            .map(|path| ChunkStore::from_parquet_pages(path, store_data))
+            .collect()
+    }
+
+    /// Chunk the files reusing the recipes of unchanged or appended files from
+    /// the cache, and update the cache with the new recipes. The blocks read
+    /// and every file done are reported to `progress`.
+    pub fn from_files_cached<P: AsRef<Path> + Send + Sync>(
+        paths: &[P],
+        cache: &mut RecipeCache,
+        progress: &Progress,
+    ) -> Result<Vec<Self>, std::io::Error> {
+        let results = paths
+            .par_iter()
+            .map(|path| {
+                let (store, recipe) = cache.chunk_file(path, progress)?;
+                progress.file_done(0)?;
+                Ok((store, recipe))
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        let mut stores = Vec::with_capacity(results.len());
+        for (path, (store, recipe)) in paths.iter().zip(results) {
+            if let Some(recipe) = recipe {
+                cache.insert(path.as_ref().to_string_lossy().into_owned(), recipe);
+            }
+            stores.push(store);
+        }
+        Ok(stores)
+    }
+
+    /// Chunk the files in parallel, with `store_data` their payloads go to a
+    /// shared `PayloadPool` as soon as each file is chunked.
     pub fn from_files<P: AsRef<Path> + Send + Sync>(
         paths: &[P],
         store_data: bool,
     ) -> Result<Vec<Self>, std::io::Error> {
+        let pool = PayloadPool::default();
         paths
             .par_iter()
             //.progress_count(paths.len() as u64)
-            .map(|path| ChunkStore::from_file(path, store_data))
+            .map(|path| {
+                let mut store = ChunkStore::from_file(path, store_data)?;
+                if store_data {
+                    store.share_payloads(&pool);
+                }
+                Ok(store)
+            })
+            .collect()
+    }
+
+    /// Chunk the files in parallel like `from_files`, calling `on_chunked`
+    /// with each store as soon as its file is done, e.g. to write its images
+    /// while the remaining files are still being chunked. With `mmap` the
+    /// files are memory mapped instead of read, see `from_file_mmap`. The
+    /// bytes chunked are reported to `progress`, the files are left to
+    /// `on_chunked`.
+    pub fn from_files_with<P, F>(
+        paths: &[P],
+        store_data: bool,
+        params: ChunkParams,
+        mmap: bool,
+        progress: &Progress,
+        on_chunked: F,
+    ) -> Result<Vec<Self>, std::io::Error>
+    where
+        P: AsRef<Path> + Send + Sync,
+        F: Fn(&P, &ChunkStore) -> Result<(), std::io::Error> + Sync,
+    {
+        paths
+            .par_iter()
+            .map(|path| {
+                let store = if mmap {
+                    ChunkStore::from_file_mmap(path, store_data, params, progress)?
+                } else {
+                    ChunkStore::from_file_with_params(path, store_data, params, progress)?
+                };
+                on_chunked(path, &store)?;
+                Ok(store)
+            })
             .collect()
     }
 
+    /// Chunk a single stream holding several logical files back to back, e.g.
+    /// tar members or multipart bodies, into one store per member without
+    /// reopening the source. `boundaries` are the ascending start offsets of
+    /// the members and bytes before the first one are skipped. Every member
+    /// extends to the next one, or with `ends` to its end offset, the bytes
+    /// up to the next member, e.g. tar headers and padding, being skipped.
+    /// Every member starts a new chunk, so each chunk is attributed to
+    /// exactly one member. The members are sniffed and chunked with `params`
+    /// like files, with `params.decompress` the compressed ones decompressed.
+    pub fn from_concatenated<R: Read + Send>(
+        reader: &mut R,
+        boundaries: &[u64],
+        ends: Option<&[u64]>,
+        store_data: bool,
+        params: ChunkParams,
+    ) -> Result<Vec<Self>, std::io::Error> {
+        let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg);
+        if boundaries.windows(2).any(|w| w[0] > w[1]) {
+            return Err(invalid("member boundaries must be in ascending order"));
+        }
+        if let Some(ends) = ends {
+            let next = boundaries.iter().skip(1).map(Some).chain([None]);
+            let valid = ends.len() == boundaries.len()
+                && boundaries
+                    .iter()
+                    .zip(ends)
+                    .zip(next)
+                    .all(|((start, end), next)| start <= end && next.is_none_or(|n| end <= n));
+            if !valid {
+                return Err(invalid(
+                    "member ends must follow their start and precede the next member",
+                ));
+            }
+        }
+        let mut stores = Vec::with_capacity(boundaries.len());
+        let mut position = 0;
+        for (i, &start) in boundaries.iter().enumerate() {
+            std::io::copy(
+                &mut reader.by_ref().take(start - position),
+                &mut std::io::sink(),
+            )?;
+            let end = match ends {
+                Some(ends) => Some(ends[i]),
+                None => boundaries.get(i + 1).copied(),
+            };
+            let mut store = ChunkStore::new(store_data);
+            store.params = params;
+            match end {
+                Some(end) => {
+                    let mut member = reader.by_ref().take(end - start);
+                    store.chunk_member(&mut member)?;
+                    // a decompressed member may end before its bytes do
+                    std::io::copy(&mut member, &mut std::io::sink())?;
+                }
+                None => store.chunk_member(&mut *reader)?,
+            }
+            position = end.unwrap_or(start);
+            stores.push(store);
+        }
+        Ok(stores)
+    }
+
+    /// Sniff and chunk a member of `from_concatenated` with the parameters of
+    /// the store.
+    fn chunk_member<R: Read + Send>(&mut self, member: R) -> Result<(), std::io::Error> {
+        let member: Box<dyn Read + Send + '_> = if self.params.decompress {
+            decompress::decompressed(member)?
+        } else {
+            Box::new(member)
+        };
+        let (sniffed, mut member) = sniff_reader(member)?;
+        self.set_sniffed(sniffed);
+        self.chunk_stream(&mut member, &Progress::default())
+    }
+
     pub fn merge(stores: &mut [ChunkStore], store_data: bool) -> Self {
-        let mut merged = ChunkStore::new(store_data);
+        let priority: Vec<usize> = (0..stores.len()).collect();
+        Self::merge_with_priority(stores, store_data, &priority)
+    }
 
-        for (index, store) in stores.iter_mut().enumerate() {
-            merged.total += store.total;
-            merged.order.extend(store.order.iter());
-            for (hash, chunk) in &mut store.chunks {
-                let entry = merged.chunks.entry(*hash).or_insert_with(|| {
-                    chunk.first_seen_in = index as i64;
-                    chunk.clone()
+    /// Merge the stores visiting them in the given priority order, so shared
+    /// chunks are attributed to the first store in that order. File indices
+    /// and the chunk order still follow the original store order. The chunk
+    /// payloads are moved out of the stores, and out of their pool, into the
+    /// merged chunks, the copies of other stores are dropped. The stores keep
+    /// their chunks, attributed like the merged ones, e.g. to render them.
+    pub fn merge_with_priority(
+        stores: &mut [ChunkStore],
+        store_data: bool,
+        priority: &[usize],
+    ) -> Self {
+        let mut merged = ChunkStore::new(store_data);
+        merged.concat_order(stores);
+        let pools: Vec<PayloadPool> = stores
+            .iter_mut()
+            .filter_map(|s| s.payloads.take())
+            .collect();
+        let maps = stores
+            .par_iter_mut()
+            .enumerate()
+            .map(|(index, store)| {
+                let chunks = store.chunks.iter_mut().map(|(hash, chunk)| {
+                    let entry = Chunk {
+                        seen_in: Vec::new(),
+                        data: chunk.data.take(),
+                        ..*chunk
+                    };
+                    (*hash, entry)
                 });
-                entry.seen_in.push(index as i64);
+                attributed(chunks, index)
+            })
+            .collect();
+        merged.chunks = merge_maps(maps, priority);
+        merged.drain_pools(pools);
+        // propagate the attribution to the per-file stores used for rendering
+        stores.par_iter_mut().for_each(|store| {
+            for (hash, chunk) in &mut store.chunks {
+                chunk.first_seen_in = merged.chunks[hash].first_seen_in;
             }
-        }
+        });
+
+        merged
+    }
 
+    /// Merge the stores like `merge` but consuming them, their chunks are
+    /// moved into the merged store rather than copied, for the callers which
+    /// only need the merged store.
+    pub fn merge_owned(mut stores: Vec<ChunkStore>, store_data: bool) -> Self {
+        let mut merged = ChunkStore::new(store_data);
+        merged.concat_order(&stores);
+        let pools: Vec<PayloadPool> = stores
+            .iter_mut()
+            .filter_map(|s| s.payloads.take())
+            .collect();
+        let priority: Vec<usize> = (0..stores.len()).collect();
+        let maps = stores
+            .into_par_iter()
+            .enumerate()
+            .map(|(index, store)| attributed(store.chunks.into_iter(), index))
+            .collect();
+        merged.chunks = merge_maps(maps, &priority);
+        merged.drain_pools(pools);
         merged
     }
 
+    /// Append the chunk order of the stores to a merged store, one input each.
+    fn concat_order(&mut self, stores: &[ChunkStore]) {
+        for store in stores {
+            let start = self.order.len();
+            self.total += store.total;
+            self.order.extend(store.order.iter());
+            self.spans.push(Some(start..self.order.len()));
+        }
+    }
+
+    /// Move the payloads of the pools of the merged stores to the merged
+    /// chunks.
+    fn drain_pools(&mut self, pools: Vec<PayloadPool>) {
+        // the stores share a pool, the first one drains it
+        for pool in pools {
+            for (hash, data) in pool.0.lock().unwrap().drain() {
+                if let Some(chunk) = self.chunks.get_mut(&hash) {
+                    chunk.data.get_or_insert(data);
+                }
+            }
+        }
+    }
+
+    /// Merge the chunks of one new store into this merged store as input
+    /// `index`, moving them into it. The index may be lower than the ones
+    /// merged so far, e.g. for files chunked out of order, in which case the
+    /// chunks it shares are attributed to it and its chunk order is inserted
+    /// before theirs, so absorbing the stores in any order is the same as
+    /// merging them. The index must not be an input of the merged store
+    /// already.
+    pub fn absorb(&mut self, mut store: ChunkStore, index: usize) {
+        if self.spans.len() <= index {
+            self.spans.resize(index + 1, None);
+        }
+        let start = self.spans[..index]
+            .iter()
+            .flatten()
+            .map(|span| span.end)
+            .max()
+            .unwrap_or(0);
+        let len = store.order.len();
+        for span in self.spans[index + 1..].iter_mut().flatten() {
+            *span = span.start + len..span.end + len;
+        }
+        self.order.splice(start..start, store.order.drain(..));
+        self.spans[index] = Some(start..start + len);
+        self.total += store.total;
+        let index = index as i64;
+        for (hash, mut chunk) in store.chunks {
+            match self.chunks.entry(hash) {
+                Entry::Occupied(mut entry) => {
+                    let entry = entry.get_mut();
+                    entry.occurrences += chunk.occurrences;
+                    entry.first_seen_in = entry.first_seen_in.min(index);
+                    if let Err(i) = entry.seen_in.binary_search(&index) {
+                        entry.seen_in.insert(i, index);
+                    }
+                    if entry.data.is_none() {
+                        entry.data = chunk.data;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    chunk.first_seen_in = index;
+                    chunk.seen_in = vec![index];
+                    entry.insert(chunk);
+                }
+            }
+        }
+        if let Some(pool) = store.payloads.take() {
+            self.drain_pools(vec![pool]);
+        }
+    }
+
+    /// Merge a merged store of further inputs into this merged store, its
+    /// input `i` becoming input `offset + i`, the same as appending them one
+    /// at a time.
+    pub fn append_merged(&mut self, other: &mut ChunkStore, offset: usize) {
+        let start = self.order.len();
+        self.total += other.total;
+        self.order.extend(other.order.iter());
+        let spans = other.spans.iter().cloned();
+        self.spans
+            .extend(spans.map(|span| span.map(|s| s.start + start..s.end + start)));
+        for (hash, chunk) in &mut other.chunks {
+            let seen_in: Vec<i64> = chunk.seen_in.iter().map(|&i| i + offset as i64).collect();
+            match self.chunks.get_mut(hash) {
+                Some(entry) => {
+                    entry.occurrences += chunk.occurrences;
+                    entry.seen_in.extend(seen_in);
+                }
+                None => {
+                    let data = chunk.data.take();
+                    let entry = Chunk {
+                        data,
+                        seen_in,
+                        first_seen_in: chunk.first_seen_in + offset as i64,
+                        ..chunk.clone()
+                    };
+                    self.chunks.insert(*hash, entry);
+                }
+            }
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn unique_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// The number of unique chunks of exactly `size` bytes, e.g. the ones cut
+    /// at the maximum chunk length instead of a content defined boundary.
+    pub fn chunks_of_size(&self, size: usize) -> usize {
+        self.chunks
+            .values()
+            .filter(|chunk| chunk.size == size)
+            .count()
+    }
+
+    pub fn order(&self) -> &[u64] {
+        &self.order
+    }
+
+    pub fn iter_chunks(&self) -> impl Iterator<Item = (&u64, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    /// Iterate over the chunks in the order they appear in the inputs.
+    pub fn iter_ordered(&self) -> impl Iterator<Item = (u64, &Chunk)> {
+        self.order.iter().map(|hash| (*hash, &self.chunks[hash]))
+    }
+
     pub fn stats(&self) -> (usize, usize, usize) {
         let total_size = self.chunks.values().map(|chunk| chunk.size).sum();
         let total_compressed = self.chunks.values().map(|chunk| chunk.compressed).sum();
         (self.total, total_size, total_compressed)
     }
 
+    pub fn bloom_filter(&self, fpp: f64) -> std::io::Result<BloomFilter> {
+        let mut bloom = BloomFilter::new(self.chunks.len(), fpp)?;
+        for hash in self.chunks.keys() {
+            bloom.insert(*hash);
+        }
+        Ok(bloom)
+    }
+
+    /// Unique bytes and the part of them whose chunks are (probably) contained
+    /// in the other party's bloom filter.
+    pub fn matches_bloom(&self, bloom: &BloomFilter) -> (usize, usize) {
+        let mut unique = 0;
+        let mut matched = 0;
+        for (hash, chunk) in &self.chunks {
+            unique += chunk.size;
+            if bloom.contains(*hash) {
+                matched += chunk.size;
+            }
+        }
+        (unique, matched)
+    }
+
+    /// The unique chunks also contained in the other store, their count,
+    /// bytes and compressed bytes.
+    pub fn shared(&self, other: &ChunkStore) -> (usize, usize, usize) {
+        let (small, large) = if self.chunks.len() <= other.chunks.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        small
+            .chunks
+            .iter()
+            .filter(|(hash, _)| large.chunks.contains_key(hash))
+            .fold((0, 0, 0), |(chunks, bytes, compressed), (_, chunk)| {
+                (
+                    chunks + 1,
+                    bytes + chunk.size,
+                    compressed + chunk.compressed,
+                )
+            })
+    }
+
+    /// Occurrence-weighted bytes against unique bytes per power-of-two chunk
+    /// size bucket, returned as (bucket lower bound, total bytes, unique
+    /// bytes, unique chunks) in ascending bucket order.
+    pub fn duplication(&self) -> Vec<(usize, usize, usize, usize)> {
+        let mut buckets: BTreeMap<usize, (usize, usize, usize)> = BTreeMap::new();
+        for chunk in self.chunks.values() {
+            let bucket = if chunk.size == 0 {
+                0
+            } else {
+                1 << chunk.size.ilog2()
+            };
+            let entry = buckets.entry(bucket).or_default();
+            entry.0 += chunk.size * chunk.occurrences;
+            entry.1 += chunk.size;
+            entry.2 += 1;
+        }
+        buckets
+            .into_iter()
+            .map(|(bucket, (total, unique, count))| (bucket, total, unique, count))
+            .collect()
+    }
+
+    /// The reference counts of the unique chunks, returned as (occurrences,
+    /// unique chunks, unique bytes) in ascending occurrences order, and the
+    /// `top` chunks with the most occurrences as (hash, size, occurrences,
+    /// inputs containing it), the largest chunks first among equal counts.
+    #[allow(clippy::type_complexity)]
+    pub fn refcounts(
+        &self,
+        top: usize,
+    ) -> (
+        Vec<(usize, usize, usize)>,
+        Vec<(u64, usize, usize, Vec<i64>)>,
+    ) {
+        let mut counts: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+        for chunk in self.chunks.values() {
+            let entry = counts.entry(chunk.occurrences).or_default();
+            entry.0 += 1;
+            entry.1 += chunk.size;
+        }
+        let mut ranked: Vec<(&u64, &Chunk)> = self.chunks.iter().collect();
+        let key = |(hash, chunk): &(&u64, &Chunk)| {
+            (
+                std::cmp::Reverse(chunk.occurrences),
+                std::cmp::Reverse(chunk.size),
+                **hash,
+            )
+        };
+        if top < ranked.len() {
+            ranked.select_nth_unstable_by_key(top, key);
+            ranked.truncate(top);
+        }
+        ranked.sort_unstable_by_key(key);
+        let counts = counts
+            .into_iter()
+            .map(|(occurrences, (chunks, bytes))| (occurrences, chunks, bytes))
+            .collect();
+        let top = ranked
+            .into_iter()
+            .map(|(hash, chunk)| (*hash, chunk.size, chunk.occurrences, chunk.seen_in.clone()))
+            .collect();
+        (counts, top)
+    }
+
+    /// Split the unique bytes into the ones referenced only by the given files
+    /// (reclaimable by deleting them) and the ones shared with other files.
+    pub fn deletion_savings(&self, deleted: &[i64]) -> (usize, usize) {
+        let mut reclaimable = 0;
+        let mut shared = 0;
+        for chunk in self.chunks.values() {
+            if !chunk.seen_in.iter().any(|i| deleted.contains(i)) {
+                continue;
+            }
+            if chunk.seen_in.iter().all(|i| deleted.contains(i)) {
+                reclaimable += chunk.size;
+            } else {
+                shared += chunk.size;
+            }
+        }
+        (reclaimable, shared)
+    }
+
+    /// Unique bytes of the file at `index` of a merge whose chunks are
+    /// attributed to another file, i.e. deduplicated across files. Requires
+    /// the attribution propagated to the per-file stores by the merge.
+    pub fn cross_file_bytes(&self, index: i64) -> usize {
+        self.chunks
+            .values()
+            .filter(|chunk| chunk.first_seen_in != index)
+            .map(|chunk| chunk.size)
+            .sum()
+    }
+
+    /// The number of non-empty chunks of the store and the hex sha256 of
+    /// their hashes in order as little endian u64, the same digest as the one
+    /// of the `chunk_boundaries` hashes of the file.
+    pub fn chunk_digest(&self) -> (usize, String) {
+        let mut sha = Sha256::new();
+        let mut count = 0;
+        for hash in &self.order {
+            if self.chunks[hash].size > 0 {
+                sha.update(hash.to_le_bytes());
+                count += 1;
+            }
+        }
+        let digest = sha.finalize().iter().map(|b| format!("{b:02x}")).collect();
+        (count, digest)
+    }
+
+    /// The (total, exclusive, shared, compressed) bytes of every input store
+    /// of this merge: the exclusive bytes are the unique chunks of the input
+    /// no other input contains, the shared ones those also contained in an
+    /// earlier input and the compressed ones all its unique chunks
+    /// compressed.
+    pub fn breakdown(&self, stores: &[ChunkStore]) -> Vec<(usize, usize, usize, usize)> {
+        stores
+            .iter()
+            .enumerate()
+            .map(|(index, store)| {
+                let index = index as i64;
+                let (mut exclusive, mut shared) = (0, 0);
+                for hash in store.chunks.keys() {
+                    let chunk = &self.chunks[hash];
+                    if chunk.seen_in == [index] {
+                        exclusive += chunk.size;
+                    } else if chunk.seen_in[0] < index {
+                        shared += chunk.size;
+                    }
+                }
+                let (total, _, compressed) = store.stats();
+                (total, exclusive, shared, compressed)
+            })
+            .collect()
+    }
+
+    /// Unique bytes and chunk counts grouped by the exact set of files containing
+    /// them, ordered by descending size; the groups are disjoint and sum up to
+    /// the unique bytes.
+    pub fn overlaps(&self) -> Vec<(Vec<i64>, usize, usize)> {
+        let mut groups: HashMap<&[i64], (usize, usize)> = HashMap::new();
+        for chunk in self.chunks.values() {
+            let entry = groups.entry(chunk.seen_in.as_slice()).or_default();
+            entry.0 += chunk.size;
+            entry.1 += 1;
+        }
+        let mut result: Vec<(Vec<i64>, usize, usize)> = groups
+            .into_iter()
+            .map(|(seen_in, (size, count))| (seen_in.to_vec(), size, count))
+            .collect();
+        result.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        result
+    }
+
     pub fn segments(&self) -> Vec<usize> {
         self.order
             .iter()
@@ -142,6 +1365,124 @@ impl ChunkStore {
             .collect()
     }
 
+    /// Segment ids marking whether each chunk is the first occurrence within
+    /// the file (0) or a repeat of an earlier chunk of the same file (1).
+    pub fn self_segments(&self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        self.order
+            .iter()
+            .map(|hash| if seen.insert(hash) { 0 } else { 1 })
+            .collect()
+    }
+
+    /// The (input, offset, hash, chunk) of every chunk occurrence of a merged
+    /// store in input order, leaving out the inputs read back from an index
+    /// since their chunk order isn't known.
+    pub fn locations(&self) -> Vec<(usize, usize, u64, &Chunk)> {
+        let mut locations = Vec::with_capacity(self.order.len());
+        for (input, span) in self.spans.iter().enumerate() {
+            let Some(span) = span else {
+                continue;
+            };
+            let mut offset = 0;
+            for hash in &self.order[span.clone()] {
+                let chunk = &self.chunks[hash];
+                locations.push((input, offset, *hash, chunk));
+                offset += chunk.size;
+            }
+        }
+        locations
+    }
+
+    /// The chunk of input `index` of a merged store containing the byte at
+    /// `offset`, as its hash and the (input, offset, size) of every
+    /// occurrence of it in the inputs, in order. None if the offset is past
+    /// the end of the input. The occurrences are found by walking the chunk
+    /// order of all the inputs, the ones read back from an index are left
+    /// out since their order isn't known and querying one of them fails.
+    pub fn who_has(
+        &self,
+        index: usize,
+        offset: usize,
+    ) -> std::io::Result<Option<(u64, Vec<(usize, usize, usize)>)>> {
+        let span = match self.spans.get(index) {
+            Some(Some(span)) => span.clone(),
+            Some(None) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("The chunk order of input {index} read from an index isn't known"),
+                ))
+            }
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("No input {index} among the {} inputs", self.spans.len()),
+                ))
+            }
+        };
+        let mut start = 0;
+        let mut found = None;
+        for hash in &self.order[span] {
+            start += self.chunks[hash].size;
+            if offset < start {
+                found = Some(*hash);
+                break;
+            }
+        }
+        let Some(found) = found else {
+            return Ok(None);
+        };
+        let occurrences = self
+            .locations()
+            .into_iter()
+            .filter(|&(_, _, hash, _)| hash == found)
+            .map(|(input, offset, _, chunk)| (input, offset, chunk.size))
+            .collect();
+        Ok(Some((found, occurrences)))
+    }
+
+    /// Pairs of byte offsets where the same chunk appears in both stores,
+    /// returned as (offset in self, offset in other, chunk size).
+    pub fn matches(&self, other: &ChunkStore) -> Vec<(usize, usize, usize)> {
+        let mut offsets: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut offset = 0;
+        for hash in &self.order {
+            offsets.entry(*hash).or_default().push(offset);
+            offset += self.chunks[hash].size;
+        }
+
+        let mut result = Vec::new();
+        let mut other_offset = 0;
+        for hash in &other.order {
+            let size = other.chunks[hash].size;
+            if let Some(self_offsets) = offsets.get(hash) {
+                for &self_offset in self_offsets {
+                    result.push((self_offset, other_offset, size));
+                }
+            }
+            other_offset += size;
+        }
+        result
+    }
+
+    pub fn sizes(&self) -> Vec<usize> {
+        self.order
+            .iter()
+            .map(|hash| self.chunks[hash].size)
+            .collect()
+    }
+
+    /// The (hash, size, compressed size) of the chunks in order.
+    pub fn recipe(&self) -> Vec<(u64, usize, usize)> {
+        self.order
+            .iter()
+            .map(|hash| {
+                let chunk = &self.chunks[hash];
+                (*hash, chunk.size, chunk.compressed)
+            })
+            .collect()
+    }
+
     pub fn chunks(&self) -> Vec<(u64, Chunk)> {
         self.order
             .iter()
@@ -0,0 +1,97 @@
+//! Secondary pass over the unique chunks estimating the redundancy left
+//! inside them, i.e. whether a smaller target chunk size would deduplicate
+//! more of the data.
+
+use crate::store::ChunkStore;
+use gearhash::Hasher;
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Redundancy of the unique chunks when cut into smaller sub-chunks.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SubChunkStats {
+    pub sub_chunks: usize,
+    pub unique_sub_chunks: usize,
+    pub chunk_bytes: usize,
+    pub redundant_bytes: usize,
+}
+
+/// Content defined chunker with the same 1/8 minimum to average size ratio
+/// as the main chunker, but a much smaller average size.
+struct SubChunker {
+    mask: u64,
+    min_len: usize,
+    seen: HashSet<u64>,
+    stats: SubChunkStats,
+}
+
+impl SubChunker {
+    fn new(avg_len: usize) -> Self {
+        SubChunker {
+            mask: !0u64 << (64 - avg_len.trailing_zeros()),
+            min_len: avg_len / 8,
+            seen: HashSet::new(),
+            stats: SubChunkStats::default(),
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        self.stats.chunk_bytes += data.len();
+        // every chunk is cut independently from a fresh rolling hash so the
+        // sub-chunks don't depend on the order the chunks are visited in
+        let mut hasher = Hasher::default();
+        let mut start = 0;
+        let mut pos = 0;
+        while let Some(size) = hasher.next_match(&data[pos..], self.mask) {
+            pos += size;
+            if pos - start >= self.min_len {
+                self.add(&data[start..pos]);
+                start = pos;
+            }
+        }
+        if start < data.len() {
+            self.add(&data[start..]);
+        }
+    }
+
+    fn add(&mut self, sub_chunk: &[u8]) {
+        self.stats.sub_chunks += 1;
+        if self.seen.insert(xxh3_64(sub_chunk)) {
+            self.stats.unique_sub_chunks += 1;
+        } else {
+            self.stats.redundant_bytes += sub_chunk.len();
+        }
+    }
+}
+
+/// Cut the unique chunks of the files into sub-chunks of each of the average
+/// sizes (powers of two) and count the bytes of the sub-chunks repeated
+/// within or across unique chunks. The files are chunked one at a time so
+/// only the data of a single file is held in memory.
+pub(crate) fn intra_chunk_redundancy<P: AsRef<Path>>(
+    paths: &[P],
+    avg_sizes: &[usize],
+) -> io::Result<Vec<SubChunkStats>> {
+    let mut seen = HashSet::new();
+    let mut chunkers: Vec<SubChunker> = avg_sizes.iter().map(|&s| SubChunker::new(s)).collect();
+    for path in paths {
+        let store = ChunkStore::from_file(path, true)?;
+        for (hash, chunk) in store.iter_ordered() {
+            if !seen.insert(hash) {
+                continue;
+            }
+            let Some(data) = &chunk.data else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "hash lists don't carry the chunk data",
+                ));
+            };
+            for chunker in &mut chunkers {
+                chunker.push(data);
+            }
+        }
+    }
+    Ok(chunkers.into_iter().map(|c| c.stats).collect())
+}
@@ -0,0 +1,134 @@
+//! Deduplication at several target chunk sizes from a single read of the
+//! files, for the curve of the dedup ratio against the chunk size.
+//!
+//! The gear hash of a position only depends on the bytes before it, never on
+//! where the previous chunk was cut, so one hash stream serves every target:
+//! each keeps its own chunk length and cuts where the hash matches its mask,
+//! within its minimum and maximum lengths, exactly like `Cutter` with the
+//! `gearhash` algorithm. The chunks are hashed incrementally, so a file is
+//! read once through a single buffer whatever the number of targets.
+
+use crate::store::{ChunkParams, READ_BUFFER_SIZE};
+use gearhash::DEFAULT_TABLE;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Mutex;
+use xxhash_rust::xxh3::Xxh3;
+
+/// Chunks of one target chunk size, totalled over the files.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SweepStats {
+    pub chunks: usize,
+    pub unique_chunks: usize,
+    pub chunk_bytes: usize,
+}
+
+/// The chunk being cut for one target in the file being read.
+struct Cut {
+    params: ChunkParams,
+    len: usize,
+    hasher: Xxh3,
+    /// The (hash, size) of the chunks of the file so far.
+    chunks: Vec<(u64, usize)>,
+}
+
+impl Cut {
+    fn new(params: ChunkParams) -> Self {
+        Cut {
+            params,
+            len: 0,
+            hasher: Xxh3::new(),
+            chunks: Vec::new(),
+        }
+    }
+
+    fn emit(&mut self) {
+        self.chunks.push((self.hasher.digest(), self.len));
+        self.hasher.reset();
+        self.len = 0;
+    }
+}
+
+/// The chunks of a file for every set of parameters, in order and leaving
+/// out the empty last ones.
+fn file_chunks<P: AsRef<Path>>(
+    path: P,
+    params: &[ChunkParams],
+) -> io::Result<Vec<Vec<(u64, usize)>>> {
+    let mut file = File::open(path)?;
+    let mut cuts: Vec<Cut> = params.iter().map(|&p| Cut::new(p)).collect();
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    let mut hash = 0u64;
+    // start of the current chunk of every target within the buffer
+    let mut starts = vec![0; cuts.len()];
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        let data = &buffer[..bytes_read];
+        starts.fill(0);
+        for (i, &b) in data.iter().enumerate() {
+            hash = (hash << 1).wrapping_add(DEFAULT_TABLE[b as usize]);
+            for (cut, start) in cuts.iter_mut().zip(&mut starts) {
+                cut.len += 1;
+                let matched = hash & cut.params.mask == 0 && cut.len >= cut.params.min_len;
+                if matched || cut.len == cut.params.max_len {
+                    cut.hasher.update(&data[*start..=i]);
+                    cut.emit();
+                    *start = i + 1;
+                }
+            }
+        }
+        for (cut, &start) in cuts.iter_mut().zip(&starts) {
+            cut.hasher.update(&data[start..]);
+        }
+    }
+    Ok(cuts
+        .into_iter()
+        .map(|mut cut| {
+            if cut.len > 0 {
+                cut.emit();
+            }
+            cut.chunks
+        })
+        .collect())
+}
+
+/// Chunk the files in parallel with every set of `params`, which must use
+/// the `gearhash` algorithm, folding the chunks of each file into a shared
+/// set of hashes per set of parameters. Returns the total bytes and the
+/// stats of every set of parameters.
+pub(crate) fn sweep_files<P: AsRef<Path> + Send + Sync>(
+    paths: &[P],
+    params: &[ChunkParams],
+) -> io::Result<(usize, Vec<SweepStats>)> {
+    let seen = Mutex::new(vec![(HashSet::new(), SweepStats::default()); params.len()]);
+    let total = paths
+        .par_iter()
+        .map(|path| {
+            let chunks = file_chunks(path, params)?;
+            let total = chunks
+                .first()
+                .map_or(0, |c| c.iter().map(|&(_, s)| s).sum());
+            let mut seen = seen.lock().unwrap();
+            for ((hashes, stats), chunks) in seen.iter_mut().zip(chunks) {
+                stats.chunks += chunks.len();
+                for (hash, size) in chunks {
+                    if hashes.insert(hash) {
+                        stats.unique_chunks += 1;
+                        stats.chunk_bytes += size;
+                    }
+                }
+            }
+            Ok(total)
+        })
+        .collect::<io::Result<Vec<usize>>>()?
+        .into_iter()
+        .sum();
+    let stats = seen.into_inner().unwrap().into_iter().map(|(_, s)| s);
+    Ok((total, stats.collect()))
+}
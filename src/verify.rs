@@ -0,0 +1,107 @@
+//! Lossless check of the chunking: an input is rebuilt from the payloads of
+//! the unique chunks of all the inputs, so a chunk may come from another
+//! file containing it, and compared byte for byte against its source.
+
+use crate::store::{chunk_file_with_params, ChunkParams};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Block size of the comparison of the rebuilt file with its source.
+const COMPARE_BLOCK_SIZE: usize = 1 << 20;
+
+/// Read as many bytes as fit in `buf` unless the reader ends first.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// The offset of the first byte differing between the two readers, the
+/// length of the shorter one if it is a prefix of the other, or None if
+/// they are identical.
+pub(crate) fn first_mismatch<A: Read, B: Read>(mut a: A, mut b: B) -> io::Result<Option<usize>> {
+    let (mut buf_a, mut buf_b) = (vec![0; COMPARE_BLOCK_SIZE], vec![0; COMPARE_BLOCK_SIZE]);
+    let mut offset = 0;
+    loop {
+        let len_a = read_full(&mut a, &mut buf_a)?;
+        let len_b = read_full(&mut b, &mut buf_b)?;
+        let len = len_a.min(len_b);
+        if let Some(i) = (0..len).find(|&i| buf_a[i] != buf_b[i]) {
+            return Ok(Some(offset + i));
+        }
+        if len_a != len_b {
+            return Ok(Some(offset + len));
+        }
+        if len == 0 {
+            return Ok(None);
+        }
+        offset += len;
+    }
+}
+
+/// Rebuild file `index` from the chunks of all the files into `output` and
+/// compare it with the file. Only the payloads of the chunks of that file are
+/// kept, taken from the first file containing them, so the memory holds at
+/// most the size of the file rather than all the unique chunks. Returns the
+/// number of bytes written and the offset of the first mismatch, None if the
+/// rebuilt file is identical.
+pub(crate) fn reconstruct<P: AsRef<Path> + Send + Sync, Q: AsRef<Path>>(
+    paths: &[P],
+    index: usize,
+    params: ChunkParams,
+    output: Q,
+) -> io::Result<(usize, Option<usize>)> {
+    let Some(source) = paths.get(index) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("No file {index} among the {} files", paths.len()),
+        ));
+    };
+    let mut order = Vec::new();
+    chunk_file_with_params(source, params, |chunk| order.push(xxh3_64(chunk)))?;
+    let needed: HashSet<u64> = order.iter().copied().collect();
+
+    // the payload of every needed chunk and the index of the file it was
+    // taken from
+    let payloads: Mutex<HashMap<u64, (usize, Vec<u8>)>> = Mutex::new(HashMap::new());
+    paths.par_iter().enumerate().try_for_each(|(i, path)| {
+        chunk_file_with_params(path, params, |chunk| {
+            let hash = xxh3_64(chunk);
+            if !needed.contains(&hash) {
+                return;
+            }
+            let mut payloads = payloads.lock().unwrap();
+            match payloads.get(&hash) {
+                Some(&(first, _)) if first <= i => {}
+                _ => {
+                    payloads.insert(hash, (i, chunk.to_vec()));
+                }
+            }
+        })
+    })?;
+    let payloads = payloads.into_inner().unwrap();
+
+    let mut w = BufWriter::new(File::create(&output)?);
+    let mut written = 0;
+    for hash in &order {
+        let (_, payload) = &payloads[hash];
+        w.write_all(payload)?;
+        written += payload.len();
+    }
+    w.into_inner()?;
+    let mismatch = first_mismatch(
+        BufReader::new(File::open(&output)?),
+        BufReader::new(File::open(source)?),
+    )?;
+    Ok((written, mismatch))
+}
@@ -1,39 +1,82 @@
+use crate::decompress;
+use crate::progress::Progress;
 use anyhow::Result;
 use deduplication::constants::TARGET_CHUNK_SIZE;
 use deduplication::Chunker;
 use std::collections::HashSet;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::Read;
 
 const READ_BUFFER_SIZE: usize = 4 * 1024 * 1024;
 
-pub fn dedup_estimate(file_paths: Vec<String>) -> Result<u64> {
+fn raw_hash(bytes: &[u8]) -> [u8; 32] {
+    let mut raw = [0u8; 32];
+    raw.copy_from_slice(bytes);
+    raw
+}
+
+/// Cut a file with the Xet chunker, calling `f` with the raw hash and the
+/// size of every chunk. With `decompress` compressed files are cut
+/// decompressed, see the `decompress` module. The bytes read are reported
+/// to `progress`.
+fn for_each_chunk<F: FnMut([u8; 32], usize)>(
+    path: &str,
+    decompress: bool,
+    progress: &Progress,
+    mut f: F,
+) -> Result<()> {
+    let mut reader = decompress::open(path, decompress)?;
+    let mut chunker = Chunker::new(*TARGET_CHUNK_SIZE);
+    let mut buf = vec![0u8; READ_BUFFER_SIZE];
+
+    loop {
+        let n = reader.read(&mut buf)?;
+        progress.add_bytes(n)?;
+        if n == 0 {
+            if let Some(chunk) = chunker.finish() {
+                f(raw_hash(chunk.hash.as_bytes()), chunk.data.len());
+            }
+            break;
+        }
+        for chunk in chunker.next_block(&buf[..n], false) {
+            f(raw_hash(chunk.hash.as_bytes()), chunk.data.len());
+        }
+    }
+    Ok(())
+}
+
+pub fn dedup_estimate(
+    file_paths: Vec<String>,
+    decompress: bool,
+    progress: &Progress,
+) -> Result<u64> {
     let mut seen = HashSet::new();
     let mut unique_bytes: u64 = 0;
 
     for path in &file_paths {
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
-        let mut chunker = Chunker::new(*TARGET_CHUNK_SIZE);
-        let mut buf = vec![0u8; READ_BUFFER_SIZE];
-
-        loop {
-            let n = reader.read(&mut buf)?;
-            if n == 0 {
-                if let Some(chunk) = chunker.finish() {
-                    if seen.insert(chunk.hash) {
-                        unique_bytes += chunk.data.len() as u64;
-                    }
-                }
-                break;
-            }
-            for chunk in chunker.next_block(&buf[..n], false) {
-                if seen.insert(chunk.hash) {
-                    unique_bytes += chunk.data.len() as u64;
-                }
+        for_each_chunk(path, decompress, progress, |hash, size| {
+            if seen.insert(hash) {
+                unique_bytes += size as u64;
             }
-        }
+        })?;
+        progress.file_done(0)?;
     }
 
     Ok(unique_bytes)
 }
+
+/// The raw 32-byte Xet chunk hashes and the sizes of the chunks of a file,
+/// in order.
+pub fn chunk_hashes(path: &str) -> Result<Vec<([u8; 32], usize)>> {
+    let mut chunks = Vec::new();
+    let progress = Progress::default();
+    for_each_chunk(path, false, &progress, |hash, size| {
+        chunks.push((hash, size))
+    })?;
+    Ok(chunks)
+}
+
+/// The keyed hash the Xet shards list the chunk hashes under when their
+/// footer carries a non-zero HMAC key.
+pub fn hmac(hash: &[u8], key: &[u8; 32]) -> [u8; 32] {
+    *blake3::keyed_hash(key, hash).as_bytes()
+}